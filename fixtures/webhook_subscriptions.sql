@@ -0,0 +1,14 @@
+INSERT INTO subscription (id,
+                          created_date_time,
+                          modification_date_time,
+                          client_id,
+                          client_name,
+                          program_id,
+                          object_operations)
+VALUES ('webhook-subscription-1',
+        '2024-07-25 08:31:10.776000 +00:00',
+        '2024-07-25 08:31:10.776000 +00:00',
+        'ven-1-client-id',
+        'abc',
+        NULL,
+        '[{"objects": ["EVENT"], "operations": ["CREATE"], "mechanism": "WEBHOOK", "callbackUrl": "https://ven-1.example.com/callback", "bearerToken": "production-token"}]'::jsonb);
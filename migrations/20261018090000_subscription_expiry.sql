@@ -0,0 +1,7 @@
+ALTER TABLE subscription
+    ADD COLUMN time_to_live      text,
+    ADD COLUMN expires_date_time timestamptz;
+
+CREATE INDEX subscription_expires_date_time_index ON subscription (expires_date_time)
+    WHERE expires_date_time IS NOT NULL;
+CREATE INDEX subscription_client_id_index ON subscription (client_id);
@@ -0,0 +1,2 @@
+ALTER TABLE subscription
+    ADD COLUMN batch_window text;
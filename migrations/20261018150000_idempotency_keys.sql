@@ -0,0 +1,15 @@
+CREATE TABLE idempotency_key
+(
+    client_id   text        NOT NULL,
+    key         text        NOT NULL,
+    fingerprint text        NOT NULL,
+    -- NULL while the first request with the key is being processed
+    status      smallint,
+    headers     jsonb,
+    body        bytea,
+    created     timestamptz NOT NULL DEFAULT now(),
+    expires     timestamptz NOT NULL,
+    PRIMARY KEY (client_id, key)
+);
+
+CREATE INDEX idempotency_key_expires_idx ON idempotency_key (expires);
@@ -0,0 +1,5 @@
+-- The attribute filters of `GET /vens` and `GET /resources` look up attribute types and values
+-- by containment (`@>`), which these indexes support.
+-- Numeric ranges are compared on the rows found that way.
+CREATE INDEX ven_attributes_index ON ven USING gin (attributes jsonb_path_ops);
+CREATE INDEX resource_attributes_index ON resource USING gin (attributes jsonb_path_ops);
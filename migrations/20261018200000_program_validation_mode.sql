@@ -0,0 +1,2 @@
+ALTER TABLE program
+    ADD COLUMN validation_mode text;
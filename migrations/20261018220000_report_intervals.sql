@@ -0,0 +1,47 @@
+-- The intervals of reports are stored one per row, such that appending intervals to a report
+-- does not rewrite the report. `report.resources` keeps the resources with empty `intervals`.
+CREATE TABLE report_interval
+(
+    report_id       text    NOT NULL REFERENCES report (id) ON DELETE CASCADE,
+    -- position of the resource in `report.resources`
+    resource_index  integer NOT NULL,
+    -- position of the interval in the resource
+    position        integer NOT NULL,
+    id              integer NOT NULL,
+    interval_period jsonb,
+    payloads        jsonb   NOT NULL,
+    PRIMARY KEY (report_id, resource_index, position)
+);
+
+CREATE INDEX report_interval_id_idx ON report_interval (report_id, resource_index, id);
+
+INSERT INTO report_interval (report_id, resource_index, position, id, interval_period, payloads)
+SELECT r.id,
+       res.ordinality - 1,
+       iv.ordinality - 1,
+       (iv.value ->> 'id')::integer,
+       iv.value -> 'intervalPeriod',
+       iv.value -> 'payloads'
+FROM report r,
+     jsonb_array_elements(r.resources) WITH ORDINALITY AS res(value, ordinality),
+     jsonb_array_elements(res.value -> 'intervals') WITH ORDINALITY AS iv(value, ordinality);
+
+UPDATE report r
+SET resources = (SELECT coalesce(jsonb_agg(jsonb_set(res.value, '{intervals}', '[]') ORDER BY res.ordinality), '[]')
+                 FROM jsonb_array_elements(r.resources) WITH ORDINALITY AS res(value, ordinality));
+
+-- The resources of a report, given its ID and `resources`, with their intervals
+CREATE FUNCTION report_resources(text, jsonb) RETURNS jsonb
+    LANGUAGE sql
+    STABLE
+AS
+$$
+SELECT coalesce(jsonb_agg(jsonb_set(res.value, '{intervals}', coalesce(
+        (SELECT jsonb_agg(jsonb_build_object('id', i.id,
+                                             'intervalPeriod', i.interval_period,
+                                             'payloads', i.payloads) ORDER BY i.position)
+         FROM report_interval i
+         WHERE i.report_id = $1
+           AND i.resource_index = res.ordinality - 1), '[]')) ORDER BY res.ordinality), '[]')
+FROM jsonb_array_elements($2) WITH ORDINALITY AS res(value, ordinality)
+$$;
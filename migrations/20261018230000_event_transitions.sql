@@ -0,0 +1,70 @@
+-- The first and the last lifecycle transition of each event (start of an interval or end of the event),
+-- such that the lifecycle scheduler only needs to look at the events with transitions around now.
+-- Both are NULL if the intervals of the event cannot be resolved to time ranges.
+ALTER TABLE event
+    ADD COLUMN first_transition timestamptz,
+    ADD COLUMN last_transition  timestamptz;
+
+CREATE INDEX event_first_transition_idx ON event (first_transition);
+CREATE INDEX event_last_transition_idx ON event (last_transition);
+
+-- The end of a period, `infinity` if it has no duration or ends after the latest timestamp,
+-- like `IntervalPeriod::end`
+CREATE FUNCTION pg_temp.period_end(start_time timestamptz, duration text) RETURNS timestamptz
+    LANGUAGE plpgsql AS
+$$
+BEGIN
+    IF duration IS NULL THEN
+        RETURN 'infinity'::timestamptz;
+    END IF;
+    RETURN start_time + duration::interval;
+EXCEPTION
+    WHEN datetime_field_overflow OR interval_field_overflow OR numeric_value_out_of_range THEN
+        RETURN 'infinity'::timestamptz;
+END;
+$$;
+
+-- Resolves the intervals of the existing events the same way as `EventRequest::resolved_intervals`
+WITH RECURSIVE event_interval AS (SELECT e.id,
+                                         iv.ordinality,
+                                         CASE
+                                             WHEN jsonb_typeof(iv.value -> 'intervalPeriod') = 'object'
+                                                 THEN iv.value -> 'intervalPeriod' END AS period,
+                                         CASE
+                                             WHEN jsonb_typeof(e.interval_period) = 'object'
+                                                 THEN e.interval_period END            AS default_period
+                                  FROM event e,
+                                       jsonb_array_elements(e.intervals) WITH ORDINALITY AS iv(value, ordinality)),
+               resolved AS (SELECT i.id,
+                                   i.ordinality,
+                                   p.start_time,
+                                   pg_temp.period_end(p.start_time, p.duration) AS end_time
+                            FROM event_interval i,
+                                 LATERAL (SELECT (coalesce(i.period, i.default_period) ->> 'start')::timestamptz AS start_time,
+                                                 coalesce(i.period, i.default_period) ->> 'duration'           AS duration) p
+                            WHERE i.ordinality = 1
+                            UNION ALL
+                            SELECT i.id,
+                                   i.ordinality,
+                                   p.start_time,
+                                   pg_temp.period_end(p.start_time, p.duration)
+                            FROM resolved r
+                                     JOIN event_interval i ON i.id = r.id AND i.ordinality = r.ordinality + 1,
+                                 LATERAL (SELECT CASE
+                                                     WHEN i.period IS NOT NULL
+                                                         THEN (i.period ->> 'start')::timestamptz
+                                                     WHEN i.default_period IS NOT NULL THEN r.end_time
+                                                     END                                                AS start_time,
+                                                 coalesce(i.period, i.default_period) ->> 'duration' AS duration) p)
+UPDATE event e
+SET first_transition = t.first_transition,
+    last_transition  = t.last_transition
+FROM (SELECT id,
+             min(start_time)                                                                  AS first_transition,
+             max(greatest(nullif(start_time, 'infinity'), nullif(end_time, 'infinity'))) AS last_transition
+      FROM resolved
+      GROUP BY id
+      HAVING bool_and(start_time IS NOT NULL)) t
+WHERE e.id = t.id;
+
+DROP FUNCTION pg_temp.period_end(timestamptz, text);
@@ -0,0 +1,18 @@
+-- The read scopes the client had when it created the subscription.
+-- Webhooks of the subscription get the same view on objects as the client had through the API.
+-- Subscriptions without owner scopes receive no objects through their webhooks.
+ALTER TABLE subscription
+    ADD COLUMN owner_scopes scope[] NOT NULL DEFAULT '{}';
+
+-- The scopes of the existing subscriptions are unknown.
+-- Clients of the internal OAuth provider keep the read scopes of their user,
+-- all other clients get the view of a VEN, as in the per-VEN MQTT topics.
+UPDATE subscription s
+SET owner_scopes = coalesce(
+        (SELECT array(SELECT unnest(u.scopes)
+                      INTERSECT
+                      SELECT unnest('{read_all,read_targets,read_ven_objects}'::scope[]))
+         FROM user_credentials c
+                  JOIN "user" u ON u.id = c.user_id
+         WHERE c.client_id = s.client_id),
+        '{read_targets,read_ven_objects}'::scope[]);
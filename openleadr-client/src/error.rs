@@ -1,13 +1,36 @@
+use openleadr_wire::problem::{ErrorCode, FieldError, Problem};
 use reqwest::StatusCode;
 
 /// Errors that can occur using the [`Client`](crate::Client)
+///
+/// Problem responses of the VTN are told apart by their [`ErrorCode`],
+/// or by their HTTP status code if the VTN does not send error codes.
 #[derive(Debug)]
 #[allow(missing_docs)]
 pub enum Error {
     Reqwest(reqwest::Error),
     Serde(serde_json::Error),
     UrlParseError(url::ParseError),
-    Problem(openleadr_wire::problem::Problem),
+    /// The request failed validation, or contradicts the descriptors of its program,
+    /// see [`Error::field_errors`] for the rejected fields
+    Validation(Problem),
+    /// The request is malformed, e.g., its body does not match the schema
+    BadRequest(Problem),
+    Unauthorized(Problem),
+    Forbidden(Problem),
+    NotFound(Problem),
+    /// An object with the same unique properties exists, see [`Error::constraint`]
+    Conflict(Problem),
+    /// The request refers to an object that does not exist,
+    /// or deletes an object others still refer to
+    ReferenceViolated(Problem),
+    ServiceUnavailable(Problem),
+    /// An operation of a batch request failed, so none of them were applied.
+    /// Holds the problem of each failed operation, in the order of the operations,
+    /// and `None` for the operations that would have succeeded.
+    BatchRolledBack(Vec<Option<Problem>>),
+    /// Any other problem, e.g., an internal error of the VTN
+    Problem(Problem),
     AuthProblem(openleadr_wire::oauth::OAuthError),
     OAuthTokenNotBearer,
     ObjectNotFound,
@@ -23,26 +46,77 @@ pub enum Error {
 }
 
 impl Error {
-    /// Checks if the [`Problem`](openleadr_wire::problem::Problem) response of the VTN is a
-    /// `409 Conflict` HTTP status code.
+    /// Checks if the [`Problem`] response of the VTN has the `409 Conflict` HTTP status code.
+    /// Besides [`Error::Conflict`], this includes [`Error::ReferenceViolated`]
+    /// and requests with an idempotency key that is still in progress.
     pub fn is_conflict(&self) -> bool {
-        match self {
-            Error::Problem(openleadr_wire::problem::Problem { status, .. }) => {
-                *status == StatusCode::CONFLICT
-            }
-            _ => false,
-        }
+        self.problem()
+            .is_some_and(|problem| problem.status == StatusCode::CONFLICT)
+    }
+
+    /// Checks if the VTN responded that an object with the same unique properties exists
+    pub fn is_unique_violation(&self) -> bool {
+        matches!(self, Error::Conflict(_))
+    }
+
+    /// Checks if the VTN responded that the request refers to an object that does not exist,
+    /// or deletes an object others still refer to
+    pub fn is_reference_violation(&self) -> bool {
+        matches!(self, Error::ReferenceViolated(_))
     }
 
     #[allow(missing_docs)]
     pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::NotFound(_))
+    }
+
+    /// Checks if sending the same request again may succeed,
+    /// e.g., because the connection failed or the VTN was busy
+    pub fn is_retryable(&self) -> bool {
         match self {
-            Error::Problem(openleadr_wire::problem::Problem { status, .. }) => {
-                *status == StatusCode::NOT_FOUND
+            Error::Reqwest(err) => {
+                err.is_timeout() || err.is_connect() || err.is_request() || err.is_body()
             }
-            _ => false,
+            Error::ServiceUnavailable(_) => true,
+            _ => self.code() == Some(ErrorCode::RequestInProgress),
         }
     }
+
+    /// The [`Problem`] response of the VTN, if the error is one.
+    /// For [`Error::BatchRolledBack`], the problem of the first failed operation.
+    pub fn problem(&self) -> Option<&Problem> {
+        match self {
+            Error::Validation(problem)
+            | Error::BadRequest(problem)
+            | Error::Unauthorized(problem)
+            | Error::Forbidden(problem)
+            | Error::NotFound(problem)
+            | Error::Conflict(problem)
+            | Error::ReferenceViolated(problem)
+            | Error::ServiceUnavailable(problem)
+            | Error::Problem(problem) => Some(problem),
+            Error::BatchRolledBack(problems) => problems.iter().flatten().next(),
+            _ => None,
+        }
+    }
+
+    /// The [`ErrorCode`] of the [`Problem`] response, if the VTN sent one
+    pub fn code(&self) -> Option<ErrorCode> {
+        self.problem()?.code
+    }
+
+    /// The fields the VTN rejected, for [`Error::Validation`]
+    pub fn field_errors(&self) -> &[FieldError] {
+        self.problem()
+            .and_then(|problem| problem.errors.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The database constraint that was violated,
+    /// for [`Error::Conflict`] and [`Error::ReferenceViolated`]
+    pub fn constraint(&self) -> Option<&str> {
+        self.problem()?.constraint.as_deref()
+    }
 }
 
 impl From<reqwest::Error> for Error {
@@ -63,9 +137,41 @@ impl From<url::ParseError> for Error {
     }
 }
 
-impl From<openleadr_wire::problem::Problem> for Error {
-    fn from(err: openleadr_wire::problem::Problem) -> Self {
-        Error::Problem(err)
+impl From<Problem> for Error {
+    fn from(problem: Problem) -> Self {
+        let code = problem.code.unwrap_or(match problem.status {
+            StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::ServiceUnavailable,
+            _ => ErrorCode::Unknown,
+        });
+
+        match code {
+            ErrorCode::ValidationFailed | ErrorCode::DescriptorMismatch => {
+                Error::Validation(problem)
+            }
+            ErrorCode::MalformedBody
+            | ErrorCode::MalformedQuery
+            | ErrorCode::MalformedIdentifier
+            | ErrorCode::BadRequest
+            | ErrorCode::PatchFailed
+            | ErrorCode::IdempotencyKeyReused => Error::BadRequest(problem),
+            ErrorCode::Unauthorized => Error::Unauthorized(problem),
+            ErrorCode::Forbidden => Error::Forbidden(problem),
+            ErrorCode::NotFound => Error::NotFound(problem),
+            ErrorCode::Conflict => Error::Conflict(problem),
+            ErrorCode::ReferenceViolated => Error::ReferenceViolated(problem),
+            ErrorCode::ServiceUnavailable => Error::ServiceUnavailable(problem),
+            ErrorCode::MethodNotAllowed
+            | ErrorCode::UnsupportedMediaType
+            | ErrorCode::NotImplemented
+            | ErrorCode::RequestInProgress
+            | ErrorCode::InternalError
+            | ErrorCode::Unknown => Error::Problem(problem),
+        }
     }
 }
 
@@ -81,7 +187,21 @@ impl std::fmt::Display for Error {
             Error::Reqwest(err) => write!(f, "Reqwest error: {err}"),
             Error::Serde(err) => write!(f, "Serde error: {err}"),
             Error::UrlParseError(err) => write!(f, "URL parse error: {err}"),
-            Error::Problem(err) => write!(f, "OpenADR Problem: {err:?}"),
+            Error::Validation(problem)
+            | Error::BadRequest(problem)
+            | Error::Unauthorized(problem)
+            | Error::Forbidden(problem)
+            | Error::NotFound(problem)
+            | Error::Conflict(problem)
+            | Error::ReferenceViolated(problem)
+            | Error::ServiceUnavailable(problem)
+            | Error::Problem(problem) => write!(f, "OpenADR Problem: {problem:?}"),
+            Error::BatchRolledBack(problems) => write!(
+                f,
+                "Batch rolled back, as {} operation(s) failed: {:?}",
+                problems.iter().flatten().count(),
+                problems.iter().flatten().collect::<Vec<_>>()
+            ),
             Error::AuthProblem(err) => write!(f, "Authentication problem: {err:?}"),
             Error::ObjectNotFound => write!(f, "Object not found"),
             Error::DuplicateObject => write!(f, "Found more than one object matching the filter"),
@@ -95,3 +215,42 @@ impl std::fmt::Display for Error {
 impl std::error::Error for Error {}
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn problem(status: StatusCode, code: Option<ErrorCode>) -> Error {
+        Problem {
+            status,
+            code,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn conflicts() {
+        let unique = problem(StatusCode::CONFLICT, Some(ErrorCode::Conflict));
+        assert!(unique.is_conflict());
+        assert!(unique.is_unique_violation());
+        assert!(!unique.is_reference_violation());
+
+        let reference = problem(StatusCode::CONFLICT, Some(ErrorCode::ReferenceViolated));
+        assert!(reference.is_conflict());
+        assert!(!reference.is_unique_violation());
+        assert!(reference.is_reference_violation());
+
+        let in_progress = problem(StatusCode::CONFLICT, Some(ErrorCode::RequestInProgress));
+        assert!(in_progress.is_conflict());
+        assert!(!in_progress.is_unique_violation());
+        assert!(in_progress.is_retryable());
+
+        // VTNs without error codes are told apart by the status only
+        let without_code = problem(StatusCode::CONFLICT, None);
+        assert!(without_code.is_conflict());
+        assert!(without_code.is_unique_violation());
+
+        assert!(!problem(StatusCode::NOT_FOUND, Some(ErrorCode::NotFound)).is_conflict());
+    }
+}
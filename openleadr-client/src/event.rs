@@ -104,37 +104,21 @@ impl<K: ClientKind> EventClient<K> {
         Ok(ReportClient::from_report(self.client.clone(), report))
     }
 
-    async fn get_reports_req(
-        &self,
-        client_name: Option<&str>,
-        skip: usize,
-        limit: usize,
-    ) -> Result<Vec<ReportClient<K>>> {
-        let skip_str = skip.to_string();
-        let limit_str = limit.to_string();
-
+    /// Get all reports from the VTN, possibly filtered by `client_name`, trying to paginate whenever possible
+    pub async fn get_report_list(&self, client_name: Option<&str>) -> Result<Vec<ReportClient<K>>> {
         let mut query = vec![
             ("programID", self.content().program_id.as_str()),
             ("eventID", self.id().as_str()),
-            ("skip", &skip_str),
-            ("limit", &limit_str),
         ];
 
         if let Some(client_name) = client_name {
             query.push(("clientName", client_name));
         }
 
-        let reports: Vec<Report> = self.client.get("reports", &query).await?;
+        let reports: Vec<Report> = self.client.iterate_pages("reports", &query).await?;
         Ok(reports
             .into_iter()
             .map(|report| ReportClient::from_report(self.client.clone(), report))
             .collect())
     }
-
-    /// Get all reports from the VTN, possibly filtered by `client_name`, trying to paginate whenever possible
-    pub async fn get_report_list(&self, client_name: Option<&str>) -> Result<Vec<ReportClient<K>>> {
-        self.client
-            .iterate_pages(|skip, limit| self.get_reports_req(client_name, skip, limit))
-            .await
-    }
 }
@@ -66,15 +66,19 @@ use async_trait::async_trait;
 use openleadr_wire::{Event, Ven, event::EventId};
 use std::{
     fmt::Debug,
-    future::Future,
     marker::PhantomData,
     sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::sync::RwLock;
+use tracing::debug;
 
-use reqwest::{Method, RequestBuilder, Response};
+use reqwest::{
+    Method, RequestBuilder, Response,
+    header::{HeaderMap, LINK},
+};
 use url::Url;
+use uuid::Uuid;
 
 pub use error::*;
 pub use event::*;
@@ -89,9 +93,19 @@ pub use ven::*;
 use crate::error::Result;
 use openleadr_wire::{
     Program,
+    batch::{BatchOperation, BatchRequest, BatchResponse},
+    event::EventRequest,
+    problem::Problem,
     program::{ProgramId, ProgramRequest},
+    resource::{BlResourceRequest, Resource, ResourceId},
     ven::{BlVenRequest, VenId, VenRequest, VenVenRequest},
 };
+use serde::de::IgnoredAny;
+
+/// How often a `POST` request is sent at most, if it keeps failing with a retryable error
+const POST_ATTEMPTS: u32 = 3;
+/// Time before the first retry of a `POST` request, doubled for each further retry
+const POST_RETRY_DELAY: Duration = Duration::from_millis(500);
 
 #[async_trait]
 /// Abstracts the implementation used for actual requests.
@@ -282,9 +296,14 @@ impl<K: ClientKind> ClientRef<K> {
 
     async fn request<T: serde::de::DeserializeOwned>(
         &self,
-        mut request: RequestBuilder,
+        request: RequestBuilder,
         query: &[(&str, &str)],
     ) -> Result<T> {
+        Ok(self.send(request, query).await?.json().await?)
+    }
+
+    /// Sends the request, and turns any error response into an [`Error`]
+    async fn send(&self, mut request: RequestBuilder, query: &[(&str, &str)]) -> Result<Response> {
         self.ensure_auth().await?;
         request = request.header("Accept", "application/json");
         if !query.is_empty() {
@@ -302,11 +321,22 @@ impl<K: ClientKind> ClientRef<K> {
 
         // handle any errors returned by the server
         if !res.status().is_success() {
-            let problem = res.json::<openleadr_wire::problem::Problem>().await?;
-            return Err(crate::error::Error::from(problem));
+            let body = res.bytes().await?;
+            if let Ok(problem) = serde_json::from_slice::<Problem>(&body) {
+                return Err(Error::from(problem));
+            }
+            // batch requests report the outcome of each operation instead
+            let batch = serde_json::from_slice::<BatchResponse<IgnoredAny>>(&body)?;
+            return Err(Error::BatchRolledBack(
+                batch
+                    .results
+                    .into_iter()
+                    .map(|result| result.problem)
+                    .collect(),
+            ));
         }
 
-        Ok(res.json().await?)
+        Ok(res)
     }
 
     async fn get<T: serde::de::DeserializeOwned>(
@@ -319,14 +349,35 @@ impl<K: ClientKind> ClientRef<K> {
         self.request(request, query).await
     }
 
+    /// Sends the `POST` request with an `Idempotency-Key`, and retries it with the same key
+    /// if it timed out, the connection failed, or the VTN was unavailable.
+    /// If an earlier attempt went through after all,
+    /// the VTN replays its response instead of creating the object again.
     async fn post<S, T>(&self, path: &str, body: &S) -> Result<T>
     where
         S: serde::ser::Serialize + Sync,
         T: serde::de::DeserializeOwned,
     {
         let url = self.vtn_base_url.join(path)?;
-        let request = self.client.request_builder(Method::POST, url).json(body);
-        self.request(request, &[]).await
+        let idempotency_key = Uuid::new_v4().to_string();
+        let mut delay = POST_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            let request = self
+                .client
+                .request_builder(Method::POST, url.clone())
+                .header("Idempotency-Key", &idempotency_key)
+                .json(body);
+            match self.request(request, &[]).await {
+                Err(err) if attempt < POST_ATTEMPTS && err.is_retryable() => {
+                    debug!(%url, attempt, "Retrying POST request: {err}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
     async fn put<S, T>(&self, path: &str, body: &S) -> Result<T>
@@ -352,28 +403,51 @@ impl<K: ClientKind> ClientRef<K> {
         self.default_page_size
     }
 
-    async fn iterate_pages<T, Fut>(
+    /// Gets all objects of the list at `path` matching the `query`.
+    ///
+    /// Follows the cursors of the `Link` headers if the VTN supports them,
+    /// such that no object is skipped or repeated if objects are created in between.
+    /// Otherwise, it requests one page after the other with `skip`,
+    /// until a page is not full.
+    async fn iterate_pages<T: serde::de::DeserializeOwned>(
         &self,
-        single_page_req: impl Fn(usize, usize) -> Fut,
-    ) -> Result<Vec<T>>
-    where
-        Fut: Future<Output = Result<Vec<T>>>,
-    {
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<T>> {
         let page_size = self.default_page_size();
+        let limit = page_size.to_string();
+        // an empty cursor starts a cursor pagination, VTNs without support ignore it
+        let mut cursor = Some(String::new());
+        let mut skip = 0;
         let mut items = vec![];
-        let mut page = 0;
-        // TODO: pagination should depend on that the server indicated there are more results
         loop {
-            let received = single_page_req(page * page_size, page_size).await?;
-            let received_all = received.len() < page_size;
-            for item in received {
-                items.push(item);
+            let skip_str = skip.to_string();
+            let mut page_query = query.to_vec();
+            page_query.push(("limit", &limit));
+            match &cursor {
+                Some(cursor) => page_query.push(("cursor", cursor)),
+                None => page_query.push(("skip", &skip_str)),
             }
 
-            if received_all {
+            let url = self.vtn_base_url.join(path)?;
+            let request = self.client.request_builder(Method::GET, url);
+            let response = self.send(request, &page_query).await?;
+            let supports_cursors = response.headers().contains_key(TOTAL_COUNT);
+            let next_cursor = next_cursor(response.headers());
+            let received: Vec<T> = response.json().await?;
+            let received_all = received.len() < page_size;
+            items.extend(received);
+
+            if supports_cursors {
+                match next_cursor {
+                    Some(next_cursor) => cursor = Some(next_cursor),
+                    None => break,
+                }
+            } else if received_all {
                 break;
             } else {
-                page += 1;
+                cursor = None;
+                skip += page_size;
             }
         }
 
@@ -381,6 +455,29 @@ impl<K: ClientKind> ClientRef<K> {
     }
 }
 
+/// Number of objects matching the filter of a list request, sent by VTNs supporting cursors
+const TOTAL_COUNT: &str = "x-total-count";
+
+/// The `cursor` of the `Link` header with `rel="next"`, if any
+fn next_cursor(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let (target, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+            params
+                .split(';')
+                .any(|param| matches!(param.trim(), "rel=\"next\"" | "rel=next"))
+                .then_some(())?;
+            let (_, query) = target.split_once('?')?;
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(name, _)| name == "cursor")
+                .map(|(_, cursor)| cursor.into_owned())
+        })
+}
+
 #[derive(Debug)]
 struct ReqwestClientRef {
     client: reqwest::Client,
@@ -468,6 +565,59 @@ impl Client<BusinessLogic> {
             .await?;
         Ok(VenClient::from_ven(self.client_ref.clone(), ven))
     }
+
+    /// Creates, updates, and deletes events in a single transaction at the VTN.
+    ///
+    /// Returns the created, updated, and deleted events in the order of the operations.
+    /// If any operation fails, none is applied, and [`Error::BatchRolledBack`] tells which failed.
+    /// This extension of the specification is only supported by the VTN of this project.
+    pub async fn batch_events(
+        &self,
+        operations: Vec<BatchOperation<EventId, EventRequest>>,
+    ) -> Result<Vec<Event>> {
+        self.batch("./events:batch", operations).await
+    }
+
+    /// Creates, updates, and deletes VENs in a single transaction at the VTN,
+    /// like [`Self::batch_events`]
+    pub async fn batch_vens(
+        &self,
+        operations: Vec<BatchOperation<VenId, BlVenRequest>>,
+    ) -> Result<Vec<Ven>> {
+        self.batch("./vens:batch", operations).await
+    }
+
+    /// Creates, updates, and deletes resources of any VEN in a single transaction at the VTN,
+    /// like [`Self::batch_events`]
+    pub async fn batch_resources(
+        &self,
+        operations: Vec<BatchOperation<ResourceId, BlResourceRequest>>,
+    ) -> Result<Vec<Resource>> {
+        self.batch("./resources:batch", operations).await
+    }
+
+    /// The path is relative to the base URL, and starts with `./`,
+    /// as the colon would otherwise make it a URL scheme
+    async fn batch<Id, C, T>(
+        &self,
+        path: &str,
+        operations: Vec<BatchOperation<Id, C>>,
+    ) -> Result<Vec<T>>
+    where
+        Id: serde::Serialize + Sync,
+        C: serde::Serialize + Sync,
+        T: serde::de::DeserializeOwned,
+    {
+        let response: BatchResponse<T> = self
+            .client_ref
+            .post(path, &BatchRequest::new(operations))
+            .await?;
+        Ok(response
+            .results
+            .into_iter()
+            .filter_map(|result| result.object)
+            .collect())
+    }
 }
 
 impl<K: ClientKind> Client<K> {
@@ -475,10 +625,12 @@ impl<K: ClientKind> Client<K> {
     ///
     /// This assumes that the VTN also works as an OAuth provider
     /// and exposes an API endpoint at `<base_url>/auth/token` to retrieve a token.
+    /// If the VTN serves its API under a base path, `base_url` must end with a slash,
+    /// e.g., `https://your-vtn.com/openadr3/3.1.0/`.
     /// If you want to use another OAuth provider, please use [`Self::with_details`].
     pub fn with_url(base_url: Url, auth: Option<ClientCredentials>) -> Self {
         let client = reqwest::Client::new();
-        let oauth_base_url = base_url.join("/auth/token").unwrap();
+        let oauth_base_url = base_url.join("auth/token").unwrap();
         Self::with_details(base_url, oauth_base_url, client, auth)
     }
 
@@ -570,11 +722,14 @@ impl<K: ClientKind> Client<K> {
         &self,
         filter: Filter<'_, impl AsRef<str> + Clone>,
     ) -> Result<Vec<ProgramClient<K>>> {
-        self.client_ref
-            .iterate_pages(|skip, limit| {
-                self.get_programs(filter.clone(), PaginationOptions { skip, limit })
-            })
-            .await
+        let programs: Vec<Program> = self
+            .client_ref
+            .iterate_pages("programs", &filter.to_query_params())
+            .await?;
+        Ok(programs
+            .into_iter()
+            .map(|program| ProgramClient::from_program(self.clone(), program))
+            .collect())
     }
 
     /// Get a program by id
@@ -624,15 +779,16 @@ impl<K: ClientKind> Client<K> {
         program_id: Option<&ProgramId>,
         filter: Filter<'_, impl AsRef<str> + Clone>,
     ) -> Result<Vec<EventClient<K>>> {
-        self.client_ref
-            .iterate_pages(|skip, limit| {
-                self.get_events(
-                    program_id,
-                    filter.clone(),
-                    PaginationOptions { skip, limit },
-                )
-            })
-            .await
+        let mut query = filter.to_query_params();
+        if let Some(program_id) = program_id {
+            query.push(("programID", program_id.as_str()));
+        }
+
+        let events: Vec<Event> = self.client_ref.iterate_pages("events", &query).await?;
+        Ok(events
+            .into_iter()
+            .map(|event| EventClient::from_event(self.client_ref.clone(), event))
+            .collect())
     }
 
     /// Get an event by id
@@ -645,26 +801,6 @@ impl<K: ClientKind> Client<K> {
         Ok(EventClient::from_event(self.client_ref.clone(), event))
     }
 
-    async fn get_vens(
-        &self,
-        skip: usize,
-        limit: usize,
-        filter: Filter<'_, impl AsRef<str>>,
-    ) -> Result<Vec<VenClient<K>>> {
-        let skip_str = skip.to_string();
-        let limit_str = limit.to_string();
-        let mut query: Vec<(&str, &str)> = vec![("skip", &skip_str), ("limit", &limit_str)];
-
-        query.extend_from_slice(filter.to_query_params().as_slice());
-
-        // send request and return response
-        let vens: Vec<Ven> = self.client_ref.get("vens", &query).await?;
-        Ok(vens
-            .into_iter()
-            .map(|ven| VenClient::from_ven(self.client_ref.clone(), ven))
-            .collect())
-    }
-
     /// Get all VENs from the VTN with the given query parameters.
     ///
     /// The client automatically tries to iterate pages where necessary.
@@ -672,9 +808,14 @@ impl<K: ClientKind> Client<K> {
         &self,
         filter: Filter<'_, impl AsRef<str> + Clone>,
     ) -> Result<Vec<VenClient<K>>> {
-        self.client_ref
-            .iterate_pages(|skip, limit| self.get_vens(skip, limit, filter.clone()))
-            .await
+        let vens: Vec<Ven> = self
+            .client_ref
+            .iterate_pages("vens", &filter.to_query_params())
+            .await?;
+        Ok(vens
+            .into_iter()
+            .map(|ven| VenClient::from_ven(self.client_ref.clone(), ven))
+            .collect())
     }
 
     /// Get VEN by id from VTN
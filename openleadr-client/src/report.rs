@@ -1,6 +1,10 @@
 use std::sync::Arc;
 
-use openleadr_wire::{Report, report::ReportRequest};
+use openleadr_wire::{
+    Report,
+    interval::Interval,
+    report::{AppendedIntervals, ReportRequest, ResourceName},
+};
 
 use crate::{ClientKind, ClientRef, error::Result};
 
@@ -75,6 +79,37 @@ impl<K: ClientKind> ReportClient<K> {
         Ok(())
     }
 
+    /// Appends the intervals to the resource of the report at the VTN,
+    /// without sending the intervals the report already has,
+    /// and adds them to the locally stored data.
+    /// Fails if the resource already has an interval with the id of a new one.
+    pub async fn append_intervals(
+        &mut self,
+        resource_name: ResourceName,
+        intervals: Vec<Interval>,
+    ) -> Result<()> {
+        let appended: AppendedIntervals = self
+            .client
+            .post(
+                &format!("reports/{}/intervals", self.id()),
+                &AppendedIntervals {
+                    resource_name,
+                    intervals,
+                },
+            )
+            .await?;
+        if let Some(resource) = self
+            .data
+            .content
+            .resources
+            .iter_mut()
+            .find(|resource| resource.resource_name == appended.resource_name)
+        {
+            resource.intervals.extend(appended.intervals);
+        }
+        Ok(())
+    }
+
     /// Delete the report from the VTN
     pub async fn delete(self) -> Result<Report> {
         self.client.delete(&format!("reports/{}", self.id())).await
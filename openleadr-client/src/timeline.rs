@@ -6,7 +6,6 @@ use tracing::warn;
 use openleadr_wire::{
     Program,
     event::{EventRequest, EventValuesMap, Priority},
-    interval::IntervalPeriod,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -66,11 +65,12 @@ impl Timeline {
     /// There is no guarantee which event will take precedence, though in the current implementation
     /// the event stored later in the `events` param will take precedence.
     ///
-    /// There must be an [`IntervalPeriod`] present on the event level,
+    /// There must be an [`IntervalPeriod`](openleadr_wire::interval::IntervalPeriod) present on the event level,
     /// or the individual intervals.
     /// If both are specified, the individual period takes precedence over the one specified in the event.
     /// If for an interval, there is no period present, and none specified in the event,
-    /// then this function will return [`None`]
+    /// then this function will return [`None`].
+    /// See [`EventRequest::resolved_intervals`] for how the periods are resolved.
     pub fn from_events(program: &Program, mut events: Vec<&EventRequest>) -> Option<Self> {
         let mut data = Self::default();
 
@@ -82,51 +82,24 @@ impl Timeline {
                 continue;
             }
 
-            let default_period = event.interval_period.as_ref();
-
-            let mut current_start = default_period.map(|p| p.start);
-
-            if let Some(event_intervals) = &event.intervals {
-                for event_interval in event_intervals {
-                    // use the event interval period when the interval doesn't specify one
-                    let (start, duration, randomize_start) =
-                        match event_interval.interval_period.as_ref() {
-                            Some(IntervalPeriod {
-                                start,
-                                duration,
-                                randomize_start,
-                            }) => (start, duration, randomize_start),
-                            None => (
-                                &current_start?,
-                                &default_period?.duration,
-                                &default_period?.randomize_start,
-                            ),
-                        };
-
-                    let range = match duration {
-                        Some(duration) => *start..*start + duration.to_chrono_at_datetime(*start),
-                        None => *start..DateTime::<Utc>::MAX_UTC,
-                    };
-
-                    current_start = Some(range.end);
-
-                    let interval = InternalInterval {
-                        id: id as u32,
-                        randomize_start: randomize_start
-                            .as_ref()
-                            .map(|d| d.to_chrono_at_datetime(*start)),
-                        value_map: event_interval.payloads.clone(),
-                        priority: event.priority,
-                    };
-
-                    for (existing_range, existing) in data.data.overlapping(&range) {
-                        if existing.priority == event.priority {
-                            warn!(?existing_range, ?existing, new_range = ?range, new = ?interval, "Overlapping ranges with equal priority");
-                        }
+            for resolved in event.resolved_intervals()? {
+                let range = resolved.range;
+                let interval = InternalInterval {
+                    id: id as u32,
+                    randomize_start: resolved
+                        .randomize_start
+                        .map(|d| d.to_chrono_at_datetime(range.start)),
+                    value_map: resolved.interval.payloads.clone(),
+                    priority: event.priority,
+                };
+
+                for (existing_range, existing) in data.data.overlapping(&range) {
+                    if existing.priority == event.priority {
+                        warn!(?existing_range, ?existing, new_range = ?range, new = ?interval, "Overlapping ranges with equal priority");
                     }
-
-                    data.data.insert(range, interval);
                 }
+
+                data.data.insert(range, interval);
             }
         }
 
@@ -254,6 +227,7 @@ mod test {
     use super::*;
     use openleadr_wire::{
         event::EventInterval,
+        interval::IntervalPeriod,
         program::{ProgramId, ProgramRequest},
         values_map::Value,
     };
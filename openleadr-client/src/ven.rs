@@ -122,40 +122,26 @@ impl<K: ClientKind> VenClient<K> {
         self.client.delete(&format!("vens/{}", self.id())).await
     }
 
-    async fn get_resources_req(
+    /// Get all resources stored as children of this VEN.
+    ///
+    /// The client automatically tries to iterate pages where necessary.
+    pub async fn get_all_resources(
         &self,
         resource_name: Option<&str>,
-        skip: usize,
-        limit: usize,
     ) -> Result<Vec<ResourceClient<K>>> {
-        let skip_str = skip.to_string();
-        let limit_str = limit.to_string();
-
-        let mut query: Vec<(&str, &str)> = vec![("skip", &skip_str), ("limit", &limit_str)];
+        let mut query: Vec<(&str, &str)> = vec![];
 
         if let Some(resource_name) = resource_name {
             query.push(("resourceName", resource_name));
         }
 
-        let resources: Vec<Resource> = self.client.get("/resources", &query).await?;
+        let resources: Vec<Resource> = self.client.iterate_pages("/resources", &query).await?;
         Ok(resources
             .into_iter()
             .map(|resource| ResourceClient::from_resource(Arc::clone(&self.client), resource))
             .collect())
     }
 
-    /// Get all resources stored as children of this VEN.
-    ///
-    /// The client automatically tries to iterate pages where necessary.
-    pub async fn get_all_resources(
-        &self,
-        resource_name: Option<&str>,
-    ) -> Result<Vec<ResourceClient<K>>> {
-        self.client
-            .iterate_pages(|skip, limit| self.get_resources_req(resource_name, skip, limit))
-            .await
-    }
-
     /// Get a resource by its ID
     pub async fn get_resource_by_id(&self, id: &ResourceId) -> Result<ResourceClient<K>> {
         let resource = self.client.get(&format!("resources/{}", id), &[]).await?;
@@ -109,8 +109,9 @@ async fn local_vtn_test_client<K: ClientKind>(db: PgPool, auth_role: AuthRole) -
     let cred = default_credentials(auth_role);
     let storage = PostgresStorage::new(db).unwrap();
 
-    let router = AppState::new(storage, &VtnConfig::from_env())
+    let router = AppState::new(storage, &VtnConfig::from_env().unwrap())
         .await
+        .unwrap()
         .into_router();
     TestContext {
         client: MockClientRef::new(router).into_client(Some(cred)),
@@ -118,15 +119,20 @@ async fn local_vtn_test_client<K: ClientKind>(db: PgPool, auth_role: AuthRole) -
 }
 
 // FIXME make this function independent of the storage backend
+pub async fn setup_mock_router(db: PgPool) -> axum::Router {
+    let storage = PostgresStorage::new(db).unwrap();
+
+    AppState::new(storage, &VtnConfig::from_env().unwrap())
+        .await
+        .unwrap()
+        .into_router()
+}
+
 pub async fn setup_mock_client<K: ClientKind>(db: PgPool) -> Client<K> {
     let client_credentials =
         ClientCredentials::new("bl-client".to_string(), "bl-client".to_string());
 
-    let storage = PostgresStorage::new(db).unwrap();
-
-    let app_state = AppState::new(storage, &VtnConfig::from_env()).await;
-
-    MockClientRef::new(app_state.into_router()).into_client(Some(client_credentials))
+    MockClientRef::new(setup_mock_router(db).await).into_client(Some(client_credentials))
 }
 
 pub fn setup_url_client<K: ClientKind>(url: Url) -> Client<K> {
@@ -164,6 +170,7 @@ pub async fn setup_program_client<K: ClientKind>(
         payload_descriptors: None,
         attributes: None,
         targets: vec![],
+        validation_mode: None,
     };
 
     client.create_program(program_content).await.unwrap()
@@ -1,7 +1,9 @@
 use axum::http::StatusCode;
 use openleadr_client::{BusinessLogic, Error, Filter, PaginationOptions};
 use openleadr_wire::{
+    batch::BatchOperation,
     event::{EventInterval, EventRequest, EventType, EventValuesMap, Priority},
+    problem::ErrorCode,
     program::{ProgramId, ProgramRequest},
     target::Target,
     values_map::Value,
@@ -320,7 +322,8 @@ async fn filter_constraint_violation(db: PgPool) {
         )
         .await
         .unwrap_err();
-    let Error::Problem(problem) = err else {
+    assert_eq!(err.field_errors()[0].field, "limit");
+    let Error::Validation(problem) = err else {
         unreachable!()
     };
     assert_eq!(problem.status, StatusCode::BAD_REQUEST);
@@ -333,8 +336,92 @@ async fn filter_constraint_violation(db: PgPool) {
         )
         .await
         .unwrap_err();
-    let Error::Problem(problem) = err else {
+    assert_eq!(err.field_errors()[0].field, "limit");
+    let Error::Validation(problem) = err else {
         unreachable!()
     };
     assert_eq!(problem.status, StatusCode::BAD_REQUEST);
 }
+
+#[sqlx::test(fixtures("users"))]
+async fn batch(db: PgPool) {
+    let client = common::setup_client::<BusinessLogic>(db).await;
+    let program = client
+        .create_program(ProgramRequest::new("program"))
+        .await
+        .unwrap();
+    let event = |name: &str| EventRequest {
+        event_name: Some(name.to_string()),
+        ..default_content(program.id())
+    };
+
+    let events = client
+        .batch_events(vec![
+            BatchOperation::Create {
+                content: event("hour-1"),
+            },
+            BatchOperation::Create {
+                content: event("hour-2"),
+            },
+        ])
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].content, event("hour-2"));
+
+    let err = client
+        .batch_events(vec![
+            BatchOperation::Delete {
+                id: events[0].id.clone(),
+            },
+            BatchOperation::Update {
+                id: "not-existent".parse().unwrap(),
+                content: event("hour-3"),
+            },
+        ])
+        .await
+        .unwrap_err();
+    let Error::BatchRolledBack(ref problems) = err else {
+        unreachable!("{err:?}")
+    };
+    assert!(problems[0].is_none());
+    assert_eq!(problems[1].as_ref().unwrap().status, StatusCode::NOT_FOUND);
+    assert_eq!(err.code(), Some(ErrorCode::NotFound));
+
+    assert_eq!(
+        program.get_event_list(Filter::none()).await.unwrap().len(),
+        2
+    );
+}
+
+#[sqlx::test(fixtures("users"))]
+async fn list_follows_cursors(db: PgPool) {
+    let client = common::setup_client::<BusinessLogic>(db).await;
+    let program = client
+        .create_program(ProgramRequest::new("program"))
+        .await
+        .unwrap();
+
+    // more than the default page size of 50
+    let created = client
+        .batch_events(
+            (0..60)
+                .map(|hour| BatchOperation::Create {
+                    content: EventRequest {
+                        event_name: Some(format!("hour-{hour}")),
+                        ..default_content(program.id())
+                    },
+                })
+                .collect(),
+        )
+        .await
+        .unwrap();
+
+    let events = program.get_event_list(Filter::none()).await.unwrap();
+    // the events of a batch share their creation time, the cursor continues by ID
+    let mut ids: Vec<_> = events.iter().map(|event| event.id().to_string()).collect();
+    let mut created: Vec<_> = created.iter().map(|event| event.id.to_string()).collect();
+    ids.sort();
+    created.sort();
+    assert_eq!(ids, created);
+}
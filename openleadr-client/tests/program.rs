@@ -1,8 +1,16 @@
+use async_trait::async_trait;
 use axum::http::StatusCode;
-use openleadr_client::{Error, Filter, PaginationOptions, VirtualEndNode};
+use openleadr_client::{
+    BusinessLogic, ClientCredentials, Error, Filter, HttpClient, PaginationOptions, VirtualEndNode,
+};
 use openleadr_wire::{program::ProgramRequest, target::Target};
+use reqwest::{Method, RequestBuilder, Response};
 use sqlx::PgPool;
-use std::str::FromStr;
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use url::Url;
 
 mod common;
 
@@ -14,6 +22,7 @@ fn default_content() -> ProgramRequest {
         payload_descriptors: None,
         attributes: None,
         targets: vec![],
+        validation_mode: None,
     }
 }
 
@@ -106,7 +115,9 @@ async fn update_same_name(db: PgPool) {
 
     *program2.content_mut() = content;
 
-    let Error::Problem(problem) = program2.update().await.unwrap_err() else {
+    let err = program2.update().await.unwrap_err();
+    assert_eq!(err.constraint(), Some("program_program_name_uindex"));
+    let Error::Conflict(problem) = err else {
         unreachable!()
     };
 
@@ -124,7 +135,7 @@ async fn create_same_name(db: PgPool) {
     };
 
     let _ = client.create_program(program1.clone()).await.unwrap();
-    let Error::Problem(problem) = client.create_program(program1).await.unwrap_err() else {
+    let Error::Conflict(problem) = client.create_program(program1).await.unwrap_err() else {
         unreachable!()
     };
 
@@ -254,3 +265,59 @@ async fn retrieve_all_with_filter(db: PgPool) {
         .unwrap();
     assert_eq!(programs.len(), 0);
 }
+
+/// Forwards the requests to the VTN, but loses the response to the first program created
+#[derive(Debug)]
+struct LosingFirstResponse {
+    inner: common::MockClientRef,
+    lost: AtomicBool,
+}
+
+#[async_trait]
+impl HttpClient for LosingFirstResponse {
+    fn request_builder(&self, method: Method, url: Url) -> RequestBuilder {
+        self.inner.request_builder(method, url)
+    }
+
+    async fn send(&self, req: RequestBuilder) -> reqwest::Result<Response> {
+        let (client, request) = req.build_split();
+        let request = request?;
+        let creates_program =
+            request.method() == Method::POST && request.url().path() == "/programs";
+        let response = self
+            .inner
+            .send(RequestBuilder::from_parts(client, request))
+            .await?;
+        if creates_program && !self.lost.swap(true, Ordering::SeqCst) {
+            let unavailable = axum::http::Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(reqwest::Body::from(r#"{"status": 503}"#))
+                .unwrap();
+            return Ok(unavailable.into());
+        }
+        Ok(response)
+    }
+}
+
+#[sqlx::test(fixtures("users"))]
+async fn create_is_retried_with_idempotency_key(db: PgPool) {
+    let http_client = LosingFirstResponse {
+        inner: common::MockClientRef::new(common::setup_mock_router(db).await),
+        lost: AtomicBool::new(false),
+    };
+    let client = openleadr_client::Client::<BusinessLogic>::with_http_client(
+        "https://example.com/".parse().unwrap(),
+        "https://example.com/auth/token".parse().unwrap(),
+        Box::new(http_client),
+        Some(ClientCredentials::new(
+            "bl-client".to_string(),
+            "bl-client".to_string(),
+        )),
+    );
+
+    let program = client.create_program(default_content()).await.unwrap();
+
+    let programs = client.get_program_list(Filter::none()).await.unwrap();
+    assert_eq!(programs.len(), 1);
+    assert_eq!(programs[0].id(), program.id());
+}
@@ -0,0 +1,187 @@
+//! The files written by `admin export`, in two formats:
+//!
+//! - JSON: a single object with the [`ArchiveHeader`] fields next to one array per object type
+//! - NDJSON: the [`ArchiveHeader`] on the first line, then one [`ArchiveRecord`] per line,
+//!   so large stores can be processed line by line, e.g., with `jq` or `grep`
+
+use std::{
+    error::Error,
+    io::{BufRead, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+use openleadr_wire::{
+    Event, Program, Report, resource::Resource, resource_group::ResourceGroup, ven::Ven,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::data_source::{Snapshot, SnapshotSubscription, SnapshotUser};
+
+/// Version of the archive format.
+/// Version 1 did not contain users, which is why these archives can still be imported.
+const VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Json,
+    Ndjson,
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            _ => Err(format!(
+                "unknown archive format '{s}', expected 'json' or 'ndjson'"
+            )),
+        }
+    }
+}
+
+impl ArchiveFormat {
+    /// NDJSON for files ending in `.ndjson`, JSON otherwise
+    pub(super) fn of(path: Option<&Path>) -> Self {
+        match path.and_then(Path::extension) {
+            Some(extension) if extension == "ndjson" => Self::Ndjson,
+            _ => Self::Json,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchiveHeader {
+    version: u32,
+    #[serde(with = "openleadr_wire::serde_rfc3339")]
+    exported_date_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonArchive {
+    #[serde(flatten)]
+    header: ArchiveHeader,
+    #[serde(flatten)]
+    snapshot: Snapshot,
+}
+
+/// A line of an NDJSON archive, after the header
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "object", rename_all = "camelCase")]
+enum ArchiveRecord {
+    User(SnapshotUser),
+    Program(Program),
+    Event(Event),
+    Report(Report),
+    Ven(Ven),
+    Resource(Resource),
+    ResourceGroup(ResourceGroup),
+    Subscription(SnapshotSubscription),
+}
+
+pub(super) fn write(
+    snapshot: Snapshot,
+    format: ArchiveFormat,
+    out: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let header = ArchiveHeader {
+        version: VERSION,
+        exported_date_time: Utc::now(),
+    };
+
+    match format {
+        ArchiveFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &JsonArchive { header, snapshot })?;
+            writeln!(out)?;
+        }
+        ArchiveFormat::Ndjson => {
+            serde_json::to_writer(&mut *out, &header)?;
+            writeln!(out)?;
+
+            let Snapshot {
+                users,
+                programs,
+                events,
+                reports,
+                vens,
+                resources,
+                resource_groups,
+                subscriptions,
+            } = snapshot;
+            let records = users
+                .into_iter()
+                .map(ArchiveRecord::User)
+                .chain(programs.into_iter().map(ArchiveRecord::Program))
+                .chain(events.into_iter().map(ArchiveRecord::Event))
+                .chain(reports.into_iter().map(ArchiveRecord::Report))
+                .chain(vens.into_iter().map(ArchiveRecord::Ven))
+                .chain(resources.into_iter().map(ArchiveRecord::Resource))
+                .chain(
+                    resource_groups
+                        .into_iter()
+                        .map(ArchiveRecord::ResourceGroup),
+                )
+                .chain(subscriptions.into_iter().map(ArchiveRecord::Subscription));
+            for record in records {
+                serde_json::to_writer(&mut *out, &record)?;
+                writeln!(out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) fn read(input: impl BufRead, format: ArchiveFormat) -> Result<Snapshot, Box<dyn Error>> {
+    match format {
+        ArchiveFormat::Json => {
+            let archive: JsonArchive = serde_json::from_reader(input)?;
+            check_version(&archive.header)?;
+            Ok(archive.snapshot)
+        }
+        ArchiveFormat::Ndjson => {
+            let mut lines = input
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()));
+            let (_, header) = lines.next().ok_or("the archive is empty")?;
+            check_version(&serde_json::from_str(&header?)?)?;
+
+            let mut snapshot = Snapshot::default();
+            for (index, line) in lines {
+                let record = serde_json::from_str(&line?)
+                    .map_err(|err| format!("line {}: {err}", index + 1))?;
+                match record {
+                    ArchiveRecord::User(user) => snapshot.users.push(user),
+                    ArchiveRecord::Program(program) => snapshot.programs.push(program),
+                    ArchiveRecord::Event(event) => snapshot.events.push(event),
+                    ArchiveRecord::Report(report) => snapshot.reports.push(report),
+                    ArchiveRecord::Ven(ven) => snapshot.vens.push(ven),
+                    ArchiveRecord::Resource(resource) => snapshot.resources.push(resource),
+                    ArchiveRecord::ResourceGroup(resource_group) => {
+                        snapshot.resource_groups.push(resource_group)
+                    }
+                    ArchiveRecord::Subscription(subscription) => {
+                        snapshot.subscriptions.push(subscription)
+                    }
+                }
+            }
+            Ok(snapshot)
+        }
+    }
+}
+
+fn check_version(header: &ArchiveHeader) -> Result<(), String> {
+    if (1..=VERSION).contains(&header.version) {
+        Ok(())
+    } else {
+        Err(format!(
+            "cannot import version {} of the archive format, only up to version {VERSION}",
+            header.version
+        ))
+    }
+}
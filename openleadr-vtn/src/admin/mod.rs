@@ -0,0 +1,656 @@
+//! Operations on the store of a VTN from the command line,
+//! e.g., to create the first users or to back up all objects.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use openleadr_wire::ClientId;
+
+#[cfg(feature = "internal-oauth")]
+use crate::jwt::Scope;
+use crate::{
+    api::{TargetQueryParams, attributes::AttributeQueryParams},
+    data_source::{DataSource, Snapshot},
+};
+
+pub use archive::ArchiveFormat;
+
+mod archive;
+
+pub const USAGE: &str = "Admin commands:
+  admin users list                         List the users with their client IDs
+  admin users create --reference <REF> [--description <TEXT>] [--scope <SCOPE>]...
+                                           Create a user, the scopes as in `read_all`
+  admin credentials add <USER-ID> <CLIENT-ID> [--secret <SECRET>]
+                                           Add credentials to a user, generating a secret if none is given
+  admin generate-secret                    Print a random value for OAUTH_BASE64_SECRET
+  admin programs                           List the programs
+  admin vens                               List the VENs
+  admin subscriptions                      List the subscriptions
+  admin purge                              Delete expired subscriptions and idempotency keys
+  admin export [--output <FILE>] [<SNAPSHOT-OPTION>]...
+                                           Write all objects, including users, to the file, or stdout
+  admin import <FILE> [<SNAPSHOT-OPTION>]...
+                                           Store all objects of an export, replacing the ones with the same ID
+
+Snapshot options:
+  --format <json|ndjson>                   Archive format, by default NDJSON for files ending in .ndjson, JSON otherwise
+  --without-secrets                        Leave out the client secrets and webhook bearer tokens,
+                                           the credentials are then not imported
+  --rewrite-client-id <OLD>=<NEW>          Replace a client ID everywhere, e.g., of a VEN";
+
+/// Number of objects listed per database query
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    #[cfg(feature = "internal-oauth")]
+    ListUsers,
+    #[cfg(feature = "internal-oauth")]
+    CreateUser {
+        reference: String,
+        description: Option<String>,
+        scopes: Vec<Scope>,
+    },
+    #[cfg(feature = "internal-oauth")]
+    AddCredential {
+        user_id: String,
+        client_id: String,
+        client_secret: Option<String>,
+    },
+    GenerateSecret,
+    ListPrograms,
+    ListVens,
+    ListSubscriptions,
+    Purge,
+    Export {
+        output: Option<PathBuf>,
+        options: SnapshotOptions,
+    },
+    Import {
+        input: PathBuf,
+        options: SnapshotOptions,
+    },
+}
+
+/// How `admin export` and `admin import` write and read snapshots
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SnapshotOptions {
+    /// Derived from the file name if not set
+    pub format: Option<ArchiveFormat>,
+    pub without_secrets: bool,
+    pub client_id_rewrites: HashMap<ClientId, ClientId>,
+}
+
+impl SnapshotOptions {
+    /// Parses `arg` and its value, returns `false` if `arg` is not a snapshot option
+    fn parse_arg(
+        &mut self,
+        arg: &str,
+        args: &mut impl Iterator<Item = String>,
+    ) -> Result<bool, String> {
+        match arg {
+            "--format" => self.format = Some(required(args.next(), "--format value")?.parse()?),
+            "--without-secrets" => self.without_secrets = true,
+            "--rewrite-client-id" => {
+                let rewrite = required(args.next(), "--rewrite-client-id value")?;
+                let (old, new) = rewrite
+                    .split_once('=')
+                    .ok_or("--rewrite-client-id requires <OLD>=<NEW>")?;
+                let parse = |id: &str| {
+                    id.parse::<ClientId>()
+                        .map_err(|err| format!("invalid client ID '{id}': {err}"))
+                };
+                self.client_id_rewrites.insert(parse(old)?, parse(new)?);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn apply(&self, snapshot: &mut Snapshot) {
+        if self.without_secrets {
+            snapshot.drop_secrets();
+        }
+        snapshot.rewrite_client_ids(&self.client_id_rewrites);
+    }
+}
+
+fn required(value: Option<String>, name: &str) -> Result<String, String> {
+    value.ok_or_else(|| format!("{name} is required"))
+}
+
+impl AdminCommand {
+    /// Parses the arguments following `admin`
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let name = args.next().ok_or("admin requires a command")?;
+
+        let command = match name.as_str() {
+            #[cfg(feature = "internal-oauth")]
+            "users" => match args.next().as_deref() {
+                Some("list") => Self::ListUsers,
+                Some("create") => {
+                    let (mut reference, mut description, mut scopes) = (None, None, Vec::new());
+                    while let Some(arg) = args.next() {
+                        match arg.as_str() {
+                            "--reference" => {
+                                reference = Some(required(args.next(), "--reference value")?)
+                            }
+                            "--description" => {
+                                description = Some(required(args.next(), "--description value")?)
+                            }
+                            "--scope" => {
+                                scopes.push(required(args.next(), "--scope value")?.parse()?)
+                            }
+                            _ => return Err(format!("unexpected argument '{arg}'")),
+                        }
+                    }
+                    Self::CreateUser {
+                        reference: required(reference, "--reference")?,
+                        description,
+                        scopes,
+                    }
+                }
+                _ => return Err("users requires 'list' or 'create'".to_string()),
+            },
+            #[cfg(feature = "internal-oauth")]
+            "credentials" => {
+                if args.next().as_deref() != Some("add") {
+                    return Err("credentials requires 'add'".to_string());
+                }
+                let user_id = required(args.next(), "<USER-ID>")?;
+                let client_id = required(args.next(), "<CLIENT-ID>")?;
+                let client_secret = match args.next().as_deref() {
+                    None => None,
+                    Some("--secret") => Some(required(args.next(), "--secret value")?),
+                    Some(arg) => return Err(format!("unexpected argument '{arg}'")),
+                };
+                Self::AddCredential {
+                    user_id,
+                    client_id,
+                    client_secret,
+                }
+            }
+            "generate-secret" => Self::GenerateSecret,
+            "programs" => Self::ListPrograms,
+            "vens" => Self::ListVens,
+            "subscriptions" => Self::ListSubscriptions,
+            "purge" => Self::Purge,
+            "export" => {
+                let (mut output, mut options) = (None, SnapshotOptions::default());
+                while let Some(arg) = args.next() {
+                    if arg == "--output" {
+                        output = Some(required(args.next(), "--output file")?.into());
+                    } else if !options.parse_arg(&arg, &mut args)? {
+                        return Err(format!("unexpected argument '{arg}'"));
+                    }
+                }
+                Self::Export { output, options }
+            }
+            "import" => {
+                let input = required(args.next(), "<FILE>")?.into();
+                let mut options = SnapshotOptions::default();
+                while let Some(arg) = args.next() {
+                    if !options.parse_arg(&arg, &mut args)? {
+                        return Err(format!("unexpected argument '{arg}'"));
+                    }
+                }
+                Self::Import { input, options }
+            }
+            _ => return Err(format!("unknown admin command '{name}'")),
+        };
+
+        match args.next() {
+            None => Ok(command),
+            Some(arg) => Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    /// Runs the command against `storage`, writing its results to `out`
+    pub async fn run(
+        self,
+        storage: &dyn DataSource,
+        out: &mut impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        match self {
+            #[cfg(feature = "internal-oauth")]
+            Self::ListUsers => {
+                for user in storage.auth().get_all_users().await? {
+                    let client_ids: Vec<_> = user.client_ids.iter().map(|id| id.as_str()).collect();
+                    writeln!(
+                        out,
+                        "{}\t{}\t{}\t{}",
+                        user.id,
+                        user.reference,
+                        serde_json::to_string(&user.scope)?,
+                        client_ids.join(",")
+                    )?;
+                }
+            }
+            #[cfg(feature = "internal-oauth")]
+            Self::CreateUser {
+                reference,
+                description,
+                scopes,
+            } => {
+                let user = storage
+                    .auth()
+                    .add_user(&reference, description.as_deref(), &scopes)
+                    .await?;
+                writeln!(out, "created user {}", user.id)?;
+            }
+            #[cfg(feature = "internal-oauth")]
+            Self::AddCredential {
+                user_id,
+                client_id,
+                client_secret,
+            } => {
+                let generated = client_secret.is_none();
+                let client_secret = client_secret.unwrap_or_else(|| {
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD
+                        .encode(rand::random::<[u8; 32]>())
+                });
+                storage
+                    .auth()
+                    .add_credential(&user_id, &client_id, &client_secret)
+                    .await?;
+                writeln!(out, "added client ID {client_id} to user {user_id}")?;
+                if generated {
+                    writeln!(out, "client secret: {client_secret}")?;
+                }
+            }
+            Self::GenerateSecret => {
+                writeln!(
+                    out,
+                    "OAUTH_BASE64_SECRET={}",
+                    STANDARD.encode(rand::random::<[u8; 32]>())
+                )?;
+                writeln!(
+                    out,
+                    "Tokens issued with the current secret are rejected once the VTN runs with this one."
+                )?;
+            }
+            Self::ListPrograms => {
+                let mut skip = 0;
+                loop {
+                    let filter = crate::api::program::QueryParams {
+                        targets: TargetQueryParams(None),
+                        skip,
+                        limit: PAGE_SIZE,
+                        cursor: None,
+                    };
+                    let programs = storage.programs().retrieve_all(&filter, &None).await?;
+                    for program in &programs {
+                        writeln!(out, "{}\t{}", program.id, program.content.program_name)?;
+                    }
+                    if (programs.len() as i64) < PAGE_SIZE {
+                        break;
+                    }
+                    skip += PAGE_SIZE;
+                }
+            }
+            Self::ListVens => {
+                let mut skip = 0;
+                loop {
+                    let filter = crate::api::ven::QueryParams {
+                        ven_name: None,
+                        targets: TargetQueryParams(None),
+                        attributes: AttributeQueryParams(None),
+                        skip,
+                        limit: PAGE_SIZE,
+                        cursor: None,
+                    };
+                    let vens = storage.vens().retrieve_all(&filter, &None).await?;
+                    for ven in &vens {
+                        writeln!(
+                            out,
+                            "{}\t{}\t{}",
+                            ven.id, ven.content.ven_name, ven.content.client_id
+                        )?;
+                    }
+                    if (vens.len() as i64) < PAGE_SIZE {
+                        break;
+                    }
+                    skip += PAGE_SIZE;
+                }
+            }
+            Self::ListSubscriptions => {
+                let mut skip = 0;
+                loop {
+                    let filter = crate::api::subscription::QueryParams {
+                        program_id: None,
+                        client_name: None,
+                        objects: None,
+                        skip,
+                        limit: PAGE_SIZE,
+                        cursor: None,
+                    };
+                    let subscriptions =
+                        storage.subscriptions().retrieve_all(&filter, &None).await?;
+                    for subscription in &subscriptions {
+                        writeln!(
+                            out,
+                            "{}\t{}\t{}\t{}\t{}",
+                            subscription.id,
+                            subscription.client_id,
+                            subscription.content.client_name,
+                            subscription
+                                .content
+                                .program_id
+                                .as_ref()
+                                .map_or("-".to_string(), ToString::to_string),
+                            subscription
+                                .expires_date_time
+                                .map_or("never".to_string(), |expires| expires.to_rfc3339())
+                        )?;
+                    }
+                    if (subscriptions.len() as i64) < PAGE_SIZE {
+                        break;
+                    }
+                    skip += PAGE_SIZE;
+                }
+            }
+            Self::Purge => {
+                let deleted = storage.subscriptions().delete_expired().await?;
+                writeln!(out, "deleted {} expired subscriptions", deleted.len())?;
+                let deleted = storage.idempotency().delete_expired().await?;
+                writeln!(out, "deleted {deleted} expired idempotency keys")?;
+            }
+            Self::Export { output, options } => {
+                let mut snapshot = storage.snapshots().export().await?;
+                options.apply(&mut snapshot);
+                let format = options
+                    .format
+                    .unwrap_or_else(|| ArchiveFormat::of(output.as_deref()));
+                match output {
+                    Some(path) => {
+                        let summary = summary(&snapshot);
+                        let mut file = BufWriter::new(File::create(&path)?);
+                        archive::write(snapshot, format, &mut file)?;
+                        file.flush()?;
+                        writeln!(out, "exported {summary} to {}", path.display())?;
+                    }
+                    None => archive::write(snapshot, format, out)?,
+                }
+            }
+            Self::Import { input, options } => {
+                let format = options
+                    .format
+                    .unwrap_or_else(|| ArchiveFormat::of(Some(&input)));
+                let mut snapshot = archive::read(BufReader::new(File::open(&input)?), format)?;
+                options.apply(&mut snapshot);
+                storage.snapshots().import(&snapshot).await?;
+                writeln!(out, "imported {}", summary(&snapshot))?;
+
+                let without_secret = snapshot
+                    .users
+                    .iter()
+                    .flat_map(|user| &user.credentials)
+                    .filter(|credential| credential.client_secret_hash.is_none())
+                    .count();
+                if without_secret > 0 {
+                    writeln!(
+                        out,
+                        "skipped {without_secret} credentials without secret, add them with `admin credentials add`"
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn summary(snapshot: &Snapshot) -> String {
+    std::iter::once(format!("{} user", snapshot.users.len()))
+        .chain(
+            snapshot
+                .object_counts()
+                .iter()
+                .map(|(object, count)| format!("{count} {object}")),
+        )
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+#[cfg(feature = "postgres")]
+mod test {
+    use std::path::Path;
+
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::data_source::PostgresStorage;
+
+    fn parse(args: &str) -> Result<AdminCommand, String> {
+        AdminCommand::parse(args.split_whitespace().map(ToOwned::to_owned))
+    }
+
+    fn temp_path(extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "openleadr-vtn-export-{}.{extension}",
+            uuid::Uuid::new_v4()
+        ))
+    }
+
+    async fn truncate(db: &PgPool) {
+        sqlx::query(
+            r#"TRUNCATE "user", program, event, report, ven, resource, resource_group, subscription CASCADE"#,
+        )
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    async fn run(command: AdminCommand, storage: &PostgresStorage) -> String {
+        let mut out = Vec::new();
+        command.run(storage, &mut out).await.unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn import(input: &Path) -> AdminCommand {
+        AdminCommand::Import {
+            input: input.to_owned(),
+            options: SnapshotOptions::default(),
+        }
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("programs"), Ok(AdminCommand::ListPrograms));
+        assert_eq!(parse("generate-secret"), Ok(AdminCommand::GenerateSecret));
+        assert_eq!(
+            parse("export --output backup.json"),
+            Ok(AdminCommand::Export {
+                output: Some("backup.json".into()),
+                options: SnapshotOptions::default(),
+            })
+        );
+        assert_eq!(
+            parse("import backup.json --format ndjson --without-secrets --rewrite-client-id a=b"),
+            Ok(AdminCommand::Import {
+                input: "backup.json".into(),
+                options: SnapshotOptions {
+                    format: Some(ArchiveFormat::Ndjson),
+                    without_secrets: true,
+                    client_id_rewrites: HashMap::from([(
+                        "a".parse().unwrap(),
+                        "b".parse().unwrap()
+                    )]),
+                },
+            })
+        );
+        assert!(parse("").is_err());
+        assert!(parse("import").is_err());
+        assert!(parse("import backup.json --format xml").is_err());
+        assert!(parse("export --rewrite-client-id a").is_err());
+        assert!(parse("purge now").is_err());
+        assert!(parse("unknown").is_err());
+    }
+
+    #[cfg(feature = "internal-oauth")]
+    #[test]
+    fn parses_user_commands() {
+        assert_eq!(
+            parse("users create --reference admin --scope read_all --scope write_users"),
+            Ok(AdminCommand::CreateUser {
+                reference: "admin".to_string(),
+                description: None,
+                scopes: vec![Scope::ReadAll, Scope::WriteUsers],
+            })
+        );
+        assert_eq!(
+            parse("credentials add user-1 client-1"),
+            Ok(AdminCommand::AddCredential {
+                user_id: "user-1".to_string(),
+                client_id: "client-1".to_string(),
+                client_secret: None,
+            })
+        );
+        assert!(parse("users create").is_err());
+        assert!(parse("users create --reference admin --scope everything").is_err());
+    }
+
+    #[sqlx::test(fixtures(
+        "../api/fixtures/users.sql",
+        "../api/fixtures/programs.sql",
+        "../api/fixtures/events.sql",
+        "../api/fixtures/reports.sql",
+        "../api/fixtures/vens.sql",
+        "../api/fixtures/resources.sql",
+        "../api/fixtures/resource_groups.sql",
+        "../api/fixtures/subscriptions.sql"
+    ))]
+    async fn export_and_import(db: PgPool) {
+        let storage = PostgresStorage::new(db.clone()).unwrap();
+        let path = temp_path("json");
+
+        let command = AdminCommand::Export {
+            output: Some(path.clone()),
+            options: SnapshotOptions::default(),
+        };
+        let out = run(command, &storage).await;
+        let exported = storage.snapshots().export().await.unwrap();
+        assert_eq!(exported.users.len(), 2);
+        assert!(!exported.programs.is_empty());
+        assert!(!exported.resource_groups.is_empty());
+        assert!(out.starts_with(&format!(
+            "exported 2 user, {} program",
+            exported.programs.len()
+        )));
+
+        truncate(&db).await;
+        assert_eq!(
+            storage.snapshots().export().await.unwrap(),
+            Snapshot::default()
+        );
+
+        run(import(&path), &storage).await;
+        assert_eq!(storage.snapshots().export().await.unwrap(), exported);
+
+        // importing again replaces the objects
+        run(import(&path), &storage).await;
+        assert_eq!(storage.snapshots().export().await.unwrap(), exported);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[sqlx::test(fixtures(
+        "../api/fixtures/users.sql",
+        "../api/fixtures/programs.sql",
+        "../api/fixtures/vens.sql",
+        "../api/fixtures/resources.sql"
+    ))]
+    async fn ndjson_without_secrets_and_rewritten_client_ids(db: PgPool) {
+        let storage = PostgresStorage::new(db.clone()).unwrap();
+        let path = temp_path("ndjson");
+
+        let command = format!(
+            "export --output {} --without-secrets --rewrite-client-id ven-1-client-id=staging-ven-1",
+            path.display()
+        );
+        run(parse(&command).unwrap(), &storage).await;
+        let exported = storage.snapshots().export().await.unwrap();
+
+        let archive = std::fs::read_to_string(&path).unwrap();
+        let mut lines = archive.lines();
+        let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header["version"], 2);
+        let objects: i64 = exported
+            .object_counts()
+            .iter()
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(lines.count(), exported.users.len() + objects as usize);
+        assert!(!archive.contains("argon2"));
+
+        truncate(&db).await;
+        let out = run(import(&path), &storage).await;
+        assert!(
+            out.contains("skipped 2 credentials without secret"),
+            "{out}"
+        );
+
+        let imported = storage.snapshots().export().await.unwrap();
+        assert_eq!(imported.users.len(), 2);
+        assert!(
+            imported
+                .users
+                .iter()
+                .all(|user| user.credentials.is_empty())
+        );
+        let ven_1 = imported
+            .vens
+            .iter()
+            .find(|ven| ven.id.as_str() == "ven-1")
+            .unwrap();
+        assert_eq!(ven_1.content.client_id.as_str(), "staging-ven-1");
+        assert!(
+            imported
+                .resources
+                .iter()
+                .filter(|resource| resource.content.ven_id.as_str() == "ven-1")
+                .all(|resource| resource.client_id.as_str() == "staging-ven-1")
+        );
+        assert_eq!(imported.programs, exported.programs);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[sqlx::test(fixtures("../api/fixtures/webhook_subscriptions.sql"))]
+    async fn without_secrets_drops_webhook_tokens(db: PgPool) {
+        let storage = PostgresStorage::new(db).unwrap();
+        let path = temp_path("json");
+
+        let command = format!("export --output {} --without-secrets", path.display());
+        run(parse(&command).unwrap(), &storage).await;
+
+        let archive = std::fs::read_to_string(&path).unwrap();
+        assert!(archive.contains("https://ven-1.example.com/callback"));
+        assert!(!archive.contains("production-token"), "{archive}");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[sqlx::test(fixtures("../api/fixtures/programs.sql", "../api/fixtures/vens.sql"))]
+    async fn lists_objects(db: PgPool) {
+        let storage = PostgresStorage::new(db).unwrap();
+
+        let out = run(AdminCommand::ListPrograms, &storage).await;
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.contains("program-1\tprogram-1\n"), "{out}");
+
+        let out = run(AdminCommand::ListVens, &storage).await;
+        assert!(
+            out.contains("ven-1\tven-1-name\tven-1-client-id\n"),
+            "{out}"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Filters on the `attributes` of VENs and resources, e.g.,
+//! `GET /resources?attributes=MAX_POWER_CONSUMPTION:gt:10&attributes=LOCATION`.
+//!
+//! Each `attributes` parameter is either an attribute type the object must have,
+//! or `TYPE:OPERATOR:VALUE`, which at least one value of that type must satisfy.
+//! `eq` compares with the JSON value, or the string if `VALUE` is no JSON.
+//! `gt`, `gte`, `lt`, and `lte` compare numbers, i.e., `Integer` and `Number` values.
+//! An object must satisfy all given filters.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, de};
+
+/// How an attribute value is compared to the value of the filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Comparison {
+    Eq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Comparison {
+    /// The operator in a JSON path expression
+    pub(crate) fn operator(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Gt => ">",
+            Comparison::Gte => ">=",
+            Comparison::Lt => "<",
+            Comparison::Lte => "<=",
+        }
+    }
+}
+
+/// A single `attributes` query parameter
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AttributeFilter {
+    pub(crate) value_type: String,
+    pub(crate) condition: Option<(Comparison, serde_json::Value)>,
+}
+
+impl FromStr for AttributeFilter {
+    type Err = String;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let mut parts = filter.splitn(3, ':');
+        let value_type = parts.next().unwrap_or_default().to_string();
+        if value_type.is_empty() || value_type.len() > 128 {
+            return Err("attribute type must have 1 to 128 characters".to_string());
+        }
+
+        let Some(operator) = parts.next() else {
+            return Ok(Self {
+                value_type,
+                condition: None,
+            });
+        };
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("missing value in attribute filter '{filter}'"))?;
+
+        let comparison = match operator {
+            "eq" => Comparison::Eq,
+            "gt" => Comparison::Gt,
+            "gte" => Comparison::Gte,
+            "lt" => Comparison::Lt,
+            "lte" => Comparison::Lte,
+            _ => return Err(format!("unknown operator '{operator}' in attribute filter")),
+        };
+        let value = match comparison {
+            Comparison::Eq => serde_json::from_str(value)
+                .unwrap_or_else(|_| serde_json::Value::String(value.to_string())),
+            _ => value
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(serde_json::Value::Number)
+                .ok_or_else(|| format!("'{operator}' requires a number, got '{value}'"))?,
+        };
+
+        Ok(Self {
+            value_type,
+            condition: Some((comparison, value)),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for AttributeFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(transparent)]
+pub(crate) struct AttributeQueryParams(pub Option<Vec<AttributeFilter>>);
+
+impl AttributeQueryParams {
+    pub fn as_deref(&self) -> &[AttributeFilter] {
+        self.0.as_deref().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "LOCATION".parse(),
+            Ok(AttributeFilter {
+                value_type: "LOCATION".to_string(),
+                condition: None
+            })
+        );
+        assert_eq!(
+            "MAX_POWER_CONSUMPTION:gt:10".parse(),
+            Ok(AttributeFilter {
+                value_type: "MAX_POWER_CONSUMPTION".to_string(),
+                condition: Some((Comparison::Gt, json!(10.0)))
+            })
+        );
+        assert_eq!(
+            "DEVICE:eq:heat pump".parse(),
+            Ok(AttributeFilter {
+                value_type: "DEVICE".to_string(),
+                condition: Some((Comparison::Eq, json!("heat pump")))
+            })
+        );
+        assert_eq!(
+            "URL:eq:\"https://example.com\"".parse::<AttributeFilter>(),
+            Ok(AttributeFilter {
+                value_type: "URL".to_string(),
+                condition: Some((Comparison::Eq, json!("https://example.com")))
+            })
+        );
+        assert_eq!(
+            "COUNT:eq:3".parse::<AttributeFilter>().unwrap().condition,
+            Some((Comparison::Eq, json!(3)))
+        );
+
+        for invalid in ["", "A:gt", "A:gt:many", "A:like:x", &"A".repeat(129)] {
+            assert!(invalid.parse::<AttributeFilter>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! Shared handling of the batch endpoints, e.g., `POST /events:batch`.
+//!
+//! The operations of a batch are applied in order in a single transaction, all or nothing.
+//! The response lists the outcome of each operation.
+//! If any operation failed, the response has the status of the first failed operation,
+//! and the operations that succeeded are reported as rolled back with `424 Failed Dependency`.
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use openleadr_wire::{
+    batch::{BatchOperation, BatchResponse, BatchResult},
+    subscription::Operation,
+};
+use serde::Serialize;
+use validator::Validate;
+
+use crate::error::AppError;
+
+/// What became of the operations of a batch request
+#[derive(Debug)]
+pub(crate) enum Outcome<T> {
+    /// All operations succeeded and were committed, with the objects to notify about
+    Committed(Vec<(Operation, T)>),
+    /// The transaction was rolled back, with the error of each failed operation
+    RolledBack(Vec<Option<AppError>>),
+}
+
+impl<T> Outcome<T> {
+    /// Validates the content of each operation like the body of a single request.
+    /// If any is invalid, the batch is rejected before it reaches the store.
+    pub(crate) fn invalid<Id, C: Validate>(operations: &[BatchOperation<Id, C>]) -> Option<Self> {
+        let errors: Vec<_> = operations
+            .iter()
+            .map(|operation| match operation {
+                BatchOperation::Create { content } | BatchOperation::Update { content, .. } => {
+                    content.validate().err().map(AppError::Validation)
+                }
+                BatchOperation::Delete { .. } => None,
+            })
+            .collect();
+
+        errors
+            .iter()
+            .any(Option::is_some)
+            .then_some(Outcome::RolledBack(errors))
+    }
+
+    /// Pairs the results of the store with the operations they belong to
+    pub(crate) fn new(operations: Vec<Operation>, results: Vec<Result<T, AppError>>) -> Self {
+        if results.iter().all(Result::is_ok) {
+            Outcome::Committed(
+                operations
+                    .into_iter()
+                    .zip(results.into_iter().flatten())
+                    .collect(),
+            )
+        } else {
+            Outcome::RolledBack(results.into_iter().map(Result::err).collect())
+        }
+    }
+}
+
+/// The kind of change an operation makes, as sent in notifications
+pub(crate) fn operation<Id, C>(operation: &BatchOperation<Id, C>) -> Operation {
+    match operation {
+        BatchOperation::Create { .. } => Operation::Create,
+        BatchOperation::Update { .. } => Operation::Update,
+        BatchOperation::Delete { .. } => Operation::Delete,
+    }
+}
+
+impl<T: Serialize> IntoResponse for Outcome<T> {
+    fn into_response(self) -> Response {
+        match self {
+            Outcome::Committed(objects) => {
+                let results = objects
+                    .into_iter()
+                    .map(|(operation, object)| BatchResult {
+                        status: match operation {
+                            Operation::Create => StatusCode::CREATED,
+                            _ => StatusCode::OK,
+                        },
+                        object: Some(object),
+                        problem: None,
+                    })
+                    .collect();
+                (StatusCode::OK, Json(BatchResponse { results })).into_response()
+            }
+            Outcome::RolledBack(errors) => {
+                let results: Vec<BatchResult<T>> = errors
+                    .into_iter()
+                    .map(|error| match error {
+                        Some(error) => {
+                            let problem = error.into_problem();
+                            BatchResult {
+                                status: problem.status,
+                                object: None,
+                                problem: Some(problem),
+                            }
+                        }
+                        None => BatchResult {
+                            status: StatusCode::FAILED_DEPENDENCY,
+                            object: None,
+                            problem: None,
+                        },
+                    })
+                    .collect();
+                let status = results
+                    .iter()
+                    .find(|result| result.problem.is_some())
+                    .map_or(StatusCode::FAILED_DEPENDENCY, |result| result.status);
+                (status, Json(BatchResponse { results })).into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, http::StatusCode};
+    use openleadr_wire::{
+        Event, Program, Ven,
+        batch::BatchResponse,
+        problem::{ErrorCode, Problem},
+    };
+    use reqwest::Method;
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use crate::{api::test::ApiTest, jwt::Scope};
+
+    fn event(name: &str) -> serde_json::Value {
+        json!({"programID": "program-1", "eventName": name, "intervals": []})
+    }
+
+    async fn events(test: &ApiTest) -> Vec<Event> {
+        let (status, events) = test
+            .request::<Vec<Event>>(Method::GET, "/events", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        events
+    }
+
+    async fn batch<T: serde::de::DeserializeOwned>(
+        test: &ApiTest,
+        path: &str,
+        operations: serde_json::Value,
+    ) -> (StatusCode, T) {
+        test.request(
+            Method::POST,
+            path,
+            Body::from(json!({"operations": operations}).to_string()),
+        )
+        .await
+    }
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn events_are_committed_together(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WriteEvents, Scope::ReadAll]).await;
+        let before = events(&test).await.len();
+
+        let (status, response) = batch::<BatchResponse<Event>>(
+            &test,
+            "/events:batch",
+            json!([
+                {"op": "create", "content": event("hour-1")},
+                {"op": "create", "content": event("hour-2")},
+                {"op": "update", "id": "event-1", "content": event("updated")},
+                {"op": "delete", "id": "event-2"},
+            ]),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let statuses: Vec<_> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::CREATED,
+                StatusCode::CREATED,
+                StatusCode::OK,
+                StatusCode::OK
+            ]
+        );
+        let names: Vec<_> = response
+            .results
+            .iter()
+            .map(|r| r.object.as_ref().unwrap().content.event_name.as_deref())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Some("hour-1"),
+                Some("hour-2"),
+                Some("updated"),
+                Some("event-2-name")
+            ]
+        );
+        assert_eq!(events(&test).await.len(), before + 1);
+    }
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn failed_operation_rolls_back_all(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WriteEvents, Scope::ReadAll]).await;
+        let before = events(&test).await;
+
+        let (status, response) = batch::<BatchResponse<Event>>(
+            &test,
+            "/events:batch",
+            json!([
+                {"op": "create", "content": event("hour-1")},
+                {"op": "update", "id": "not-existent", "content": event("updated")},
+                {"op": "delete", "id": "event-1"},
+                {"op": "create", "content": {"programID": "not-existent", "intervals": []}},
+            ]),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let statuses: Vec<_> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::FAILED_DEPENDENCY,
+                StatusCode::NOT_FOUND,
+                StatusCode::FAILED_DEPENDENCY,
+                StatusCode::CONFLICT
+            ]
+        );
+        assert!(response.results.iter().all(|r| r.object.is_none()));
+        assert_eq!(
+            response.results[3].problem.as_ref().unwrap().code,
+            Some(ErrorCode::ReferenceViolated)
+        );
+        assert_eq!(events(&test).await, before);
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn invalid_content(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WriteEvents, Scope::ReadAll]).await;
+
+        let (status, response) = batch::<BatchResponse<Event>>(
+            &test,
+            "/events:batch",
+            json!([
+                {"op": "create", "content": event("hour-1")},
+                {"op": "create", "content": {
+                    "programID": "program-1",
+                    "intervals": [{"id": 0, "payloads": []}]
+                }},
+            ]),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let problem = response.results[1].problem.as_ref().unwrap();
+        assert_eq!(problem.code, Some(ErrorCode::ValidationFailed));
+        assert_eq!(
+            problem.errors.as_ref().unwrap()[0].field,
+            "intervals[0].payloads"
+        );
+        assert!(events(&test).await.is_empty());
+
+        let (status, _) = batch::<Problem>(&test, "/events:batch", json!([])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn strict_program_rejects_operation(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "bl-client",
+            vec![Scope::WritePrograms, Scope::WriteEvents, Scope::ReadAll],
+        )
+        .await;
+        let (status, program) = test
+            .request::<Program>(
+                Method::POST,
+                "/programs",
+                Body::from(
+                    json!({
+                        "programName": "strict",
+                        "payloadDescriptors": [
+                            {"objectType": "EVENT_PAYLOAD_DESCRIPTOR", "payloadType": "SIMPLE"}
+                        ],
+                        "validationMode": "STRICT"
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let event = |payload_type: &str| {
+            json!({
+                "programID": program.id,
+                "intervals": [{"id": 0, "payloads": [{"type": payload_type, "values": [1]}]}]
+            })
+        };
+
+        let (status, response) = batch::<BatchResponse<Event>>(
+            &test,
+            "/events:batch",
+            json!([
+                {"op": "create", "content": event("SIMPLE")},
+                {"op": "create", "content": event("PRICE")},
+            ]),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.results[0].status, StatusCode::FAILED_DEPENDENCY);
+        let problem = response.results[1].problem.as_ref().unwrap();
+        assert_eq!(problem.code, Some(ErrorCode::DescriptorMismatch));
+        assert_eq!(
+            problem.errors.as_ref().unwrap()[0].field,
+            "intervals[0].payloads[0].type"
+        );
+        assert!(events(&test).await.is_empty());
+    }
+
+    #[sqlx::test(fixtures("vens"))]
+    async fn vens(db: PgPool) {
+        let test = ApiTest::new(
+            db.clone(),
+            "bl-client",
+            vec![Scope::WriteVensBl, Scope::ReadAll],
+        )
+        .await;
+
+        let (status, response) = batch::<BatchResponse<Ven>>(
+            &test,
+            "/vens:batch",
+            json!([
+                {"op": "create", "content": {"venName": "new-ven", "clientID": "new-client"}},
+                {"op": "delete", "id": "ven-2"},
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response.results[0]
+                .object
+                .as_ref()
+                .unwrap()
+                .content
+                .ven_name,
+            "new-ven"
+        );
+
+        // VEN clients cannot use batches
+        let test = ApiTest::new(db, "ven-1-client-id", vec![Scope::WriteVensVen]).await;
+        let (status, _) = batch::<Problem>(
+            &test,
+            "/vens:batch",
+            json!([{"op": "delete", "id": "ven-1"}]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(fixtures("vens", "subscriptions"))]
+    async fn ven_deletes_cascade_to_subscriptions(db: PgPool) {
+        let test = ApiTest::new(
+            db.clone(),
+            "bl-client",
+            vec![Scope::WriteVensBl, Scope::ReadAll],
+        )
+        .await;
+        let subscriptions = || async {
+            sqlx::query_scalar::<_, i64>("SELECT count(*) FROM subscription")
+                .fetch_one(&db)
+                .await
+                .unwrap()
+        };
+
+        let (status, _) = batch::<BatchResponse<Ven>>(
+            &test,
+            "/vens:batch",
+            json!([
+                {"op": "delete", "id": "ven-1"},
+                {"op": "delete", "id": "not-existent"},
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(subscriptions().await, 2);
+
+        let (status, _) = batch::<BatchResponse<Ven>>(
+            &test,
+            "/vens:batch",
+            json!([{"op": "delete", "id": "ven-1"}]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(subscriptions().await, 0);
+    }
+}
@@ -0,0 +1,402 @@
+//! Reporting compliance of the VENs for an event.
+//!
+//! This is not part of the OpenADR specification and requires the `read_all` scope.
+//! For every VEN the event is visible to, and every other client that reported for the event,
+//! the reports are compared to the report descriptors of the event
+//! as in [`descriptors::report_violations`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+use openleadr_wire::{
+    ClientId, Report,
+    event::EventId,
+    problem::FieldError,
+    report::{ReportDescriptor, ReportId, ReportType},
+    target::Target,
+    ven::VenId,
+};
+
+use crate::{
+    api::{
+        AppResponse,
+        descriptors::{self, name},
+        pagination::Cursor,
+        report::QueryParams,
+        subscription::NotifierState,
+    },
+    data_source::{EventCrud, ReportCrud, VenObjectPrivacy},
+    error::AppError,
+    jwt::{Scope, User},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VenCompliance {
+    /// `None` for clients that reported without being a VEN
+    #[serde(rename = "venID")]
+    pub(crate) ven_id: Option<VenId>,
+    #[serde(rename = "clientID")]
+    pub(crate) client_id: ClientId,
+    #[serde(rename = "reportIDs")]
+    pub(crate) report_ids: Vec<ReportId>,
+    /// The described payload types targeting the VEN that none of its reports contains
+    pub(crate) missing_payload_types: Vec<ReportType>,
+    pub(crate) violations: Vec<ReportViolation>,
+    pub(crate) compliant: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ReportViolation {
+    #[serde(rename = "reportID")]
+    pub(crate) report_id: ReportId,
+    #[serde(flatten)]
+    pub(crate) violation: FieldError,
+}
+
+/// The reports of an event are loaded this many at a time
+const PAGE_SIZE: i64 = 50;
+
+/// What the reports of one client contain, collected page by page
+#[derive(Default)]
+struct Reported {
+    report_ids: Vec<ReportId>,
+    payload_types: HashSet<String>,
+    violations: Vec<ReportViolation>,
+}
+
+impl Reported {
+    fn add(&mut self, report: &Report, descriptors: &[ReportDescriptor]) {
+        self.report_ids.push(report.id.clone());
+        self.payload_types.extend(
+            report
+                .content
+                .resources
+                .iter()
+                .flat_map(|resource| &resource.intervals)
+                .flat_map(|interval| &interval.payloads)
+                .map(|payload| payload.value_type.0.clone()),
+        );
+        self.violations.extend(
+            descriptors::report_violations(&report.content, descriptors)
+                .into_iter()
+                .map(|violation| ReportViolation {
+                    report_id: report.id.clone(),
+                    violation,
+                }),
+        );
+    }
+}
+
+pub async fn get(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(report_source): State<Arc<dyn ReportCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<EventId>,
+    User(user): User,
+) -> AppResponse<Vec<VenCompliance>> {
+    if !user.has_scope(Scope::ReadAll) {
+        return Err(AppError::Forbidden("Missing 'read_all' scope"));
+    }
+
+    let event = event_source.retrieve(&id, &None).await?;
+    let descriptors = event
+        .content
+        .report_descriptors
+        .as_deref()
+        .unwrap_or_default();
+
+    let mut reported: HashMap<ClientId, Reported> = HashMap::new();
+    let mut cursor = Cursor::default();
+    loop {
+        let reports = report_source
+            .retrieve_all(
+                &QueryParams {
+                    program_id: None,
+                    event_id: Some(id.clone()),
+                    client_name: None,
+                    skip: 0,
+                    limit: PAGE_SIZE,
+                    cursor: Some(cursor),
+                },
+                &None,
+            )
+            .await?;
+        for report in &reports {
+            reported
+                .entry(report.client_id.clone())
+                .or_default()
+                .add(report, descriptors);
+        }
+        match reports.last() {
+            Some(last) if reports.len() as i64 == PAGE_SIZE => cursor = Cursor::after(last),
+            _ => break,
+        }
+    }
+
+    let nothing_reported = Reported::default();
+    let mut compliance = vec![];
+    for ven in notifier_state.ven_visibility(&*privacy).await?.vens() {
+        let ven_reported = reported.get(&ven.client_id);
+        if ven_reported.is_none() && !targeted(&event.content.targets, &ven.targets) {
+            continue;
+        }
+        compliance.push(ven_compliance(
+            Some(ven.ven_id.clone()),
+            ven.client_id.clone(),
+            &ven.targets,
+            descriptors,
+            ven_reported.unwrap_or(&nothing_reported),
+        ));
+    }
+
+    let vens: HashSet<ClientId> = compliance.iter().map(|ven| ven.client_id.clone()).collect();
+    for (client_id, client_reported) in &reported {
+        if !vens.contains(client_id) {
+            compliance.push(ven_compliance(
+                None,
+                client_id.clone(),
+                &HashSet::new(),
+                descriptors,
+                client_reported,
+            ));
+        }
+    }
+
+    compliance.sort_by(|a, b| a.client_id.as_str().cmp(b.client_id.as_str()));
+
+    trace!(%id, client_id = user.sub, "retrieved compliance of {} VENs", compliance.len());
+
+    Ok(Json(compliance))
+}
+
+/// Whether an object with the targets is visible to a VEN with the VEN targets
+fn targeted(targets: &[Target], ven_targets: &HashSet<Target>) -> bool {
+    targets.is_empty() || targets.iter().any(|target| ven_targets.contains(target))
+}
+
+fn ven_compliance(
+    ven_id: Option<VenId>,
+    client_id: ClientId,
+    ven_targets: &HashSet<Target>,
+    descriptors: &[ReportDescriptor],
+    reported: &Reported,
+) -> VenCompliance {
+    let mut missing_payload_types: Vec<ReportType> = vec![];
+    for descriptor in descriptors {
+        if targeted(
+            descriptor.targets.as_deref().unwrap_or_default(),
+            ven_targets,
+        ) && !reported
+            .payload_types
+            .contains(name(&descriptor.payload_type).as_str())
+            && !missing_payload_types.contains(&descriptor.payload_type)
+        {
+            missing_payload_types.push(descriptor.payload_type.clone());
+        }
+    }
+
+    VenCompliance {
+        ven_id,
+        client_id,
+        report_ids: reported.report_ids.clone(),
+        compliant: missing_payload_types.is_empty() && reported.violations.is_empty(),
+        missing_payload_types,
+        violations: reported.violations.clone(),
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "live-db-test")]
+mod test {
+    use axum::body::Body;
+    use openleadr_wire::{Event, Report};
+    use reqwest::{Method, StatusCode};
+    use sqlx::PgPool;
+
+    use super::{PAGE_SIZE, VenCompliance};
+    use crate::{api::test::ApiTest, jwt::Scope};
+
+    async fn ven(db: &PgPool, client_id: &str) -> ApiTest {
+        ApiTest::new(db.clone(), client_id, vec![Scope::WriteReports]).await
+    }
+
+    async fn report(ven: &ApiTest, event_id: &str, payload_types: &[&str]) {
+        let (status, _) = ven
+            .request::<Report>(
+                Method::POST,
+                "/reports",
+                Body::from(
+                    serde_json::json!({
+                        "eventID": event_id,
+                        "clientName": "ven-client",
+                        "resources": [{
+                            "resourceName": "resource-1",
+                            "intervals": [{
+                                "id": 0,
+                                "payloads": payload_types
+                                    .iter()
+                                    .map(|payload_type| serde_json::json!({
+                                        "type": payload_type,
+                                        "values": [1.5]
+                                    }))
+                                    .collect::<Vec<_>>()
+                            }]
+                        }]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[sqlx::test(fixtures("programs", "vens"))]
+    async fn compliance_per_ven(db: PgPool) {
+        let bl = ApiTest::new(
+            db.clone(),
+            "bl-client",
+            vec![Scope::WriteEvents, Scope::ReadAll],
+        )
+        .await;
+        let (status, event) = bl
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(
+                    serde_json::json!({
+                        "programID": "program-1",
+                        "targets": ["group-2"],
+                        "reportDescriptors": [
+                            {"payloadType": "USAGE"},
+                            {"payloadType": "DEMAND", "targets": ["group-1"]}
+                        ],
+                        "intervals": []
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let event_id = event.id.to_string();
+
+        report(&ven(&db, "ven-2-client-id").await, &event_id, &["USAGE"]).await;
+        // not targeted by the event, but reporting anyway
+        report(
+            &ven(&db, "ven-1-client-id").await,
+            &event_id,
+            &["DEMAND", "BASELINE"],
+        )
+        .await;
+
+        let (status, compliance) = bl
+            .request::<Vec<VenCompliance>>(
+                Method::GET,
+                &format!("/events/{event_id}/compliance"),
+                Body::empty(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        let summary: Vec<_> = compliance
+            .iter()
+            .map(|ven| {
+                (
+                    ven.client_id.as_str(),
+                    ven.report_ids.len(),
+                    serde_json::to_value(&ven.missing_payload_types).unwrap(),
+                    ven.violations
+                        .iter()
+                        .map(|violation| violation.violation.field.as_str())
+                        .collect::<Vec<_>>(),
+                    ven.compliant,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "ven-1-client-id",
+                    1,
+                    serde_json::json!(["USAGE"]),
+                    vec!["resources[0].intervals[0].payloads[1].type"],
+                    false
+                ),
+                ("ven-2-client-id", 1, serde_json::json!([]), vec![], true),
+                (
+                    "ven-4-client-id",
+                    0,
+                    serde_json::json!(["USAGE", "DEMAND"]),
+                    vec![],
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[sqlx::test(fixtures("programs", "vens"))]
+    async fn reports_over_several_pages(db: PgPool) {
+        let bl = ApiTest::new(
+            db.clone(),
+            "bl-client",
+            vec![Scope::WriteEvents, Scope::ReadAll],
+        )
+        .await;
+        let (status, event) = bl
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(
+                    serde_json::json!({
+                        "programID": "program-1",
+                        "reportDescriptors": [{"payloadType": "USAGE"}],
+                        "intervals": []
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let event_id = event.id.to_string();
+
+        let ven = ven(&db, "ven-2-client-id").await;
+        for _ in 0..=PAGE_SIZE {
+            report(&ven, &event_id, &["USAGE"]).await;
+        }
+
+        let (status, compliance) = bl
+            .request::<Vec<VenCompliance>>(
+                Method::GET,
+                &format!("/events/{event_id}/compliance"),
+                Body::empty(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        let ven = compliance
+            .iter()
+            .find(|ven| ven.client_id.as_str() == "ven-2-client-id")
+            .unwrap();
+        assert_eq!(ven.report_ids.len() as i64, PAGE_SIZE + 1);
+        assert!(ven.compliant);
+    }
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn requires_read_all(db: PgPool) {
+        let ven = ApiTest::new(db, "ven-1-client-id", vec![Scope::ReadVenObjects]).await;
+
+        let status = ven
+            .empty_request(Method::GET, "/events/event-1/compliance")
+            .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,709 @@
+//! Checks of events against the descriptors of their program,
+//! and of reports against the report descriptors of their event.
+//!
+//! Each payload of the intervals of an event must have a type the event payload descriptors
+//! of the program describe, and the payload descriptors of the event must not contradict the
+//! units or currency the program describes for the same type.
+//! The intervals must lie within the interval period of the program.
+//! Programs without event payload descriptors or without interval period are not checked for those.
+//!
+//! Each payload of the intervals of a report must have a type the report descriptors of the event
+//! describe, and the payload descriptors of the report must not contradict the reading type or
+//! units described for the same type. Resources report a type as `AGGREGATED_REPORT` exactly if
+//! its descriptor asks for an aggregate, with no more than `numIntervals` intervals, and with
+//! interval ids up to `startInterval` if `historical`, and from `startInterval` on otherwise.
+//! Events without report descriptors are not checked.
+//!
+//! Depending on the `validationMode` of the program, violations are rejected with a
+//! `descriptor_mismatch` problem, or the request succeeds with a `Warning` header per violation.
+
+use std::{collections::BTreeMap, convert::Infallible};
+
+use axum::{
+    http::{HeaderValue, header},
+    response::{IntoResponseParts, ResponseParts},
+};
+use chrono::{DateTime, Utc};
+use openleadr_wire::{
+    event::{EventId, EventPayloadDescriptor, EventRequest, EventType},
+    interval::{Interval, IntervalPeriod},
+    problem::FieldError,
+    program::{PayloadDescriptor, ProgramRequest, ValidationMode},
+    report::{AppendedIntervals, ReportDescriptor, ReportRequest, ResourceName},
+};
+use serde::Serialize;
+use tracing::info;
+
+use crate::{
+    data_source::{EventCrud, ProgramCrud},
+    error::AppError,
+};
+
+/// Violations a lenient program tolerates, responded as `Warning` headers
+#[derive(Debug, Default)]
+pub(crate) struct Warnings(Vec<FieldError>);
+
+impl Warnings {
+    /// Prefixes the fields, e.g., with the batch operation they belong to
+    pub(crate) fn prefixed(self, prefix: &str) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .map(|warning| FieldError {
+                    field: format!("{prefix}.{}", warning.field),
+                    ..warning
+                })
+                .collect(),
+        )
+    }
+}
+
+impl FromIterator<Warnings> for Warnings {
+    fn from_iter<I: IntoIterator<Item = Warnings>>(iter: I) -> Self {
+        Self(iter.into_iter().flat_map(|warnings| warnings.0).collect())
+    }
+}
+
+impl IntoResponseParts for Warnings {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        for warning in self.0 {
+            let text = format!(
+                "{}: {}",
+                warning.field,
+                warning.message.unwrap_or(warning.code)
+            )
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+            // 299 is the code of miscellaneous persistent warnings, see RFC 7234, section 5.5
+            if let Ok(value) = HeaderValue::try_from(format!("299 - \"{text}\"")) {
+                res.headers_mut().append(header::WARNING, value);
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Checks the event against the descriptors of its program.
+/// Fails with [`AppError::DescriptorMismatch`] if the program is strict,
+/// and returns the violations as warnings otherwise.
+/// Events of programs that do not exist are left to fail in the store.
+pub(crate) async fn check_event(
+    programs: &dyn ProgramCrud,
+    event: &EventRequest,
+) -> Result<Warnings, AppError> {
+    let program = match programs.retrieve(&event.program_id, &None).await {
+        Ok(program) => program,
+        Err(AppError::NotFound) => return Ok(Warnings::default()),
+        Err(err) => return Err(err),
+    };
+
+    let violations = event_violations(event, &program.content);
+    if violations.is_empty() {
+        return Ok(Warnings::default());
+    }
+    match program.content.validation_mode.unwrap_or_default() {
+        ValidationMode::Strict => Err(AppError::DescriptorMismatch(violations)),
+        ValidationMode::Lenient => {
+            info!(program_id = %program.id, ?violations, "event does not match the descriptors of its program");
+            Ok(Warnings(violations))
+        }
+    }
+}
+
+/// Checks the report against the report descriptors of its event,
+/// in the validation mode of the program of the event.
+/// Reports of events that do not exist are left to fail in the store.
+pub(crate) async fn check_report(
+    events: &dyn EventCrud,
+    programs: &dyn ProgramCrud,
+    report: &ReportRequest,
+) -> Result<Warnings, AppError> {
+    check_against_event(events, programs, &report.event_id, |descriptors| {
+        report_violations(report, descriptors)
+    })
+    .await
+}
+
+/// The report descriptors of an event, with the validation mode of its program,
+/// loaded ahead of checks that must not query the store, e.g., while holding a lock
+pub(crate) struct ReportDescriptors {
+    event_id: EventId,
+    descriptors: Vec<ReportDescriptor>,
+    mode: ValidationMode,
+}
+
+impl ReportDescriptors {
+    /// Events that do not exist have no report descriptors,
+    /// and the program is only retrieved if the event has any
+    pub(crate) async fn load(
+        events: &dyn EventCrud,
+        programs: &dyn ProgramCrud,
+        event_id: &EventId,
+    ) -> Result<Self, AppError> {
+        let event = match events.retrieve(event_id, &None).await {
+            Ok(event) => event,
+            Err(AppError::NotFound) => {
+                return Ok(Self {
+                    event_id: event_id.clone(),
+                    descriptors: vec![],
+                    mode: ValidationMode::default(),
+                });
+            }
+            Err(err) => return Err(err),
+        };
+
+        let descriptors = event.content.report_descriptors.unwrap_or_default();
+        let mode = if descriptors.is_empty() {
+            ValidationMode::default()
+        } else {
+            match programs.retrieve(&event.content.program_id, &None).await {
+                Ok(program) => program.content.validation_mode.unwrap_or_default(),
+                Err(AppError::NotFound) => ValidationMode::default(),
+                Err(err) => return Err(err),
+            }
+        };
+
+        Ok(Self {
+            event_id: event.id,
+            descriptors,
+            mode,
+        })
+    }
+
+    pub(crate) fn event_id(&self) -> &EventId {
+        &self.event_id
+    }
+
+    /// Checks intervals appended to a resource of a report like [`check_report`],
+    /// without looking at the intervals the resource already has,
+    /// except for their `interval_counts` per payload type, which count towards `numIntervals`.
+    pub(crate) fn check_appended_intervals(
+        &self,
+        appended: &AppendedIntervals,
+        interval_counts: &BTreeMap<String, i32>,
+    ) -> Result<Warnings, AppError> {
+        if self.descriptors.is_empty() {
+            return Ok(Warnings::default());
+        }
+        self.check(resource_violations(
+            "",
+            &appended.resource_name,
+            &appended.intervals,
+            interval_counts,
+            &self.descriptors,
+        ))
+    }
+
+    fn check(&self, violations: Vec<FieldError>) -> Result<Warnings, AppError> {
+        if violations.is_empty() {
+            return Ok(Warnings::default());
+        }
+        match self.mode {
+            ValidationMode::Strict => Err(AppError::DescriptorMismatch(violations)),
+            ValidationMode::Lenient => {
+                info!(event_id = %self.event_id, ?violations, "report does not match the report descriptors of its event");
+                Ok(Warnings(violations))
+            }
+        }
+    }
+}
+
+/// The violations of the report descriptors of the event,
+/// rejected or returned as warnings depending on the validation mode of its program
+async fn check_against_event(
+    events: &dyn EventCrud,
+    programs: &dyn ProgramCrud,
+    event_id: &EventId,
+    violations: impl FnOnce(&[ReportDescriptor]) -> Vec<FieldError>,
+) -> Result<Warnings, AppError> {
+    let descriptors = ReportDescriptors::load(events, programs, event_id).await?;
+    descriptors.check(violations(&descriptors.descriptors))
+}
+
+fn violation(field: String, code: &str, message: String) -> FieldError {
+    FieldError {
+        field,
+        code: code.to_string(),
+        message: Some(message),
+    }
+}
+
+/// The JSON representation of an enum like `EventType` or `Unit`
+pub(crate) fn name(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::from("?"),
+    }
+}
+
+fn outside(period: &IntervalPeriod, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+    start < period.start || end > period.end()
+}
+
+fn event_violations(event: &EventRequest, program: &ProgramRequest) -> Vec<FieldError> {
+    let mut violations = vec![];
+
+    let descriptors: Vec<&EventPayloadDescriptor> = program
+        .payload_descriptors
+        .iter()
+        .flatten()
+        .filter_map(|descriptor| match descriptor {
+            PayloadDescriptor::EventPayloadDescriptor(descriptor) => Some(descriptor),
+            PayloadDescriptor::ReportPayloadDescriptor(_) => None,
+        })
+        .collect();
+    if !descriptors.is_empty() {
+        let described = |payload_type: &EventType| {
+            descriptors
+                .iter()
+                .find(|descriptor| descriptor.payload_type == *payload_type)
+        };
+
+        for (i, interval) in event.intervals.iter().flatten().enumerate() {
+            for (j, payload) in interval.payloads.iter().enumerate() {
+                if described(&payload.value_type).is_none() {
+                    violations.push(violation(
+                        format!("intervals[{i}].payloads[{j}].type"),
+                        "payload_type",
+                        format!(
+                            "payload type {} is not described by the program",
+                            name(&payload.value_type)
+                        ),
+                    ));
+                }
+            }
+        }
+
+        for (i, descriptor) in event.payload_descriptors.iter().flatten().enumerate() {
+            let field = format!("payloadDescriptors[{i}]");
+            let Some(described) = described(&descriptor.payload_type) else {
+                violations.push(violation(
+                    format!("{field}.payloadType"),
+                    "payload_type",
+                    format!(
+                        "payload type {} is not described by the program",
+                        name(&descriptor.payload_type)
+                    ),
+                ));
+                continue;
+            };
+            if let (Some(units), Some(expected)) = (&descriptor.units, &described.units)
+                && units != expected
+            {
+                violations.push(violation(
+                    format!("{field}.units"),
+                    "units",
+                    format!(
+                        "units {} contradict the units {} of the program",
+                        name(units),
+                        name(expected)
+                    ),
+                ));
+            }
+            if let (Some(currency), Some(expected)) = (&descriptor.currency, &described.currency)
+                && currency != expected
+            {
+                violations.push(violation(
+                    format!("{field}.currency"),
+                    "currency",
+                    format!(
+                        "currency {} contradicts the currency {} of the program",
+                        name(currency),
+                        name(expected)
+                    ),
+                ));
+            }
+        }
+    }
+
+    if let Some(period) = &program.interval_period {
+        if let Some(event_period) = &event.interval_period
+            && outside(period, event_period.start, event_period.end())
+        {
+            violations.push(violation(
+                "intervalPeriod".to_string(),
+                "interval_period",
+                "interval period lies outside the interval period of the program".to_string(),
+            ));
+        }
+        for (i, interval) in event.resolved_intervals().into_iter().flatten().enumerate() {
+            if outside(period, interval.range.start, interval.range.end) {
+                violations.push(violation(
+                    format!("intervals[{i}]"),
+                    "interval_period",
+                    "interval lies outside the interval period of the program".to_string(),
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+/// The violations of the report descriptors of an event by a report for that event
+pub(crate) fn report_violations(
+    report: &ReportRequest,
+    descriptors: &[ReportDescriptor],
+) -> Vec<FieldError> {
+    let mut violations = vec![];
+    if descriptors.is_empty() {
+        return violations;
+    }
+    for (i, descriptor) in report.payload_descriptors.iter().flatten().enumerate() {
+        let field = format!("payloadDescriptors[{i}]");
+        let payload_type = name(&descriptor.payload_type);
+        let Some(described) = described(descriptors, &payload_type) else {
+            violations.push(violation(
+                format!("{field}.payloadType"),
+                "payload_type",
+                format!("payload type {payload_type} is not described by the event"),
+            ));
+            continue;
+        };
+        if let Some(expected) = &described.reading_type
+            && descriptor.reading_type != *expected
+        {
+            violations.push(violation(
+                format!("{field}.readingType"),
+                "reading_type",
+                format!(
+                    "reading type {} contradicts the reading type {} of the event",
+                    name(&descriptor.reading_type),
+                    name(expected)
+                ),
+            ));
+        }
+        if let (Some(units), Some(expected)) = (&descriptor.units, &described.units)
+            && units != expected
+        {
+            violations.push(violation(
+                format!("{field}.units"),
+                "units",
+                format!(
+                    "units {} contradict the units {} of the event",
+                    name(units),
+                    name(expected)
+                ),
+            ));
+        }
+    }
+
+    for (r, resource) in report.resources.iter().enumerate() {
+        violations.extend(resource_violations(
+            &format!("resources[{r}]."),
+            &resource.resource_name,
+            &resource.intervals,
+            &BTreeMap::new(),
+            descriptors,
+        ));
+    }
+
+    violations
+}
+
+/// The violations of the report descriptors by the intervals of a resource,
+/// whose fields start with the `prefix`.
+/// The resource has `interval_counts` intervals per payload type besides these intervals.
+fn resource_violations(
+    prefix: &str,
+    resource_name: &ResourceName,
+    intervals: &[Interval],
+    interval_counts: &BTreeMap<String, i32>,
+    descriptors: &[ReportDescriptor],
+) -> Vec<FieldError> {
+    let mut violations = vec![];
+    // the number of intervals per described payload type
+    let mut counts: BTreeMap<&str, i32> = BTreeMap::new();
+    for (i, interval) in intervals.iter().enumerate() {
+        for (p, payload) in interval.payloads.iter().enumerate() {
+            let payload_type = payload.value_type.0.as_str();
+            let Some(descriptor) = described(descriptors, payload_type) else {
+                violations.push(violation(
+                    format!("{prefix}intervals[{i}].payloads[{p}].type"),
+                    "payload_type",
+                    format!("payload type {payload_type} is not described by the event"),
+                ));
+                continue;
+            };
+            if descriptor.start_interval >= 0 {
+                let relation = if descriptor.historical {
+                    (interval.id > descriptor.start_interval).then_some("follows")
+                } else {
+                    (interval.id < descriptor.start_interval).then_some("precedes")
+                };
+                if let Some(relation) = relation {
+                    violations.push(violation(
+                        format!("{prefix}intervals[{i}].id"),
+                        "start_interval",
+                        format!(
+                            "interval {} {relation} the start interval {} of {payload_type}",
+                            interval.id, descriptor.start_interval
+                        ),
+                    ));
+                }
+            }
+            *counts.entry(payload_type).or_default() += 1;
+        }
+    }
+
+    let aggregated = *resource_name == ResourceName::AggregatedReport;
+    for (payload_type, count) in counts {
+        let Some(descriptor) = described(descriptors, payload_type) else {
+            continue;
+        };
+        if descriptor.aggregate != aggregated {
+            let message = if descriptor.aggregate {
+                format!("{payload_type} must be reported as AGGREGATED_REPORT")
+            } else {
+                format!("{payload_type} must be reported per resource, not aggregated")
+            };
+            violations.push(violation(
+                format!("{prefix}resourceName"),
+                "aggregate",
+                message,
+            ));
+        }
+        let count = count
+            + interval_counts
+                .get(payload_type)
+                .copied()
+                .unwrap_or_default();
+        if descriptor.num_intervals >= 0 && count > descriptor.num_intervals {
+            violations.push(violation(
+                format!("{prefix}intervals"),
+                "num_intervals",
+                format!(
+                    "{count} intervals of {payload_type} exceed the {} intervals described by the event",
+                    descriptor.num_intervals
+                ),
+            ));
+        }
+    }
+
+    violations
+}
+
+/// The descriptor of the payload type
+fn described<'a>(
+    descriptors: &'a [ReportDescriptor],
+    payload_type: &str,
+) -> Option<&'a ReportDescriptor> {
+    descriptors
+        .iter()
+        .find(|descriptor| name(&descriptor.payload_type) == payload_type)
+}
+
+#[cfg(test)]
+mod test {
+    use openleadr_wire::{
+        Duration, Unit,
+        event::{EventInterval, EventValuesMap},
+        interval::Interval,
+        report::{ReadingType, ReportPayloadDescriptor, ReportResource, ReportType},
+        values_map::{Value, ValueType, ValuesMap},
+    };
+
+    use super::*;
+
+    fn program() -> ProgramRequest {
+        let mut price = EventPayloadDescriptor::new(EventType::Price);
+        price.units = Some(Unit::KWH);
+        price.currency = serde_json::from_value(serde_json::json!("EUR")).unwrap();
+
+        ProgramRequest {
+            interval_period: Some(IntervalPeriod {
+                start: "2024-01-01T00:00:00Z".parse().unwrap(),
+                duration: Some(Duration::PT1H),
+                randomize_start: None,
+            }),
+            payload_descriptors: Some(vec![
+                PayloadDescriptor::EventPayloadDescriptor(price),
+                PayloadDescriptor::EventPayloadDescriptor(EventPayloadDescriptor::new(
+                    EventType::Simple,
+                )),
+            ]),
+            ..ProgramRequest::new("program")
+        }
+    }
+
+    fn interval(id: i32, value_type: EventType, value: Value) -> EventInterval {
+        EventInterval::new(
+            id,
+            vec![EventValuesMap {
+                value_type,
+                values: vec![value],
+            }],
+        )
+    }
+
+    fn fields(violations: Vec<FieldError>) -> Vec<(String, String)> {
+        violations
+            .into_iter()
+            .map(|violation| (violation.field, violation.code))
+            .collect()
+    }
+
+    #[test]
+    fn matching_event() {
+        let mut descriptor = EventPayloadDescriptor::new(EventType::Price);
+        descriptor.units = Some(Unit::KWH);
+        let event = EventRequest::new("program-1".parse().unwrap())
+            .with_interval_period(IntervalPeriod {
+                start: "2024-01-01T00:00:00Z".parse().unwrap(),
+                duration: Some(Duration::PT1H),
+                randomize_start: None,
+            })
+            .with_payload_descriptors(vec![descriptor])
+            .with_intervals(vec![
+                interval(0, EventType::Price, Value::Number(0.3)),
+                interval(1, EventType::Simple, Value::Integer(1)),
+            ]);
+
+        // the second interval starts when the first ends, outside the program
+        assert_eq!(
+            fields(event_violations(&event, &program())),
+            [("intervals[1]".to_string(), "interval_period".to_string())]
+        );
+
+        let single = event.with_intervals(vec![interval(0, EventType::Price, Value::Number(0.3))]);
+        assert!(event_violations(&single, &program()).is_empty());
+        // nothing is checked against programs without descriptors
+        assert!(event_violations(&single, &ProgramRequest::new("program")).is_empty());
+    }
+
+    #[test]
+    fn contradicting_event() {
+        let mut descriptor = EventPayloadDescriptor::new(EventType::Price);
+        descriptor.units = Some(Unit::KW);
+        descriptor.currency = serde_json::from_value(serde_json::json!("USD")).unwrap();
+        let event = EventRequest::new("program-1".parse().unwrap())
+            .with_interval_period(IntervalPeriod::new("2023-12-31T23:00:00Z".parse().unwrap()))
+            .with_payload_descriptors(vec![
+                descriptor,
+                EventPayloadDescriptor::new(EventType::GHG),
+            ])
+            .with_intervals(vec![interval(
+                0,
+                EventType::ExportPrice,
+                Value::Number(0.1),
+            )]);
+
+        assert_eq!(
+            fields(event_violations(&event, &program())),
+            [
+                ("intervals[0].payloads[0].type", "payload_type"),
+                ("payloadDescriptors[0].units", "units"),
+                ("payloadDescriptors[0].currency", "currency"),
+                ("payloadDescriptors[1].payloadType", "payload_type"),
+                ("intervalPeriod", "interval_period"),
+                ("intervals[0]", "interval_period"),
+            ]
+            .map(|(field, code)| (field.to_string(), code.to_string()))
+        );
+    }
+
+    fn report_descriptors() -> Vec<ReportDescriptor> {
+        let mut usage = ReportDescriptor::new(ReportType::Usage);
+        usage.reading_type = Some(ReadingType::DirectRead);
+        usage.units = Some(Unit::KWH);
+        usage.aggregate = true;
+        usage.num_intervals = 2;
+        let mut demand = ReportDescriptor::new(ReportType::Demand);
+        demand.start_interval = 1;
+        demand.historical = false;
+        vec![usage, demand]
+    }
+
+    fn resource(name: ResourceName, intervals: Vec<(i32, &str)>) -> ReportResource {
+        ReportResource {
+            resource_name: name,
+            interval_period: None,
+            intervals: intervals
+                .into_iter()
+                .map(|(id, value_type)| {
+                    Interval::new(
+                        id,
+                        vec![ValuesMap {
+                            value_type: ValueType(value_type.to_string()),
+                            values: vec![Value::Number(1.0)],
+                        }],
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn report(resources: Vec<ReportResource>) -> ReportRequest {
+        ReportRequest {
+            event_id: "event-1".parse().unwrap(),
+            client_name: "ven-1".to_string(),
+            report_name: None,
+            payload_descriptors: None,
+            resources,
+        }
+    }
+
+    #[test]
+    fn matching_report() {
+        let mut usage = ReportPayloadDescriptor::new(ReportType::Usage);
+        usage.units = Some(Unit::KWH);
+        let report = report(vec![
+            resource(
+                ResourceName::AggregatedReport,
+                vec![(0, "USAGE"), (1, "USAGE")],
+            ),
+            resource(
+                ResourceName::Private("resource-1".to_string()),
+                vec![(1, "DEMAND"), (2, "DEMAND"), (3, "DEMAND")],
+            ),
+        ])
+        .with_payload_descriptors(vec![usage]);
+
+        assert!(report_violations(&report, &report_descriptors()).is_empty());
+        // nothing is checked against events without report descriptors
+        let mut undescribed = report.clone();
+        undescribed.resources.push(resource(
+            ResourceName::AggregatedReport,
+            vec![(0, "PRIVATE_TYPE")],
+        ));
+        assert!(report_violations(&undescribed, &[]).is_empty());
+    }
+
+    #[test]
+    fn contradicting_report() {
+        let mut usage = ReportPayloadDescriptor::new(ReportType::Usage);
+        usage.reading_type = ReadingType::Estimated;
+        usage.units = Some(Unit::KW);
+        let report = report(vec![
+            resource(
+                ResourceName::Private("resource-1".to_string()),
+                vec![(0, "USAGE"), (1, "USAGE"), (2, "USAGE")],
+            ),
+            resource(
+                ResourceName::AggregatedReport,
+                vec![(0, "DEMAND"), (1, "BASELINE")],
+            ),
+        ])
+        .with_payload_descriptors(vec![
+            usage,
+            ReportPayloadDescriptor::new(ReportType::Baseline),
+        ]);
+
+        assert_eq!(
+            fields(report_violations(&report, &report_descriptors())),
+            [
+                ("payloadDescriptors[0].readingType", "reading_type"),
+                ("payloadDescriptors[0].units", "units"),
+                ("payloadDescriptors[1].payloadType", "payload_type"),
+                ("resources[0].resourceName", "aggregate"),
+                ("resources[0].intervals", "num_intervals"),
+                ("resources[1].intervals[0].id", "start_interval"),
+                ("resources[1].intervals[1].payloads[0].type", "payload_type"),
+                ("resources[1].resourceName", "aggregate"),
+            ]
+            .map(|(field, code)| (field.to_string(), code.to_string()))
+        );
+    }
+}
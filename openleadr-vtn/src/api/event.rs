@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
     http::StatusCode,
 };
 use serde::Deserialize;
@@ -10,7 +10,8 @@ use tracing::{info, trace};
 use validator::Validate;
 
 use openleadr_wire::{
-    Event,
+    ClientId, Event,
+    batch::{BatchOperation, BatchRequest},
     event::{EventId, EventRequest},
     program::ProgramId,
     subscription::{AnyObject, Operation},
@@ -18,10 +19,12 @@ use openleadr_wire::{
 
 use crate::{
     api::{
-        AppResponse, TargetQueryParams, ValidatedJson, ValidatedQuery,
+        AppResponse, Patch, TargetQueryParams, ValidatedJson, ValidatedQuery, batch,
+        descriptors::{self, Warnings},
+        pagination::{Cursor, Page, Paginated, deserialize_cursor, validate_cursor},
         subscription::{self, NotifierState},
     },
-    data_source::{EventCrud, VenCrud, VenObjectPrivacy},
+    data_source::{EventCrud, Locked, ProgramCrud, VenObjectPrivacy},
     error::AppError,
     jwt::{Scope, User},
 };
@@ -30,22 +33,27 @@ pub async fn get_all(
     State(event_source): State<Arc<dyn EventCrud>>,
     ValidatedQuery(query_params): ValidatedQuery<QueryParams>,
     User(user): User,
-) -> AppResponse<Vec<Event>> {
+    RawQuery(query): RawQuery,
+) -> Result<Page<Event>, AppError> {
     trace!(?query_params);
-    let events = if user.has_scope(Scope::ReadAll) {
-        event_source.retrieve_all(&query_params, &None).await?
+    let permission_filter = if user.has_scope(Scope::ReadAll) {
+        None
     } else if user.has_scope(Scope::ReadTargets) {
-        event_source
-            .retrieve_all(&query_params, &Some(user.client_id()?))
-            .await?
+        Some(user.client_id()?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'read_all' or 'read_targets' scope",
         ));
     };
+    let events = event_source
+        .retrieve_all(&query_params, &permission_filter)
+        .await?;
+    let total = event_source
+        .count(&query_params, &permission_filter)
+        .await?;
     trace!(client_id = user.sub, "retrieved {} events", events.len());
 
-    Ok(Json(events))
+    Ok(Page::new(events, total, &query_params, query.as_deref()))
 }
 
 pub async fn get(
@@ -69,16 +77,17 @@ pub async fn get(
 }
 
 pub async fn add(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(notifier_state): State<Arc<NotifierState>>,
     User(user): User,
     ValidatedJson(new_event): ValidatedJson<EventRequest>,
-) -> Result<(StatusCode, Json<Event>), AppError> {
+) -> Result<(StatusCode, Warnings, Json<Event>), AppError> {
     if !user.has_scope(Scope::WriteEvents) {
         return Err(AppError::Forbidden("Missing 'write_events' scope"));
     }
+    let warnings = descriptors::check_event(&*program_source, &new_event).await?;
 
     let event = event_source
         .create(new_event, &Some(user.client_id()?))
@@ -87,7 +96,6 @@ pub async fn add(
     info!(%event.id, event_name=event.content.event_name, client_id = user.sub, "event created");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -96,30 +104,94 @@ pub async fn add(
     )
     .await;
 
-    Ok((StatusCode::CREATED, Json(event)))
+    Ok((StatusCode::CREATED, warnings, Json(event)))
 }
 
 pub async fn edit(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(notifier_state): State<Arc<NotifierState>>,
     Path(id): Path<EventId>,
     User(user): User,
     ValidatedJson(content): ValidatedJson<EventRequest>,
-) -> AppResponse<Event> {
+) -> Result<(Warnings, Json<Event>), AppError> {
     if !user.has_scope(Scope::WriteEvents) {
         return Err(AppError::Forbidden("Missing 'write_events' scope"));
     }
+    let warnings = descriptors::check_event(&*program_source, &content).await?;
+
+    let lock = event_source.lock(&id).await?;
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        warnings,
+        User(user),
+        ValidatedJson(content),
+    )
+    .await
+}
 
-    let event = event_source
-        .update(&id, content, &Some(user.client_id()?))
+/// Applies a merge patch or JSON patch to the event, as if the result was sent with `PUT`
+pub async fn patch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<EventId>,
+    User(user): User,
+    patch: Patch,
+) -> Result<(Warnings, Json<Event>), AppError> {
+    if !user.has_scope(Scope::WriteEvents) {
+        return Err(AppError::Forbidden("Missing 'write_events' scope"));
+    }
+
+    // The patched event is checked against its program before locking the event.
+    // If the event changed in the meantime, the patch is applied to the new event.
+    loop {
+        let Json(event) = get(
+            State(event_source.clone()),
+            Path(id.clone()),
+            User(user.clone()),
+        )
         .await?;
+        let content = patch.apply(&event.content)?;
+        let warnings = descriptors::check_event(&*program_source, &content).await?;
+
+        let mut lock = event_source.lock(&id).await?;
+        if lock.current().await?.modification_date_time == event.modification_date_time {
+            return edit_locked(
+                lock,
+                State(event_source),
+                State(privacy),
+                State(notifier_state),
+                warnings,
+                User(user),
+                ValidatedJson(content),
+            )
+            .await;
+        }
+    }
+}
+
+/// Updates the event through the `lock`, which [`edit`] and [`patch`] take after checking
+/// the scopes and the descriptors of the program, and before reading the event
+async fn edit_locked(
+    lock: Box<dyn Locked<EventRequest, Event, Option<ClientId>, AppError>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    warnings: Warnings,
+    User(user): User,
+    ValidatedJson(content): ValidatedJson<EventRequest>,
+) -> Result<(Warnings, Json<Event>), AppError> {
+    let event = lock.update(content, &Some(user.client_id()?)).await?;
 
     info!(%event.id, event_name=event.content.event_name, client_id = user.sub, "event updated");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -128,11 +200,10 @@ pub async fn edit(
     )
     .await;
 
-    Ok(Json(event))
+    Ok((warnings, Json(event)))
 }
 
 pub async fn delete(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(notifier_state): State<Arc<NotifierState>>,
@@ -147,7 +218,6 @@ pub async fn delete(
     info!(%event.id, event.event_name=event.content.event_name, client_id = user.sub, "deleted event");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -159,9 +229,72 @@ pub async fn delete(
     Ok(Json(event))
 }
 
+/// Creates, updates, and deletes events in a single transaction.
+/// Notifications are sent once all operations are committed.
+pub async fn batch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    User(user): User,
+    ValidatedJson(request): ValidatedJson<BatchRequest<EventId, EventRequest>>,
+) -> Result<(Warnings, batch::Outcome<Event>), AppError> {
+    if !user.has_scope(Scope::WriteEvents) {
+        return Err(AppError::Forbidden("Missing 'write_events' scope"));
+    }
+    if let Some(invalid) = batch::Outcome::invalid(&request.operations) {
+        return Ok((Warnings::default(), invalid));
+    }
+
+    let mut checks = Vec::with_capacity(request.operations.len());
+    for (index, operation) in request.operations.iter().enumerate() {
+        checks.push(match operation {
+            BatchOperation::Create { content } | BatchOperation::Update { content, .. } => {
+                descriptors::check_event(&*program_source, content)
+                    .await
+                    .map(|warnings| warnings.prefixed(&format!("operations[{index}].content")))
+            }
+            BatchOperation::Delete { .. } => Ok(Warnings::default()),
+        });
+    }
+    if checks.iter().any(Result::is_err) {
+        let errors = checks.into_iter().map(Result::err).collect();
+        return Ok((Warnings::default(), batch::Outcome::RolledBack(errors)));
+    }
+    let warnings = checks.into_iter().flatten().collect();
+
+    let operations = request.operations.iter().map(batch::operation).collect();
+    let results = event_source
+        .batch(request.operations, &Some(user.client_id()?))
+        .await?;
+    let outcome = batch::Outcome::new(operations, results);
+
+    if let batch::Outcome::Committed(events) = &outcome {
+        info!(
+            client_id = user.sub,
+            "applied batch of {} events",
+            events.len()
+        );
+
+        for (operation, event) in events {
+            subscription::notify(
+                &*event_source,
+                &*privacy,
+                &notifier_state,
+                *operation,
+                AnyObject::Event(event.clone()),
+            )
+            .await;
+        }
+    }
+
+    Ok((warnings, outcome))
+}
+
 #[derive(Deserialize, Validate, Debug)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 #[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_cursor"))]
 pub struct QueryParams {
     #[serde(rename = "programID")]
     pub(crate) program_id: Option<ProgramId>,
@@ -172,6 +305,22 @@ pub struct QueryParams {
     #[validate(range(min = 1, max = 50))]
     #[serde(default = "get_50")]
     pub(crate) limit: i64,
+    #[serde(default, deserialize_with = "deserialize_cursor")]
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Paginated for QueryParams {
+    fn skip(&self) -> i64 {
+        self.skip
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
 }
 
 fn get_50() -> i64 {
@@ -197,7 +346,7 @@ mod test {
     use http_body_util::BodyExt;
     use openleadr_wire::{
         event::{EventInterval, EventPayloadDescriptor, EventType, EventValuesMap, Priority},
-        problem::Problem,
+        problem::{ErrorCode, FieldError, Problem},
         target::Target,
         values_map::Value,
     };
@@ -219,6 +368,7 @@ mod test {
                 ])),
                 skip: 1,
                 limit: 2,
+                cursor: None,
             }
         );
 
@@ -276,7 +426,12 @@ mod test {
             assert_eq!(events[events.len() - 1].content, event)
         }
 
-        (AppState::new(store, &VtnConfig::from_env()).await, events)
+        (
+            AppState::new(store, &VtnConfig::from_env().unwrap())
+                .await
+                .unwrap(),
+            events,
+        )
     }
 
     async fn get_help(id: &str, token: &str, app: &mut Router) -> Response<Body> {
@@ -537,10 +692,19 @@ mod test {
         assert_eq!(status, StatusCode::OK);
         assert_eq!(events.len(), 2);
 
-        let (status, _) = test
+        let (status, problem) = test
             .request::<Problem>(Method::GET, "/events?limit=-1", Body::empty())
             .await;
         assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(problem.code, Some(ErrorCode::ValidationFailed));
+        assert_eq!(
+            problem.errors,
+            Some(vec![FieldError {
+                field: "limit".to_string(),
+                code: "range".to_string(),
+                message: None,
+            }])
+        );
 
         let (status, _) = test
             .request::<Problem>(Method::GET, "/events?limit=0", Body::empty())
@@ -688,6 +852,173 @@ mod test {
         }
     }
 
+    #[sqlx::test(fixtures("programs"))]
+    async fn huge_durations_last_forever(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![Scope::WriteEvents, Scope::ReadAll]).await;
+
+        for duration in ["P100000000Y", "PT99999999999999999999S"] {
+            let event = serde_json::json!({
+                "programID": "program-1",
+                "intervalPeriod": {"start": "2024-01-01T00:00:00Z", "duration": duration},
+                "intervals": [
+                    {"id": 0, "payloads": [{"type": "PRICE", "values": [0.17]}]},
+                    {"id": 1, "payloads": [{"type": "PRICE", "values": [0.18]}]}
+                ]
+            });
+            let (status, event) = test
+                .request::<Event>(Method::POST, "/events", Body::from(event.to_string()))
+                .await;
+            assert_eq!(status, StatusCode::CREATED, "{duration}");
+
+            let (status, _) = test
+                .request::<Event>(
+                    Method::PUT,
+                    &format!("/events/{}", event.id),
+                    Body::from(serde_json::to_vec(&event.content).unwrap()),
+                )
+                .await;
+            assert_eq!(status, StatusCode::OK, "{duration}");
+        }
+    }
+
+    /// Creates a program with a price descriptor in EUR per kWh, covering the first hour of 2024
+    async fn described_program(test: &ApiTest, validation_mode: &str) -> String {
+        let (status, program) = test
+            .request::<openleadr_wire::Program>(
+                Method::POST,
+                "/programs",
+                Body::from(
+                    serde_json::json!({
+                        "programName": validation_mode,
+                        "intervalPeriod": {"start": "2024-01-01T00:00:00Z", "duration": "PT1H"},
+                        "payloadDescriptors": [{
+                            "objectType": "EVENT_PAYLOAD_DESCRIPTOR",
+                            "payloadType": "PRICE",
+                            "units": "KWH",
+                            "currency": "EUR"
+                        }],
+                        "validationMode": validation_mode
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        program.id.to_string()
+    }
+
+    fn described_event(program_id: &str, payload_type: &str, start: &str) -> serde_json::Value {
+        serde_json::json!({
+            "programID": program_id,
+            "payloadDescriptors": [{"payloadType": payload_type, "currency": "USD"}],
+            "intervals": [{
+                "id": 0,
+                "intervalPeriod": {"start": start, "duration": "PT15M"},
+                "payloads": [{"type": payload_type, "values": [0.3]}]
+            }]
+        })
+    }
+
+    #[sqlx::test]
+    async fn strict_program_rejects_contradicting_events(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "test-client",
+            vec![Scope::WritePrograms, Scope::WriteEvents, Scope::ReadAll],
+        )
+        .await;
+        let program_id = described_program(&test, "STRICT").await;
+
+        let event = described_event(&program_id, "GHG", "2024-01-01T00:50:00Z");
+        let (status, problem) = test
+            .request::<Problem>(Method::POST, "/events", Body::from(event.to_string()))
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem.code, Some(ErrorCode::DescriptorMismatch));
+        let fields: Vec<_> = problem
+            .errors
+            .unwrap()
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "intervals[0].payloads[0].type",
+                "payloadDescriptors[0].payloadType",
+                "intervals[0]"
+            ]
+        );
+
+        let mut matching = described_event(&program_id, "PRICE", "2024-01-01T00:00:00Z");
+        matching["payloadDescriptors"][0]["currency"] = "EUR".into();
+        let (status, event) = test
+            .request::<Event>(Method::POST, "/events", Body::from(matching.to_string()))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // updates are checked as well
+        let (status, problem) = test
+            .request::<Problem>(
+                Method::PUT,
+                &format!("/events/{}", event.id),
+                Body::from(
+                    described_event(&program_id, "PRICE", "2024-01-01T00:00:00Z").to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            problem.errors.unwrap()[0].field,
+            "payloadDescriptors[0].currency"
+        );
+    }
+
+    #[sqlx::test]
+    async fn lenient_program_warns_about_contradicting_events(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "test-client",
+            vec![Scope::WritePrograms, Scope::WriteEvents, Scope::ReadAll],
+        )
+        .await;
+        let program_id = described_program(&test, "LENIENT").await;
+
+        let response = test
+            .state()
+            .clone()
+            .into_router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/events")
+                    .header(
+                        http::header::AUTHORIZATION,
+                        format!("Bearer {}", test.token()),
+                    )
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        described_event(&program_id, "PRICE", "2024-01-01T00:00:00Z").to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let warnings: Vec<_> = response
+            .headers()
+            .get_all(http::header::WARNING)
+            .iter()
+            .map(|warning| warning.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "299 - \"payloadDescriptors[0].currency: currency USD contradicts the currency EUR of the program\""
+            ]
+        );
+    }
+
     mod permissions {
         use super::*;
 
@@ -0,0 +1,322 @@
+//! Time-triggered notifications about the lifecycle of events.
+//!
+//! Notifications on CRUD operations alone require VENs to run their own timers to know when an
+//! interval becomes active. The scheduler in this module additionally notifies subscribers
+//! when the first interval of an event starts, when each later interval starts,
+//! and when the last interval ends.
+//! The intervals are resolved with [`EventRequest::resolved_intervals`],
+//! the same way the client's `Timeline` does.
+
+use std::{collections::BTreeSet, time::Duration};
+
+use chrono::{DateTime, Utc};
+use openleadr_wire::{
+    Event,
+    event::EventRequest,
+    subscription::{AnyObject, Operation},
+};
+use tracing::{trace, warn};
+
+use crate::{api::subscription, state::AppState};
+
+/// Upper bound for the time the scheduler sleeps without looking for new transitions.
+/// Changes to events wake the scheduler up earlier.
+const MAX_SLEEP: Duration = Duration::from_secs(300);
+
+/// Runs the scheduler until the application terminates.
+/// Transitions that happened before the scheduler started are not notified.
+pub(crate) async fn run(state: AppState) {
+    let mut last_tick = Utc::now();
+
+    loop {
+        let now = Utc::now();
+        let next = match tick(&state, last_tick, now).await {
+            Ok(next) => {
+                last_tick = now;
+                next
+            }
+            Err(err) => {
+                warn!(
+                    "Could not retrieve events for lifecycle notifications: {}",
+                    err
+                );
+                None
+            }
+        };
+
+        let sleep = next
+            .and_then(|next| (next - Utc::now()).to_std().ok())
+            .unwrap_or(MAX_SLEEP)
+            .min(MAX_SLEEP);
+
+        tokio::select! {
+            _ = tokio::time::sleep(sleep) => {}
+            _ = state.notifier.events_changed.notified() => {}
+        }
+    }
+}
+
+/// Notifies all transitions in `(last_tick, now]` and returns the time of the next transition
+async fn tick(
+    state: &AppState,
+    last_tick: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, crate::error::AppError> {
+    let events = state
+        .storage
+        .events()
+        .retrieve_with_transitions(last_tick, now)
+        .await?;
+
+    // events that did not start yet are not retrieved, but may start before any other transition
+    let mut next = state.storage.events().next_first_transition(now).await?;
+    for event in events {
+        let (due, event_next) = due_transitions(&event.content, last_tick, now);
+        next = match (next, event_next) {
+            (Some(next), Some(event_next)) => Some(next.min(event_next)),
+            (next, event_next) => next.or(event_next),
+        };
+
+        for operation in due {
+            trace!(%event.id, ?operation, "event lifecycle transition");
+            notify(state, operation, &event).await;
+        }
+    }
+
+    Ok(next)
+}
+
+async fn notify(state: &AppState, operation: Operation, event: &Event) {
+    subscription::notify(
+        &*state.storage.events(),
+        &*state.storage.ven_object_privacy(),
+        &state.notifier,
+        operation,
+        AnyObject::Event(event.clone()),
+    )
+    .await;
+}
+
+/// The transitions of an event in `(last_tick, now]` and the time of the first transition after `now`
+fn due_transitions(
+    event: &EventRequest,
+    last_tick: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> (Vec<Operation>, Option<DateTime<Utc>>) {
+    let transitions = transitions(event);
+    let due = transitions
+        .iter()
+        .filter(|(at, _)| last_tick < *at && *at <= now)
+        .map(|(_, operation)| *operation)
+        .collect();
+    let next = transitions.iter().map(|(at, _)| *at).find(|at| *at > now);
+
+    (due, next)
+}
+
+/// All lifecycle transitions of an event, ordered by time
+pub(crate) fn transitions(event: &EventRequest) -> Vec<(DateTime<Utc>, Operation)> {
+    let Some(intervals) = event.resolved_intervals() else {
+        return vec![];
+    };
+
+    // intervals after one that lasts forever never start
+    let starts: BTreeSet<_> = intervals
+        .iter()
+        .map(|i| i.range.start)
+        .filter(|start| *start != DateTime::<Utc>::MAX_UTC)
+        .collect();
+    let Some(end) = intervals.iter().map(|i| i.range.end).max() else {
+        return vec![];
+    };
+
+    let mut transitions: Vec<_> = starts
+        .into_iter()
+        .enumerate()
+        .map(|(i, start)| {
+            let operation = if i == 0 {
+                Operation::EventStarted
+            } else {
+                Operation::IntervalStarted
+            };
+            (start, operation)
+        })
+        .collect();
+
+    if end != DateTime::<Utc>::MAX_UTC {
+        transitions.push((end, Operation::EventEnded));
+    }
+
+    transitions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use openleadr_wire::{
+        event::{EventInterval, EventType, EventValuesMap},
+        interval::IntervalPeriod,
+        values_map::Value,
+    };
+
+    fn event(start: DateTime<Utc>, intervals: usize) -> EventRequest {
+        let payloads = vec![EventValuesMap {
+            value_type: EventType::Simple,
+            values: vec![Value::Integer(1)],
+        }];
+        EventRequest::new("program-1".parse().unwrap())
+            .with_interval_period(IntervalPeriod {
+                start,
+                duration: Some(openleadr_wire::Duration::PT1H),
+                randomize_start: None,
+            })
+            .with_intervals(
+                (0..intervals)
+                    .map(|id| EventInterval::new(id as i32, payloads.clone()))
+                    .collect(),
+            )
+    }
+
+    fn hours(start: DateTime<Utc>, hours: i64) -> DateTime<Utc> {
+        start + chrono::Duration::hours(hours)
+    }
+
+    #[test]
+    fn transitions_of_event() {
+        let start: DateTime<Utc> = "2024-01-01T10:00:00Z".parse().unwrap();
+
+        assert_eq!(
+            transitions(&event(start, 3)),
+            vec![
+                (start, Operation::EventStarted),
+                (hours(start, 1), Operation::IntervalStarted),
+                (hours(start, 2), Operation::IntervalStarted),
+                (hours(start, 3), Operation::EventEnded),
+            ]
+        );
+
+        assert_eq!(transitions(&event(start, 0)), vec![]);
+
+        let mut endless = event(start, 1);
+        endless.interval_period.as_mut().unwrap().duration = None;
+        assert_eq!(
+            transitions(&endless),
+            vec![(start, Operation::EventStarted)]
+        );
+
+        let mut without_period = event(start, 1);
+        without_period.interval_period = None;
+        assert_eq!(transitions(&without_period), vec![]);
+
+        // the first interval lasts beyond the latest representable time
+        let mut huge = event(start, 2);
+        huge.interval_period.as_mut().unwrap().duration = Some("P100000000Y".parse().unwrap());
+        assert_eq!(transitions(&huge), vec![(start, Operation::EventStarted)]);
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn notifies_webhooks(db: sqlx::PgPool) {
+        use crate::{api::test::ApiTest, jwt::Scope};
+        use axum::body::Body;
+        use openleadr_wire::subscription::Subscription;
+        use reqwest::{Method, StatusCode};
+
+        let test = ApiTest::new(
+            db,
+            "bl-client",
+            vec![
+                Scope::ReadAll,
+                Scope::WriteEvents,
+                Scope::WriteSubscriptionsBl,
+            ],
+        )
+        .await;
+        let (callback_url, mut notifications) = crate::api::test::webhook_receiver().await;
+
+        let (status, _) = test
+            .request::<Subscription>(
+                Method::POST,
+                "/subscriptions",
+                Body::from(
+                    serde_json::json!({
+                        "clientName": "bl-client",
+                        "objectOperations": [{
+                            "objects": ["EVENT"],
+                            "operations": ["EVENT_STARTED", "INTERVAL_STARTED", "EVENT_ENDED"],
+                            "callbackUrl": callback_url,
+                        }]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let start: DateTime<Utc> = "2024-01-01T10:00:00Z".parse().unwrap();
+        let (status, event) = test
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(serde_json::to_string(&event(start, 2)).unwrap()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let next = tick(test.state(), hours(start, -2), hours(start, -1))
+            .await
+            .unwrap();
+        assert_eq!(next, Some(start));
+
+        let next = tick(test.state(), hours(start, -1), hours(start, 1))
+            .await
+            .unwrap();
+        assert_eq!(next, Some(hours(start, 2)));
+
+        // webhooks to the same callback URL are delivered in the order of the transitions
+        let mut operations = vec![];
+        for _ in 0..2 {
+            let (_, notification) =
+                tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(notification.object, AnyObject::Event(event.clone()));
+            operations.push(notification.operation);
+        }
+        assert_eq!(
+            operations,
+            [Operation::EventStarted, Operation::IntervalStarted]
+        );
+    }
+
+    #[test]
+    fn due_transitions_between_ticks() {
+        let start: DateTime<Utc> = "2024-01-01T10:00:00Z".parse().unwrap();
+        let event = event(start, 2);
+
+        assert_eq!(
+            due_transitions(&event, hours(start, -1), hours(start, -1)),
+            (vec![], Some(start))
+        );
+        assert_eq!(
+            due_transitions(&event, hours(start, -1), start),
+            (vec![Operation::EventStarted], Some(hours(start, 1)))
+        );
+        // a transition exactly at the last tick was notified in that tick already
+        assert_eq!(
+            due_transitions(&event, start, hours(start, 1)),
+            (vec![Operation::IntervalStarted], Some(hours(start, 2)))
+        );
+        assert_eq!(
+            due_transitions(&event, hours(start, -1), hours(start, 5)),
+            (
+                vec![
+                    Operation::EventStarted,
+                    Operation::IntervalStarted,
+                    Operation::EventEnded
+                ],
+                None
+            )
+        );
+    }
+}
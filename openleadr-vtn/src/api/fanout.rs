@@ -0,0 +1,1094 @@
+//! Building blocks to fan out a notification to many subscribers without touching the database
+//! for each of them.
+//!
+//! * [`SubscriptionIndex`] finds the subscriptions for an object type and program
+//!   without iterating all subscriptions.
+//! * [`VenVisibilityCache`] keeps the targets and resource groups of all VENs in memory,
+//!   such that the per-VEN privacy filtering does not need a query per VEN.
+//!   [`notify`](super::subscription::notify) invalidates it whenever a VEN, resource,
+//!   or resource group changes.
+//! * [`DeliveryWorkers`] publish MQTT messages and call webhooks in the background,
+//!   such that API requests don't wait for the delivery of their notifications.
+//!   They batch notifications per destination if configured,
+//!   and keep track of the outcome of webhook deliveries.
+//!   Notifications that do not fit into the queues are dropped rather than waited for.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use openleadr_wire::{
+    ClientId, Identifier, ObjectType,
+    program::ProgramId,
+    resource_group::ResourceGroupId,
+    subscription::{Notification, NotificationBatch, Operation, Subscription, SubscriptionId},
+    target::Target,
+    ven::VenId,
+};
+use paho_mqtt::QoS;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{OnceCell, RwLock, mpsc, watch},
+    task::{JoinHandle, JoinSet},
+};
+use tracing::{Instrument, info_span, trace, warn};
+
+use crate::{
+    correlation::{self, Origin},
+    data_source::{VenObjectPrivacy, VenVisibility},
+    error::AppError,
+    jwt::Scope,
+};
+
+/// Subscriptions, indexed by the object types they subscribe to and their program filter
+#[derive(Default)]
+pub(crate) struct SubscriptionIndex {
+    subscriptions: HashMap<SubscriptionId, Arc<Subscriber>>,
+    by_object: HashMap<ObjectType, ProgramIndex>,
+}
+
+/// A subscription in the [`SubscriptionIndex`]
+pub(crate) struct Subscriber {
+    pub(crate) subscription: Subscription,
+    /// The read scopes the client had when it created the subscription,
+    /// which determine the objects its webhooks receive
+    pub(crate) owner_scopes: Vec<Scope>,
+}
+
+#[derive(Default)]
+struct ProgramIndex {
+    /// Subscriptions without a program filter
+    any_program: HashSet<SubscriptionId>,
+    by_program: HashMap<ProgramId, HashSet<SubscriptionId>>,
+}
+
+impl SubscriptionIndex {
+    /// Adds the subscription, replacing an existing subscription with the same ID
+    pub(crate) fn insert(&mut self, subscription: Subscription, owner_scopes: Vec<Scope>) {
+        self.remove(&subscription.id);
+
+        for object in object_types(&subscription) {
+            let programs = self.by_object.entry(object).or_default();
+            let ids = match &subscription.content.program_id {
+                None => &mut programs.any_program,
+                Some(program_id) => programs.by_program.entry(program_id.clone()).or_default(),
+            };
+            ids.insert(subscription.id.clone());
+        }
+
+        self.subscriptions.insert(
+            subscription.id.clone(),
+            Arc::new(Subscriber {
+                subscription,
+                owner_scopes,
+            }),
+        );
+    }
+
+    /// Replaces an updated subscription, keeping the scopes of its owner.
+    /// A subscription that is not in the index is added without owner scopes.
+    pub(crate) fn update(&mut self, subscription: Subscription) {
+        let owner_scopes = self
+            .subscriptions
+            .get(&subscription.id)
+            .map(|subscriber| subscriber.owner_scopes.clone())
+            .unwrap_or_default();
+        self.insert(subscription, owner_scopes);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    pub(crate) fn remove(&mut self, id: &SubscriptionId) -> Option<Arc<Subscriber>> {
+        let subscriber = self.subscriptions.remove(id)?;
+        let subscription = &subscriber.subscription;
+
+        for object in object_types(subscription) {
+            let Some(programs) = self.by_object.get_mut(&object) else {
+                continue;
+            };
+            match &subscription.content.program_id {
+                None => {
+                    programs.any_program.remove(id);
+                }
+                Some(program_id) => {
+                    if let Some(ids) = programs.by_program.get_mut(program_id) {
+                        ids.remove(id);
+                        if ids.is_empty() {
+                            programs.by_program.remove(program_id);
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(subscriber)
+    }
+
+    /// The subscriptions for objects of type `object`.
+    /// Subscriptions with a program filter only match if it equals `program_id`.
+    /// The operations of the subscriptions are not checked.
+    pub(crate) fn matching(
+        &self,
+        object: ObjectType,
+        program_id: Option<&ProgramId>,
+    ) -> Vec<Arc<Subscriber>> {
+        let Some(programs) = self.by_object.get(&object) else {
+            return vec![];
+        };
+
+        let by_program = program_id
+            .and_then(|program_id| programs.by_program.get(program_id))
+            .into_iter()
+            .flatten();
+
+        programs
+            .any_program
+            .iter()
+            .chain(by_program)
+            .filter_map(|id| self.subscriptions.get(id).cloned())
+            .collect()
+    }
+}
+
+impl FromIterator<(Subscription, Vec<Scope>)> for SubscriptionIndex {
+    fn from_iter<T: IntoIterator<Item = (Subscription, Vec<Scope>)>>(iter: T) -> Self {
+        let mut index = Self::default();
+        for (subscription, owner_scopes) in iter {
+            index.insert(subscription, owner_scopes);
+        }
+        index
+    }
+}
+
+fn object_types(subscription: &Subscription) -> HashSet<ObjectType> {
+    subscription
+        .content
+        .object_operations
+        .iter()
+        .flat_map(|object_operation| object_operation.objects.iter().copied())
+        .collect()
+}
+
+/// The [`VenVisibility`] of all VENs at one point in time
+pub(crate) struct VenVisibilitySnapshot {
+    vens: Vec<VenVisibility>,
+    by_client_id: HashMap<ClientId, usize>,
+    loaded_at: Instant,
+}
+
+impl VenVisibilitySnapshot {
+    fn new(vens: Vec<VenVisibility>) -> Self {
+        let by_client_id = vens
+            .iter()
+            .enumerate()
+            .map(|(i, ven)| (ven.client_id.clone(), i))
+            .collect();
+
+        Self {
+            vens,
+            by_client_id,
+            loaded_at: Instant::now(),
+        }
+    }
+
+    pub(crate) fn vens(&self) -> &[VenVisibility] {
+        &self.vens
+    }
+
+    fn ven(&self, client_id: &ClientId) -> Option<&VenVisibility> {
+        self.by_client_id.get(client_id).map(|&i| &self.vens[i])
+    }
+}
+
+/// Answers the same way as the storage the snapshot was loaded from
+#[async_trait]
+impl VenObjectPrivacy for VenVisibilitySnapshot {
+    async fn targets_by_client_id(&self, client_id: &ClientId) -> Result<Vec<Target>, AppError> {
+        self.ven(client_id)
+            .map(|ven| ven.targets.iter().cloned().collect())
+            .ok_or(AppError::NotFound)
+    }
+
+    async fn resource_group_visible_for_client(
+        &self,
+        client_id: &ClientId,
+        resource_group_id: &ResourceGroupId,
+    ) -> Result<bool, AppError> {
+        Ok(self
+            .ven(client_id)
+            .is_some_and(|ven| ven.resource_groups.contains(resource_group_id)))
+    }
+
+    async fn ven_id_by_client_id(&self, client_id: &ClientId) -> Result<Option<VenId>, AppError> {
+        Ok(self.ven(client_id).map(|ven| ven.ven_id.clone()))
+    }
+
+    async fn all_ven_visibility(&self) -> Result<Vec<VenVisibility>, AppError> {
+        Ok(self.vens.clone())
+    }
+}
+
+/// Caches a [`VenVisibilitySnapshot`] until it gets invalidated.
+pub(crate) struct VenVisibilityCache {
+    snapshot: RwLock<Option<Arc<VenVisibilitySnapshot>>>,
+    /// Incremented on every invalidation, such that a snapshot loaded concurrently
+    /// to an invalidation is not cached
+    generation: AtomicU64,
+}
+
+impl VenVisibilityCache {
+    /// Snapshots are reloaded after this time even without invalidation,
+    /// to pick up changes that did not go through this VTN instance.
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    pub(crate) fn new() -> Self {
+        Self {
+            snapshot: RwLock::new(None),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) async fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *self.snapshot.write().await = None;
+    }
+
+    pub(crate) async fn get(
+        &self,
+        source: &dyn VenObjectPrivacy,
+    ) -> Result<Arc<VenVisibilitySnapshot>, AppError> {
+        if let Some(snapshot) = &*self.snapshot.read().await
+            && snapshot.loaded_at.elapsed() < Self::MAX_AGE
+        {
+            return Ok(snapshot.clone());
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let snapshot = Arc::new(VenVisibilitySnapshot::new(
+            source.all_ven_visibility().await?,
+        ));
+        trace!(vens = snapshot.vens.len(), "loaded VEN visibility");
+
+        let mut cached = self.snapshot.write().await;
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *cached = Some(snapshot.clone());
+        }
+
+        Ok(snapshot)
+    }
+
+    /// The privacy lookups for a single notification.
+    /// The snapshot is only loaded if a lookup is necessary.
+    pub(crate) fn privacy<'a>(&'a self, source: &'a dyn VenObjectPrivacy) -> CachedPrivacy<'a> {
+        CachedPrivacy {
+            cache: self,
+            source,
+            snapshot: OnceCell::new(),
+        }
+    }
+}
+
+pub(crate) struct CachedPrivacy<'a> {
+    cache: &'a VenVisibilityCache,
+    source: &'a dyn VenObjectPrivacy,
+    snapshot: OnceCell<Option<Arc<VenVisibilitySnapshot>>>,
+}
+
+impl CachedPrivacy<'_> {
+    /// The cached snapshot, or `None` if it could not be loaded
+    pub(crate) async fn snapshot(&self) -> Option<&VenVisibilitySnapshot> {
+        self.snapshot
+            .get_or_init(|| async {
+                self.cache
+                    .get(self.source)
+                    .await
+                    .inspect_err(|err| warn!("Could not load the targets of all VENs: {}", err))
+                    .ok()
+            })
+            .await
+            .as_deref()
+    }
+
+    /// The snapshot if available, falls back to the underlying storage otherwise
+    pub(crate) async fn get(&self) -> &dyn VenObjectPrivacy {
+        match self.snapshot().await {
+            Some(snapshot) => snapshot,
+            None => self.source,
+        }
+    }
+}
+
+/// Identifies notifications that can be merged within a batch:
+/// an update of an object replaces an earlier update of the same object.
+pub(crate) type MergeKey = (ObjectType, Identifier);
+
+/// The [`MergeKey`] of a notification, if it can be merged at all
+pub(crate) fn merge_key(notification: &Notification) -> Option<MergeKey> {
+    (notification.operation == Operation::Update)
+        .then(|| (notification.object.kind(), notification.object.id()))
+}
+
+pub(crate) enum Delivery {
+    Mqtt {
+        topic: String,
+        payload: Vec<u8>,
+        merge_key: Option<MergeKey>,
+        origin: Origin,
+    },
+    Webhook {
+        subscription_id: SubscriptionId,
+        callback_url: String,
+        bearer_token: Option<String>,
+        notification: Box<Notification>,
+        /// Collect the notifications to this callback for this long and deliver them together
+        batch_window: Option<Duration>,
+        origin: Origin,
+    },
+}
+
+impl Delivery {
+    fn destination(&self) -> &str {
+        match self {
+            Delivery::Mqtt { topic, .. } => topic,
+            Delivery::Webhook { callback_url, .. } => callback_url,
+        }
+    }
+}
+
+/// Background tasks that deliver MQTT messages and webhooks.
+///
+/// Deliveries are sharded by their destination, i.e., the MQTT topic or the callback URL.
+/// Therefore, notifications to the same destination are delivered in the order they were queued,
+/// and each worker can batch the notifications to the destinations it is responsible for.
+/// Each worker calls the webhooks of a callback URL in a task of its own,
+/// such that a slow callback only delays the notifications to itself.
+pub(crate) struct DeliveryWorkers {
+    queues: Vec<mpsc::Sender<Delivery>>,
+    stats: Arc<std::sync::Mutex<HashMap<SubscriptionId, DeliveryStats>>>,
+    /// Set to make the workers deliver what they have queued and stop
+    stop: watch::Sender<bool>,
+    workers: std::sync::Mutex<Vec<JoinHandle<()>>>,
+}
+
+/// Outcome of the webhook deliveries of a subscription since the VTN started
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DeliveryStats {
+    #[serde(with = "openleadr_wire::serde_rfc3339::option")]
+    pub(crate) last_success_date_time: Option<DateTime<Utc>>,
+    #[serde(with = "openleadr_wire::serde_rfc3339::option")]
+    pub(crate) last_error_date_time: Option<DateTime<Utc>>,
+    pub(crate) last_error: Option<String>,
+    pub(crate) failure_count: u64,
+    /// The first failure since the last success, if the last delivery failed
+    #[serde(with = "openleadr_wire::serde_rfc3339::option")]
+    pub(crate) failing_since_date_time: Option<DateTime<Utc>>,
+}
+
+impl DeliveryStats {
+    fn success(&mut self) {
+        self.last_success_date_time = Some(Utc::now());
+        self.failing_since_date_time = None;
+    }
+
+    fn failure(&mut self, error: String) {
+        let now = Utc::now();
+        self.last_error_date_time = Some(now);
+        self.last_error = Some(error);
+        self.failure_count += 1;
+        self.failing_since_date_time.get_or_insert(now);
+    }
+}
+
+impl DeliveryWorkers {
+    const WORKERS: usize = 16;
+    const QUEUE_SIZE: usize = 1024;
+    /// Of the webhook calls to a single callback URL
+    const WEBHOOK_QUEUE_SIZE: usize = 256;
+    /// Of the task calling the webhooks of a callback URL, before it stops
+    const WEBHOOK_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Must be called from within a Tokio runtime.
+    /// If `mqtt_batch_window` is set, the messages to each MQTT topic are published in batches.
+    pub(crate) fn spawn(
+        mqtt_client: Option<paho_mqtt::AsyncClient>,
+        mqtt_batch_window: Option<Duration>,
+        http_client: reqwest::Client,
+    ) -> Self {
+        let stats = Arc::default();
+        let (stop, _) = watch::channel(false);
+        let mut workers = Vec::with_capacity(Self::WORKERS);
+        let queues = (0..Self::WORKERS)
+            .map(|_| {
+                let (tx, rx) = mpsc::channel(Self::QUEUE_SIZE);
+                let worker = Worker {
+                    mqtt_client: mqtt_client.clone(),
+                    mqtt_batch_window,
+                    http_client: http_client.clone(),
+                    stats: Arc::clone(&stats),
+                    mqtt_batches: HashMap::new(),
+                    webhook_batches: HashMap::new(),
+                    webhooks: HashMap::new(),
+                    webhook_tasks: JoinSet::new(),
+                };
+                workers.push(tokio::spawn(worker.run(rx, stop.subscribe())));
+                tx
+            })
+            .collect();
+
+        Self {
+            queues,
+            stats,
+            stop,
+            workers: std::sync::Mutex::new(workers),
+        }
+    }
+
+    /// Stops accepting deliveries and waits until the queued ones, including pending batches,
+    /// are delivered. Deliveries still pending at `deadline` are dropped.
+    pub(crate) async fn shutdown(&self, deadline: tokio::time::Instant) {
+        self.stop.send_replace(true);
+        let workers = std::mem::take(&mut *self.workers.lock().expect("workers lock poisoned"));
+
+        let aborts: Vec<_> = workers.iter().map(JoinHandle::abort_handle).collect();
+        let drained = tokio::time::timeout_at(deadline, async {
+            for worker in workers {
+                let _ = worker.await;
+            }
+        })
+        .await;
+        if drained.is_err() {
+            warn!("Shutdown deadline reached, dropping undelivered notifications");
+            for abort in aborts {
+                abort.abort();
+            }
+        }
+    }
+
+    /// The subscriptions whose webhooks failed continuously since `since` or earlier
+    pub(crate) fn failing_webhooks(&self, since: DateTime<Utc>) -> Vec<SubscriptionId> {
+        self.stats
+            .lock()
+            .expect("delivery stats lock poisoned")
+            .iter()
+            .filter(|(_, stats)| {
+                stats
+                    .failing_since_date_time
+                    .is_some_and(|failing_since| failing_since <= since)
+            })
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// The webhook delivery stats of all subscriptions that had a webhook delivery
+    pub(crate) fn stats(&self) -> Vec<(SubscriptionId, DeliveryStats)> {
+        self.stats
+            .lock()
+            .expect("delivery stats lock poisoned")
+            .iter()
+            .map(|(id, stats)| (id.clone(), stats.clone()))
+            .collect()
+    }
+
+    /// Stops tracking the webhook deliveries of a deleted subscription
+    pub(crate) fn forget(&self, subscription_id: &SubscriptionId) {
+        self.stats
+            .lock()
+            .expect("delivery stats lock poisoned")
+            .remove(subscription_id);
+    }
+
+    /// Queues the delivery. Drops it if the queue of the responsible worker is full.
+    pub(crate) fn deliver(&self, delivery: Delivery) {
+        let shard = self.shard(delivery.destination());
+        match self.queues[shard].try_send(delivery) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(delivery)) => {
+                warn!(
+                    destination = delivery.destination(),
+                    "Notification delivery queue full, dropping notification"
+                );
+                #[cfg(feature = "metrics")]
+                crate::metrics::notification_dropped(match delivery {
+                    Delivery::Mqtt { .. } => crate::metrics::Binding::Mqtt,
+                    Delivery::Webhook { .. } => crate::metrics::Binding::Webhook,
+                });
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                warn!("Notification delivery worker stopped, dropping notification");
+            }
+        }
+    }
+
+    /// The worker responsible for the destination
+    fn shard(&self, destination: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        destination.hash(&mut hasher);
+        hasher.finish() as usize % self.queues.len()
+    }
+}
+
+/// Serialized MQTT messages waiting to be published as one JSON array
+struct MqttBatch {
+    deadline: tokio::time::Instant,
+    messages: Vec<(Option<MergeKey>, Vec<u8>)>,
+    /// Of the first message
+    origin: Origin,
+}
+
+impl MqttBatch {
+    /// Adds the message, replacing an earlier message with the same [`MergeKey`]
+    fn push(&mut self, merge_key: Option<MergeKey>, payload: Vec<u8>) {
+        if let Some(key) = &merge_key
+            && let Some((_, earlier)) = self
+                .messages
+                .iter_mut()
+                .find(|(earlier_key, _)| earlier_key.as_ref() == Some(key))
+        {
+            *earlier = payload;
+        } else {
+            self.messages.push((merge_key, payload));
+        }
+    }
+
+    fn into_payload(self) -> Vec<u8> {
+        let mut payload = vec![b'['];
+        for (i, (_, message)) in self.messages.into_iter().enumerate() {
+            if i > 0 {
+                payload.push(b',');
+            }
+            payload.extend(message);
+        }
+        payload.push(b']');
+        payload
+    }
+}
+
+/// Webhook notifications of one subscription waiting to be delivered together
+struct WebhookBatch {
+    deadline: tokio::time::Instant,
+    bearer_token: Option<String>,
+    notifications: NotificationBatch,
+    /// Of the first notification
+    origin: Origin,
+}
+
+/// A webhook call queued for the [`WebhookTask`] of its callback URL
+struct WebhookCall {
+    subscription_id: SubscriptionId,
+    bearer_token: Option<String>,
+    body: WebhookBody,
+    origin: Origin,
+}
+
+enum WebhookBody {
+    Notification(Box<Notification>),
+    Batch(NotificationBatch),
+}
+
+struct Worker {
+    mqtt_client: Option<paho_mqtt::AsyncClient>,
+    mqtt_batch_window: Option<Duration>,
+    http_client: reqwest::Client,
+    stats: Arc<std::sync::Mutex<HashMap<SubscriptionId, DeliveryStats>>>,
+    /// By topic
+    mqtt_batches: HashMap<String, MqttBatch>,
+    /// By subscription and callback URL
+    webhook_batches: HashMap<(SubscriptionId, String), WebhookBatch>,
+    /// The queues of the [`WebhookTask`]s, by callback URL
+    webhooks: HashMap<String, mpsc::Sender<WebhookCall>>,
+    /// Aborted when the worker is dropped, e.g., if the shutdown deadline is reached
+    webhook_tasks: JoinSet<()>,
+}
+
+impl Worker {
+    async fn run(mut self, mut queue: mpsc::Receiver<Delivery>, mut stop: watch::Receiver<bool>) {
+        let mut stopping = false;
+        loop {
+            let deadline = self.next_deadline();
+            tokio::select! {
+                // the queue still yields the deliveries queued before closing it, then `None`
+                _ = stop.changed(), if !stopping => {
+                    stopping = true;
+                    queue.close();
+                }
+                delivery = queue.recv() => match delivery {
+                    Some(delivery) => self.handle(delivery).await,
+                    None => {
+                        self.flush(None).await;
+                        // the webhook tasks stop after calling the webhooks queued for them
+                        self.webhooks.clear();
+                        while self.webhook_tasks.join_next().await.is_some() {}
+                        return;
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                    if deadline.is_some() =>
+                {
+                    self.flush(Some(tokio::time::Instant::now())).await;
+                }
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<tokio::time::Instant> {
+        let mqtt = self.mqtt_batches.values().map(|batch| batch.deadline);
+        let webhooks = self.webhook_batches.values().map(|batch| batch.deadline);
+        mqtt.chain(webhooks).min()
+    }
+
+    async fn handle(&mut self, delivery: Delivery) {
+        match delivery {
+            Delivery::Mqtt {
+                topic,
+                payload,
+                merge_key,
+                origin,
+            } => match self.mqtt_batch_window {
+                Some(window) => self
+                    .mqtt_batches
+                    .entry(topic)
+                    .or_insert_with(|| MqttBatch {
+                        deadline: tokio::time::Instant::now() + window,
+                        messages: Vec::new(),
+                        origin,
+                    })
+                    .push(merge_key, payload),
+                None => self.publish(&topic, payload, &origin).await,
+            },
+            Delivery::Webhook {
+                subscription_id,
+                callback_url,
+                bearer_token,
+                notification,
+                batch_window,
+                origin,
+            } => match batch_window {
+                Some(window) => self
+                    .webhook_batches
+                    .entry((subscription_id, callback_url))
+                    .or_insert_with(|| WebhookBatch {
+                        deadline: tokio::time::Instant::now() + window,
+                        bearer_token,
+                        notifications: NotificationBatch::default(),
+                        origin,
+                    })
+                    .notifications
+                    .push(*notification),
+                None => self.call_webhook(
+                    callback_url,
+                    WebhookCall {
+                        subscription_id,
+                        bearer_token,
+                        body: WebhookBody::Notification(notification),
+                        origin,
+                    },
+                ),
+            },
+        }
+    }
+
+    /// Delivers the batches due at `now`, or all batches if `now` is `None`
+    async fn flush(&mut self, now: Option<tokio::time::Instant>) {
+        let is_due = |deadline: tokio::time::Instant| now.is_none_or(|now| deadline <= now);
+
+        let due_topics: Vec<_> = self
+            .mqtt_batches
+            .iter()
+            .filter(|(_, batch)| is_due(batch.deadline))
+            .map(|(topic, _)| topic.clone())
+            .collect();
+        for topic in due_topics {
+            if let Some(batch) = self.mqtt_batches.remove(&topic) {
+                trace!(topic, messages = batch.messages.len(), "publish mqtt batch");
+                let origin = batch.origin.clone();
+                self.publish(&topic, batch.into_payload(), &origin).await;
+            }
+        }
+
+        let due_webhooks: Vec<_> = self
+            .webhook_batches
+            .iter()
+            .filter(|(_, batch)| is_due(batch.deadline))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in due_webhooks {
+            if let Some(batch) = self.webhook_batches.remove(&key) {
+                let (subscription_id, callback_url) = key;
+                self.call_webhook(
+                    callback_url,
+                    WebhookCall {
+                        subscription_id,
+                        bearer_token: batch.bearer_token,
+                        body: WebhookBody::Batch(batch.notifications),
+                        origin: batch.origin,
+                    },
+                );
+            }
+        }
+    }
+
+    async fn publish(&self, topic: &str, payload: Vec<u8>, origin: &Origin) {
+        let Some(mqtt_client) = &self.mqtt_client else {
+            return;
+        };
+        let result = mqtt_client
+            .publish(paho_mqtt::Message::new(topic, payload, QoS::AtMostOnce))
+            .instrument(info_span!(parent: &origin.span, "mqtt_publish", topic))
+            .await;
+        #[cfg(feature = "metrics")]
+        crate::metrics::notification(crate::metrics::Binding::Mqtt, result.is_ok());
+        if let Err(err) = result {
+            warn!(topic, "Could not send mqtt notification: {}", err)
+        }
+    }
+
+    /// Queues the call for the [`WebhookTask`] of the callback URL, starting it if necessary.
+    /// Drops the call if the queue of the task is full.
+    fn call_webhook(&mut self, callback_url: String, call: WebhookCall) {
+        let mut stopped = false;
+        while self.webhook_tasks.try_join_next().is_some() {
+            stopped = true;
+        }
+        if stopped {
+            self.webhooks.retain(|_, queue| !queue.is_closed());
+        }
+
+        let call = match self.webhooks.get(&callback_url) {
+            Some(queue) => match queue.try_send(call) {
+                Ok(()) => return,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    warn!(callback_url, "Webhook queue full, dropping notification");
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::notification_dropped(crate::metrics::Binding::Webhook);
+                    return;
+                }
+                // the task stopped after being idle
+                Err(mpsc::error::TrySendError::Closed(call)) => call,
+            },
+            None => call,
+        };
+
+        let (queue, calls) = mpsc::channel(DeliveryWorkers::WEBHOOK_QUEUE_SIZE);
+        let _ = queue.try_send(call);
+        self.webhooks.insert(callback_url.clone(), queue);
+        let task = WebhookTask {
+            http_client: self.http_client.clone(),
+            stats: Arc::clone(&self.stats),
+            callback_url,
+        };
+        self.webhook_tasks.spawn(task.run(calls));
+    }
+}
+
+/// Calls the webhooks of a callback URL one after another, in the order they were queued
+struct WebhookTask {
+    http_client: reqwest::Client,
+    stats: Arc<std::sync::Mutex<HashMap<SubscriptionId, DeliveryStats>>>,
+    callback_url: String,
+}
+
+impl WebhookTask {
+    /// Stops once the queue is closed, or after being idle for a while
+    async fn run(self, mut calls: mpsc::Receiver<WebhookCall>) {
+        loop {
+            match tokio::time::timeout(DeliveryWorkers::WEBHOOK_IDLE_TIMEOUT, calls.recv()).await {
+                Ok(Some(call)) => self.call(call).await,
+                Ok(None) => return,
+                // the queue still yields the calls queued before closing it, then `None`
+                Err(_) => calls.close(),
+            }
+        }
+    }
+
+    /// Posts a [`Notification`] or [`NotificationBatch`] and updates the [`DeliveryStats`]
+    async fn call(&self, call: WebhookCall) {
+        let callback_url = self.callback_url.as_str();
+        let span = info_span!(parent: &call.origin.span, "webhook", callback_url);
+        let headers =
+            span.in_scope(|| correlation::outgoing_headers(call.origin.request_id.as_deref()));
+
+        let request = self.http_client.post(callback_url).headers(headers);
+        let mut request = match &call.body {
+            WebhookBody::Notification(notification) => request.json(notification),
+            WebhookBody::Batch(notifications) => request.json(notifications),
+        };
+        if let Some(bearer_token) = call.bearer_token {
+            request = request.bearer_auth(bearer_token);
+        }
+
+        let result = request
+            .send()
+            .instrument(span)
+            .await
+            .and_then(|response| response.error_for_status());
+        #[cfg(feature = "metrics")]
+        crate::metrics::notification(crate::metrics::Binding::Webhook, result.is_ok());
+
+        let mut stats = self.stats.lock().expect("delivery stats lock poisoned");
+        let stats = stats.entry(call.subscription_id).or_default();
+        match result {
+            Ok(_) => {
+                trace!(callback_url, "delivered webhook");
+                stats.success();
+            }
+            Err(err) => {
+                warn!(
+                    callback_url,
+                    "Could not deliver webhook notification: {}", err
+                );
+                stats.failure(err.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use openleadr_wire::subscription::{
+        AnyObject, NotificationMechanism, Operation, SubscriptionObjectOperation,
+        SubscriptionRequest,
+    };
+
+    fn subscription(id: &str, program_id: Option<&str>, objects: Vec<ObjectType>) -> Subscription {
+        Subscription {
+            id: id.parse().unwrap(),
+            created_date_time: Default::default(),
+            modification_date_time: Default::default(),
+            client_id: "client".parse().unwrap(),
+            expires_date_time: None,
+            content: SubscriptionRequest {
+                client_name: "client".into(),
+                program_id: program_id.map(|id| id.parse().unwrap()),
+                time_to_live: None,
+                batch_window: None,
+                object_operations: vec![SubscriptionObjectOperation {
+                    objects,
+                    operations: vec![Operation::Create],
+                    mechanism: NotificationMechanism::Websocket,
+                    callback_url: None,
+                    bearer_token: None,
+                }],
+            },
+        }
+    }
+
+    fn matching_ids(
+        index: &SubscriptionIndex,
+        object: ObjectType,
+        program_id: Option<&str>,
+    ) -> Vec<String> {
+        let program_id: Option<ProgramId> = program_id.map(|id| id.parse().unwrap());
+        let mut ids: Vec<_> = index
+            .matching(object, program_id.as_ref())
+            .iter()
+            .map(|subscriber| subscriber.subscription.id.to_string())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn subscription_index() {
+        let mut index: SubscriptionIndex = [
+            subscription("all", None, vec![ObjectType::Event, ObjectType::Program]),
+            subscription("program-1", Some("program-1"), vec![ObjectType::Event]),
+            subscription("program-2", Some("program-2"), vec![ObjectType::Event]),
+        ]
+        .into_iter()
+        .map(|subscription| (subscription, vec![Scope::ReadAll]))
+        .collect();
+
+        assert_eq!(
+            matching_ids(&index, ObjectType::Event, Some("program-1")),
+            vec!["all", "program-1"]
+        );
+        assert_eq!(matching_ids(&index, ObjectType::Event, None), vec!["all"]);
+        assert_eq!(
+            matching_ids(&index, ObjectType::Program, Some("program-2")),
+            vec!["all"]
+        );
+        assert!(matching_ids(&index, ObjectType::Report, None).is_empty());
+
+        // updates replace the previous version in the index, but keep the scopes of the owner
+        index.update(subscription(
+            "program-1",
+            Some("program-2"),
+            vec![ObjectType::Event],
+        ));
+        assert_eq!(
+            matching_ids(&index, ObjectType::Event, Some("program-1")),
+            vec!["all"]
+        );
+        assert!(
+            index
+                .matching(ObjectType::Event, Some(&"program-2".parse().unwrap()))
+                .iter()
+                .all(|subscriber| subscriber.owner_scopes == [Scope::ReadAll])
+        );
+        assert_eq!(
+            matching_ids(&index, ObjectType::Event, Some("program-2")),
+            vec!["all", "program-1", "program-2"]
+        );
+
+        assert!(index.remove(&"all".parse().unwrap()).is_some());
+        assert!(index.remove(&"all".parse().unwrap()).is_none());
+        assert!(matching_ids(&index, ObjectType::Program, None).is_empty());
+        assert_eq!(
+            matching_ids(&index, ObjectType::Event, Some("program-2")),
+            vec!["program-1", "program-2"]
+        );
+    }
+
+    struct CountingPrivacy(std::sync::atomic::AtomicUsize);
+
+    #[async_trait]
+    impl VenObjectPrivacy for CountingPrivacy {
+        async fn targets_by_client_id(&self, _: &ClientId) -> Result<Vec<Target>, AppError> {
+            unimplemented!()
+        }
+
+        async fn resource_group_visible_for_client(
+            &self,
+            _: &ClientId,
+            _: &ResourceGroupId,
+        ) -> Result<bool, AppError> {
+            unimplemented!()
+        }
+
+        async fn ven_id_by_client_id(&self, _: &ClientId) -> Result<Option<VenId>, AppError> {
+            unimplemented!()
+        }
+
+        async fn all_ven_visibility(&self) -> Result<Vec<VenVisibility>, AppError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![VenVisibility {
+                ven_id: "ven-1".parse().unwrap(),
+                client_id: "client-1".parse().unwrap(),
+                targets: HashSet::from(["target-1".parse().unwrap()]),
+                resource_groups: HashSet::from(["group-1".parse().unwrap()]),
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn ven_visibility_cache() {
+        let source = CountingPrivacy(Default::default());
+        let cache = VenVisibilityCache::new();
+
+        let snapshot = cache.get(&source).await.unwrap();
+        let client_1 = "client-1".parse().unwrap();
+        let client_2 = "client-2".parse().unwrap();
+        assert_eq!(
+            snapshot.targets_by_client_id(&client_1).await.unwrap(),
+            vec!["target-1".parse().unwrap()]
+        );
+        assert!(snapshot.targets_by_client_id(&client_2).await.is_err());
+        assert!(
+            snapshot
+                .resource_group_visible_for_client(&client_1, &"group-1".parse().unwrap())
+                .await
+                .unwrap()
+        );
+        assert!(
+            !snapshot
+                .resource_group_visible_for_client(&client_2, &"group-1".parse().unwrap())
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            snapshot.ven_id_by_client_id(&client_1).await.unwrap(),
+            Some("ven-1".parse().unwrap())
+        );
+        assert_eq!(snapshot.ven_id_by_client_id(&client_2).await.unwrap(), None);
+
+        cache.get(&source).await.unwrap();
+        assert_eq!(source.0.load(Ordering::SeqCst), 1);
+
+        cache.invalidate().await;
+        cache.get(&source).await.unwrap();
+        assert_eq!(source.0.load(Ordering::SeqCst), 2);
+
+        // the snapshot is only loaded when it is used
+        let privacy = cache.privacy(&source);
+        cache.invalidate().await;
+        assert_eq!(source.0.load(Ordering::SeqCst), 2);
+        privacy.get().await;
+        privacy.get().await;
+        assert_eq!(source.0.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn mqtt_batch_merges_updates() {
+        let key = |id: &str| Some((ObjectType::Event, id.parse().unwrap()));
+        let mut batch = MqttBatch {
+            deadline: tokio::time::Instant::now(),
+            messages: Vec::new(),
+            origin: Origin::current(),
+        };
+        batch.push(None, b"1".to_vec());
+        batch.push(key("event-1"), b"2".to_vec());
+        batch.push(key("event-2"), b"3".to_vec());
+        batch.push(key("event-1"), b"4".to_vec());
+        batch.push(None, b"5".to_vec());
+
+        assert_eq!(batch.into_payload(), b"[1,4,3,5]");
+    }
+
+    #[tokio::test]
+    async fn slow_webhook_does_not_delay_other_callbacks() {
+        let (callback_url, mut notifications) = crate::api::test::webhook_receiver().await;
+
+        // accepts webhook calls, but never responds to them
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let slow_address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+
+        let workers = DeliveryWorkers::spawn(None, None, reqwest::Client::new());
+        let shard = workers.shard(&callback_url);
+        // callback URLs handled by the same worker as the responsive one
+        let slow_urls = (0..)
+            .map(|i| format!("http://{slow_address}/callback/{i}"))
+            .filter(|url| workers.shard(url) == shard)
+            .take(3);
+
+        let webhook = |callback_url: String| Delivery::Webhook {
+            subscription_id: "subscription-1".parse().unwrap(),
+            callback_url,
+            bearer_token: None,
+            notification: Box::new(Notification {
+                id: "notification-1".parse().unwrap(),
+                operation: Operation::Test,
+                object: AnyObject::Subscription(subscription("subscription-1", None, vec![])),
+            }),
+            batch_window: None,
+            origin: Origin::current(),
+        };
+        for slow_url in slow_urls {
+            workers.deliver(webhook(slow_url));
+        }
+        workers.deliver(webhook(callback_url));
+
+        let (_, notification) = tokio::time::timeout(Duration::from_secs(2), notifications.recv())
+            .await
+            .expect("webhook delayed by slow callbacks")
+            .unwrap();
+        assert_eq!(notification.operation, Operation::Test);
+    }
+}
@@ -0,0 +1,333 @@
+//! Liveness and readiness probes, e.g., for Kubernetes.
+//!
+//! Like `/health`, the probes do not require authentication.
+//! The readiness probe reports the state of every dependency of the VTN,
+//! so its response is also suitable for a status page.
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use axum::{Json, extract::State};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{data_source::SchemaVersion, state::AppState};
+
+/// A dependency that did not respond within this time counts as down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Up,
+    Down,
+}
+
+impl Status {
+    fn from_up(up: bool) -> Self {
+        if up { Status::Up } else { Status::Down }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Liveness {
+    pub(crate) status: Status,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Readiness {
+    /// `up` if all checks are `up`
+    pub(crate) status: Status,
+    pub(crate) checks: Checks,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Checks {
+    pub(crate) database: Check,
+    pub(crate) migrations: MigrationsCheck,
+    /// `None` if MQTT is not configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) mqtt: Option<Check>,
+    /// `None` if the tokens are not validated with keys from a JWKS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) jwks: Option<Check>,
+    pub(crate) notifier: NotifierCheck,
+}
+
+impl Checks {
+    fn status(&self) -> Status {
+        let statuses = [
+            Some(self.database.status),
+            Some(self.migrations.status),
+            self.mqtt.as_ref().map(|mqtt| mqtt.status),
+            self.jwks.as_ref().map(|jwks| jwks.status),
+            Some(self.notifier.status),
+        ];
+        Status::from_up(statuses.into_iter().flatten().all(|s| s == Status::Up))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Check {
+    pub(crate) status: Status,
+    /// Round trip time of the check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl Check {
+    fn new<T>(result: Result<T, String>, latency: Option<Duration>) -> Self {
+        Self {
+            status: Status::from_up(result.is_ok()),
+            latency_ms: latency.map(|latency| latency.as_secs_f64() * 1000.0),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MigrationsCheck {
+    pub(crate) status: Status,
+    /// Version of the last applied migration
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) applied: Option<i64>,
+    /// Version of the last migration this VTN ships with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) latest: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl From<Result<SchemaVersion, String>> for MigrationsCheck {
+    fn from(result: Result<SchemaVersion, String>) -> Self {
+        let version = match result {
+            Ok(version) => version,
+            Err(error) => {
+                return Self {
+                    status: Status::Down,
+                    applied: None,
+                    latest: None,
+                    error: Some(error),
+                };
+            }
+        };
+
+        let error = version.incompatibility();
+        Self {
+            status: Status::from_up(error.is_none()),
+            applied: version.applied,
+            latest: Some(version.latest),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotifierCheck {
+    pub(crate) status: Status,
+    /// Subscriptions loaded into the notifier
+    pub(crate) subscriptions: usize,
+}
+
+/// Runs a check, failing it if it takes longer than [`CHECK_TIMEOUT`]
+async fn run<T, E: Display>(
+    check: impl Future<Output = Result<T, E>>,
+) -> (Result<T, String>, Duration) {
+    let start = Instant::now();
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(_) => Err(format!(
+            "no response within {} seconds",
+            CHECK_TIMEOUT.as_secs()
+        )),
+    };
+    (result, start.elapsed())
+}
+
+/// Handles `GET /health/live`.
+/// The VTN is alive as long as it responds, regardless of its dependencies,
+/// such that an unavailable database does not cause restarts.
+pub(crate) async fn live() -> Json<Liveness> {
+    Json(Liveness { status: Status::Up })
+}
+
+/// Handles `GET /health/ready`, responding with `503 Service Unavailable` if any check is down
+pub(crate) async fn ready(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let statistics = state.storage.statistics();
+    let jwks = async {
+        match state.jwt_manager.check_jwks() {
+            Some(check) => Some(run(check).await),
+            None => None,
+        }
+    };
+
+    let ((database, database_latency), (schema_version, _), jwks, subscriptions) = tokio::join!(
+        run(statistics.ping()),
+        run(statistics.schema_version()),
+        jwks,
+        state.notifier.subscription_count(),
+    );
+
+    let checks = Checks {
+        database: Check::new(database, Some(database_latency)),
+        migrations: schema_version.into(),
+        mqtt: state.notifier.mqtt_connection().map(|(_, connected)| {
+            let result = if connected {
+                Ok(())
+            } else {
+                Err("not connected to the broker".to_string())
+            };
+            Check::new(result, None)
+        }),
+        jwks: jwks.map(|(result, latency)| Check::new(result, Some(latency))),
+        // notifications of new subscriptions or after shutting down would not reach subscribers
+        notifier: NotifierCheck {
+            status: Status::from_up(
+                state.notifier.is_loaded() && !state.notifier.is_shutting_down(),
+            ),
+            subscriptions,
+        },
+    };
+
+    let status = checks.status();
+    if status == Status::Down {
+        warn!(?checks, "VTN is not ready");
+    }
+    let code = match status {
+        Status::Up => StatusCode::OK,
+        Status::Down => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (code, Json(Readiness { status, checks }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt;
+    use jsonwebtoken::Validation;
+    use reqwest::Method;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{api::test::ApiTest, config::OAuthKeyType, jwt::JwtManager};
+
+    async fn probe(state: AppState, path: &str) -> (StatusCode, serde_json::Value) {
+        let response = state
+            .into_router()
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[sqlx::test]
+    async fn ready(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![]).await;
+
+        let (status, live) = probe(test.state().clone(), "/health/live").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(live["status"], "up");
+
+        let (status, ready) = probe(test.state().clone(), "/health/ready").await;
+        assert_eq!(status, StatusCode::OK, "{ready}");
+        let ready: Readiness = serde_json::from_value(ready).unwrap();
+        assert_eq!(ready.status, Status::Up);
+        assert_eq!(ready.checks.database.status, Status::Up);
+        assert!(ready.checks.database.latency_ms.is_some());
+        assert_eq!(
+            ready.checks.migrations.applied,
+            ready.checks.migrations.latest
+        );
+        assert!(ready.checks.jwks.is_none());
+        assert_eq!(ready.checks.notifier.status, Status::Up);
+        assert_eq!(ready.checks.notifier.subscriptions, 0);
+        if let Some(mqtt) = ready.checks.mqtt {
+            assert_eq!(mqtt.status, Status::Up);
+        }
+
+        // the old endpoint is kept
+        assert_eq!(
+            test.empty_request(Method::GET, "/health").await,
+            StatusCode::OK
+        );
+    }
+
+    #[sqlx::test]
+    async fn pending_migrations(db: PgPool) {
+        let test = ApiTest::new(db.clone(), "test-client", vec![]).await;
+        let latest: i64 = sqlx::query_scalar("SELECT max(version) FROM _sqlx_migrations")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+            .bind(latest)
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let (status, ready) = probe(test.state().clone(), "/health/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ready["status"], "down");
+        assert_eq!(ready["checks"]["migrations"]["status"], "down");
+        assert_eq!(
+            ready["checks"]["migrations"]["error"],
+            format!("migration {latest} is pending")
+        );
+        assert_eq!(ready["checks"]["database"]["status"], "up");
+
+        let (status, _) = probe(test.state().clone(), "/health/live").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn unreachable_jwks(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![]).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let jwks_location = format!("http://{}/jwks", listener.local_addr().unwrap());
+        // nothing listens on the port anymore
+        drop(listener);
+
+        let mut state = test.state().clone();
+        state.jwt_manager = Arc::new(JwtManager::new(
+            None,
+            Some(jwks_location),
+            OAuthKeyType::Rsa,
+            Validation::default(),
+            "http://localhost/token".parse().unwrap(),
+        ));
+
+        let (status, ready) = probe(state, "/health/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ready["checks"]["jwks"]["status"], "down");
+        assert!(ready["checks"]["jwks"]["error"].is_string());
+        assert_eq!(ready["checks"]["database"]["status"], "up");
+    }
+
+    #[sqlx::test]
+    async fn shutting_down(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![]).await;
+        assert!(test.state().notifier.is_loaded());
+
+        test.state().notifier.stop_accepting().await;
+        let (status, ready) = probe(test.state().clone(), "/health/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ready["checks"]["notifier"]["status"], "down");
+        assert_eq!(ready["checks"]["database"]["status"], "up");
+    }
+}
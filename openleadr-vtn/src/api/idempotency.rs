@@ -0,0 +1,337 @@
+//! Safe retries of create requests with an `Idempotency-Key` header.
+//!
+//! The first request with a key reserves the key for its client, together with a fingerprint
+//! of the method, path and body of the request.
+//! If the request succeeds, its response is stored for the configured `IDEMPOTENCY_WINDOW`
+//! and replayed for each retry of the same request with the same key,
+//! marked by an `Idempotent-Replayed: true` header.
+//! Using the key for another request, or retrying while the first request is still
+//! being processed, is rejected.
+//! Failed requests release the key, so they can be retried with it.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderName, HeaderValue, StatusCode, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use openleadr_wire::ClientId;
+use sha2::{Digest, Sha256};
+use tracing::{error, trace, warn};
+
+use crate::{
+    data_source::{IdempotencySource, IdempotencyState, StoredResponse},
+    error::AppError,
+    jwt::User,
+    state::AppState,
+};
+
+pub(crate) static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+pub(crate) static IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LENGTH: usize = 255;
+/// Request bodies are buffered up to this size, the limit of the JSON extractor
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+/// Time between two deletions of expired keys
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+fn is_valid(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Middleware replaying the stored response if a request with the same `Idempotency-Key`
+/// succeeded before, and storing the response otherwise.
+/// Requests without the header are passed through.
+pub(crate) async fn middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(key) = request.headers().get(&IDEMPOTENCY_KEY) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| is_valid(key))
+        .ok_or(AppError::BadRequest(
+            "Idempotency-Key must consist of 1 to 255 visible ASCII characters",
+        ))?
+        .to_string();
+
+    let (mut parts, body) = request.into_parts();
+    // keys are scoped per client, unauthenticated requests fail here like in the handler
+    let User(user) = User::from_request_parts(&mut parts, &state).await?;
+    let client_id = user.client_id()?;
+    let body = to_bytes(body, MAX_BODY_SIZE)
+        .await
+        .map_err(|_| AppError::BadRequest("Request body too large"))?;
+
+    let store = state.storage.idempotency();
+    let fingerprint = fingerprint(&parts, &body);
+    match store
+        .begin(
+            &client_id,
+            &key,
+            &fingerprint,
+            expires(state.idempotency_window),
+        )
+        .await?
+    {
+        IdempotencyState::New => {}
+        IdempotencyState::InProgress => return Err(AppError::RequestInProgress),
+        IdempotencyState::Mismatch => return Err(AppError::IdempotencyKeyReused),
+        IdempotencyState::Completed(response) => {
+            trace!(%client_id, key, "replaying response for idempotency key");
+            return Ok(replay(response));
+        }
+    }
+
+    let reservation = Reservation {
+        store,
+        client_id,
+        key,
+        done: false,
+    };
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !response.status().is_success() {
+        reservation.release().await;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let Ok(body) = to_bytes(body, usize::MAX).await else {
+        error!("Could not buffer the response to store it for the idempotency key");
+        reservation.release().await;
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        headers: parts
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: body.to_vec(),
+    };
+    reservation.complete(&stored).await;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Hash of what makes up a request, to tell a retry from another request with the same key
+fn fingerprint(parts: &Parts, body: &Bytes) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str());
+    hasher.update(b"\n");
+    hasher.update(
+        parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or_default(),
+    );
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+fn expires(window: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(window)
+        .ok()
+        .and_then(|window| Utc::now().checked_add_signed(window))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let headers = response.headers_mut();
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(
+        IDEMPOTENT_REPLAYED.clone(),
+        HeaderValue::from_static("true"),
+    );
+    response
+}
+
+/// A key reserved for the request being processed.
+/// Released if the request is cancelled, e.g., because the client disconnected.
+struct Reservation {
+    store: Arc<dyn IdempotencySource>,
+    client_id: ClientId,
+    key: String,
+    done: bool,
+}
+
+impl Reservation {
+    async fn complete(mut self, response: &StoredResponse) {
+        if let Err(err) = self
+            .store
+            .complete(&self.client_id, &self.key, response)
+            .await
+        {
+            // the next retry creates the object again, as if no key was given
+            warn!(client_id = %self.client_id, key = self.key, "Could not store response for idempotency key: {}", err);
+            return;
+        }
+        self.done = true;
+    }
+
+    async fn release(mut self) {
+        if let Err(err) = self.store.release(&self.client_id, &self.key).await {
+            warn!(client_id = %self.client_id, key = self.key, "Could not release idempotency key: {}", err);
+        }
+        self.done = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let (store, client_id, key) = (
+            self.store.clone(),
+            self.client_id.clone(),
+            std::mem::take(&mut self.key),
+        );
+        tokio::spawn(async move {
+            if let Err(err) = store.release(&client_id, &key).await {
+                warn!(%client_id, key, "Could not release idempotency key: {}", err);
+            }
+        });
+    }
+}
+
+/// Deletes the expired keys until the application terminates
+pub(crate) async fn run_cleanup(state: AppState) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        if let Err(err) = state.storage.idempotency().delete_expired().await {
+            warn!("Could not delete expired idempotency keys: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{self, Method, Request, StatusCode, header},
+        response::Response,
+    };
+    use http_body_util::BodyExt;
+    use openleadr_wire::{
+        Program,
+        problem::{ErrorCode, Problem},
+    };
+    use serde::de::DeserializeOwned;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use super::{IDEMPOTENCY_KEY, IDEMPOTENT_REPLAYED};
+    use crate::{api::test::ApiTest, jwt::Scope};
+
+    async fn post(test: &ApiTest, key: &str, body: serde_json::Value) -> Response {
+        test.state()
+            .clone()
+            .into_router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/programs")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", test.token()))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .header(&IDEMPOTENCY_KEY, key)
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn json<T: DeserializeOwned>(response: Response) -> T {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn programs(test: &ApiTest) -> Vec<Program> {
+        let (status, programs) = test
+            .request::<Vec<Program>>(Method::GET, "/programs", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        programs
+    }
+
+    #[sqlx::test]
+    async fn retry_returns_original_object(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WritePrograms, Scope::ReadAll]).await;
+        let body = serde_json::json!({"programName": "program-1"});
+
+        let response = post(&test, "key-1", body.clone()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(!response.headers().contains_key(&IDEMPOTENT_REPLAYED));
+        let created: Program = json(response).await;
+
+        let response = post(&test, "key-1", body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[&IDEMPOTENT_REPLAYED], "true");
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            mime::APPLICATION_JSON.as_ref()
+        );
+        let replayed: Program = json(response).await;
+        assert_eq!(replayed, created);
+
+        assert_eq!(programs(&test).await, vec![created]);
+    }
+
+    #[sqlx::test]
+    async fn key_cannot_be_reused_for_another_request(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WritePrograms, Scope::ReadAll]).await;
+
+        let response = post(&test, "key-1", serde_json::json!({"programName": "a"})).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = post(&test, "key-1", serde_json::json!({"programName": "b"})).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Problem = json(response).await;
+        assert_eq!(problem.code, Some(ErrorCode::IdempotencyKeyReused));
+        assert_eq!(programs(&test).await.len(), 1);
+    }
+
+    #[sqlx::test]
+    async fn failed_request_releases_key(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WritePrograms, Scope::ReadAll]).await;
+
+        let response = post(&test, "key-1", serde_json::json!({"programName": ""})).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = post(&test, "key-1", serde_json::json!({"programName": "a"})).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(!response.headers().contains_key(&IDEMPOTENT_REPLAYED));
+    }
+
+    #[sqlx::test]
+    async fn invalid_key(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WritePrograms, Scope::ReadAll]).await;
+
+        for key in ["", &"k".repeat(256), "with space"] {
+            let response = post(&test, key, serde_json::json!({"programName": "a"})).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{key}");
+        }
+        assert!(programs(&test).await.is_empty());
+    }
+}
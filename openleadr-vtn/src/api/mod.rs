@@ -7,17 +7,29 @@ use axum::{
 use axum_extra::extract::{Query, QueryRejection};
 use openleadr_wire::target::Target;
 use reqwest::StatusCode;
-use serde::{Deserialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::fmt::Debug;
 use validator::Validate;
 
+pub(crate) mod attributes;
 pub(crate) mod auth;
+pub(crate) mod batch;
+pub(crate) mod compliance;
+pub(crate) mod descriptors;
 pub(crate) mod event;
+pub(crate) mod event_lifecycle;
+pub(crate) mod fanout;
+pub(crate) mod health;
+pub(crate) mod idempotency;
+pub(crate) mod mqtt_auth;
+pub(crate) mod notifier_admin;
+pub(crate) mod pagination;
 pub(crate) mod program;
 pub(crate) mod report;
 pub(crate) mod resource;
 pub(crate) mod resource_group;
 pub(crate) mod subscription;
+pub(crate) mod subscription_cleanup;
 #[cfg(feature = "internal-oauth")]
 pub(crate) mod user;
 pub(crate) mod ven;
@@ -34,6 +46,61 @@ pub(crate) struct ValidatedQuery<T>(pub T);
 #[derive(Debug, Clone)]
 pub(crate) struct ValidatedJson<T>(pub T);
 
+/// Body of a `PATCH` request, chosen by its `Content-Type`
+#[derive(Debug, Clone)]
+pub(crate) enum Patch {
+    /// `application/merge-patch+json`, see RFC 7396
+    Merge(serde_json::Value),
+    /// `application/json-patch+json`, see RFC 6902
+    Json(json_patch::Patch),
+}
+
+pub(crate) const MERGE_PATCH: &str = "application/merge-patch+json";
+pub(crate) const JSON_PATCH: &str = "application/json-patch+json";
+
+impl Patch {
+    /// Applies the patch to the JSON representation of `content`,
+    /// and validates the result like the body of a `PUT` request
+    pub(crate) fn apply<T>(&self, content: &T) -> Result<T, AppError>
+    where
+        T: Serialize + DeserializeOwned + Validate,
+    {
+        let mut value =
+            serde_json::to_value(content).map_err(|err| AppError::PatchFailed(err.to_string()))?;
+        match self {
+            Patch::Merge(patch) => json_patch::merge(&mut value, patch),
+            Patch::Json(patch) => json_patch::patch(&mut value, patch)
+                .map_err(|err| AppError::PatchFailed(err.to_string()))?,
+        }
+        let patched: T =
+            serde_json::from_value(value).map_err(|err| AppError::PatchFailed(err.to_string()))?;
+        patched.validate()?;
+        Ok(patched)
+    }
+}
+
+impl<S> FromRequest<S> for Patch
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<mime::Mime>().ok());
+        match content_type.as_ref().map(mime::Mime::essence_str) {
+            Some(MERGE_PATCH) => Ok(Patch::Merge(Json::from_request(req, state).await?.0)),
+            Some(JSON_PATCH) => Ok(Patch::Json(Json::from_request(req, state).await?.0)),
+            _ => Err(AppError::UnsupportedMediaType(format!(
+                "Expected request with `Content-Type: {MERGE_PATCH}` or `{JSON_PATCH}`"
+            ))),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 #[serde(transparent)]
@@ -106,12 +173,12 @@ pub async fn healthcheck(State(app_state): State<AppState>) -> Result<impl IntoR
 pub mod test {
     use crate::{VtnConfig, data_source::PostgresStorage, jwt::Scope, state::AppState};
     use axum::{
-        Router,
+        Json, Router,
         body::Body,
         http::{self, Request, StatusCode},
     };
     use http_body_util::BodyExt;
-    use openleadr_wire::problem::Problem;
+    use openleadr_wire::{problem::Problem, subscription::Notification};
     use reqwest::Method;
     use serde::de::DeserializeOwned;
     use sqlx::PgPool;
@@ -125,16 +192,25 @@ pub mod test {
 
     pub(crate) struct ApiTest {
         vtn_config: VtnConfig,
+        state: AppState,
         router: Router,
         token: String,
     }
 
     impl ApiTest {
         pub(crate) async fn new(db: PgPool, client_id: impl Display, scope: Vec<Scope>) -> Self {
+            Self::with_config(db, VtnConfig::from_env().unwrap(), client_id, scope).await
+        }
+
+        pub(crate) async fn with_config(
+            db: PgPool,
+            mut vtn_config: VtnConfig,
+            client_id: impl Display,
+            scope: Vec<Scope>,
+        ) -> Self {
             let store = PostgresStorage::new(db).unwrap();
-            let mut vtn_config = VtnConfig::from_env();
             vtn_config.mqtt_topic_prefix = uuid::Uuid::new_v4().to_string() + "/";
-            let app_state = AppState::new(store, &vtn_config).await;
+            let app_state = AppState::new(store, &vtn_config).await.unwrap();
 
             let token = app_state
                 .jwt_manager
@@ -145,10 +221,11 @@ pub mod test {
                 )
                 .unwrap();
 
-            let router = app_state.into_router();
+            let router = app_state.clone().into_router();
 
             Self {
                 vtn_config,
+                state: app_state,
                 router,
                 token,
             }
@@ -225,6 +302,14 @@ pub mod test {
         pub(crate) fn vtn_config(&self) -> &VtnConfig {
             &self.vtn_config
         }
+
+        pub(crate) fn token(&self) -> &str {
+            &self.token
+        }
+
+        pub(crate) fn state(&self) -> &AppState {
+            &self.state
+        }
     }
 
     #[cfg(feature = "internal-oauth")]
@@ -243,9 +328,45 @@ pub mod test {
             .unwrap()
     }
 
+    /// Starts an HTTP server that accepts webhook notifications at the returned URL
+    /// and forwards their `Authorization` header and body to the returned receiver.
+    pub(crate) async fn webhook_receiver() -> (
+        String,
+        tokio::sync::mpsc::UnboundedReceiver<(Option<String>, Notification)>,
+    ) {
+        webhook_receiver_of().await
+    }
+
+    /// Like [`webhook_receiver`], for webhooks with other bodies than a single notification
+    pub(crate) async fn webhook_receiver_of<T: DeserializeOwned + Send + 'static>() -> (
+        String,
+        tokio::sync::mpsc::UnboundedReceiver<(Option<String>, T)>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let router = Router::new().route(
+            "/callback",
+            axum::routing::post(
+                move |headers: http::HeaderMap, Json(notification): Json<T>| async move {
+                    let authorization = headers
+                        .get(http::header::AUTHORIZATION)
+                        .map(|value| value.to_str().unwrap().to_owned());
+                    let _ = tx.send((authorization, notification));
+                },
+            ),
+        );
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .await
+            .unwrap();
+        let url = format!("http://{}/callback", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, router).into_future());
+        (url, rx)
+    }
+
     pub(crate) async fn state(db: PgPool) -> AppState {
         let store = PostgresStorage::new(db).unwrap();
-        AppState::new(store, &VtnConfig::from_env()).await
+        AppState::new(store, &VtnConfig::from_env().unwrap())
+            .await
+            .unwrap()
     }
 
     #[sqlx::test]
@@ -314,6 +435,261 @@ pub mod test {
         assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
+    #[sqlx::test(fixtures("programs"))]
+    async fn base_paths(db: PgPool) {
+        let config = VtnConfig {
+            base_path: "/openadr3/3.1.0".to_string(),
+            additional_base_paths: vec!["/openadr3/3.0.1".to_string()],
+            ..VtnConfig::from_env().unwrap()
+        };
+        let test = ApiTest::with_config(db, config, "test-client", vec![Scope::ReadAll]).await;
+
+        for path in [
+            "/openadr3/3.1.0/programs/program-1",
+            "/openadr3/3.0.1/programs/program-1",
+            "/health/ready",
+        ] {
+            assert_eq!(test.empty_request(Method::GET, path).await, StatusCode::OK);
+        }
+        for path in ["/programs/program-1", "/openadr3/3.1.0/health/ready"] {
+            assert_eq!(
+                test.empty_request(Method::GET, path).await,
+                StatusCode::NOT_FOUND
+            );
+        }
+    }
+
+    async fn patch(test: &ApiTest, content_type: &str, body: serde_json::Value) -> StatusCode {
+        patch_at(test, "/programs/program-1", content_type, body).await
+    }
+
+    async fn patch_at(
+        test: &ApiTest,
+        path: &str,
+        content_type: &str,
+        body: serde_json::Value,
+    ) -> StatusCode {
+        test.router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::PATCH)
+                    .uri(path)
+                    .header(
+                        http::header::AUTHORIZATION,
+                        format!("Bearer {}", test.token),
+                    )
+                    .header(http::header::CONTENT_TYPE, content_type)
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn patch_program(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "test-client",
+            vec![Scope::ReadAll, Scope::WritePrograms],
+        )
+        .await;
+
+        let status = patch(
+            &test,
+            super::MERGE_PATCH,
+            serde_json::json!({"programName": "patched", "attributes": null}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let status = patch(
+            &test,
+            super::JSON_PATCH,
+            serde_json::json!([{"op": "add", "path": "/targets/-", "value": "new-target"}]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, program) = test
+            .request::<openleadr_wire::Program>(Method::GET, "/programs/program-1", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(program.content.program_name, "patched");
+        assert_eq!(program.content.attributes, None);
+        assert_eq!(
+            program.content.targets.last().map(|target| target.as_str()),
+            Some("new-target")
+        );
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn interleaved_patches_are_not_lost(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "test-client",
+            vec![Scope::ReadAll, Scope::WritePrograms],
+        )
+        .await;
+
+        // holding the lock makes both patches wait for it, such that they run at the same time
+        let lock = test
+            .state()
+            .storage
+            .programs()
+            .lock(&"program-1".parse().unwrap())
+            .await
+            .unwrap();
+        let release = async move {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            drop(lock);
+        };
+        let patches = async {
+            tokio::join!(
+                patch(
+                    &test,
+                    super::JSON_PATCH,
+                    serde_json::json!([{"op": "add", "path": "/targets/-", "value": "target-a"}]),
+                ),
+                patch(
+                    &test,
+                    super::JSON_PATCH,
+                    serde_json::json!([{"op": "add", "path": "/targets/-", "value": "target-b"}]),
+                ),
+            )
+        };
+        let ((status_a, status_b), ()) = tokio::join!(patches, release);
+        assert_eq!(status_a, StatusCode::OK);
+        assert_eq!(status_b, StatusCode::OK);
+
+        let (_, program) = test
+            .request::<openleadr_wire::Program>(Method::GET, "/programs/program-1", Body::empty())
+            .await;
+        let targets: Vec<_> = program
+            .content
+            .targets
+            .iter()
+            .map(|target| target.as_str())
+            .collect();
+        assert!(targets.contains(&"target-a"), "{targets:?}");
+        assert!(targets.contains(&"target-b"), "{targets:?}");
+    }
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn interleaved_event_patches_are_not_lost(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![Scope::ReadAll, Scope::WriteEvents]).await;
+
+        // both patches read the event and check it against its program before waiting for the lock,
+        // so the second one has to apply its patch again to the event the first one wrote
+        let lock = test
+            .state()
+            .storage
+            .events()
+            .lock(&"event-1".parse().unwrap())
+            .await
+            .unwrap();
+        let release = async move {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            drop(lock);
+        };
+        let add_target = |target: &str| {
+            patch_at(
+                &test,
+                "/events/event-1",
+                super::JSON_PATCH,
+                serde_json::json!([{"op": "add", "path": "/targets/-", "value": target}]),
+            )
+        };
+        let ((status_a, status_b), ()) = tokio::join!(
+            async { tokio::join!(add_target("target-a"), add_target("target-b")) },
+            release
+        );
+        assert_eq!(status_a, StatusCode::OK);
+        assert_eq!(status_b, StatusCode::OK);
+
+        let (_, event) = test
+            .request::<openleadr_wire::Event>(Method::GET, "/events/event-1", Body::empty())
+            .await;
+        let targets: Vec<_> = event
+            .content
+            .targets
+            .iter()
+            .map(|target| target.as_str())
+            .collect();
+        assert!(targets.contains(&"target-a"), "{targets:?}");
+        assert!(targets.contains(&"target-b"), "{targets:?}");
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn patch_without_scope_does_not_wait_for_lock(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![Scope::ReadAll]).await;
+
+        let _lock = test
+            .state()
+            .storage
+            .programs()
+            .lock(&"program-1".parse().unwrap())
+            .await
+            .unwrap();
+        let status = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            patch(
+                &test,
+                super::MERGE_PATCH,
+                serde_json::json!({"programName": "patched"}),
+            ),
+        )
+        .await
+        .expect("the scope is checked before waiting for the lock");
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn patch_program_rejected(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "test-client",
+            vec![Scope::ReadAll, Scope::WritePrograms],
+        )
+        .await;
+
+        // the result must be a valid program
+        let status = patch(
+            &test,
+            super::MERGE_PATCH,
+            serde_json::json!({"programName": ""}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // failing `test` operations reject the whole patch
+        let status = patch(
+            &test,
+            super::JSON_PATCH,
+            serde_json::json!([
+                {"op": "replace", "path": "/programName", "value": "patched"},
+                {"op": "test", "path": "/programName", "value": "other"},
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let status = patch(
+            &test,
+            mime::APPLICATION_JSON.as_ref(),
+            serde_json::json!({"programName": "patched"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let (_, program) = test
+            .request::<openleadr_wire::Program>(Method::GET, "/programs/program-1", Body::empty())
+            .await;
+        assert_eq!(program.content.program_name, "program-1");
+    }
+
     #[sqlx::test]
     async fn healthcheck(db: PgPool) {
         let test = ApiTest::new(db.clone(), "test-client", vec![]).await;
@@ -0,0 +1,858 @@
+//! HTTP authentication and access control hooks for MQTT brokers.
+//!
+//! Brokers such as Mosquitto with the [go-auth](https://github.com/iegomez/mosquitto-go-auth)
+//! plugin or EMQX with its HTTP authenticator and authorizer can delegate the access control
+//! to the VTN by calling these endpoints.
+//! Clients authenticate to the broker with their OAuth client ID as username and a VTN bearer
+//! token as password, as advertised by the `/notifiers` endpoint.
+//! Business logic clients with the `read_all` scope may subscribe to all topics,
+//! VEN clients only to the topics of their own VEN.
+//! Only the account the VTN itself uses to connect to the broker may publish.
+//! The broker authenticates to the hooks with a shared secret, without it they are disabled.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    extract::{FromRef, FromRequestParts, Query, State},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{Authorization, authorization::Bearer},
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace};
+
+use openleadr_wire::ven::VenId;
+
+use crate::{
+    api::subscription::NotifierState,
+    data_source::VenObjectPrivacy,
+    error::AppError,
+    jwt::{Claims, JwtManager, Scope},
+    state::AppState,
+};
+
+pub(crate) fn router() -> axum::Router<AppState> {
+    axum::Router::new()
+        .route("/user", post(user))
+        .route("/superuser", post(superuser))
+        .route("/acl", post(acl))
+}
+
+/// A request of the MQTT broker, which sends the shared secret of the hooks either as bearer
+/// token or, for brokers that cannot set headers, as `secret` query parameter.
+/// The hooks are not found if MQTT or the secret is not configured.
+struct Broker;
+
+#[derive(Deserialize)]
+struct BrokerQuery {
+    secret: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Broker
+where
+    Arc<NotifierState>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let notifier_state = Arc::<NotifierState>::from_ref(state);
+        let Some(expected) = notifier_state.mqtt_auth_hook_secret() else {
+            return Err(AppError::NotFound);
+        };
+
+        let secret = match Query::<BrokerQuery>::try_from_uri(&parts.uri) {
+            Ok(Query(BrokerQuery {
+                secret: Some(secret),
+            })) => Some(secret),
+            _ => TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .ok()
+                .map(|TypedHeader(bearer)| bearer.token().to_string()),
+        };
+
+        if secret.is_some_and(|secret| secrets_match(secret.as_bytes(), expected.as_bytes())) {
+            Ok(Broker)
+        } else {
+            Err(AppError::Auth(
+                "MQTT auth hooks require the secret of the broker".to_string(),
+            ))
+        }
+    }
+}
+
+/// Compares in constant time for secrets of the same length
+fn secrets_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |diff, (given, expected)| diff | (given ^ expected))
+            == 0
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct MqttUserRequest {
+    username: String,
+    password: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct MqttAclRequest {
+    username: String,
+    /// Not sent by most brokers on access checks. If present, it is validated again instead of
+    /// relying on the claims the client authenticated with.
+    password: Option<String>,
+    topic: String,
+    /// Access as sent by mosquitto-go-auth: 1 = read, 2 = write, 3 = read and write, 4 = subscribe
+    acc: Option<u8>,
+    /// Access as sent by EMQX: `publish`, `subscribe` or `all`
+    action: Option<String>,
+}
+
+/// Both the `result` field used by EMQX and the `ok` field used by mosquitto-go-auth
+/// are included in the response, such that either broker can use the JSON response mode.
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct MqttAuthResponse {
+    result: MqttAuthResult,
+    ok: bool,
+    is_superuser: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    #[serde(skip)]
+    deny_status: StatusCode,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MqttAuthResult {
+    Allow,
+    Deny,
+}
+
+impl MqttAuthResponse {
+    fn allow(is_superuser: bool) -> Self {
+        Self {
+            result: MqttAuthResult::Allow,
+            ok: true,
+            is_superuser,
+            error: None,
+            deny_status: StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn deny(reason: &'static str) -> Self {
+        Self {
+            result: MqttAuthResult::Deny,
+            ok: false,
+            is_superuser: false,
+            error: Some(reason),
+            deny_status: StatusCode::FORBIDDEN,
+        }
+    }
+
+    /// Denies with `200 OK` and only the `result` in the body.
+    /// EMQX ignores authorizer responses with another status and applies its
+    /// `authorization.no_match` setting instead.
+    fn deny_in_body(self) -> Self {
+        Self {
+            deny_status: StatusCode::OK,
+            ..self
+        }
+    }
+}
+
+impl IntoResponse for MqttAuthResponse {
+    fn into_response(self) -> Response {
+        let status = match self.result {
+            MqttAuthResult::Allow => StatusCode::OK,
+            MqttAuthResult::Deny => self.deny_status,
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum MqttAccess {
+    Subscribe,
+    Publish,
+}
+
+impl MqttAccess {
+    fn from_request(acc: Option<u8>, action: Option<&str>) -> Option<Self> {
+        match (acc, action) {
+            (Some(1 | 4), _) => Some(Self::Subscribe),
+            (Some(2 | 3), _) => Some(Self::Publish),
+            (None, Some("subscribe")) => Some(Self::Subscribe),
+            (None, Some("publish" | "all")) => Some(Self::Publish),
+            _ => None,
+        }
+    }
+}
+
+/// Most MQTT sessions remembered at once.
+/// Brokers that do not send the password on access checks need the claims of each client.
+const MAX_MQTT_SESSIONS: usize = 10_000;
+
+/// Claims of MQTT clients the broker authenticated through the auth hook, by username.
+/// Expired sessions are dropped on each insert and when they are looked up.
+/// With [`MAX_MQTT_SESSIONS`] left, the session expiring first makes room.
+#[derive(Debug, Default)]
+pub(crate) struct MqttSessions {
+    claims: HashMap<String, Claims>,
+    /// The usernames of all sessions, ordered by the expiration of their claims
+    expirations: BTreeSet<(i64, String)>,
+}
+
+impl MqttSessions {
+    pub(crate) fn insert(&mut self, username: String, claims: Claims) {
+        self.remove(&username);
+
+        let now = chrono::Utc::now().timestamp();
+        while self.expirations.first().is_some_and(|(exp, _)| *exp < now)
+            || self.claims.len() >= MAX_MQTT_SESSIONS
+        {
+            let Some((_, first)) = self.expirations.pop_first() else {
+                break;
+            };
+            self.claims.remove(&first);
+        }
+
+        self.expirations
+            .insert((claims.expiration(), username.clone()));
+        self.claims.insert(username, claims);
+    }
+
+    /// Returns the claims of an MQTT client that authenticated earlier, if they did not expire yet
+    pub(crate) fn get(&mut self, username: &str) -> Option<Claims> {
+        match self.claims.get(username) {
+            Some(claims) if claims.is_expired() => {
+                self.remove(username);
+                None
+            }
+            claims => claims.cloned(),
+        }
+    }
+
+    fn remove(&mut self, username: &str) {
+        if let Some(claims) = self.claims.remove(username) {
+            self.expirations
+                .remove(&(claims.expiration(), username.to_string()));
+        }
+    }
+}
+
+enum MqttPrincipal {
+    /// The account the VTN uses to publish notifications
+    Vtn,
+    Client(Claims),
+}
+
+async fn authenticate(
+    notifier_state: &NotifierState,
+    jwt_manager: &JwtManager,
+    username: &str,
+    password: &str,
+) -> Result<MqttPrincipal, &'static str> {
+    if notifier_state.is_vtn_mqtt_account(username, Some(password)) {
+        return Ok(MqttPrincipal::Vtn);
+    }
+
+    let claims = jwt_manager
+        .decode_and_validate(password)
+        .await
+        .map_err(|_| "invalid bearer token")?;
+
+    // The username of the MQTT binding is the distinguished string "{clientID}",
+    // see the `/notifiers` endpoint
+    if claims.sub != username {
+        return Err("username does not match the subject of the bearer token");
+    }
+
+    // Checks without a password identify the VTN by its username alone,
+    // so no client may hold a session under that name
+    if notifier_state.is_vtn_mqtt_account(username, None) {
+        return Err("username is reserved for the VTN");
+    }
+
+    Ok(MqttPrincipal::Client(claims))
+}
+
+async fn user(
+    _: Broker,
+    State(notifier_state): State<Arc<NotifierState>>,
+    State(jwt_manager): State<Arc<JwtManager>>,
+    Json(request): Json<MqttUserRequest>,
+) -> Result<MqttAuthResponse, AppError> {
+    let Some(password) = request.password else {
+        return Ok(MqttAuthResponse::deny("missing password"));
+    };
+
+    match authenticate(&notifier_state, &jwt_manager, &request.username, &password).await {
+        Ok(MqttPrincipal::Vtn) => Ok(MqttAuthResponse::allow(true)),
+        Ok(MqttPrincipal::Client(claims)) => {
+            trace!(username = request.username, "MQTT client authenticated");
+            notifier_state
+                .insert_mqtt_session(request.username, claims)
+                .await;
+            Ok(MqttAuthResponse::allow(false))
+        }
+        Err(reason) => {
+            debug!(username = request.username, reason, "MQTT client denied");
+            Ok(MqttAuthResponse::deny(reason))
+        }
+    }
+}
+
+async fn superuser(
+    _: Broker,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Json(request): Json<MqttUserRequest>,
+) -> Result<MqttAuthResponse, AppError> {
+    if notifier_state.is_vtn_mqtt_account(&request.username, request.password.as_deref()) {
+        Ok(MqttAuthResponse::allow(true))
+    } else {
+        Ok(MqttAuthResponse::deny("not a superuser"))
+    }
+}
+
+async fn acl(
+    _: Broker,
+    State(notifier_state): State<Arc<NotifierState>>,
+    State(jwt_manager): State<Arc<JwtManager>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    Json(request): Json<MqttAclRequest>,
+) -> Result<MqttAuthResponse, AppError> {
+    // EMQX sends the `action`, mosquitto-go-auth the `acc`
+    let emqx = request.acc.is_none() && request.action.is_some();
+    let response = check_acl(&notifier_state, &jwt_manager, &*privacy, request).await?;
+
+    Ok(if emqx {
+        response.deny_in_body()
+    } else {
+        response
+    })
+}
+
+async fn check_acl(
+    notifier_state: &NotifierState,
+    jwt_manager: &JwtManager,
+    privacy: &dyn VenObjectPrivacy,
+    request: MqttAclRequest,
+) -> Result<MqttAuthResponse, AppError> {
+    let Some(prefix) = notifier_state.mqtt_topic_prefix() else {
+        return Err(AppError::NotFound);
+    };
+
+    let Some(access) = MqttAccess::from_request(request.acc, request.action.as_deref()) else {
+        return Ok(MqttAuthResponse::deny("unknown access type"));
+    };
+
+    let principal = match &request.password {
+        Some(password) => {
+            match authenticate(notifier_state, jwt_manager, &request.username, password).await {
+                Ok(principal) => principal,
+                Err(reason) => return Ok(MqttAuthResponse::deny(reason)),
+            }
+        }
+        // The broker only asks for access control of clients it authenticated before
+        None if notifier_state.is_vtn_mqtt_account(&request.username, None) => MqttPrincipal::Vtn,
+        None => match notifier_state.mqtt_session(&request.username).await {
+            Some(claims) => MqttPrincipal::Client(claims),
+            None => return Ok(MqttAuthResponse::deny("unknown or expired session")),
+        },
+    };
+
+    let claims = match principal {
+        MqttPrincipal::Vtn => return Ok(MqttAuthResponse::allow(true)),
+        MqttPrincipal::Client(claims) => claims,
+    };
+
+    if access == MqttAccess::Publish {
+        return Ok(MqttAuthResponse::deny("only the VTN may publish"));
+    }
+
+    let Some(topic) = request.topic.strip_prefix(prefix) else {
+        return Ok(MqttAuthResponse::deny(
+            "topic outside of the VTN topic prefix",
+        ));
+    };
+
+    if claims.has_scope(Scope::ReadAll) {
+        return Ok(MqttAuthResponse::allow(false));
+    }
+
+    let ven_id = privacy.ven_id_by_client_id(&claims.client_id()?).await?;
+    let allowed = ven_id.is_some_and(|ven_id| {
+        ven_topic_allowed(&topic.split('/').collect::<Vec<_>>(), &ven_id, &claims)
+    });
+
+    trace!(
+        username = request.username,
+        topic = request.topic,
+        allowed,
+        "MQTT access check"
+    );
+
+    if allowed {
+        Ok(MqttAuthResponse::allow(false))
+    } else {
+        Ok(MqttAuthResponse::deny(
+            "topic not accessible for this client",
+        ))
+    }
+}
+
+/// Checks whether a VEN client may subscribe to the given topic (filter), split by `/`
+/// and without the topic prefix.
+/// The topic must be below `vens/{ven_id}/` or `push/vens/{ven_id}/` of the VEN the client
+/// owns, and each kind of object it may match must be readable with the scopes of the client.
+fn ven_topic_allowed(levels: &[&str], ven_id: &VenId, claims: &Claims) -> bool {
+    let levels = levels.strip_prefix(&["push"]).unwrap_or(levels);
+    let [vens, id, rest @ ..] = levels else {
+        return false;
+    };
+    if *vens != "vens" || *id != ven_id.as_str() {
+        return false;
+    }
+
+    let is_operation = |level: &str| {
+        matches!(
+            level,
+            "create"
+                | "update"
+                | "delete"
+                | "event_started"
+                | "interval_started"
+                | "event_ended"
+                | "+"
+        )
+    };
+    let read_targets = claims.has_scope(Scope::ReadTargets);
+    let read_ven_objects = claims.has_scope(Scope::ReadVenObjects);
+
+    match rest {
+        ["#"] => read_targets && read_ven_objects,
+        // the VEN object itself
+        [operation] if *operation != "+" => is_operation(operation) && read_ven_objects,
+        ["+"] => read_ven_objects,
+        [kind, operation] if is_operation(operation) || *operation == "#" => match *kind {
+            "events" | "programs" => read_targets,
+            "resources" | "resource_groups" => read_ven_objects,
+            "+" => read_targets && read_ven_objects,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{api::test::ApiTest, config::VtnConfig};
+    use axum::body::Body;
+    use reqwest::Method;
+    use sqlx::PgPool;
+
+    fn allowed(topic: &str, scopes: Vec<Scope>) -> bool {
+        ven_topic_allowed(
+            &topic.split('/').collect::<Vec<_>>(),
+            &"ven-1".parse().unwrap(),
+            &Claims::from_scopes(scopes),
+        )
+    }
+
+    #[test]
+    fn mqtt_sessions_drop_expired_and_excess_sessions() {
+        let in_an_hour = chrono::Utc::now().timestamp() + 3600;
+        let claims = |exp: i64| Claims::from_scopes(vec![]).with_expiration(exp);
+        let mut sessions = MqttSessions::default();
+
+        sessions.insert("expired".to_string(), claims(0));
+        assert!(sessions.get("expired").is_none());
+        assert!(sessions.claims.is_empty());
+        assert!(sessions.expirations.is_empty());
+
+        // inserts drop the expired sessions
+        sessions.insert("expired".to_string(), claims(0));
+        sessions.insert("client-0".to_string(), claims(in_an_hour));
+        assert!(!sessions.claims.contains_key("expired"));
+
+        for i in 1..MAX_MQTT_SESSIONS {
+            sessions.insert(format!("client-{i}"), claims(in_an_hour + i as i64));
+        }
+        assert_eq!(sessions.claims.len(), MAX_MQTT_SESSIONS);
+
+        // renewing a session keeps a single entry
+        sessions.insert("client-1".to_string(), claims(in_an_hour + 1));
+        assert_eq!(sessions.claims.len(), MAX_MQTT_SESSIONS);
+        assert_eq!(sessions.expirations.len(), MAX_MQTT_SESSIONS);
+
+        // a full map drops the session expiring first
+        let last = in_an_hour + MAX_MQTT_SESSIONS as i64;
+        sessions.insert("client-new".to_string(), claims(last));
+        assert_eq!(sessions.claims.len(), MAX_MQTT_SESSIONS);
+        assert_eq!(sessions.expirations.len(), MAX_MQTT_SESSIONS);
+        assert!(sessions.get("client-0").is_none());
+        assert!(sessions.get("client-1").is_some());
+        assert!(sessions.get("client-new").is_some());
+    }
+
+    #[test]
+    fn ven_topics() {
+        let all = || vec![Scope::ReadTargets, Scope::ReadVenObjects];
+
+        assert!(allowed("vens/ven-1/update", all()));
+        assert!(allowed("push/vens/ven-1/delete", all()));
+        assert!(allowed("vens/ven-1/events/create", all()));
+        assert!(allowed("vens/ven-1/events/interval_started", all()));
+        assert!(allowed("vens/ven-1/programs/+", all()));
+        assert!(allowed("push/vens/ven-1/resource_groups/#", all()));
+        assert!(allowed("vens/ven-1/#", all()));
+        assert!(allowed("vens/ven-1/+/update", all()));
+
+        assert!(!allowed("vens/ven-2/update", all()));
+        assert!(!allowed("vens/+/update", all()));
+        assert!(!allowed("vens/#", all()));
+        assert!(!allowed("#", all()));
+        assert!(!allowed("events/create", all()));
+        assert!(!allowed("push/programs/create", all()));
+        assert!(!allowed("vens/ven-1", all()));
+        assert!(!allowed("vens/ven-1/reports/create", all()));
+        assert!(!allowed("vens/ven-1/events/create/more", all()));
+        assert!(!allowed("vens/ven-1/events/something", all()));
+    }
+
+    #[test]
+    fn ven_topics_require_scopes() {
+        assert!(allowed(
+            "vens/ven-1/events/create",
+            vec![Scope::ReadTargets]
+        ));
+        assert!(!allowed(
+            "vens/ven-1/events/create",
+            vec![Scope::ReadVenObjects]
+        ));
+        assert!(!allowed("vens/ven-1/resources/+", vec![Scope::ReadTargets]));
+        assert!(allowed(
+            "vens/ven-1/resources/+",
+            vec![Scope::ReadVenObjects]
+        ));
+        assert!(!allowed("vens/ven-1/update", vec![Scope::ReadTargets]));
+        assert!(!allowed("vens/ven-1/#", vec![Scope::ReadTargets]));
+        assert!(!allowed("vens/ven-1/+/create", vec![Scope::ReadVenObjects]));
+    }
+
+    #[test]
+    fn access_from_request() {
+        assert_eq!(
+            MqttAccess::from_request(Some(4), None),
+            Some(MqttAccess::Subscribe)
+        );
+        assert_eq!(
+            MqttAccess::from_request(Some(1), None),
+            Some(MqttAccess::Subscribe)
+        );
+        assert_eq!(
+            MqttAccess::from_request(Some(3), None),
+            Some(MqttAccess::Publish)
+        );
+        assert_eq!(
+            MqttAccess::from_request(None, Some("subscribe")),
+            Some(MqttAccess::Subscribe)
+        );
+        assert_eq!(
+            MqttAccess::from_request(None, Some("all")),
+            Some(MqttAccess::Publish)
+        );
+        assert_eq!(MqttAccess::from_request(None, None), None);
+    }
+
+    const HOOK_SECRET: &str = "hook-secret";
+
+    async fn hook_test(db: PgPool, client_id: &str, scopes: Vec<Scope>) -> ApiTest {
+        let mut config = VtnConfig::from_env().unwrap();
+        config.mqtt_auth_hook_secret = Some(HOOK_SECRET.to_string());
+        ApiTest::with_config(db, config, client_id, scopes).await
+    }
+
+    async fn hook(
+        test: &ApiTest,
+        path: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        test.request(
+            Method::POST,
+            &format!("/notifiers/mqtt/auth/{path}?secret={HOOK_SECRET}"),
+            Body::from(body.to_string()),
+        )
+        .await
+    }
+
+    #[test]
+    fn compare_secrets() {
+        assert!(secrets_match(b"secret", b"secret"));
+        assert!(!secrets_match(b"secreT", b"secret"));
+        assert!(!secrets_match(b"secret", b"secret-but-longer"));
+        assert!(!secrets_match(b"", b"secret"));
+    }
+
+    #[sqlx::test]
+    async fn hooks_require_the_secret(db: PgPool) {
+        let test = hook_test(db.clone(), "bl-client", vec![Scope::ReadAll]).await;
+        let body = || {
+            Body::from(
+                serde_json::json!({"username": "bl-client", "password": test.token()}).to_string(),
+            )
+        };
+
+        // the bearer token of a client is not the secret of the broker
+        let (status, _) = test
+            .request::<serde_json::Value>(Method::POST, "/notifiers/mqtt/auth/user", body())
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = test
+            .request::<serde_json::Value>(
+                Method::POST,
+                "/notifiers/mqtt/auth/superuser?secret=not-the-secret",
+                body(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, response) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": "bl-client", "password": test.token()}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["result"], "allow");
+
+        // without a secret, the hooks are disabled
+        let test = ApiTest::new(db, "bl-client", vec![Scope::ReadAll]).await;
+        assert!(test.vtn_config().mqtt_auth_hook_secret.is_none());
+        let (status, _) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": "bl-client", "password": test.token()}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("vens"))]
+    async fn emqx_denials_in_body(db: PgPool) {
+        let test = hook_test(
+            db,
+            "ven-1-client-id",
+            vec![Scope::ReadTargets, Scope::ReadVenObjects],
+        )
+        .await;
+        let prefix = test.vtn_config().mqtt_topic_prefix.clone();
+
+        let (status, _) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": "ven-1-client-id", "password": test.token()}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, response) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "ven-1-client-id", "topic": format!("{prefix}vens/ven-1/events/create"), "action": "subscribe"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["result"], "allow");
+
+        for (topic, action) in [
+            ("vens/ven-2/events/create", "subscribe"),
+            ("vens/ven-1/events/create", "publish"),
+            ("vens/ven-1/events/create", "all"),
+            ("vens/ven-1/events/create", "retain"),
+        ] {
+            let (status, response) = hook(
+                &test,
+                "acl",
+                serde_json::json!({"username": "ven-1-client-id", "topic": format!("{prefix}{topic}"), "action": action}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{action} {topic}");
+            assert_eq!(response["result"], "deny", "{action} {topic}");
+        }
+    }
+
+    #[sqlx::test(fixtures("vens"))]
+    async fn ven_client(db: PgPool) {
+        let test = hook_test(
+            db,
+            "ven-1-client-id",
+            vec![Scope::ReadTargets, Scope::ReadVenObjects],
+        )
+        .await;
+        let prefix = test.vtn_config().mqtt_topic_prefix.clone();
+
+        let (status, response) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": "ven-1-client-id", "password": test.token()}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["result"], "allow");
+        assert_eq!(response["is_superuser"], false);
+
+        let (status, response) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": "ven-2-client-id", "password": test.token()}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(response["result"], "deny");
+
+        let (status, _) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": "ven-1-client-id", "password": "not a token"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "ven-1-client-id", "topic": format!("{prefix}vens/ven-1/events/create"), "acc": 4}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "ven-1-client-id", "topic": format!("{prefix}vens/ven-2/events/create"), "acc": 4}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, response) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "ven-1-client-id", "topic": format!("{prefix}events/create"), "action": "subscribe"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["result"], "deny");
+
+        let (status, response) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "ven-1-client-id", "topic": format!("{prefix}vens/ven-1/events/create"), "action": "publish"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["result"], "deny");
+
+        // clients that did not authenticate before are denied
+        let (status, _) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "ven-2-client-id", "topic": format!("{prefix}vens/ven-2/events/create"), "acc": 4}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(fixtures("vens"))]
+    async fn bl_client(db: PgPool) {
+        let test = hook_test(db, "bl-client", vec![Scope::ReadAll]).await;
+        let prefix = test.vtn_config().mqtt_topic_prefix.clone();
+
+        let (status, _) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "bl-client", "password": test.token(), "topic": format!("{prefix}push/events/#"), "acc": 4}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": "bl-client", "password": test.token(), "topic": format!("{prefix}events/create"), "acc": 2}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test]
+    async fn vtn_account(db: PgPool) {
+        let test = hook_test(db, "bl-client", vec![]).await;
+        let config = test.vtn_config().clone();
+        let username = config.mqtt_username.unwrap();
+        let password = config.mqtt_password.unwrap();
+
+        let (status, response) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": username, "password": password}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response["is_superuser"], true);
+
+        let (status, _) = hook(
+            &test,
+            "superuser",
+            serde_json::json!({"username": username}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": username, "topic": format!("{}events/create", config.mqtt_topic_prefix), "acc": 2}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test(fixtures("vens"))]
+    async fn client_with_the_vtn_username(db: PgPool) {
+        let mut config = VtnConfig::from_env().unwrap();
+        config.mqtt_auth_hook_secret = Some(HOOK_SECRET.to_string());
+        let username = config.mqtt_username.clone().unwrap();
+        let prefix = config.mqtt_topic_prefix.clone();
+        let test = ApiTest::with_config(db, config, &username, vec![Scope::ReadAll]).await;
+
+        let (status, response) = hook(
+            &test,
+            "user",
+            serde_json::json!({"username": username, "password": test.token()}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(response["result"], "deny");
+
+        let (status, _) = hook(
+            &test,
+            "acl",
+            serde_json::json!({"username": username, "password": test.token(), "topic": format!("{prefix}events/create"), "acc": 2}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
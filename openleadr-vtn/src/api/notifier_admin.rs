@@ -0,0 +1,314 @@
+//! Operator endpoints to inspect and manage the notification delivery of the VTN.
+//!
+//! These are not part of the OpenADR specification and require the `write_subscriptions` scope
+//! of a business logic.
+
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use openleadr_wire::{
+    ClientId,
+    subscription::{Notification, SubscriptionId},
+};
+
+use crate::{
+    api::{AppResponse, fanout::DeliveryStats, subscription::NotifierState},
+    data_source::SubscriptionCrud,
+    error::AppError,
+    jwt::{Claims, Scope, User},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NotifierOverview {
+    /// `None` if MQTT is not configured
+    pub(crate) mqtt: Option<MqttStatus>,
+    pub(crate) websockets: Vec<WebsocketStatus>,
+    pub(crate) deliveries: Vec<SubscriptionDeliveryStats>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MqttStatus {
+    pub(crate) url: String,
+    pub(crate) connected: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WebsocketStatus {
+    #[serde(rename = "clientID")]
+    pub(crate) client_id: ClientId,
+    #[serde(with = "openleadr_wire::serde_rfc3339")]
+    pub(crate) connected_date_time: DateTime<Utc>,
+    /// Notifications waiting to be sent to the client
+    pub(crate) queue_depth: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SubscriptionDeliveryStats {
+    #[serde(rename = "subscriptionID")]
+    pub(crate) subscription_id: SubscriptionId,
+    #[serde(flatten)]
+    pub(crate) stats: DeliveryStats,
+}
+
+fn require_bl(user: &Claims) -> Result<(), AppError> {
+    if user.has_scope(Scope::WriteSubscriptionsBl) {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden("Missing 'write_subscriptions' scope"))
+    }
+}
+
+pub async fn get(
+    State(notifier_state): State<Arc<NotifierState>>,
+    User(user): User,
+) -> AppResponse<NotifierOverview> {
+    require_bl(&user)?;
+
+    let mqtt = notifier_state
+        .mqtt_connection()
+        .map(|(url, connected)| MqttStatus {
+            url: url.to_owned(),
+            connected,
+        });
+
+    let mut websockets: Vec<_> = notifier_state
+        .websocket_connections()
+        .await
+        .into_iter()
+        .map(|(client_id, connection)| WebsocketStatus {
+            client_id,
+            connected_date_time: connection.connected_date_time,
+            queue_depth: connection.queue_depth(),
+        })
+        .collect();
+    websockets.sort_by_key(|websocket| websocket.connected_date_time);
+
+    let mut deliveries: Vec<_> = notifier_state
+        .deliveries
+        .stats()
+        .into_iter()
+        .map(|(subscription_id, stats)| SubscriptionDeliveryStats {
+            subscription_id,
+            stats,
+        })
+        .collect();
+    deliveries.sort_by(|a, b| a.subscription_id.as_str().cmp(b.subscription_id.as_str()));
+
+    Ok(Json(NotifierOverview {
+        mqtt,
+        websockets,
+        deliveries,
+    }))
+}
+
+pub async fn disconnect_websocket(
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(client_id): Path<ClientId>,
+    User(user): User,
+) -> Result<StatusCode, AppError> {
+    require_bl(&user)?;
+
+    notifier_state
+        .disconnect_websocket(&client_id)
+        .await
+        .ok_or(AppError::NotFound)?;
+
+    info!(%client_id, operator = user.sub, "disconnected websocket client");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn send_test_notification(
+    State(subscription_source): State<Arc<dyn SubscriptionCrud>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<SubscriptionId>,
+    User(user): User,
+) -> AppResponse<Notification> {
+    require_bl(&user)?;
+
+    let subscription = subscription_source.retrieve(&id, &None).await?;
+    let notification = notifier_state.send_test_notification(subscription).await;
+
+    info!(%id, operator = user.sub, "sent test notification");
+
+    Ok(Json(notification))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use openleadr_wire::subscription::{AnyObject, Notification, Operation, Subscription};
+    use reqwest::{Method, StatusCode};
+    use sqlx::PgPool;
+
+    use super::NotifierOverview;
+    use crate::{
+        api::test::{ApiTest, webhook_receiver},
+        jwt::Scope,
+    };
+
+    #[sqlx::test]
+    async fn requires_bl_scope(db: PgPool) {
+        let server = ApiTest::new(db, "ven-client", vec![Scope::WriteSubscriptionsVen]).await;
+
+        let status = server.empty_request(Method::GET, "/notifiers/admin").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let status = server
+            .empty_request(Method::DELETE, "/notifiers/admin/websockets/ven-client")
+            .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test]
+    async fn disconnect_unknown_client(db: PgPool) {
+        let server = ApiTest::new(db, "bl-client", vec![Scope::WriteSubscriptionsBl]).await;
+
+        let status = server
+            .empty_request(Method::DELETE, "/notifiers/admin/websockets/nobody")
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn test_notification_and_delivery_stats(db: PgPool) {
+        let server = ApiTest::new(
+            db,
+            "bl-client",
+            vec![Scope::WriteSubscriptionsBl, Scope::ReadAll],
+        )
+        .await;
+        let (callback_url, mut notifications) = webhook_receiver().await;
+
+        let body = serde_json::json!({
+            "clientName": "bl-client",
+            "objectOperations": [{
+                "objects": ["EVENT"],
+                "operations": ["CREATE"],
+                "mechanism": "WEBHOOK",
+                "callbackUrl": callback_url,
+            }]
+        });
+        let (status, subscription) = server
+            .request::<Subscription>(Method::POST, "/subscriptions", Body::from(body.to_string()))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, sent) = server
+            .request::<Notification>(
+                Method::POST,
+                &format!(
+                    "/notifiers/admin/subscriptions/{}/test",
+                    subscription.id.as_str()
+                ),
+                Body::empty(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(sent.operation, Operation::Test);
+
+        let (_, received) = tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, sent);
+        let AnyObject::Subscription(object) = received.object else {
+            panic!("expected a subscription, got {:?}", received.object);
+        };
+        assert_eq!(object.id, subscription.id);
+
+        let overview = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let (status, overview) = server
+                    .request::<NotifierOverview>(Method::GET, "/notifiers/admin", Body::empty())
+                    .await;
+                assert_eq!(status, StatusCode::OK);
+                if !overview.deliveries.is_empty() {
+                    return overview;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert!(overview.websockets.is_empty());
+        let delivery = &overview.deliveries[0];
+        assert_eq!(delivery.subscription_id, subscription.id);
+        assert!(delivery.stats.last_success_date_time.is_some());
+        assert_eq!(delivery.stats.failure_count, 0);
+    }
+
+    #[cfg(feature = "experimental-websockets")]
+    #[sqlx::test]
+    async fn disconnect_websocket(db: PgPool) {
+        use futures::StreamExt;
+        use tokio_tungstenite::{
+            connect_async,
+            tungstenite::{ClientRequestBuilder, Message},
+        };
+
+        let server = ApiTest::new(db, "bl-client", vec![Scope::WriteSubscriptionsBl]).await;
+
+        let (token, addr, handle) = server.run().await;
+        let request = ClientRequestBuilder::new(
+            format!("ws://localhost:{}/notifiers/ws", addr.port())
+                .parse()
+                .unwrap(),
+        )
+        .with_header("Authorization", format!("Bearer {token}"));
+        let (mut client_socket, _) = connect_async(request).await.unwrap();
+
+        let (status, overview) = server
+            .request::<NotifierOverview>(Method::GET, "/notifiers/admin", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(overview.websockets.len(), 1);
+        assert_eq!(overview.websockets[0].client_id.as_str(), "bl-client");
+        assert_eq!(overview.websockets[0].queue_depth, 0);
+
+        let status = server
+            .empty_request(Method::DELETE, "/notifiers/admin/websockets/bl-client")
+            .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let message = tokio::time::timeout(Duration::from_secs(5), client_socket.next())
+            .await
+            .unwrap();
+        assert!(
+            matches!(message, Some(Ok(Message::Close(_))) | None),
+            "{message:?}"
+        );
+
+        let (_, overview) = server
+            .request::<NotifierOverview>(Method::GET, "/notifiers/admin", Body::empty())
+            .await;
+        assert!(overview.websockets.is_empty());
+
+        handle.abort();
+    }
+
+    #[sqlx::test]
+    async fn test_notification_unknown_subscription(db: PgPool) {
+        let server = ApiTest::new(db, "bl-client", vec![Scope::WriteSubscriptionsBl]).await;
+
+        let status = server
+            .empty_request(Method::POST, "/notifiers/admin/subscriptions/unknown/test")
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,324 @@
+//! Cursor pagination and total counts of the list endpoints, e.g., `GET /events`.
+//!
+//! With an empty `cursor` query parameter, a list is ordered by `(created_date_time, id)`
+//! instead of its default order.
+//! Each full page then has a `Link` header with `rel="next"`,
+//! whose `cursor` continues right after the last object of the page.
+//! Unlike `skip`, a cursor neither skips nor repeats objects created or deleted in between.
+//! Each list response has an `X-Total-Count` header with the number of objects matching the filter,
+//! regardless of `skip`, `limit`, and `cursor`.
+
+use axum::{
+    Json,
+    http::{HeaderName, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use openleadr_wire::{
+    Event, Program, Report, resource::Resource, resource_group::ResourceGroup,
+    subscription::Subscription, ven::Ven,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use url::form_urlencoded;
+use validator::ValidationError;
+
+pub(crate) static TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// Position in a list ordered by `(created_date_time, id)`, as sent in the `cursor` query parameter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Cursor(Option<(DateTime<Utc>, String)>);
+
+impl Cursor {
+    /// Right after the given object
+    pub(crate) fn after(object: &impl Listed) -> Self {
+        let (created_date_time, id) = object.position();
+        Self(Some((created_date_time, id.to_string())))
+    }
+
+    /// The `created_date_time` and `id` of the last object of the previous page,
+    /// or `None` for the first page
+    pub(crate) fn position(cursor: Option<&Cursor>) -> (Option<DateTime<Utc>>, Option<&str>) {
+        match cursor.and_then(|cursor| cursor.0.as_ref()) {
+            Some((created_date_time, id)) => (Some(*created_date_time), Some(id.as_str())),
+            None => (None, None),
+        }
+    }
+
+    fn encode(&self) -> String {
+        match &self.0 {
+            Some((created_date_time, id)) => {
+                URL_SAFE_NO_PAD.encode(format!("{}:{id}", created_date_time.timestamp_micros()))
+            }
+            None => String::new(),
+        }
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        if cursor.is_empty() {
+            return Some(Self(None));
+        }
+        let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let (micros, id) = decoded.split_once(':')?;
+        let created_date_time = DateTime::from_timestamp_micros(micros.parse().ok()?)?;
+        Some(Self(Some((created_date_time, id.to_string()))))
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cursor = String::deserialize(deserializer)?;
+        Self::decode(&cursor).ok_or_else(|| de::Error::custom("invalid cursor"))
+    }
+}
+
+/// Deserializes a present `cursor`, even if it is empty,
+/// which would otherwise be taken as `None` for the first page
+pub(crate) fn deserialize_cursor<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Cursor>, D::Error> {
+    Cursor::deserialize(deserializer).map(Some)
+}
+
+/// The pagination parameters every list endpoint accepts
+pub(crate) trait Paginated {
+    fn skip(&self) -> i64;
+    fn limit(&self) -> i64;
+    fn cursor(&self) -> Option<&Cursor>;
+}
+
+/// A cursor already determines where the page starts.
+/// Takes the reference `#[validate(schema)]` passes to it.
+pub(crate) fn validate_cursor<T: Paginated>(params: &&T) -> Result<(), ValidationError> {
+    if params.cursor().is_some() && params.skip() != 0 {
+        return Err(ValidationError::new("cursor")
+            .with_message("'skip' cannot be combined with 'cursor'".into()));
+    }
+    Ok(())
+}
+
+/// Objects that can be listed with a cursor
+pub(crate) trait Listed {
+    fn position(&self) -> (DateTime<Utc>, &str);
+}
+
+macro_rules! listed {
+    ($($t:ty),*) => {
+        $(impl Listed for $t {
+            fn position(&self) -> (DateTime<Utc>, &str) {
+                (self.created_date_time, self.id.as_str())
+            }
+        })*
+    };
+}
+
+listed!(
+    Program,
+    Event,
+    Report,
+    Ven,
+    Resource,
+    ResourceGroup,
+    Subscription
+);
+
+/// A page of a list endpoint, responded with the `X-Total-Count` and `Link` headers
+#[derive(Debug)]
+pub(crate) struct Page<T> {
+    objects: Vec<T>,
+    total: u64,
+    next: Option<String>,
+}
+
+impl<T: Listed> Page<T> {
+    /// `query` is the query string of the request,
+    /// which the `next` link repeats with the position of the next page
+    pub(crate) fn new(
+        objects: Vec<T>,
+        total: u64,
+        params: &impl Paginated,
+        query: Option<&str>,
+    ) -> Self {
+        let full = objects.len() as i64 >= params.limit();
+        let next = match params.cursor() {
+            Some(_) if full => objects
+                .last()
+                .map(|last| ("cursor", Cursor::after(last).encode())),
+            Some(_) => None,
+            None => {
+                let skip = params.skip() + objects.len() as i64;
+                (full && (skip as u64) < total).then(|| ("skip", skip.to_string()))
+            }
+        };
+
+        Self {
+            next: next.map(|(name, value)| next_query(query.unwrap_or_default(), name, &value)),
+            objects,
+            total,
+        }
+    }
+}
+
+/// The query string with `name` replacing the previous position
+fn next_query(query: &str, name: &str, value: &str) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(
+            form_urlencoded::parse(query.as_bytes())
+                .filter(|(key, _)| key != "cursor" && key != "skip"),
+        )
+        .append_pair(name, value)
+        .finish()
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+    fn into_response(self) -> Response {
+        let mut response = Json(self.objects).into_response();
+        let headers = response.headers_mut();
+        headers.insert(TOTAL_COUNT.clone(), HeaderValue::from(self.total));
+        // relative to the request, such that it is also correct behind a reverse proxy
+        if let Some(next) = self.next
+            && let Ok(link) = HeaderValue::try_from(format!("<?{next}>; rel=\"next\""))
+        {
+            headers.insert(header::LINK, link);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use http_body_util::BodyExt;
+    use openleadr_wire::{Event, problem::Problem};
+    use reqwest::Method;
+    use serde_json::json;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{api::test::ApiTest, jwt::Scope};
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor(Some((
+            DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            "event:1".to_string(),
+        )));
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode(""), Some(Cursor(None)));
+        assert_eq!(Cursor::decode("not a cursor"), None);
+    }
+
+    #[test]
+    fn next_query_replaces_position() {
+        assert_eq!(
+            next_query("targets=a&targets=b&skip=4&limit=2", "skip", "6"),
+            "targets=a&targets=b&limit=2&skip=6"
+        );
+        assert_eq!(next_query("", "cursor", "abc"), "cursor=abc");
+    }
+
+    /// The page, the value of `X-Total-Count`, and the query of the `next` link
+    async fn list(test: &ApiTest, query: &str) -> (Vec<Event>, String, Option<String>) {
+        let response = test
+            .state()
+            .clone()
+            .into_router()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/events?{query}"))
+                    .header(header::AUTHORIZATION, format!("Bearer {}", test.token()))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let total = response.headers()[&TOTAL_COUNT]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let next = response.headers().get(header::LINK).map(|link| {
+            let link = link.to_str().unwrap();
+            link.strip_prefix("<?")
+                .and_then(|link| link.strip_suffix(">; rel=\"next\""))
+                .unwrap()
+                .to_string()
+        });
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (serde_json::from_slice(&body).unwrap(), total, next)
+    }
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn cursor_pages_are_stable(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::WriteEvents, Scope::ReadAll]).await;
+
+        let (first, total, next) = list(&test, "limit=2&cursor=").await;
+        assert_eq!(total, "5");
+        assert_eq!(first.len(), 2);
+        let next = next.unwrap();
+
+        // objects created in the meantime are neither skipped nor repeated
+        let (status, created) = test
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(
+                    json!({"programID": "program-1", "eventName": "new", "intervals": []})
+                        .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let mut seen = first;
+        let mut next = Some(next);
+        while let Some(query) = next {
+            let (page, total, following) = list(&test, &query).await;
+            assert_eq!(total, "6");
+            seen.extend(page);
+            next = following;
+        }
+
+        let mut positions: Vec<_> = seen.iter().map(Listed::position).collect();
+        assert_eq!(positions.len(), 6);
+        assert!(positions.is_sorted());
+        positions.dedup();
+        assert_eq!(positions.len(), 6);
+        assert_eq!(seen.last(), Some(&created));
+    }
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn offset_pages_link_to_the_next(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::ReadAll]).await;
+
+        let (page, total, next) = list(&test, "limit=2&skip=2").await;
+        assert_eq!((page.len(), total.as_str()), (2, "5"));
+        assert_eq!(next.as_deref(), Some("limit=2&skip=4"));
+
+        let (page, _, next) = list(&test, "limit=2&skip=4").await;
+        assert_eq!(page.len(), 1);
+        assert_eq!(next, None);
+    }
+
+    #[sqlx::test]
+    async fn invalid_cursor(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::ReadAll]).await;
+
+        for query in ["cursor=not-a-cursor", "cursor=&skip=1"] {
+            let (status, _) = test
+                .request::<Problem>(Method::GET, &format!("/events?{query}"), Body::empty())
+                .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
 };
 use reqwest::StatusCode;
 use serde::Deserialize;
@@ -11,15 +11,17 @@ use validator::Validate;
 
 use crate::{
     api::{
-        AppResponse, TargetQueryParams, ValidatedJson, ValidatedQuery, subscription,
+        AppResponse, Patch, TargetQueryParams, ValidatedJson, ValidatedQuery,
+        pagination::{Cursor, Page, Paginated, deserialize_cursor, validate_cursor},
+        subscription,
         subscription::NotifierState,
     },
-    data_source::{EventCrud, ProgramCrud, VenCrud, VenObjectPrivacy},
+    data_source::{EventCrud, Locked, ProgramCrud, VenObjectPrivacy},
     error::AppError,
     jwt::{Scope, User},
 };
 use openleadr_wire::{
-    Program,
+    ClientId, Program,
     program::{ProgramId, ProgramRequest},
     subscription::{AnyObject, Operation},
 };
@@ -28,20 +30,25 @@ pub async fn get_all(
     State(program_source): State<Arc<dyn ProgramCrud>>,
     ValidatedQuery(query_params): ValidatedQuery<QueryParams>,
     User(user): User,
-) -> AppResponse<Vec<Program>> {
+    RawQuery(query): RawQuery,
+) -> Result<Page<Program>, AppError> {
     trace!(?query_params);
 
-    let programs = if user.has_scope(Scope::ReadAll) {
-        program_source.retrieve_all(&query_params, &None).await?
+    let permission_filter = if user.has_scope(Scope::ReadAll) {
+        None
     } else if user.has_scope(Scope::ReadTargets) {
-        program_source
-            .retrieve_all(&query_params, &Some(user.client_id()?))
-            .await?
+        Some(user.client_id()?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'read_all' or 'read_targets' scope",
         ));
     };
+    let programs = program_source
+        .retrieve_all(&query_params, &permission_filter)
+        .await?;
+    let total = program_source
+        .count(&query_params, &permission_filter)
+        .await?;
 
     trace!(
         client_id = user.sub,
@@ -49,7 +56,7 @@ pub async fn get_all(
         programs.len()
     );
 
-    Ok(Json(programs))
+    Ok(Page::new(programs, total, &query_params, query.as_deref()))
 }
 
 pub async fn get(
@@ -80,7 +87,6 @@ pub async fn get(
 }
 
 pub async fn add(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(program_source): State<Arc<dyn ProgramCrud>>,
@@ -104,7 +110,6 @@ pub async fn add(
     );
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -116,12 +121,7 @@ pub async fn add(
     Ok((StatusCode::CREATED, Json(program)))
 }
 
-#[expect(
-    clippy::too_many_arguments,
-    reason = "Handler which uses many aspects of the state"
-)]
 pub async fn edit(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(program_source): State<Arc<dyn ProgramCrud>>,
@@ -134,9 +134,64 @@ pub async fn edit(
         return Err(AppError::Forbidden("Missing 'write_programs' scope"));
     }
 
-    let program = program_source
-        .update(&id, content, &Some(user.client_id()?))
-        .await?;
+    let lock = program_source.lock(&id).await?;
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        User(user),
+        ValidatedJson(content),
+    )
+    .await
+}
+
+/// Applies a merge patch or JSON patch to the program, as if the result was sent with `PUT`
+pub async fn patch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<ProgramId>,
+    User(user): User,
+    patch: Patch,
+) -> AppResponse<Program> {
+    if !user.has_scope(Scope::WritePrograms) {
+        return Err(AppError::Forbidden("Missing 'write_programs' scope"));
+    }
+    // fails if the client may not see the program
+    let _ = get(
+        State(program_source.clone()),
+        Path(id.clone()),
+        User(user.clone()),
+    )
+    .await?;
+
+    let mut lock = program_source.lock(&id).await?;
+    let content = patch.apply(&lock.current().await?.content)?;
+
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        User(user),
+        ValidatedJson(content),
+    )
+    .await
+}
+
+/// Updates the program through the `lock`, which [`edit`] and [`patch`] take
+/// after checking the scopes, and before reading the program
+async fn edit_locked(
+    lock: Box<dyn Locked<ProgramRequest, Program, Option<ClientId>, AppError>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    User(user): User,
+    ValidatedJson(content): ValidatedJson<ProgramRequest>,
+) -> AppResponse<Program> {
+    let program = lock.update(content, &Some(user.client_id()?)).await?;
 
     info!(
         %program.id,
@@ -146,7 +201,6 @@ pub async fn edit(
     );
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -159,7 +213,6 @@ pub async fn edit(
 }
 
 pub async fn delete(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(program_source): State<Arc<dyn ProgramCrud>>,
@@ -175,7 +228,6 @@ pub async fn delete(
     info!(%id, client_id = user.sub, "deleted program");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -189,6 +241,7 @@ pub async fn delete(
 
 #[derive(Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_cursor"))]
 pub struct QueryParams {
     pub(crate) targets: TargetQueryParams,
     #[serde(default)]
@@ -197,6 +250,22 @@ pub struct QueryParams {
     #[validate(range(min = 1, max = 50))]
     #[serde(default = "get_50")]
     pub(crate) limit: i64,
+    #[serde(default, deserialize_with = "deserialize_cursor")]
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Paginated for QueryParams {
+    fn skip(&self) -> i64 {
+        self.skip
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
 }
 
 fn get_50() -> i64 {
@@ -232,6 +301,7 @@ mod test {
             payload_descriptors: None,
             attributes: None,
             targets: vec![],
+            validation_mode: None,
         }
     }
 
@@ -267,7 +337,12 @@ mod test {
             programs.push(p);
         }
 
-        (AppState::new(store, &VtnConfig::from_env()).await, programs)
+        (
+            AppState::new(store, &VtnConfig::from_env().unwrap())
+                .await
+                .unwrap(),
+            programs,
+        )
     }
 
     async fn help_get(app: &mut Router, token: &str, id: &str) -> Response<Body> {
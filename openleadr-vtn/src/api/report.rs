@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
     http::StatusCode,
 };
 use serde::{Deserialize, Serialize};
@@ -10,19 +10,21 @@ use tracing::{info, instrument, trace};
 use validator::Validate;
 
 use openleadr_wire::{
-    Report,
+    ClientId, Report,
     event::EventId,
     program::ProgramId,
-    report::{ReportId, ReportRequest},
+    report::{AppendedIntervals, ReportId, ReportRequest},
     subscription::{AnyObject, Operation},
 };
 
 use crate::{
     api::{
-        AppResponse, ValidatedJson, ValidatedQuery,
+        AppResponse, Patch, ValidatedJson, ValidatedQuery,
+        descriptors::{self, ReportDescriptors, Warnings},
+        pagination::{Cursor, Page, Paginated, deserialize_cursor, validate_cursor},
         subscription::{self, NotifierState},
     },
-    data_source::{EventCrud, ReportCrud, VenCrud, VenObjectPrivacy},
+    data_source::{EventCrud, Locked, ProgramCrud, ReportCrud, VenObjectPrivacy},
     error::AppError,
     jwt::{Scope, User},
 };
@@ -32,22 +34,27 @@ pub async fn get_all(
     State(report_source): State<Arc<dyn ReportCrud>>,
     ValidatedQuery(query_params): ValidatedQuery<QueryParams>,
     User(user): User,
-) -> AppResponse<Vec<Report>> {
-    let reports = if user.has_scope(Scope::ReadAll) {
-        report_source.retrieve_all(&query_params, &None).await?
+    RawQuery(query): RawQuery,
+) -> Result<Page<Report>, AppError> {
+    let permission_filter = if user.has_scope(Scope::ReadAll) {
+        None
     } else if user.has_scope(Scope::ReadVenObjects) {
-        report_source
-            .retrieve_all(&query_params, &Some(user.client_id()?))
-            .await?
+        Some(user.client_id()?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'read_all' or 'read_ven_objects' scope",
         ));
     };
+    let reports = report_source
+        .retrieve_all(&query_params, &permission_filter)
+        .await?;
+    let total = report_source
+        .count(&query_params, &permission_filter)
+        .await?;
 
     trace!(client_id = user.sub, "retrieved {} reports", reports.len());
 
-    Ok(Json(reports))
+    Ok(Page::new(reports, total, &query_params, query.as_deref()))
 }
 
 #[instrument(skip(user, report_source))]
@@ -73,20 +80,30 @@ pub async fn get(
     Ok(Json(report))
 }
 
-#[instrument(skip(user, ven_source, event_source, privacy, report_source, notifier_state))]
+#[instrument(skip(
+    user,
+    event_source,
+    program_source,
+    privacy,
+    report_source,
+    notifier_state
+))]
 pub async fn add(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(report_source): State<Arc<dyn ReportCrud>>,
     State(notifier_state): State<Arc<NotifierState>>,
     User(user): User,
     ValidatedJson(new_report): ValidatedJson<ReportRequest>,
-) -> Result<(StatusCode, Json<Report>), AppError> {
-    let report = if user.has_scope(Scope::WriteReports) {
-        report_source
+) -> Result<(StatusCode, Warnings, Json<Report>), AppError> {
+    let (warnings, report) = if user.has_scope(Scope::WriteReports) {
+        let warnings =
+            descriptors::check_report(&*event_source, &*program_source, &new_report).await?;
+        let report = report_source
             .create(new_report, &Some(user.client_id()?))
-            .await?
+            .await?;
+        (warnings, report)
     } else {
         return Err(AppError::Forbidden("Missing 'write_reports' scope"));
     };
@@ -94,7 +111,6 @@ pub async fn add(
     info!(%report.id, report_name=?report.content.report_name, client_id = user.sub, "report created");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -103,36 +119,122 @@ pub async fn add(
     )
     .await;
 
-    Ok((StatusCode::CREATED, Json(report)))
+    Ok((StatusCode::CREATED, warnings, Json(report)))
 }
 
+#[instrument(skip(
+    user,
+    event_source,
+    program_source,
+    privacy,
+    report_source,
+    notifier_state
+))]
 #[expect(
     clippy::too_many_arguments,
-    reason = "Handler which uses many aspects of the state"
+    reason = "This is a handler which needs a lot of the state."
 )]
-#[instrument(skip(user, ven_source, event_source, privacy, report_source, notifier_state))]
 pub async fn edit(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(report_source): State<Arc<dyn ReportCrud>>,
     State(notifier_state): State<Arc<NotifierState>>,
     Path(id): Path<ReportId>,
     User(user): User,
     ValidatedJson(content): ValidatedJson<ReportRequest>,
-) -> AppResponse<Report> {
-    let report = if user.has_scope(Scope::WriteReports) {
-        report_source
-            .update(&id, content, &Some(user.client_id()?))
-            .await?
-    } else {
+) -> Result<(Warnings, Json<Report>), AppError> {
+    if !user.has_scope(Scope::WriteReports) {
         return Err(AppError::Forbidden("Missing 'write_reports' scope"));
-    };
+    }
+    let warnings = descriptors::check_report(&*event_source, &*program_source, &content).await?;
+
+    let lock = report_source.lock(&id).await?;
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        warnings,
+        User(user),
+        ValidatedJson(content),
+    )
+    .await
+}
+
+/// Applies a merge patch or JSON patch to the report, as if the result was sent with `PUT`
+#[instrument(skip(
+    user,
+    event_source,
+    program_source,
+    privacy,
+    report_source,
+    notifier_state,
+    patch
+))]
+#[expect(
+    clippy::too_many_arguments,
+    reason = "This is a handler which needs a lot of the state."
+)]
+pub async fn patch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(report_source): State<Arc<dyn ReportCrud>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<ReportId>,
+    User(user): User,
+    patch: Patch,
+) -> Result<(Warnings, Json<Report>), AppError> {
+    if !user.has_scope(Scope::WriteReports) {
+        return Err(AppError::Forbidden("Missing 'write_reports' scope"));
+    }
+
+    // The patched report is checked against its event before locking the report.
+    // If the report changed in the meantime, the patch is applied to the new report.
+    loop {
+        let Json(report) = get(
+            State(report_source.clone()),
+            Path(id.clone()),
+            User(user.clone()),
+        )
+        .await?;
+        let content = patch.apply(&report.content)?;
+        let warnings =
+            descriptors::check_report(&*event_source, &*program_source, &content).await?;
+
+        let mut lock = report_source.lock(&id).await?;
+        if lock.current().await?.modification_date_time == report.modification_date_time {
+            return edit_locked(
+                lock,
+                State(event_source),
+                State(privacy),
+                State(notifier_state),
+                warnings,
+                User(user),
+                ValidatedJson(content),
+            )
+            .await;
+        }
+    }
+}
+
+/// Updates the report through the `lock`, which [`edit`] and [`patch`] take after checking
+/// the scopes and the report descriptors of the event, and before reading the report
+async fn edit_locked(
+    lock: Box<dyn Locked<ReportRequest, Report, Option<ClientId>, AppError>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    warnings: Warnings,
+    User(user): User,
+    ValidatedJson(content): ValidatedJson<ReportRequest>,
+) -> Result<(Warnings, Json<Report>), AppError> {
+    let report = lock.update(content, &Some(user.client_id()?)).await?;
 
     info!(%report.id, report_name=?report.content.report_name, client_id = user.sub, "report updated");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -141,12 +243,84 @@ pub async fn edit(
     )
     .await;
 
-    Ok(Json(report))
+    Ok((warnings, Json(report)))
+}
+
+/// Appends intervals to a resource of the report,
+/// such that a VEN does not have to send the intervals it reported before again
+#[expect(
+    clippy::too_many_arguments,
+    reason = "This is a handler which needs a lot of the state."
+)]
+#[instrument(skip(
+    user,
+    event_source,
+    program_source,
+    privacy,
+    report_source,
+    notifier_state,
+    appended
+))]
+pub async fn append_intervals(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(program_source): State<Arc<dyn ProgramCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(report_source): State<Arc<dyn ReportCrud>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<ReportId>,
+    User(user): User,
+    ValidatedJson(appended): ValidatedJson<AppendedIntervals>,
+) -> Result<(StatusCode, Warnings, Json<AppendedIntervals>), AppError> {
+    if !user.has_scope(Scope::WriteReports) {
+        return Err(AppError::Forbidden("Missing 'write_reports' scope"));
+    }
+    let client_id = Some(user.client_id()?);
+
+    // The lock is not held while querying the event and program of the report. Instead, the
+    // report descriptors of its event are loaded after releasing the lock, and the intervals are
+    // locked again, until the descriptors belong to the event the locked report refers to.
+    let mut descriptors: Option<ReportDescriptors> = None;
+    let (lock, warnings) = loop {
+        let lock = report_source
+            .lock_intervals(&id, &appended.resource_name, &client_id)
+            .await?;
+        let event_id = &lock.report().content.event_id;
+        match &descriptors {
+            Some(descriptors) if descriptors.event_id() == event_id => {
+                // only the appended intervals are checked, while the existing ones are merely counted
+                let warnings =
+                    descriptors.check_appended_intervals(&appended, lock.interval_counts())?;
+                break (lock, warnings);
+            }
+            _ => {
+                let event_id = event_id.clone();
+                drop(lock);
+                descriptors = Some(
+                    ReportDescriptors::load(&*event_source, &*program_source, &event_id).await?,
+                );
+            }
+        }
+    };
+
+    let report = lock.append(appended.intervals.clone()).await?;
+
+    info!(%report.id, intervals = appended.intervals.len(), client_id = user.sub, "report intervals appended");
+
+    // subscribers get the whole report, as with any other update of it
+    subscription::notify(
+        &*event_source,
+        &*privacy,
+        &notifier_state,
+        Operation::Update,
+        AnyObject::Report(report),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, warnings, Json(appended)))
 }
 
-#[instrument(skip(user, ven_source, event_source, privacy, report_source, notifier_state))]
+#[instrument(skip(user, event_source, privacy, report_source, notifier_state))]
 pub async fn delete(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(report_source): State<Arc<dyn ReportCrud>>,
@@ -168,7 +342,6 @@ pub async fn delete(
     info!(%id, report_name=?report.content.report_name, client_id = user.sub, "deleted report");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -182,6 +355,7 @@ pub async fn delete(
 
 #[derive(Serialize, Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_cursor"))]
 pub struct QueryParams {
     #[serde(rename = "programID")]
     pub(crate) program_id: Option<ProgramId>,
@@ -194,6 +368,22 @@ pub struct QueryParams {
     #[validate(range(min = 1, max = 50))]
     #[serde(default = "get_50")]
     pub(crate) limit: i64,
+    #[serde(default, deserialize_with = "deserialize_cursor")]
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Paginated for QueryParams {
+    fn skip(&self) -> i64 {
+        self.skip
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
 }
 
 fn get_50() -> i64 {
@@ -204,12 +394,19 @@ fn get_50() -> i64 {
 #[cfg(feature = "live-db-test")]
 mod test {
     use crate::{api::test::ApiTest, jwt::Scope};
-    use axum::{body::Body, http, http::StatusCode};
+    use axum::{
+        body::Body,
+        http,
+        http::{Method, Request, StatusCode},
+    };
     use openleadr_wire::{
-        problem::Problem,
+        Event, Report,
+        problem::{ErrorCode, Problem},
         report::{ReportPayloadDescriptor, ReportRequest, ReportType},
+        subscription::{AnyObject, Operation},
     };
     use sqlx::PgPool;
+    use tower::ServiceExt;
 
     fn default() -> ReportRequest {
         ReportRequest {
@@ -283,4 +480,357 @@ mod test {
             )
         }
     }
+
+    /// Creates an event asking for the usage in kWh of each resource, at most two intervals
+    async fn described_event(test: &ApiTest, validation_mode: &str) -> String {
+        let (status, program) = test
+            .request::<openleadr_wire::Program>(
+                Method::POST,
+                "/programs",
+                Body::from(
+                    serde_json::json!({
+                        "programName": validation_mode,
+                        "validationMode": validation_mode
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, event) = test
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(
+                    serde_json::json!({
+                        "programID": program.id,
+                        "reportDescriptors": [{
+                            "payloadType": "USAGE",
+                            "units": "KWH",
+                            "numIntervals": 2
+                        }],
+                        "intervals": []
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        event.id.to_string()
+    }
+
+    fn described_report(event_id: &str, resource_name: &str, interval_ids: &[i32]) -> String {
+        serde_json::json!({
+            "eventID": event_id,
+            "clientName": "ven-1",
+            "payloadDescriptors": [{"payloadType": "USAGE", "units": "KW"}],
+            "resources": [{
+                "resourceName": resource_name,
+                "intervals": interval_ids
+                    .iter()
+                    .map(|id| serde_json::json!({
+                        "id": id,
+                        "payloads": [{"type": "USAGE", "values": [1.5]}]
+                    }))
+                    .collect::<Vec<_>>()
+            }]
+        })
+        .to_string()
+    }
+
+    #[sqlx::test(fixtures("users", "vens"))]
+    async fn strict_program_rejects_contradicting_reports(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "ven-1-client-id",
+            vec![
+                Scope::WritePrograms,
+                Scope::WriteEvents,
+                Scope::WriteReports,
+                Scope::ReadAll,
+            ],
+        )
+        .await;
+        let event_id = described_event(&test, "STRICT").await;
+
+        let (status, problem) = test
+            .request::<Problem>(
+                Method::POST,
+                "/reports",
+                Body::from(described_report(&event_id, "AGGREGATED_REPORT", &[0, 1, 2])),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem.code, Some(ErrorCode::DescriptorMismatch));
+        let fields: Vec<_> = problem
+            .errors
+            .unwrap()
+            .into_iter()
+            .map(|error| (error.field, error.code))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("payloadDescriptors[0].units", "units"),
+                ("resources[0].resourceName", "aggregate"),
+                ("resources[0].intervals", "num_intervals"),
+            ]
+            .map(|(field, code)| (field.to_string(), code.to_string()))
+        );
+
+        let mut matching: serde_json::Value =
+            serde_json::from_str(&described_report(&event_id, "resource-1", &[0, 1])).unwrap();
+        matching["payloadDescriptors"][0]["units"] = "KWH".into();
+        let (status, report) = test
+            .request::<Report>(Method::POST, "/reports", Body::from(matching.to_string()))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // updates are checked as well
+        let (status, problem) = test
+            .request::<Problem>(
+                Method::PUT,
+                &format!("/reports/{}", report.id),
+                Body::from(described_report(&event_id, "resource-1", &[0, 1])),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            problem.errors.unwrap()[0].field,
+            "payloadDescriptors[0].units"
+        );
+
+        // appended intervals count towards the intervals the resource already has
+        let (status, problem) = test
+            .request::<Problem>(
+                Method::POST,
+                &format!("/reports/{}/intervals", report.id),
+                Body::from(
+                    serde_json::json!({
+                        "resourceName": "resource-1",
+                        "intervals": [{"id": 2, "payloads": [{"type": "USAGE", "values": [1.5]}]}]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let fields: Vec<_> = problem
+            .errors
+            .unwrap()
+            .into_iter()
+            .map(|error| (error.field, error.code))
+            .collect();
+        assert_eq!(
+            fields,
+            [("intervals".to_string(), "num_intervals".to_string())]
+        );
+    }
+
+    #[sqlx::test(fixtures("users", "vens"))]
+    async fn lenient_program_warns_about_contradicting_reports(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "ven-1-client-id",
+            vec![
+                Scope::WritePrograms,
+                Scope::WriteEvents,
+                Scope::WriteReports,
+                Scope::ReadAll,
+            ],
+        )
+        .await;
+        let event_id = described_event(&test, "LENIENT").await;
+
+        let response = test
+            .state()
+            .clone()
+            .into_router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/reports")
+                    .header(
+                        http::header::AUTHORIZATION,
+                        format!("Bearer {}", test.token()),
+                    )
+                    .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(described_report(&event_id, "resource-1", &[0])))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let warnings: Vec<_> = response
+            .headers()
+            .get_all(http::header::WARNING)
+            .iter()
+            .map(|warning| warning.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "299 - \"payloadDescriptors[0].units: units KW contradict the units KWH of the event\""
+            ]
+        );
+    }
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn append_intervals(db: PgPool) {
+        let test = ApiTest::new(
+            db,
+            "ven-1-client-id",
+            vec![
+                Scope::WriteReports,
+                Scope::WriteSubscriptionsBl,
+                Scope::ReadAll,
+            ],
+        )
+        .await;
+        let (callback_url, mut notifications) = crate::api::test::webhook_receiver().await;
+        let intervals = |ids: &[i32]| -> Vec<serde_json::Value> {
+            ids.iter()
+                .map(|id| {
+                    serde_json::json!({"id": id, "payloads": [{"type": "USAGE", "values": [id]}]})
+                })
+                .collect()
+        };
+
+        let (status, report) = test
+            .request::<Report>(
+                Method::POST,
+                "/reports",
+                Body::from(
+                    serde_json::json!({
+                        "eventID": "event-1",
+                        "clientName": "ven-1",
+                        "resources": [
+                            {"resourceName": "resource-1", "intervals": []},
+                            {"resourceName": "resource-2", "intervals": intervals(&[0, 1])}
+                        ]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let path = format!("/reports/{}/intervals", report.id);
+
+        let (status, _) = test
+            .request::<serde_json::Value>(
+                Method::POST,
+                "/subscriptions",
+                Body::from(
+                    serde_json::json!({
+                        "clientName": "ven-1",
+                        "objectOperations": [{
+                            "objects": ["REPORT"],
+                            "operations": ["UPDATE"],
+                            "mechanism": "WEBHOOK",
+                            "callbackUrl": callback_url,
+                        }]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        for ids in [&[2, 3][..], &[4]] {
+            let (status, _) = test
+                .request::<serde_json::Value>(
+                    Method::POST,
+                    &path,
+                    Body::from(
+                        serde_json::json!({"resourceName": "resource-2", "intervals": intervals(ids)})
+                            .to_string(),
+                    ),
+                )
+                .await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let (status, stored) = test
+            .request::<Report>(
+                Method::GET,
+                &format!("/reports/{}", report.id),
+                Body::empty(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(stored.content.resources[0].intervals.is_empty());
+        let ids: Vec<_> = stored.content.resources[1]
+            .intervals
+            .iter()
+            .map(|interval| interval.id)
+            .collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+        assert!(stored.modification_date_time > report.modification_date_time);
+
+        // the update notifications contain the whole report
+        let mut notified = Vec::new();
+        for _ in 0..2 {
+            let (_, notification) =
+                tokio::time::timeout(std::time::Duration::from_secs(5), notifications.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(notification.operation, Operation::Update);
+            let AnyObject::Report(notified_report) = notification.object else {
+                panic!("expected a report");
+            };
+            notified.push(notified_report);
+        }
+        notified.sort_by_key(|report| report.modification_date_time);
+        let ids: Vec<_> = notified[1].content.resources[1]
+            .intervals
+            .iter()
+            .map(|interval| interval.id)
+            .collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+        assert_eq!(notified[1], stored);
+
+        // ids already used by the resource, or twice in the request, conflict
+        for ids in [&[5, 1][..], &[6, 6]] {
+            let (status, problem) = test
+                .request::<Problem>(
+                    Method::POST,
+                    &path,
+                    Body::from(
+                        serde_json::json!({"resourceName": "resource-2", "intervals": intervals(ids)})
+                            .to_string(),
+                    ),
+                )
+                .await;
+            assert_eq!(status, StatusCode::CONFLICT);
+            assert_eq!(problem.code, Some(ErrorCode::Conflict));
+        }
+
+        let (status, _) = test
+            .request::<Problem>(
+                Method::POST,
+                &path,
+                Body::from(
+                    serde_json::json!({"resourceName": "resource-3", "intervals": intervals(&[0])})
+                        .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // replacing the report replaces its intervals
+        let mut content = stored.content;
+        content.resources.remove(1);
+        let (status, updated) = test
+            .request::<Report>(
+                Method::PUT,
+                &format!("/reports/{}", report.id),
+                Body::from(serde_json::to_string(&content).unwrap()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated.content.resources.len(), 1);
+        assert!(updated.content.resources[0].intervals.is_empty());
+    }
 }
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
 };
 use reqwest::StatusCode;
 use serde::Deserialize;
@@ -10,39 +10,50 @@ use tracing::{info, trace};
 use validator::Validate;
 
 use openleadr_wire::{
-    resource::{BlResourceRequest, Resource, ResourceId, ResourceRequest},
+    ClientId,
+    batch::BatchRequest,
+    resource::{BlResourceRequest, Resource, ResourceId, ResourceRequest, VenResourceRequest},
     subscription::{AnyObject, Operation},
     ven::VenId,
 };
 
 use crate::{
     api::{
-        AppResponse, TargetQueryParams, ValidatedJson, ValidatedQuery, subscription,
+        AppResponse, Patch, TargetQueryParams, ValidatedJson, ValidatedQuery,
+        attributes::AttributeQueryParams,
+        batch,
+        pagination::{Cursor, Page, Paginated, deserialize_cursor, validate_cursor},
+        subscription,
         subscription::NotifierState,
     },
-    data_source::{EventCrud, ResourceCrud, VenCrud, VenObjectPrivacy},
+    data_source::{EventCrud, Locked, ResourceCrud, VenObjectPrivacy},
     error::AppError,
-    jwt::{Scope, User},
+    jwt::{Claims, Scope, User},
 };
 
 pub async fn get_all(
     State(resource_source): State<Arc<dyn ResourceCrud>>,
     ValidatedQuery(query_params): ValidatedQuery<QueryParams>,
     User(user): User,
-) -> AppResponse<Vec<Resource>> {
+    RawQuery(query): RawQuery,
+) -> Result<Page<Resource>, AppError> {
     trace!(?query_params);
 
-    let resources = if user.has_scope(Scope::ReadAll) {
-        resource_source.retrieve_all(&query_params, &None).await?
+    let permission_filter = if user.has_scope(Scope::ReadAll) {
+        None
     } else if user.has_scope(Scope::ReadVenObjects) {
-        resource_source
-            .retrieve_all(&query_params, &Some(user.client_id()?))
-            .await?
+        Some(user.client_id()?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'read_all' or 'read_ven_objects' scope",
         ));
     };
+    let resources = resource_source
+        .retrieve_all(&query_params, &permission_filter)
+        .await?;
+    let total = resource_source
+        .count(&query_params, &permission_filter)
+        .await?;
 
     trace!(
         client_id = user.sub,
@@ -50,7 +61,7 @@ pub async fn get_all(
         resources.len()
     );
 
-    Ok(Json(resources))
+    Ok(Page::new(resources, total, &query_params, query.as_deref()))
 }
 
 pub async fn get(
@@ -80,12 +91,7 @@ pub async fn get(
     Ok(Json(resource))
 }
 
-#[expect(
-    clippy::too_many_arguments,
-    reason = "Handler which uses many aspects of the state"
-)]
 pub async fn add(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(resource_source): State<Arc<dyn ResourceCrud>>,
@@ -139,7 +145,6 @@ pub async fn add(
     );
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -156,7 +161,6 @@ pub async fn add(
     reason = "This is a handler which needs a lot of the state."
 )]
 pub async fn edit(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(resource_source): State<Arc<dyn ResourceCrud>>,
@@ -166,47 +170,141 @@ pub async fn edit(
     User(user): User,
     ValidatedJson(update): ValidatedJson<ResourceRequest>,
 ) -> AppResponse<Resource> {
-    let resource = if user.has_scope(Scope::WriteVensBl) {
+    let update = if user.has_scope(Scope::WriteVensBl) {
         let ResourceRequest::BlResourceRequest(update) = update else {
             return Err(AppError::BadRequest(
                 "Did receive a VEN_RESOURCE_REQUEST, but user is authenticated as a BL client",
             ));
         };
-        resource_source.update(&id, update, &None).await?
+        ResourceUpdate::Bl(update)
     } else if user.has_scope(Scope::WriteVensVen) {
         let ResourceRequest::VenResourceRequest(update) = update else {
             return Err(AppError::BadRequest(
                 "Did receive a BL_RESOURCE_REQUEST, but user is authenticated as a VEN client",
             ));
         };
-        let Some(ven_id) = object_privacy
-            .ven_id_by_client_id(&user.client_id()?)
-            .await?
-        else {
-            return Err(AppError::Forbidden(
-                "No VEN object associated with this clientID",
-            ));
-        };
+        ResourceUpdate::Ven(own_ven(&user, &*object_privacy).await?, update)
+    } else {
+        return Err(AppError::Forbidden(
+            "Missing 'write_vens_bl' or 'write_vens_ven' scope",
+        ));
+    };
 
-        let orig_resource = resource_source
-            .retrieve(&id, &Some(user.client_id()?))
-            .await?;
+    let lock = resource_source.lock(&id).await?;
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        User(user),
+        update,
+    )
+    .await
+}
 
-        let new_resource = BlResourceRequest {
-            resource_name: update.resource_name,
-            ven_id,
-            // VEN clients are not allowed to specify the targets of their resources
-            targets: orig_resource.content.targets,
-            attributes: update.attributes,
-        };
-        resource_source
-            .update(&id, new_resource, &Some(user.client_id()?))
-            .await?
+/// Applies a merge patch or JSON patch to the resource, as if the result was sent with `PUT`.
+/// For VEN clients, the patch applies to the fields of a resource request of a VEN.
+#[expect(
+    clippy::too_many_arguments,
+    reason = "This is a handler which needs a lot of the state."
+)]
+pub async fn patch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(resource_source): State<Arc<dyn ResourceCrud>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    State(object_privacy): State<Arc<dyn VenObjectPrivacy>>,
+    Path(id): Path<ResourceId>,
+    User(user): User,
+    patch: Patch,
+) -> AppResponse<Resource> {
+    let ven_id = if user.has_scope(Scope::WriteVensBl) {
+        None
+    } else if user.has_scope(Scope::WriteVensVen) {
+        Some(own_ven(&user, &*object_privacy).await?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'write_vens_bl' or 'write_vens_ven' scope",
         ));
     };
+    // fails if the client may not see the resource
+    let _ = get(
+        State(resource_source.clone()),
+        Path(id.clone()),
+        User(user.clone()),
+    )
+    .await?;
+
+    let mut lock = resource_source.lock(&id).await?;
+    let resource = lock.current().await?;
+    let update = match ven_id {
+        None => ResourceUpdate::Bl(patch.apply(&resource.content)?),
+        Some(ven_id) => ResourceUpdate::Ven(
+            ven_id,
+            patch.apply(&VenResourceRequest {
+                resource_name: resource.content.resource_name,
+                attributes: resource.content.attributes,
+            })?,
+        ),
+    };
+
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        User(user),
+        update,
+    )
+    .await
+}
+
+/// A resource update, checked against the scopes of the client before locking the resource
+enum ResourceUpdate {
+    Bl(BlResourceRequest),
+    /// An update of a VEN client to a resource of its VEN
+    Ven(VenId, VenResourceRequest),
+}
+
+/// The VEN of a VEN client
+async fn own_ven(user: &Claims, object_privacy: &dyn VenObjectPrivacy) -> Result<VenId, AppError> {
+    object_privacy
+        .ven_id_by_client_id(&user.client_id()?)
+        .await?
+        .ok_or(AppError::Forbidden(
+            "No VEN object associated with this clientID",
+        ))
+}
+
+/// Updates the resource through the `lock`, which [`edit`] and [`patch`] take
+/// after checking the scopes, and before reading the resource
+async fn edit_locked(
+    mut lock: Box<dyn Locked<BlResourceRequest, Resource, Option<ClientId>, AppError>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    User(user): User,
+    update: ResourceUpdate,
+) -> AppResponse<Resource> {
+    let resource = match update {
+        ResourceUpdate::Bl(update) => lock.update(update, &None).await?,
+        ResourceUpdate::Ven(ven_id, update) => {
+            let orig_resource = lock.current().await?;
+            // VEN clients cannot see the resources of other VENs
+            if orig_resource.content.ven_id != ven_id {
+                return Err(AppError::NotFound);
+            }
+
+            let new_resource = BlResourceRequest {
+                resource_name: update.resource_name,
+                ven_id,
+                // VEN clients are not allowed to specify the targets of their resources
+                targets: orig_resource.content.targets,
+                attributes: update.attributes,
+            };
+            lock.update(new_resource, &Some(user.client_id()?)).await?
+        }
+    };
 
     info!(
         %resource.id,
@@ -216,7 +314,6 @@ pub async fn edit(
     );
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -229,7 +326,6 @@ pub async fn edit(
 }
 
 pub async fn delete(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(resource_source): State<Arc<dyn ResourceCrud>>,
@@ -252,7 +348,6 @@ pub async fn delete(
     info!(%id, client_id = user.sub, "deleted resource");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -264,20 +359,82 @@ pub async fn delete(
     Ok(Json(resource))
 }
 
+/// Creates, updates, and deletes resources of any VEN in a single transaction.
+/// Only available for business logic clients.
+/// Notifications are sent once all operations are committed.
+pub async fn batch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(resource_source): State<Arc<dyn ResourceCrud>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    User(user): User,
+    ValidatedJson(request): ValidatedJson<BatchRequest<ResourceId, BlResourceRequest>>,
+) -> Result<batch::Outcome<Resource>, AppError> {
+    if !user.has_scope(Scope::WriteVensBl) {
+        return Err(AppError::Forbidden("Missing 'write_vens_bl' scope"));
+    }
+    if let Some(invalid) = batch::Outcome::invalid(&request.operations) {
+        return Ok(invalid);
+    }
+
+    let operations = request.operations.iter().map(batch::operation).collect();
+    let results = resource_source.batch(request.operations, &None).await?;
+    let outcome = batch::Outcome::new(operations, results);
+
+    if let batch::Outcome::Committed(resources) = &outcome {
+        info!(
+            client_id = user.sub,
+            "applied batch of {} resources",
+            resources.len()
+        );
+
+        for (operation, resource) in resources {
+            subscription::notify(
+                &*event_source,
+                &*privacy,
+                &notifier_state,
+                *operation,
+                AnyObject::Resource(resource.clone()),
+            )
+            .await;
+        }
+    }
+
+    Ok(outcome)
+}
+
 #[derive(Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_cursor"))]
 pub struct QueryParams {
     #[validate(length(min = 1, max = 128))]
     pub(crate) resource_name: Option<String>,
     #[serde(rename = "venID")]
     pub(crate) ven_id: Option<VenId>,
     pub(crate) targets: TargetQueryParams,
+    pub(crate) attributes: AttributeQueryParams,
     #[serde(default)]
     #[validate(range(min = 0))]
     pub(crate) skip: i64,
     #[validate(range(min = 1, max = 50))]
     #[serde(default = "get_50")]
     pub(crate) limit: i64,
+    #[serde(default, deserialize_with = "deserialize_cursor")]
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Paginated for QueryParams {
+    fn skip(&self) -> i64 {
+        self.skip
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
 }
 
 fn get_50() -> i64 {
@@ -487,6 +644,87 @@ mod test {
         assert_eq!(resources.len(), 3);
     }
 
+    #[sqlx::test(fixtures("vens"))]
+    async fn bl_get_all_filtered_by_attributes(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![Scope::ReadAll, Scope::WriteVensBl]).await;
+
+        for (name, attributes) in [
+            (
+                "heat-pump",
+                r#"[{"type": "MAX_POWER_CONSUMPTION", "values": [12.5]},
+                    {"type": "DEVICE", "values": ["heat pump"]}]"#,
+            ),
+            (
+                "charger",
+                r#"[{"type": "MAX_POWER_CONSUMPTION", "values": [11]},
+                    {"type": "DEVICE", "values": ["charger"]}]"#,
+            ),
+            (
+                "boiler",
+                r#"[{"type": "MAX_POWER_CONSUMPTION", "values": [3]}]"#,
+            ),
+            ("meter", "null"),
+        ] {
+            let (status, _) = test
+                .request::<Resource>(
+                    Method::POST,
+                    "/resources",
+                    Body::from(format!(
+                        r#"{{"resourceName": "{name}", "venID": "ven-1", "clientID": "ven-1-client-id",
+                            "objectType": "BL_RESOURCE_REQUEST", "attributes": {attributes}}}"#
+                    )),
+                )
+                .await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        for (query, expected) in [
+            ("attributes=DEVICE", vec!["heat-pump", "charger"]),
+            (
+                "attributes=MAX_POWER_CONSUMPTION:gt:10",
+                vec!["heat-pump", "charger"],
+            ),
+            (
+                "attributes=MAX_POWER_CONSUMPTION:lte:11",
+                vec!["charger", "boiler"],
+            ),
+            ("attributes=MAX_POWER_CONSUMPTION:eq:3", vec!["boiler"]),
+            (
+                "attributes=MAX_POWER_CONSUMPTION:gt:10&attributes=DEVICE:eq:charger",
+                vec!["charger"],
+            ),
+            (
+                "attributes=MAX_POWER_CONSUMPTION:gt:3&attributes=MAX_POWER_CONSUMPTION:lt:12",
+                vec!["charger"],
+            ),
+            ("attributes=LOCATION", vec![]),
+        ] {
+            let (status, resources) = test
+                .request::<Vec<Resource>>(
+                    Method::GET,
+                    &format!("/resources?{query}"),
+                    Body::empty(),
+                )
+                .await;
+            assert_eq!(status, StatusCode::OK, "{query}");
+            let names: Vec<_> = resources
+                .iter()
+                .map(|resource| resource.content.resource_name.as_str())
+                .collect();
+            assert_eq!(names, expected, "{query}");
+        }
+
+        for query in [
+            "attributes=DEVICE:like:pump",
+            "attributes=MAX_POWER_CONSUMPTION:gt:ten",
+        ] {
+            let (status, _) = test
+                .request::<Problem>(Method::GET, &format!("/resources?{query}"), Body::empty())
+                .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+    }
+
     #[sqlx::test(fixtures("vens", "resources"))]
     async fn ven_get_all_filtered(db: PgPool) {
         let test = ApiTest::new(db, "ven-1-client-id", vec![Scope::ReadVenObjects]).await;
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
 };
 use reqwest::StatusCode;
 use serde::Deserialize;
@@ -10,16 +10,18 @@ use tracing::{info, trace};
 use validator::Validate;
 
 use openleadr_wire::{
+    ClientId,
     resource_group::{BlResourceGroupRequest, ResourceGroup, ResourceGroupId},
     subscription::{AnyObject, Operation},
 };
 
 use crate::{
     api::{
-        AppResponse, TargetQueryParams, ValidatedJson, ValidatedQuery,
+        AppResponse, Patch, TargetQueryParams, ValidatedJson, ValidatedQuery,
+        pagination::{Cursor, Page, Paginated, deserialize_cursor, validate_cursor},
         subscription::{self, NotifierState},
     },
-    data_source::{EventCrud, ResourceGroupCrud, VenCrud, VenObjectPrivacy},
+    data_source::{EventCrud, Locked, ResourceGroupCrud, VenObjectPrivacy},
     error::AppError,
     jwt::{Scope, User},
 };
@@ -28,22 +30,25 @@ pub async fn get_all(
     State(resource_group_source): State<Arc<dyn ResourceGroupCrud>>,
     ValidatedQuery(query_params): ValidatedQuery<QueryParams>,
     User(user): User,
-) -> AppResponse<Vec<ResourceGroup>> {
+    RawQuery(query): RawQuery,
+) -> Result<Page<ResourceGroup>, AppError> {
     trace!(?query_params);
 
-    let resource_groups = if user.has_scope(Scope::ReadAll) {
-        resource_group_source
-            .retrieve_all(&query_params, &None)
-            .await?
+    let permission_filter = if user.has_scope(Scope::ReadAll) {
+        None
     } else if user.has_scope(Scope::ReadVenObjects) {
-        resource_group_source
-            .retrieve_all(&query_params, &Some(user.client_id()?))
-            .await?
+        Some(user.client_id()?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'read_all' or 'read_ven_objects' scope",
         ));
     };
+    let resource_groups = resource_group_source
+        .retrieve_all(&query_params, &permission_filter)
+        .await?;
+    let total = resource_group_source
+        .count(&query_params, &permission_filter)
+        .await?;
 
     trace!(
         client_id = user.sub,
@@ -51,7 +56,12 @@ pub async fn get_all(
         resource_groups.len()
     );
 
-    Ok(Json(resource_groups))
+    Ok(Page::new(
+        resource_groups,
+        total,
+        &query_params,
+        query.as_deref(),
+    ))
 }
 
 pub async fn get(
@@ -82,7 +92,6 @@ pub async fn get(
 }
 
 pub async fn add(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(resource_group_source): State<Arc<dyn ResourceGroupCrud>>,
@@ -105,7 +114,6 @@ pub async fn add(
     );
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -117,12 +125,7 @@ pub async fn add(
     Ok((StatusCode::CREATED, Json(resource_group)))
 }
 
-#[expect(
-    clippy::too_many_arguments,
-    reason = "Handler needs access to a lot of the state"
-)]
 pub async fn edit(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(resource_group_source): State<Arc<dyn ResourceGroupCrud>>,
@@ -130,6 +133,64 @@ pub async fn edit(
     Path(id): Path<ResourceGroupId>,
     User(user): User,
     ValidatedJson(update): ValidatedJson<BlResourceGroupRequest>,
+) -> AppResponse<ResourceGroup> {
+    if !user.has_scope(Scope::WriteVensBl) {
+        return Err(AppError::Forbidden("Missing 'write_vens_bl' scope"));
+    }
+
+    let lock = resource_group_source.lock(&id).await?;
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        ValidatedJson(update),
+    )
+    .await
+}
+
+/// Applies a merge patch or JSON patch to the resource group, as if the result was sent with `PUT`
+pub async fn patch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(resource_group_source): State<Arc<dyn ResourceGroupCrud>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<ResourceGroupId>,
+    User(user): User,
+    patch: Patch,
+) -> AppResponse<ResourceGroup> {
+    if !user.has_scope(Scope::WriteVensBl) {
+        return Err(AppError::Forbidden("Missing 'write_vens_bl' scope"));
+    }
+    // fails if the client may not see the resource group
+    let _ = get(
+        State(resource_group_source.clone()),
+        Path(id.clone()),
+        User(user.clone()),
+    )
+    .await?;
+
+    let mut lock = resource_group_source.lock(&id).await?;
+    let content = patch.apply(&lock.current().await?.content)?;
+
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        ValidatedJson(content),
+    )
+    .await
+}
+
+/// Updates the resource group through the `lock`, which [`edit`] and [`patch`] take
+/// after checking the scopes, and before reading the resource group
+async fn edit_locked(
+    lock: Box<dyn Locked<BlResourceGroupRequest, ResourceGroup, Option<ClientId>, AppError>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    ValidatedJson(update): ValidatedJson<BlResourceGroupRequest>,
 ) -> AppResponse<ResourceGroup> {
     let new_resource_group = BlResourceGroupRequest {
         resource_group_name: update.resource_group_name,
@@ -138,13 +199,7 @@ pub async fn edit(
         children: update.children,
     };
 
-    let resource_group = if user.has_scope(Scope::WriteVensBl) {
-        resource_group_source
-            .update(&id, new_resource_group, &None)
-            .await?
-    } else {
-        return Err(AppError::Forbidden("Missing 'write_vens_bl' scope"));
-    };
+    let resource_group = lock.update(new_resource_group, &None).await?;
 
     info!(
         %resource_group.id,
@@ -153,7 +208,6 @@ pub async fn edit(
     );
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -166,7 +220,6 @@ pub async fn edit(
 }
 
 pub async fn delete(
-    State(ven_source): State<Arc<dyn VenCrud>>,
     State(event_source): State<Arc<dyn EventCrud>>,
     State(privacy): State<Arc<dyn VenObjectPrivacy>>,
     State(resource_group_source): State<Arc<dyn ResourceGroupCrud>>,
@@ -183,7 +236,6 @@ pub async fn delete(
     info!(%id, "deleted resource group");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -197,6 +249,7 @@ pub async fn delete(
 
 #[derive(Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_cursor"))]
 pub struct QueryParams {
     #[validate(length(min = 1, max = 128))]
     pub(crate) resource_group_name: Option<String>,
@@ -207,6 +260,22 @@ pub struct QueryParams {
     #[validate(range(min = 1, max = 50))]
     #[serde(default = "get_50")]
     pub(crate) limit: i64,
+    #[serde(default, deserialize_with = "deserialize_cursor")]
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Paginated for QueryParams {
+    fn skip(&self) -> i64 {
+        self.skip
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
 }
 
 fn get_50() -> i64 {
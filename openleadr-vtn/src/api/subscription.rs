@@ -1,8 +1,16 @@
-use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
     routing::MethodRouter,
 };
 #[cfg(feature = "experimental-websockets")]
@@ -10,27 +18,36 @@ use axum::{
     extract::ws::{Message, WebSocketUpgrade},
     response::Response,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use openleadr_wire::{
     ClientId, Identifier, ObjectType,
     program::ProgramId,
     subscription::{
         AnyObject, MqttNotifierAuthentication, MqttNotifierBindingObject, MqttPushNotification,
-        Notification, NotifierOperationsTopics, NotifierTopicsResponse, NotifiersResponse,
-        Operation, SerializationType, Subscription, SubscriptionId, SubscriptionRequest,
+        Notification, NotificationBatch, NotificationMechanism, NotifierOperationsTopics,
+        NotifierTopicsResponse, NotifiersResponse, Operation, SerializationType, Subscription,
+        SubscriptionId, SubscriptionRequest,
     },
 };
-use paho_mqtt::QoS;
 use reqwest::StatusCode;
 use serde::Deserialize;
-use tokio::sync::{Mutex, mpsc};
-use tracing::{error, info, trace};
+use tokio::sync::{Mutex, Notify, mpsc, watch};
+use tracing::{error, info, trace, warn};
 use uuid::{ContextV7, Uuid};
 use validator::Validate;
 
 use crate::{
-    api::{AppResponse, ValidatedJson, ValidatedQuery},
-    data_source::{EventCrud, SubscriptionCrud, VenCrud, VenObjectPrivacy},
+    api::{
+        AppResponse, Patch, ValidatedJson, ValidatedQuery,
+        fanout::{
+            CachedPrivacy, Delivery, DeliveryWorkers, MergeKey, SubscriptionIndex,
+            VenVisibilityCache, VenVisibilitySnapshot, merge_key,
+        },
+        mqtt_auth::MqttSessions,
+        pagination::{Cursor, Page, Paginated, deserialize_cursor, validate_cursor},
+    },
+    correlation::Origin,
+    data_source::{EventCrud, Locked, SubscriptionCrud, VenObjectPrivacy},
     error::AppError,
     jwt::{Claims, Scope, User},
     state::AppState,
@@ -39,14 +56,95 @@ use crate::{
 struct MqttState {
     url: String,
     client: paho_mqtt::AsyncClient,
+    username: String,
+    password: String,
     topic_prefix: String,
+    auth_hook_secret: Option<String>,
+    batch_window: Option<Duration>,
 }
 
 pub(crate) struct NotifierState {
     uuidv7_context: Arc<Mutex<ContextV7>>,
-    websockets: Mutex<HashMap<ClientId, (mpsc::UnboundedSender<Notification>, Claims)>>,
-    subscriptions: Mutex<HashMap<SubscriptionId, Subscription>>,
+    websockets: Mutex<HashMap<ClientId, WebsocketConnection>>,
+    subscriptions: Mutex<SubscriptionIndex>,
     mqtt_state: Option<MqttState>,
+    mqtt_sessions: Mutex<MqttSessions>,
+    /// Invalidated on every change to a VEN, resource, or resource group
+    ven_visibility: VenVisibilityCache,
+    pub(crate) deliveries: DeliveryWorkers,
+    /// Webhook subscriptions are dropped if their callback failed for this long
+    pub(crate) webhook_failure_timeout: Duration,
+    /// Signaled whenever an event is created, updated or deleted,
+    /// such that the event lifecycle notifications can be rescheduled
+    pub(crate) events_changed: Notify,
+    /// Set once the subscriptions are loaded from the storage, before the VTN serves requests
+    loaded: AtomicBool,
+    /// Set once the VTN shuts down, to reject new subscriptions and websocket connections
+    shutting_down: AtomicBool,
+    /// The number of running websocket connection tasks
+    websocket_tasks: watch::Sender<usize>,
+}
+
+/// A notification for an open websocket connection
+pub(crate) struct WebsocketNotification {
+    notification: Notification,
+    /// Collect the notifications on this connection for this long and send them together
+    batch_window: Option<Duration>,
+}
+
+#[derive(Clone)]
+pub(crate) struct WebsocketConnection {
+    tx: mpsc::UnboundedSender<WebsocketNotification>,
+    claims: Claims,
+    pub(crate) connected_date_time: DateTime<Utc>,
+    /// Notifications sent to the connection task that it did not take yet
+    queue_depth: Arc<AtomicUsize>,
+    /// Signaled to close the connection
+    disconnect: Arc<Notify>,
+}
+
+impl WebsocketConnection {
+    fn new(tx: mpsc::UnboundedSender<WebsocketNotification>, claims: Claims) -> Self {
+        Self {
+            tx,
+            claims,
+            connected_date_time: Utc::now(),
+            queue_depth: Arc::default(),
+            disconnect: Arc::default(),
+        }
+    }
+
+    fn send(&self, notification: WebsocketNotification) {
+        if self.tx.send(notification).is_ok() {
+            self.queue_depth.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+}
+
+/// Batch windows of subscriptions are capped, such that notifications are not delayed arbitrarily
+const MAX_BATCH_WINDOW: Duration = Duration::from_secs(60);
+
+/// The batch window of a subscription, if it batches notifications at all
+fn batch_window(subscription: &Subscription) -> Option<Duration> {
+    let now = Utc::now();
+    subscription
+        .content
+        .batch_window
+        .as_ref()
+        .and_then(|window| window.to_chrono_at_datetime(now).to_std().ok())
+        .filter(|window| !window.is_zero())
+        .map(|window| window.min(MAX_BATCH_WINDOW))
+}
+
+fn webhook_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("HTTP client for webhooks must be constructible")
 }
 
 pub(crate) struct MqttConfig {
@@ -54,12 +152,15 @@ pub(crate) struct MqttConfig {
     pub(crate) username: String,
     pub(crate) password: String,
     pub(crate) topic_prefix: String,
+    pub(crate) auth_hook_secret: Option<String>,
+    pub(crate) batch_window: Option<Duration>,
 }
 
 impl NotifierState {
     pub(crate) async fn load_from_storage(
         storage: &dyn SubscriptionCrud,
         mqtt_config: Option<MqttConfig>,
+        webhook_failure_timeout: Duration,
     ) -> Result<Self, AppError> {
         let subscriptions = storage
             .retrieve_all(
@@ -69,10 +170,16 @@ impl NotifierState {
                     objects: None,
                     skip: 0,
                     limit: i64::MAX,
+                    cursor: None,
                 },
                 &None,
             )
             .await?;
+        let mut owner_scopes = storage.owner_scopes().await?;
+        let subscriptions = subscriptions.into_iter().map(|subscription| {
+            let scopes = owner_scopes.remove(&subscription.id).unwrap_or_default();
+            (subscription, scopes)
+        });
 
         let mqtt_state = if let Some(mqtt_config) = mqtt_config {
             let mqtt_client = paho_mqtt::AsyncClient::new(paho_mqtt::CreateOptions::new())?;
@@ -80,8 +187,8 @@ impl NotifierState {
                 .connect(
                     paho_mqtt::ConnectOptionsBuilder::new()
                         .server_uris(&[&mqtt_config.url])
-                        .user_name(mqtt_config.username)
-                        .password(mqtt_config.password)
+                        .user_name(&mqtt_config.username)
+                        .password(&mqtt_config.password)
                         .automatic_reconnect(Duration::from_millis(1), Duration::from_secs(16))
                         .finalize(),
                 )
@@ -90,7 +197,11 @@ impl NotifierState {
             Some(MqttState {
                 url: mqtt_config.url,
                 client: mqtt_client,
+                username: mqtt_config.username,
+                password: mqtt_config.password,
                 topic_prefix: mqtt_config.topic_prefix,
+                auth_hook_secret: mqtt_config.auth_hook_secret,
+                batch_window: mqtt_config.batch_window,
             })
         } else {
             None
@@ -99,22 +210,236 @@ impl NotifierState {
         Ok(Self {
             uuidv7_context: Arc::new(Mutex::new(ContextV7::new())),
             websockets: Mutex::new(HashMap::new()),
-            subscriptions: Mutex::new(
-                subscriptions
-                    .into_iter()
-                    .map(|subscription| (subscription.id.clone(), subscription))
-                    .collect(),
+            subscriptions: Mutex::new(subscriptions.collect()),
+            deliveries: DeliveryWorkers::spawn(
+                mqtt_state
+                    .as_ref()
+                    .map(|mqtt_state| mqtt_state.client.clone()),
+                mqtt_state
+                    .as_ref()
+                    .and_then(|mqtt_state| mqtt_state.batch_window),
+                webhook_client(),
             ),
             mqtt_state,
+            mqtt_sessions: Mutex::new(MqttSessions::default()),
+            ven_visibility: VenVisibilityCache::new(),
+            webhook_failure_timeout,
+            events_changed: Notify::new(),
+            loaded: AtomicBool::new(true),
+            shutting_down: AtomicBool::new(false),
+            websocket_tasks: watch::Sender::new(0),
+        })
+    }
+
+    /// The prefix of all MQTT topics, or `None` if MQTT is not configured
+    pub(crate) fn mqtt_topic_prefix(&self) -> Option<&str> {
+        self.mqtt_state
+            .as_ref()
+            .map(|mqtt_state| mqtt_state.topic_prefix.as_str())
+    }
+
+    /// The secret the MQTT broker authenticates to the auth hooks with,
+    /// or `None` if MQTT or the hooks are not configured
+    pub(crate) fn mqtt_auth_hook_secret(&self) -> Option<&str> {
+        self.mqtt_state
+            .as_ref()
+            .and_then(|mqtt_state| mqtt_state.auth_hook_secret.as_deref())
+    }
+
+    /// Whether the given credentials belong to the account the VTN itself uses to publish
+    pub(crate) fn is_vtn_mqtt_account(&self, username: &str, password: Option<&str>) -> bool {
+        self.mqtt_state.as_ref().is_some_and(|mqtt_state| {
+            mqtt_state.username == username
+                && password.is_none_or(|password| mqtt_state.password == password)
         })
     }
+
+    #[cfg(test)]
+    pub(crate) async fn insert_websocket_for_test(
+        &self,
+        client_id: ClientId,
+        tx: mpsc::UnboundedSender<WebsocketNotification>,
+        claims: Claims,
+    ) {
+        self.websockets
+            .lock()
+            .await
+            .insert(client_id, WebsocketConnection::new(tx, claims));
+    }
+
+    /// The open websocket connections, by client
+    pub(crate) async fn websocket_connections(&self) -> Vec<(ClientId, WebsocketConnection)> {
+        self.websockets
+            .lock()
+            .await
+            .iter()
+            .map(|(client_id, connection)| (client_id.clone(), connection.clone()))
+            .collect()
+    }
+
+    /// Closes the websocket connection of the client, if it has one
+    pub(crate) async fn disconnect_websocket(
+        &self,
+        client_id: &ClientId,
+    ) -> Option<WebsocketConnection> {
+        let connection = self.websockets.lock().await.remove(client_id)?;
+        connection.disconnect.notify_one();
+        Some(connection)
+    }
+
+    /// Whether the subscriptions are loaded, such that notifications reach all subscribers
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Relaxed)
+    }
+
+    /// Whether the VTN is shutting down and rejects new subscriptions and websocket connections
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// Rejects new subscriptions and websocket connections from now on,
+    /// and closes the open websocket connections after sending their queued notifications
+    pub(crate) async fn stop_accepting(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+        for (client_id, connection) in self.websockets.lock().await.drain() {
+            trace!(%client_id, "closing websocket connection for shutdown");
+            connection.disconnect.notify_one();
+        }
+    }
+
+    /// Stops accepting new work, delivers the queued notifications, and disconnects from
+    /// the MQTT broker. Notifications that are not delivered by `deadline` are dropped.
+    pub(crate) async fn shutdown(&self, deadline: tokio::time::Instant) {
+        self.stop_accepting().await;
+
+        let mut websocket_tasks = self.websocket_tasks.subscribe();
+        if tokio::time::timeout_at(deadline, websocket_tasks.wait_for(|&tasks| tasks == 0))
+            .await
+            .is_err()
+        {
+            warn!("Shutdown deadline reached before all websocket connections were closed");
+        }
+
+        self.deliveries.shutdown(deadline).await;
+
+        if let Some(mqtt_state) = &self.mqtt_state {
+            let timeout = deadline.saturating_duration_since(tokio::time::Instant::now());
+            let options = paho_mqtt::DisconnectOptionsBuilder::new()
+                .timeout(timeout)
+                .finalize();
+            match tokio::time::timeout_at(deadline, mqtt_state.client.disconnect(options)).await {
+                Ok(Ok(_)) => info!(url = mqtt_state.url, "disconnected from MQTT broker"),
+                Ok(Err(err)) => warn!("Could not disconnect from MQTT broker: {}", err),
+                Err(_) => warn!("Shutdown deadline reached before disconnecting from MQTT broker"),
+            }
+        }
+    }
+
+    /// The URL of the MQTT broker and whether the VTN is currently connected to it,
+    /// or `None` if MQTT is not configured
+    pub(crate) fn mqtt_connection(&self) -> Option<(&str, bool)> {
+        self.mqtt_state
+            .as_ref()
+            .map(|mqtt_state| (mqtt_state.url.as_str(), mqtt_state.client.is_connected()))
+    }
+
+    /// The visibility of all VENs, cached the same way as for fanning out notifications
+    pub(crate) async fn ven_visibility(
+        &self,
+        privacy: &dyn VenObjectPrivacy,
+    ) -> Result<Arc<VenVisibilitySnapshot>, AppError> {
+        self.ven_visibility.get(privacy).await
+    }
+
+    /// The number of subscriptions notifications are currently fanned out to
+    pub(crate) async fn subscription_count(&self) -> usize {
+        self.subscriptions.lock().await.len()
+    }
+
+    /// Sends a [`Operation::Test`] notification through the webhooks and the websocket
+    /// of the subscription, regardless of the operations it subscribed to
+    pub(crate) async fn send_test_notification(&self, subscription: Subscription) -> Notification {
+        let notification = Notification {
+            id: self.next_notification_id().await,
+            operation: Operation::Test,
+            object: AnyObject::Subscription(subscription.clone()),
+        };
+
+        if let Some(connection) = self.websockets.lock().await.get(&subscription.client_id) {
+            connection.send(WebsocketNotification {
+                notification: notification.clone(),
+                batch_window: None,
+            });
+        }
+
+        for object_operation in &subscription.content.object_operations {
+            if object_operation.mechanism == NotificationMechanism::Webhook
+                && let Some(callback_url) = &object_operation.callback_url
+            {
+                self.deliveries.deliver(Delivery::Webhook {
+                    subscription_id: subscription.id.clone(),
+                    callback_url: callback_url.clone(),
+                    bearer_token: object_operation.bearer_token.clone(),
+                    notification: Box::new(notification.clone()),
+                    batch_window: None,
+                    origin: Origin::current(),
+                });
+            }
+        }
+
+        notification
+    }
+
+    /// Removes the websocket connection of the client after it closed,
+    /// unless the client opened another connection in the meantime
+    #[cfg(feature = "experimental-websockets")]
+    async fn forget_websocket(&self, client_id: &ClientId, disconnect: &Arc<Notify>) {
+        let mut websockets = self.websockets.lock().await;
+        if websockets
+            .get(client_id)
+            .is_some_and(|connection| Arc::ptr_eq(&connection.disconnect, disconnect))
+        {
+            websockets.remove(client_id);
+        }
+    }
+
+    async fn next_notification_id(&self) -> Identifier {
+        Uuid::new_v7(uuid::Timestamp::now(&*self.uuidv7_context.lock().await))
+            .to_string()
+            .parse()
+            .expect("uuid should always be a valid identifier")
+    }
+
+    #[cfg(test)]
+    pub(crate) async fn insert_subscription_for_test(&self, subscription: Subscription) {
+        self.subscriptions.lock().await.insert(subscription, vec![]);
+    }
+
+    /// The webhook subscriptions whose callbacks failed for longer than the configured timeout
+    pub(crate) fn failing_webhooks(&self) -> Vec<SubscriptionId> {
+        let since = chrono::Duration::from_std(self.webhook_failure_timeout)
+            .ok()
+            .and_then(|timeout| Utc::now().checked_sub_signed(timeout))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        self.deliveries.failing_webhooks(since)
+    }
+
+    pub(crate) async fn insert_mqtt_session(&self, username: String, claims: Claims) {
+        self.mqtt_sessions.lock().await.insert(username, claims);
+    }
+
+    /// Returns the claims of an MQTT client that authenticated earlier, if they did not expire yet
+    pub(crate) async fn mqtt_session(&self, username: &str) -> Option<Claims> {
+        self.mqtt_sessions.lock().await.get(username)
+    }
 }
 
 pub async fn get_all(
     State(subscription_source): State<Arc<dyn SubscriptionCrud>>,
     ValidatedQuery(query_params): ValidatedQuery<QueryParams>,
     User(user): User,
-) -> AppResponse<Vec<Subscription>> {
+    RawQuery(query): RawQuery,
+) -> Result<Page<Subscription>, AppError> {
     trace!(?query_params);
 
     // FIXME update retrieve_all implementation when removing this
@@ -128,19 +453,21 @@ pub async fn get_all(
         return Err(AppError::BadRequest(error));
     }
 
-    let resources = if user.has_scope(Scope::ReadAll) {
-        subscription_source
-            .retrieve_all(&query_params, &None)
-            .await?
+    let permission_filter = if user.has_scope(Scope::ReadAll) {
+        None
     } else if user.has_scope(Scope::ReadVenObjects) {
-        subscription_source
-            .retrieve_all(&query_params, &Some(user.client_id()?))
-            .await?
+        Some(user.client_id()?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'read_all' or 'read_ven_objects' scope",
         ));
     };
+    let resources = subscription_source
+        .retrieve_all(&query_params, &permission_filter)
+        .await?;
+    let total = subscription_source
+        .count(&query_params, &permission_filter)
+        .await?;
 
     trace!(
         client_id = user.sub,
@@ -148,7 +475,7 @@ pub async fn get_all(
         resources.len()
     );
 
-    Ok(Json(resources))
+    Ok(Page::new(resources, total, &query_params, query.as_deref()))
 }
 
 pub async fn get(
@@ -186,11 +513,16 @@ pub async fn add(
 ) -> Result<(StatusCode, Json<Subscription>), AppError> {
     let client_id = user.client_id()?;
 
+    if app_state.notifier.is_shutting_down() {
+        return Err(AppError::ServiceUnavailable("the VTN is shutting down"));
+    }
+
+    let owner_scopes = user.read_scopes();
     let subscription = if user.has_scope(Scope::WriteSubscriptionsVen)
         || user.has_scope(Scope::WriteSubscriptionsBl)
     {
         subscription_source
-            .create(new_subscription, &Some(client_id))
+            .create_with_owner_scopes(new_subscription, &client_id, &owner_scopes)
             .await?
     } else {
         return Err(AppError::Forbidden("Missing 'write_vens' scope"));
@@ -201,7 +533,7 @@ pub async fn add(
         .subscriptions
         .lock()
         .await
-        .insert(subscription.id.clone(), subscription.clone());
+        .insert(subscription.clone(), owner_scopes);
 
     info!(
         %subscription.id,
@@ -219,15 +551,54 @@ pub async fn edit(
     Path(id): Path<SubscriptionId>,
     User(user): User,
     ValidatedJson(update): ValidatedJson<SubscriptionRequest>,
+) -> AppResponse<Subscription> {
+    if !user.has_scope(Scope::WriteSubscriptionsBl) && !user.has_scope(Scope::WriteSubscriptionsVen)
+    {
+        return Err(AppError::Forbidden("Missing 'write_subscriptions' scope"));
+    }
+
+    let lock = subscription_source.lock(&id).await?;
+    edit_locked(lock, State(app_state), User(user), ValidatedJson(update)).await
+}
+
+/// Applies a merge patch or JSON patch to the subscription, as if the result was sent with `PUT`
+pub async fn patch(
+    State(subscription_source): State<Arc<dyn SubscriptionCrud>>,
+    State(app_state): State<AppState>,
+    Path(id): Path<SubscriptionId>,
+    User(user): User,
+    patch: Patch,
+) -> AppResponse<Subscription> {
+    if !user.has_scope(Scope::WriteSubscriptionsBl) && !user.has_scope(Scope::WriteSubscriptionsVen)
+    {
+        return Err(AppError::Forbidden("Missing 'write_subscriptions' scope"));
+    }
+    // fails if the client may not see the subscription
+    let _ = get(
+        State(subscription_source.clone()),
+        Path(id.clone()),
+        User(user.clone()),
+    )
+    .await?;
+
+    let mut lock = subscription_source.lock(&id).await?;
+    let content = patch.apply(&lock.current().await?.content)?;
+
+    edit_locked(lock, State(app_state), User(user), ValidatedJson(content)).await
+}
+
+/// Updates the subscription through the `lock`, which [`edit`] and [`patch`] take
+/// after checking the scopes, and before reading the subscription
+async fn edit_locked(
+    lock: Box<dyn Locked<SubscriptionRequest, Subscription, Option<ClientId>, AppError>>,
+    State(app_state): State<AppState>,
+    User(user): User,
+    ValidatedJson(update): ValidatedJson<SubscriptionRequest>,
 ) -> AppResponse<Subscription> {
     let subscription = if user.has_scope(Scope::WriteSubscriptionsBl) {
-        subscription_source.update(&id, update, &None).await?
-    } else if user.has_scope(Scope::WriteSubscriptionsVen) {
-        subscription_source
-            .update(&id, update, &Some(user.client_id()?))
-            .await?
+        lock.update(update, &None).await?
     } else {
-        return Err(AppError::Forbidden("Missing 'write_subscriptions' scope"));
+        lock.update(update, &Some(user.client_id()?)).await?
     };
 
     app_state
@@ -235,7 +606,7 @@ pub async fn edit(
         .subscriptions
         .lock()
         .await
-        .insert(subscription.id.clone(), subscription.clone());
+        .update(subscription.clone());
 
     info!(
         %subscription.id,
@@ -269,14 +640,78 @@ pub async fn delete(
         .lock()
         .await
         .remove(&subscription.id);
+    app_state.notifier.deliveries.forget(&subscription.id);
 
     info!(%id, client_id = user.sub, "deleted subscription");
 
     Ok(Json(subscription))
 }
 
+pub async fn renew(
+    State(subscription_source): State<Arc<dyn SubscriptionCrud>>,
+    State(app_state): State<AppState>,
+    Path(id): Path<SubscriptionId>,
+    User(user): User,
+) -> AppResponse<Subscription> {
+    let subscription = if user.has_scope(Scope::WriteSubscriptionsBl) {
+        subscription_source.renew(&id, &None).await?
+    } else if user.has_scope(Scope::WriteSubscriptionsVen) {
+        subscription_source
+            .renew(&id, &Some(user.client_id()?))
+            .await?
+    } else {
+        return Err(AppError::Forbidden("Missing 'write_subscriptions' scope"));
+    };
+
+    app_state
+        .notifier
+        .subscriptions
+        .lock()
+        .await
+        .update(subscription.clone());
+
+    trace!(
+        %subscription.id,
+        expires_date_time=?subscription.expires_date_time,
+        client_id = user.sub,
+        "renewed subscription"
+    );
+
+    Ok(Json(subscription))
+}
+
+/// Removes subscriptions that were deleted from the storage without a request to delete them,
+/// e.g., because they expired or their client was deleted, and notifies about their deletion.
+pub(crate) async fn forget_deleted(
+    event_source: &dyn EventCrud,
+    privacy: &dyn VenObjectPrivacy,
+    notifier_state: &NotifierState,
+    subscriptions: Vec<Subscription>,
+) {
+    for subscription in subscriptions {
+        notifier_state
+            .subscriptions
+            .lock()
+            .await
+            .remove(&subscription.id);
+        notifier_state.deliveries.forget(&subscription.id);
+
+        info!(%subscription.id, client_id = %subscription.client_id, "dropped subscription");
+
+        notify(
+            event_source,
+            privacy,
+            notifier_state,
+            Operation::Delete,
+            AnyObject::Subscription(subscription),
+        )
+        .await;
+    }
+}
+
 #[derive(Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_cursor"))]
 pub struct QueryParams {
     #[serde(rename = "programID")]
     pub(crate) program_id: Option<ProgramId>,
@@ -290,6 +725,22 @@ pub struct QueryParams {
     #[validate(range(min = 1, max = 50))]
     #[serde(default = "get_50")]
     pub(crate) limit: i64,
+    #[serde(default, deserialize_with = "deserialize_cursor")]
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Paginated for QueryParams {
+    fn skip(&self) -> i64 {
+        self.skip
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
 }
 
 fn get_50() -> i64 {
@@ -393,19 +844,13 @@ async fn privacy_filter_object(
 }
 
 pub(crate) async fn notify(
-    ven_source: &dyn VenCrud,
     event_source: &dyn EventCrud,
     privacy: &dyn VenObjectPrivacy,
     notifier_state: &NotifierState,
     operation: Operation,
     object: AnyObject,
 ) {
-    let uuid: Identifier = Uuid::new_v7(uuid::Timestamp::now(
-        &*notifier_state.uuidv7_context.lock().await,
-    ))
-    .to_string()
-    .parse()
-    .expect("uuid should always be a valid identifier");
+    let uuid = notifier_state.next_notification_id().await;
 
     let event_program_id;
     let target_program_id = match &object {
@@ -427,9 +872,20 @@ pub(crate) async fn notify(
 
     trace!(id = %object.id(), object = ?object, "notify {operation:?}");
 
+    if matches!(object, AnyObject::Event(_)) && !operation.is_lifecycle() {
+        notifier_state.events_changed.notify_one();
+    }
+
+    if matches!(
+        object,
+        AnyObject::Ven(_) | AnyObject::Resource(_) | AnyObject::ResourceGroup(_)
+    ) {
+        notifier_state.ven_visibility.invalidate().await;
+    }
+    let privacy = notifier_state.ven_visibility.privacy(privacy);
+
     notify_mqtt(
-        ven_source,
-        privacy,
+        &privacy,
         notifier_state,
         Notification {
             id: uuid.clone(),
@@ -439,133 +895,173 @@ pub(crate) async fn notify(
     )
     .await;
 
-    for subscription in notifier_state.subscriptions.lock().await.values() {
-        let program_id = subscription.content.program_id.as_ref();
+    // don't hold the lock while filtering and delivering the notifications
+    let subscriptions = notifier_state
+        .subscriptions
+        .lock()
+        .await
+        .matching(object.kind(), target_program_id);
+
+    let now = Utc::now();
+    for subscriber in subscriptions {
+        let subscription = &subscriber.subscription;
+        // expired subscriptions are deleted periodically, until then they are ignored
+        if subscription
+            .expires_date_time
+            .is_some_and(|expires_date_time| expires_date_time <= now)
+        {
+            continue;
+        }
+        let batch_window = batch_window(subscription);
 
         for object_operation in &subscription.content.object_operations {
             if !object_operation.operations.contains(&operation)
                 || !object_operation.objects.contains(&object.kind())
-                || (program_id.is_some() && program_id != target_program_id)
             {
                 continue;
             }
 
-            if let Some((tx, claims)) = notifier_state
+            let websocket = notifier_state
                 .websockets
                 .lock()
                 .await
                 .get(&subscription.client_id)
-                && let Some(object) =
-                    privacy_filter_object(&object, privacy, &subscription.client_id, claims).await
+                .cloned();
+            if let Some(connection) = websocket
+                && let Some(object) = privacy_filter_object(
+                    &object,
+                    privacy.get().await,
+                    &subscription.client_id,
+                    &connection.claims,
+                )
+                .await
             {
-                let _ = tx.send(Notification {
-                    id: uuid.clone(),
-                    operation,
-                    object: object.clone(),
+                connection.send(WebsocketNotification {
+                    notification: Notification {
+                        id: uuid.clone(),
+                        operation,
+                        object: object.clone(),
+                    },
+                    batch_window,
+                });
+            }
+
+            if object_operation.mechanism == NotificationMechanism::Webhook
+                && let Some(callback_url) = &object_operation.callback_url
+                && let Some(object) = privacy_filter_object(
+                    &object,
+                    privacy.get().await,
+                    &subscription.client_id,
+                    &Claims::temporary_claims_for_webhook(
+                        &subscription.client_id,
+                        &subscriber.owner_scopes,
+                    ),
+                )
+                .await
+            {
+                notifier_state.deliveries.deliver(Delivery::Webhook {
+                    subscription_id: subscription.id.clone(),
+                    callback_url: callback_url.clone(),
+                    bearer_token: object_operation.bearer_token.clone(),
+                    notification: Box::new(Notification {
+                        id: uuid.clone(),
+                        operation,
+                        object,
+                    }),
+                    batch_window,
+                    origin: Origin::current(),
                 });
             }
         }
     }
 }
 
-async fn publish_mqtt_push(
+/// The last level of the MQTT topics notifications for an operation are published to
+pub(crate) fn operation_topic(operation: Operation) -> &'static str {
+    match operation {
+        Operation::Create => "create",
+        Operation::Update => "update",
+        Operation::Delete => "delete",
+        Operation::EventStarted => "event_started",
+        Operation::IntervalStarted => "interval_started",
+        Operation::EventEnded => "event_ended",
+        Operation::Test => "test",
+        // `Operation` is non-exhaustive, but all its variants are listed above
+        _ => "other",
+    }
+}
+
+fn publish_mqtt_push(
+    notifier_state: &NotifierState,
     mqtt_state: &MqttState,
+    merge_key: &Option<MergeKey>,
     notification: Vec<u8>,
     push_notification: Vec<u8>,
     topic: &str,
 ) {
-    if let Err(err) = mqtt_state
-        .client
-        .publish(paho_mqtt::Message::new(
-            format!("{}{}", mqtt_state.topic_prefix, topic),
-            notification,
-            QoS::AtMostOnce,
-        ))
-        .await
-    {
-        tracing::warn!(
-            topic = format!("{}{}", mqtt_state.topic_prefix, topic),
-            "Could not send mqtt notification: {}",
-            err
-        )
-    }
-    if let Err(err) = mqtt_state
-        .client
-        .publish(paho_mqtt::Message::new(
-            format!("{}push/{}", mqtt_state.topic_prefix, topic),
-            push_notification,
-            QoS::AtMostOnce,
-        ))
-        .await
-    {
-        tracing::warn!(
-            topic = format!("{}push/{}", mqtt_state.topic_prefix, topic),
-            "Could not send mqtt notification: {}",
-            err
-        )
-    }
+    notifier_state.deliveries.deliver(Delivery::Mqtt {
+        topic: format!("{}{}", mqtt_state.topic_prefix, topic),
+        payload: notification,
+        merge_key: merge_key.clone(),
+        origin: Origin::current(),
+    });
+    notifier_state.deliveries.deliver(Delivery::Mqtt {
+        topic: format!("{}push/{}", mqtt_state.topic_prefix, topic),
+        payload: push_notification,
+        merge_key: merge_key.clone(),
+        origin: Origin::current(),
+    });
 }
 
 async fn publish_mqtt_push_by_targets(
-    ven_source: &dyn VenCrud,
-    privacy: &dyn VenObjectPrivacy,
+    privacy: &CachedPrivacy<'_>,
+    notifier_state: &NotifierState,
     mqtt_state: &MqttState,
+    merge_key: &Option<MergeKey>,
     notification: &Notification,
     push_notification: Vec<u8>,
     topic: &str,
 ) {
-    if let Ok(vens) = ven_source
-        .retrieve_all(
-            &super::ven::QueryParams {
-                ven_name: None,
-                targets: crate::api::TargetQueryParams(None),
-                skip: 0,
-                limit: i64::MAX,
-            },
-            &None,
+    let Some(snapshot) = privacy.snapshot().await else {
+        return;
+    };
+
+    for ven in snapshot.vens() {
+        if let Some(object) = privacy_filter_object(
+            &notification.object,
+            snapshot,
+            &ven.client_id,
+            &Claims::temporary_claims_for_mqtt_ven(&ven.client_id),
         )
         .await
-    {
-        for ven in vens {
-            if let Some(object) = privacy_filter_object(
-                &notification.object,
-                privacy,
-                &ven.content.client_id,
-                &Claims::temporary_claims_for_mqtt_ven(&ven),
-            )
-            .await
-            {
-                publish_mqtt_push(
-                    mqtt_state,
-                    serde_json::to_vec(&Notification {
-                        id: notification.id.clone(),
-                        operation: notification.operation,
-                        object,
-                    })
-                    .unwrap(),
-                    push_notification.clone(),
-                    &format!("vens/{}/{}", ven.id, topic),
-                )
-                .await;
-            }
+        {
+            publish_mqtt_push(
+                notifier_state,
+                mqtt_state,
+                merge_key,
+                serde_json::to_vec(&Notification {
+                    id: notification.id.clone(),
+                    operation: notification.operation,
+                    object,
+                })
+                .unwrap(),
+                push_notification.clone(),
+                &format!("vens/{}/{}", ven.ven_id, topic),
+            );
         }
     }
 }
 
 async fn notify_mqtt(
-    ven_source: &dyn VenCrud,
-    privacy: &dyn VenObjectPrivacy,
+    privacy: &CachedPrivacy<'_>,
     notifier_state: &NotifierState,
     notification: Notification,
 ) {
     let notification_date_time = Utc::now();
-    let operation_str = match notification.operation {
-        Operation::Create => "create",
-        Operation::Update => "update",
-        Operation::Delete => "delete",
-    };
+    let operation_str = operation_topic(notification.operation);
 
     if let Some(mqtt_state) = &notifier_state.mqtt_state {
+        let merge_key = merge_key(&notification);
         let mqtt_notification = serde_json::to_vec(&notification).unwrap();
         let mqtt_push_notification = serde_json::to_vec(&MqttPushNotification {
             id: notification.object.id(),
@@ -579,50 +1075,56 @@ async fn notify_mqtt(
         match notification.object {
             AnyObject::Ven(ven) => {
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("vens/{operation_str}"),
-                )
-                .await;
+                );
                 if notification.operation != Operation::Create {
                     publish_mqtt_push(
+                        notifier_state,
                         mqtt_state,
+                        &merge_key,
                         mqtt_notification,
                         mqtt_push_notification,
                         &format!("vens/{}/{operation_str}", ven.id),
-                    )
-                    .await;
+                    );
                 }
             }
             AnyObject::Resource(resource) => {
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("resources/{operation_str}"),
-                )
-                .await;
+                );
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("vens/{}/resources/{operation_str}", resource.content.ven_id),
-                )
-                .await;
+                );
             }
             AnyObject::ResourceGroup(_) => {
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("resource_groups/{operation_str}"),
-                )
-                .await;
+                );
                 publish_mqtt_push_by_targets(
-                    ven_source,
                     privacy,
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     &notification,
                     mqtt_push_notification,
                     &format!("resource_groups/{operation_str}"),
@@ -631,23 +1133,26 @@ async fn notify_mqtt(
             }
             AnyObject::Program(ref program) => {
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("programs/{}/{operation_str}", program.id),
-                )
-                .await;
+                );
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("programs/{operation_str}"),
-                )
-                .await;
+                );
                 publish_mqtt_push_by_targets(
-                    ven_source,
                     privacy,
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     &notification,
                     mqtt_push_notification,
                     &format!("programs/{operation_str}"),
@@ -656,26 +1161,29 @@ async fn notify_mqtt(
             }
             AnyObject::Event(ref event) => {
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!(
                         "events/program/{}/{operation_str}",
                         event.content.program_id
                     ),
-                )
-                .await;
+                );
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("events/{operation_str}"),
-                )
-                .await;
+                );
                 publish_mqtt_push_by_targets(
-                    ven_source,
                     privacy,
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     &notification,
                     mqtt_push_notification,
                     &format!("events/{operation_str}"),
@@ -684,12 +1192,13 @@ async fn notify_mqtt(
             }
             AnyObject::Report(_) => {
                 publish_mqtt_push(
+                    notifier_state,
                     mqtt_state,
+                    &merge_key,
                     mqtt_notification.clone(),
                     mqtt_push_notification.clone(),
                     &format!("reports/{operation_str}"),
-                )
-                .await;
+                );
             }
             AnyObject::Subscription(_) => {}
         }
@@ -711,6 +1220,9 @@ pub(crate) async fn notifier_get(
                 authentication: MqttNotifierAuthentication::Oauth2BearerToken {
                     username: "{clientID}".to_owned(),
                 },
+                batch_window: mqtt_state
+                    .batch_window
+                    .map(|window| openleadr_wire::Duration::seconds(window.as_secs_f32())),
             }),
         push_mqtt: notifier_state
             .mqtt_state
@@ -721,6 +1233,9 @@ pub(crate) async fn notifier_get(
                 authentication: MqttNotifierAuthentication::Oauth2BearerToken {
                     username: "{clientID}".to_owned(),
                 },
+                batch_window: mqtt_state
+                    .batch_window
+                    .map(|window| openleadr_wire::Duration::seconds(window.as_secs_f32())),
             }),
     }))
 }
@@ -734,6 +1249,10 @@ pub(crate) async fn notifier_websocket_get(
     let client_id = user.client_id()?;
 
     let mut websockets = notifier_state.websockets.lock().await;
+    // checked while holding the lock, such that shutting down cannot miss this connection
+    if notifier_state.is_shutting_down() {
+        return Err(AppError::ServiceUnavailable("the VTN is shutting down"));
+    }
     if websockets.contains_key(&client_id) {
         // FIXME close existing connection instead
         return Err(AppError::Conflict(
@@ -742,23 +1261,130 @@ pub(crate) async fn notifier_websocket_get(
         ));
     }
     let (tx, mut rx) = mpsc::unbounded_channel(); // FIXME use bounded channel
-    websockets.insert(client_id.clone(), (tx, user));
+    let connection = WebsocketConnection::new(tx, user);
+    let queue_depth = Arc::clone(&connection.queue_depth);
+    let disconnect = Arc::clone(&connection.disconnect);
+    websockets.insert(client_id.clone(), connection);
     drop(websockets);
 
     Ok(ws.on_upgrade(|mut socket| async move {
-        while let Some(msg) = rx.recv().await {
-            if socket
-                .send(Message::Text(serde_json::to_string(&msg).unwrap().into()))
-                .await
-                .is_err()
-            {
-                break;
+        let _task = WebsocketTask::start(&notifier_state);
+        let mut batch = NotificationBatch::default();
+        let mut deadline = None;
+        loop {
+            let mut messages = Vec::new();
+            tokio::select! {
+                // disconnecting drops the sender as well, so check for it first to close the connection properly
+                biased;
+                _ = disconnect.notified() => {
+                    info!(%client_id, "closing websocket connection on request");
+                    // deliver what was queued before, e.g., when shutting down
+                    while let Ok(notification) = rx.try_recv() {
+                        queue_depth.fetch_sub(1, Ordering::Relaxed);
+                        push_websocket_message(notification, &mut batch, &mut deadline, &mut messages);
+                    }
+                    if !batch.is_empty() {
+                        messages.push(serde_json::to_string(&batch).unwrap());
+                    }
+                    for message in messages {
+                        let result = socket.send(Message::Text(message.into())).await;
+                        #[cfg(feature = "metrics")]
+                        crate::metrics::notification(crate::metrics::Binding::Websocket, result.is_ok());
+                        if result.is_err() {
+                            return;
+                        }
+                    }
+                    let _ = socket.send(Message::Close(None)).await;
+                    // Wait for the client to acknowledge the close frame,
+                    // as closing the TCP connection right away may reset it before the client read the frame
+                    let _ = tokio::time::timeout(Duration::from_secs(5), async {
+                        while let Some(Ok(_)) = socket.recv().await {}
+                    })
+                    .await;
+                    return;
+                }
+                msg = rx.recv() => match msg.inspect(|_| {
+                    queue_depth.fetch_sub(1, Ordering::Relaxed);
+                }) {
+                    Some(notification) => {
+                        push_websocket_message(notification, &mut batch, &mut deadline, &mut messages);
+                    }
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                    if deadline.is_some() =>
+                {
+                    deadline = None;
+                    messages.push(serde_json::to_string(&std::mem::take(&mut batch)).unwrap());
+                }
+            }
+
+            for message in messages {
+                let result = socket.send(Message::Text(message.into())).await;
+                #[cfg(feature = "metrics")]
+                crate::metrics::notification(crate::metrics::Binding::Websocket, result.is_ok());
+                if result.is_err() {
+                    notifier_state.forget_websocket(&client_id, &disconnect).await;
+                    return;
+                }
             }
         }
-        notifier_state.websockets.lock().await.remove(&client_id);
+        notifier_state.forget_websocket(&client_id, &disconnect).await;
     }))
 }
 
+/// Adds the notification to the current batch if its subscription batches notifications,
+/// otherwise to the messages to send right away
+#[cfg(feature = "experimental-websockets")]
+fn push_websocket_message(
+    notification: WebsocketNotification,
+    batch: &mut NotificationBatch,
+    deadline: &mut Option<tokio::time::Instant>,
+    messages: &mut Vec<String>,
+) {
+    match notification {
+        WebsocketNotification {
+            notification,
+            batch_window: Some(window),
+        } => {
+            batch.push(notification);
+            deadline.get_or_insert_with(|| tokio::time::Instant::now() + window);
+        }
+        WebsocketNotification {
+            notification,
+            batch_window: None,
+        } => {
+            // keep the order of batched and unbatched notifications
+            if !batch.is_empty() {
+                *deadline = None;
+                messages.push(serde_json::to_string(&std::mem::take(batch)).unwrap());
+            }
+            messages.push(serde_json::to_string(&notification).unwrap());
+        }
+    }
+}
+
+/// Counts a running websocket connection task, such that shutting down can wait for it
+#[cfg(feature = "experimental-websockets")]
+struct WebsocketTask<'a>(&'a NotifierState);
+
+#[cfg(feature = "experimental-websockets")]
+impl<'a> WebsocketTask<'a> {
+    fn start(notifier_state: &'a NotifierState) -> Self {
+        notifier_state
+            .websocket_tasks
+            .send_modify(|tasks| *tasks += 1);
+        Self(notifier_state)
+    }
+}
+
+#[cfg(feature = "experimental-websockets")]
+impl Drop for WebsocketTask<'_> {
+    fn drop(&mut self) {
+        self.0.websocket_tasks.send_modify(|tasks| *tasks -= 1);
+    }
+}
+
 pub(crate) fn mqtt_notifier() -> axum::Router<AppState> {
     axum::Router::new()
         // Public routes
@@ -801,6 +1427,9 @@ pub(crate) fn mqtt_notifier() -> axum::Router<AppState> {
             "/topics/vens/{ven_id}/resource_groups",
             mqtt_route_by_ven_id("resource_groups/", true),
         )
+        //
+        // Authentication and access control hooks for the MQTT broker
+        .nest("/auth", super::mqtt_auth::router())
 }
 
 pub(crate) fn push_mqtt_notifier() -> axum::Router<AppState> {
@@ -971,16 +1600,17 @@ fn mqtt_route_by_ven_id(
 #[cfg(test)]
 mod test {
     use std::{
-        collections::{BTreeSet, HashMap},
+        collections::{BTreeSet, HashMap, HashSet},
         sync::Arc,
         time::Duration,
     };
 
     use async_trait::async_trait;
     use axum::body::Body;
-    use chrono::DateTime;
+    use chrono::{DateTime, Utc};
     use openleadr_wire::{
         ClientId, Event, ObjectType, Program, Report, Ven,
+        batch::BatchOperation,
         event::{EventId, EventRequest, Priority},
         problem::Problem,
         program::ProgramRequest,
@@ -988,8 +1618,9 @@ mod test {
         resource::{BlResourceRequest, Resource, ResourceRequest},
         resource_group::ResourceGroupId,
         subscription::{
-            AnyObject, MqttPushNotification, Notification, NotificationMechanism, Operation,
-            Subscription, SubscriptionObjectOperation, SubscriptionRequest,
+            AnyObject, MqttPushNotification, MqttPushNotificationBatch, Notification,
+            NotificationBatch, NotificationMechanism, NotifiersResponse, Operation, Subscription,
+            SubscriptionObjectOperation, SubscriptionRequest,
         },
         target::Target,
         ven::{BlVenRequest, VenId, VenRequest},
@@ -1003,10 +1634,11 @@ mod test {
     use crate::{
         api::{
             self,
-            subscription::{NotifierState, notify, privacy_filter_object},
+            fanout::{DeliveryWorkers, VenVisibilityCache},
+            subscription::{NotifierState, WebsocketConnection, notify, privacy_filter_object},
             test::ApiTest,
         },
-        data_source::{Crud, EventCrud, VenCrud, VenObjectPrivacy},
+        data_source::{Crud, EventCrud, Locked, VenObjectPrivacy, VenVisibility},
         error::AppError,
         jwt::{Claims, Scope},
     };
@@ -1037,6 +1669,15 @@ mod test {
         ) -> Result<Option<VenId>, AppError> {
             unimplemented!()
         }
+
+        async fn all_ven_visibility(&self) -> Result<Vec<VenVisibility>, AppError> {
+            Ok(vec![VenVisibility {
+                ven_id: "test_ven_id".parse().unwrap(),
+                client_id: "test_client_id".parse().unwrap(),
+                targets: HashSet::from(["test_target_1".parse().unwrap()]),
+                resource_groups: HashSet::new(),
+            }])
+        }
     }
 
     struct TestVenObjectPrivacyNoCallExpected;
@@ -1064,6 +1705,10 @@ mod test {
         ) -> Result<Option<VenId>, AppError> {
             unimplemented!()
         }
+
+        async fn all_ven_visibility(&self) -> Result<Vec<VenVisibility>, AppError> {
+            unimplemented!()
+        }
     }
 
     struct TestEventCrud;
@@ -1098,6 +1743,13 @@ mod test {
         ) -> Result<Vec<Self::Type>, Self::Error> {
             unimplemented!()
         }
+        async fn count(
+            &self,
+            _filter: &Self::Filter,
+            _permission_filter: &Self::PermissionFilter,
+        ) -> Result<u64, Self::Error> {
+            unimplemented!()
+        }
         async fn update(
             &self,
             _id: &Self::Id,
@@ -1113,61 +1765,44 @@ mod test {
         ) -> Result<Self::Type, Self::Error> {
             unimplemented!()
         }
-    }
-
-    impl EventCrud for TestEventCrud {}
-
-    struct TestVenCrud;
-
-    #[async_trait]
-    impl Crud for TestVenCrud {
-        type Type = Ven;
-        type Id = VenId;
-        type NewType = BlVenRequest;
-        type Error = AppError;
-        type Filter = api::ven::QueryParams;
-        type PermissionFilter = Option<ClientId>;
 
-        async fn create(
-            &self,
-            _new: Self::NewType,
-            _permission_filter: &Self::PermissionFilter,
-        ) -> Result<Self::Type, Self::Error> {
-            unimplemented!()
-        }
-        async fn retrieve(
+        async fn lock(
             &self,
             _id: &Self::Id,
-            _permission_filter: &Self::PermissionFilter,
-        ) -> Result<Self::Type, Self::Error> {
+        ) -> Result<
+            Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+            Self::Error,
+        > {
             unimplemented!()
         }
-        async fn retrieve_all(
+    }
+
+    #[async_trait]
+    impl EventCrud for TestEventCrud {
+        async fn batch(
             &self,
-            _filter: &Self::Filter,
-            _permission_filter: &Self::PermissionFilter,
-        ) -> Result<Vec<Self::Type>, Self::Error> {
+            _operations: Vec<BatchOperation<EventId, EventRequest>>,
+            _permission_filter: &Option<ClientId>,
+        ) -> Result<Vec<Result<Event, AppError>>, AppError> {
             unimplemented!()
         }
-        async fn update(
+
+        async fn retrieve_with_transitions(
             &self,
-            _id: &Self::Id,
-            _new: Self::NewType,
-            _permission_filter: &Self::PermissionFilter,
-        ) -> Result<Self::Type, Self::Error> {
+            _since: DateTime<Utc>,
+            _until: DateTime<Utc>,
+        ) -> Result<Vec<Event>, AppError> {
             unimplemented!()
         }
-        async fn delete(
+
+        async fn next_first_transition(
             &self,
-            _id: &Self::Id,
-            _permission_filter: &Self::PermissionFilter,
-        ) -> Result<Self::Type, Self::Error> {
+            _after: DateTime<Utc>,
+        ) -> Result<Option<DateTime<Utc>>, AppError> {
             unimplemented!()
         }
     }
 
-    impl VenCrud for TestVenCrud {}
-
     #[tokio::test]
     async fn subscription_filtering() {
         let (test_client_a_tx, mut test_client_a_rx) = unbounded_channel();
@@ -1176,18 +1811,24 @@ mod test {
         let websockets = HashMap::from([
             (
                 "test_client_a".parse().unwrap(),
-                (test_client_a_tx, Claims::from_scopes(vec![Scope::ReadAll])),
+                WebsocketConnection::new(
+                    test_client_a_tx,
+                    Claims::from_scopes(vec![Scope::ReadAll]),
+                ),
             ),
             (
                 "test_client_id".parse().unwrap(),
-                (
+                WebsocketConnection::new(
                     test_client_b_tx,
                     Claims::from_scopes(vec![Scope::ReadVenObjects, Scope::ReadTargets]),
                 ),
             ),
             (
                 "test_client_c".parse().unwrap(),
-                (test_client_c_tx, Claims::from_scopes(vec![Scope::ReadAll])),
+                WebsocketConnection::new(
+                    test_client_c_tx,
+                    Claims::from_scopes(vec![Scope::ReadAll]),
+                ),
             ),
         ]);
 
@@ -1196,9 +1837,12 @@ mod test {
             created_date_time: DateTime::from_timestamp_nanos(15_000_000),
             modification_date_time: DateTime::from_timestamp_nanos(15_000_000),
             client_id: "test_client_a".parse().unwrap(),
+            expires_date_time: None,
             content: SubscriptionRequest {
                 client_name: "subscription 1".into(),
                 program_id: None,
+                time_to_live: None,
+                batch_window: None,
                 object_operations: vec![SubscriptionObjectOperation {
                     objects: vec![ObjectType::Event],
                     operations: vec![Operation::Create],
@@ -1214,9 +1858,12 @@ mod test {
             created_date_time: DateTime::from_timestamp_nanos(15_000_000),
             modification_date_time: DateTime::from_timestamp_nanos(15_000_000),
             client_id: "test_client_id".parse().unwrap(),
+            expires_date_time: None,
             content: SubscriptionRequest {
                 client_name: "subscription 2".into(),
                 program_id: None,
+                time_to_live: None,
+                batch_window: None,
                 object_operations: vec![SubscriptionObjectOperation {
                     objects: vec![ObjectType::Event],
                     operations: vec![Operation::Create, Operation::Delete],
@@ -1232,9 +1879,12 @@ mod test {
             created_date_time: DateTime::from_timestamp_nanos(15_000_000),
             modification_date_time: DateTime::from_timestamp_nanos(15_000_000),
             client_id: "test_client_c".parse().unwrap(),
+            expires_date_time: None,
             content: SubscriptionRequest {
                 client_name: "subscription 3".into(),
                 program_id: Some("program_2".parse().unwrap()),
+                time_to_live: None,
+                batch_window: None,
                 object_operations: vec![SubscriptionObjectOperation {
                     objects: vec![ObjectType::Event],
                     operations: vec![Operation::Create],
@@ -1245,21 +1895,29 @@ mod test {
             },
         };
 
-        let subscriptions = HashMap::from([
-            ("subscription_1".parse().unwrap(), subscription_1),
-            ("subscription_2".parse().unwrap(), subscription_2),
-            ("subscription_3".parse().unwrap(), subscription_3),
-        ]);
+        let subscriptions = [subscription_1, subscription_2, subscription_3];
 
         let state = NotifierState {
             uuidv7_context: Arc::new(Mutex::new(ContextV7::new())),
             websockets: Mutex::new(websockets),
-            subscriptions: Mutex::new(subscriptions),
+            subscriptions: Mutex::new(
+                subscriptions
+                    .into_iter()
+                    .map(|subscription| (subscription, vec![]))
+                    .collect(),
+            ),
             mqtt_state: None,
+            mqtt_sessions: Mutex::new(Default::default()),
+            ven_visibility: VenVisibilityCache::new(),
+            deliveries: DeliveryWorkers::spawn(None, None, super::webhook_client()),
+            webhook_failure_timeout: Duration::from_secs(60),
+            events_changed: tokio::sync::Notify::new(),
+            loaded: std::sync::atomic::AtomicBool::new(true),
+            shutting_down: Default::default(),
+            websocket_tasks: tokio::sync::watch::Sender::new(0),
         };
 
         notify(
-            &TestVenCrud,
             &TestEventCrud,
             &TestVenObjectPrivacyTargets,
             &state,
@@ -1290,7 +1948,6 @@ mod test {
         assert!(test_client_c_rx.try_recv().is_err());
 
         notify(
-            &TestVenCrud,
             &TestEventCrud,
             &TestVenObjectPrivacyTargets,
             &state,
@@ -1321,7 +1978,6 @@ mod test {
         assert!(test_client_c_rx.try_recv().is_err());
 
         notify(
-            &TestVenCrud,
             &TestEventCrud,
             &TestVenObjectPrivacyTargets,
             &state,
@@ -1345,72 +2001,603 @@ mod test {
         )
         .await;
 
-        assert!(test_client_a_rx.try_recv().is_err());
-        assert!(test_client_b_rx.try_recv().is_ok());
-        assert!(test_client_b_rx.try_recv().is_err());
-        assert!(test_client_c_rx.try_recv().is_err());
+        assert!(test_client_a_rx.try_recv().is_err());
+        assert!(test_client_b_rx.try_recv().is_ok());
+        assert!(test_client_b_rx.try_recv().is_err());
+        assert!(test_client_c_rx.try_recv().is_err());
+    }
+
+    #[cfg(feature = "experimental-websockets")]
+    #[sqlx::test(fixtures("vens", "programs", "events"))]
+    async fn websocket_end_to_end(db: PgPool) {
+        use futures::StreamExt;
+        use tokio_tungstenite::{
+            connect_async,
+            tungstenite::{ClientRequestBuilder, Message},
+        };
+
+        let server = ApiTest::new(
+            db,
+            "ven-1-client-id",
+            vec![
+                Scope::WriteReports,
+                Scope::WriteSubscriptionsBl,
+                Scope::ReadAll,
+            ],
+        )
+        .await;
+
+        let (status, _) = server
+            .request::<Subscription>(
+                Method::POST,
+                "/subscriptions",
+                Body::from(r#"{"clientName": "ven-1-name", "programID": "program-1", "objectOperations": [{"objects": ["REPORT"], "operations": ["CREATE"], "mechanism": "WEBHOOK"}]}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (token, addr, handle) = server.run().await;
+        let request = ClientRequestBuilder::new(
+            format!("ws://localhost:{}/notifiers/ws", addr.port())
+                .parse()
+                .unwrap(),
+        )
+        .with_header("Authorization", format!("Bearer {token}"));
+        let (mut client_socket, _) = connect_async(request).await.unwrap();
+
+        let (status, _) = server
+            .request::<Report>(
+                Method::POST,
+                "/reports",
+                Body::from(
+                    r#"{"eventID": "event-1", "clientName": "report-1-name", "resources": []}"#,
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let Message::Text(notification) = client_socket.next().await.unwrap().unwrap() else {
+            panic!("Unexpected message type");
+        };
+
+        let notification: Notification = serde_json::from_str(&notification).unwrap();
+
+        assert_eq!(notification.operation, Operation::Create);
+
+        client_socket.close(None).await.ok();
+
+        handle.abort();
+    }
+
+    #[cfg(feature = "experimental-websockets")]
+    #[sqlx::test(fixtures("vens", "programs", "events"))]
+    async fn websocket_batching(db: PgPool) {
+        use futures::StreamExt;
+        use tokio_tungstenite::{
+            connect_async,
+            tungstenite::{ClientRequestBuilder, Message},
+        };
+
+        let server = ApiTest::new(
+            db,
+            "ven-1-client-id",
+            vec![
+                Scope::WriteReports,
+                Scope::WriteSubscriptionsBl,
+                Scope::ReadAll,
+            ],
+        )
+        .await;
+
+        let (status, _) = server
+            .request::<Subscription>(
+                Method::POST,
+                "/subscriptions",
+                Body::from(r#"{"clientName": "ven-1-name", "batchWindow": "PT0.3S", "objectOperations": [{"objects": ["REPORT"], "operations": ["CREATE"], "mechanism": "WEBSOCKET"}]}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (token, addr, handle) = server.run().await;
+        let request = ClientRequestBuilder::new(
+            format!("ws://localhost:{}/notifiers/ws", addr.port())
+                .parse()
+                .unwrap(),
+        )
+        .with_header("Authorization", format!("Bearer {token}"));
+        let (mut client_socket, _) = connect_async(request).await.unwrap();
+
+        for name in ["report-1-name", "report-2-name"] {
+            let (status, _) = server
+                .request::<Report>(
+                    Method::POST,
+                    "/reports",
+                    Body::from(
+                        serde_json::json!({
+                            "eventID": "event-1",
+                            "clientName": name,
+                            "resources": [],
+                        })
+                        .to_string(),
+                    ),
+                )
+                .await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let Message::Text(batch) = client_socket.next().await.unwrap().unwrap() else {
+            panic!("Unexpected message type");
+        };
+        let batch: NotificationBatch = serde_json::from_str(&batch).unwrap();
+        assert_eq!(batch.0.len(), 2);
+        assert!(
+            batch
+                .0
+                .iter()
+                .all(|notification| notification.operation == Operation::Create)
+        );
+
+        client_socket.close(None).await.ok();
+
+        handle.abort();
+    }
+
+    #[sqlx::test(fixtures("vens", "programs"))]
+    async fn webhook_end_to_end(db: PgPool) {
+        let server = ApiTest::new(
+            db,
+            "bl-client",
+            vec![
+                Scope::WriteEvents,
+                Scope::WriteSubscriptionsBl,
+                Scope::ReadAll,
+            ],
+        )
+        .await;
+        let (callback_url, mut notifications) = crate::api::test::webhook_receiver().await;
+
+        let (status, _) = server
+            .request::<Subscription>(
+                Method::POST,
+                "/subscriptions",
+                Body::from(
+                    serde_json::json!({
+                        "clientName": "bl-client",
+                        "objectOperations": [{
+                            "objects": ["EVENT"],
+                            "operations": ["CREATE"],
+                            "mechanism": "WEBHOOK",
+                            "callbackUrl": callback_url,
+                            "bearerToken": "secret",
+                        }]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, event) = server
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(r#"{"programID": "program-1", "intervals": []}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (authorization, notification) =
+            tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(authorization.as_deref(), Some("Bearer secret"));
+        assert_eq!(notification.operation, Operation::Create);
+        assert_eq!(notification.object, AnyObject::Event(event));
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn webhook_of_ven_client_without_ven(db: PgPool) {
+        let server = ApiTest::new(
+            db,
+            "ven-without-ven-object",
+            vec![
+                Scope::WriteEvents,
+                Scope::WriteSubscriptionsVen,
+                Scope::WriteVensVen,
+                Scope::ReadTargets,
+                Scope::ReadVenObjects,
+            ],
+        )
+        .await;
+        let (callback_url, mut notifications) = crate::api::test::webhook_receiver().await;
+
+        let (status, _) = server
+            .request::<Subscription>(
+                Method::POST,
+                "/subscriptions",
+                Body::from(
+                    serde_json::json!({
+                        "clientName": "ven-without-ven-object",
+                        "objectOperations": [{
+                            "objects": ["EVENT", "VEN"],
+                            "operations": ["CREATE"],
+                            "mechanism": "WEBHOOK",
+                            "callbackUrl": callback_url,
+                        }]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // the client cannot read events targeted at others, not even before it has a VEN
+        let (status, _) = server
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(
+                    r#"{"programID": "program-1", "targets": ["group-1"], "intervals": []}"#,
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, ven) = server
+            .request::<Ven>(
+                Method::POST,
+                "/vens",
+                Body::from(r#"{"venName": "new-ven", "objectType": "VEN_VEN_REQUEST"}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // notifications to the same callback are delivered in order
+        let (_, notification) = tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification.object, AnyObject::Ven(ven));
     }
 
     #[cfg(feature = "experimental-websockets")]
-    #[sqlx::test(fixtures("vens", "programs", "events"))]
-    async fn websocket_end_to_end(db: PgPool) {
+    #[sqlx::test(fixtures("programs"))]
+    async fn shutdown_delivers_queued_notifications(db: PgPool) {
         use futures::StreamExt;
         use tokio_tungstenite::{
             connect_async,
-            tungstenite::{ClientRequestBuilder, Message},
+            tungstenite::{self, ClientRequestBuilder, Message},
         };
+        use tower::ServiceExt;
 
         let server = ApiTest::new(
             db,
-            "ven-1-client-id",
+            "bl-client",
             vec![
-                Scope::WriteReports,
+                Scope::WriteEvents,
                 Scope::WriteSubscriptionsBl,
                 Scope::ReadAll,
             ],
         )
         .await;
-
+        let (callback_url, mut batches) =
+            crate::api::test::webhook_receiver_of::<NotificationBatch>().await;
+
+        let subscription = serde_json::json!({
+            "clientName": "bl-client",
+            "batchWindow": "PT60S",
+            "objectOperations": [{
+                "objects": ["EVENT"],
+                "operations": ["CREATE"],
+                "mechanism": "WEBHOOK",
+                "callbackUrl": callback_url,
+            }]
+        })
+        .to_string();
         let (status, _) = server
             .request::<Subscription>(
                 Method::POST,
                 "/subscriptions",
-                Body::from(r#"{"clientName": "ven-1-name", "programID": "program-1", "objectOperations": [{"objects": ["REPORT"], "operations": ["CREATE"], "mechanism": "WEBHOOK"}]}"#),
+                Body::from(subscription.clone()),
             )
             .await;
         assert_eq!(status, StatusCode::CREATED);
 
         let (token, addr, handle) = server.run().await;
-        let request = ClientRequestBuilder::new(
-            format!("ws://localhost:{}/notifiers/ws", addr.port())
-                .parse()
-                .unwrap(),
+        let websocket_request = || {
+            ClientRequestBuilder::new(
+                format!("ws://localhost:{}/notifiers/ws", addr.port())
+                    .parse()
+                    .unwrap(),
+            )
+            .with_header("Authorization", format!("Bearer {token}"))
+        };
+        let (mut client_socket, _) = connect_async(websocket_request()).await.unwrap();
+
+        let (status, event) = server
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(r#"{"programID": "program-1", "intervals": []}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // the batch window is far longer than the shutdown deadline
+        let notifier = Arc::clone(&server.state().notifier);
+        let shutdown = tokio::spawn(async move {
+            notifier
+                .shutdown(tokio::time::Instant::now() + Duration::from_secs(10))
+                .await
+        });
+
+        let Some(Ok(Message::Text(batch))) = client_socket.next().await else {
+            panic!("expected the queued notifications");
+        };
+        let batch: NotificationBatch = serde_json::from_str(&batch).unwrap();
+        assert_eq!(batch.0.len(), 1);
+        assert_eq!(batch.0[0].object, AnyObject::Event(event.clone()));
+        let message = client_socket.next().await;
+        assert!(
+            matches!(message, Some(Ok(Message::Close(_)))),
+            "{message:?}"
+        );
+        // lets the client acknowledge the close frame
+        assert!(client_socket.next().await.is_none());
+
+        tokio::time::timeout(Duration::from_secs(5), shutdown)
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, batch) = batches.try_recv().unwrap();
+        assert_eq!(batch.0.len(), 1);
+        assert_eq!(batch.0[0].object, AnyObject::Event(event));
+
+        let response = server
+            .state()
+            .clone()
+            .into_router()
+            .oneshot(
+                axum::http::Request::post("/subscriptions")
+                    .header("Authorization", format!("Bearer {}", server.token()))
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(subscription))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let Err(tungstenite::Error::Http(response)) = connect_async(websocket_request()).await
+        else {
+            panic!("websocket connection should be rejected");
+        };
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        handle.abort();
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn webhook_batching(db: PgPool) {
+        let server = ApiTest::new(
+            db,
+            "bl-client",
+            vec![
+                Scope::WriteEvents,
+                Scope::WriteSubscriptionsBl,
+                Scope::ReadAll,
+            ],
         )
-        .with_header("Authorization", format!("Bearer {token}"));
-        let (mut client_socket, _) = connect_async(request).await.unwrap();
+        .await;
+        let (callback_url, mut batches) =
+            crate::api::test::webhook_receiver_of::<NotificationBatch>().await;
 
         let (status, _) = server
-            .request::<Report>(
+            .request::<Subscription>(
                 Method::POST,
-                "/reports",
+                "/subscriptions",
                 Body::from(
-                    r#"{"eventID": "event-1", "clientName": "report-1-name", "resources": []}"#,
+                    serde_json::json!({
+                        "clientName": "bl-client",
+                        "batchWindow": "PT0.5S",
+                        "objectOperations": [{
+                            "objects": ["EVENT"],
+                            "operations": ["CREATE", "UPDATE"],
+                            "mechanism": "WEBHOOK",
+                            "callbackUrl": callback_url,
+                        }]
+                    })
+                    .to_string(),
                 ),
             )
             .await;
         assert_eq!(status, StatusCode::CREATED);
 
-        let Message::Text(notification) = client_socket.next().await.unwrap().unwrap() else {
-            panic!("Unexpected message type");
+        let (status, event_1) = server
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(r#"{"programID": "program-1", "intervals": []}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let mut last_update = None;
+        for name in ["first", "second"] {
+            let (status, event) = server
+                .request::<Event>(
+                    Method::PUT,
+                    &format!("/events/{}", event_1.id.as_str()),
+                    Body::from(
+                        serde_json::json!({
+                            "programID": "program-1",
+                            "eventName": name,
+                            "intervals": [],
+                        })
+                        .to_string(),
+                    ),
+                )
+                .await;
+            assert_eq!(status, StatusCode::OK);
+            last_update = Some(event);
+        }
+        let (status, event_2) = server
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(r#"{"programID": "program-1", "intervals": []}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (_, batch) = tokio::time::timeout(Duration::from_secs(5), batches.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let received: Vec<_> = batch
+            .0
+            .into_iter()
+            .map(|notification| (notification.operation, notification.object))
+            .collect();
+        assert_eq!(
+            received,
+            vec![
+                (Operation::Create, AnyObject::Event(event_1)),
+                (Operation::Update, AnyObject::Event(last_update.unwrap())),
+                (Operation::Create, AnyObject::Event(event_2)),
+            ]
+        );
+        assert!(batches.try_recv().is_err());
+    }
+
+    #[sqlx::test]
+    async fn mqtt_batching(db: PgPool) {
+        let vtn_config = crate::VtnConfig {
+            mqtt_batch_window: Some(Duration::from_millis(300)),
+            ..crate::VtnConfig::from_env().unwrap()
         };
+        let server = ApiTest::with_config(
+            db,
+            vtn_config,
+            "bl-client",
+            vec![Scope::WritePrograms, Scope::ReadAll],
+        )
+        .await;
 
-        let notification: Notification = serde_json::from_str(&notification).unwrap();
+        let (status, notifiers) = server
+            .request::<NotifiersResponse>(Method::GET, "/notifiers", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(notifiers.mqtt.unwrap().batch_window.is_some());
 
-        assert_eq!(notification.operation, Operation::Create);
+        let vtn_config = server.vtn_config();
+        let mut mqtt_client = paho_mqtt::AsyncClient::new(paho_mqtt::CreateOptions::new()).unwrap();
+        mqtt_client
+            .connect(
+                paho_mqtt::ConnectOptionsBuilder::new()
+                    .server_uris(&[&vtn_config.mqtt_url.as_ref().unwrap()])
+                    .user_name(vtn_config.mqtt_username.as_ref().unwrap())
+                    .password(vtn_config.mqtt_password.as_ref().unwrap())
+                    .finalize(),
+            )
+            .await
+            .unwrap();
+        mqtt_client
+            .subscribe(
+                format!("{}push/programs/update", vtn_config.mqtt_topic_prefix),
+                QoS::ExactlyOnce,
+            )
+            .await
+            .unwrap();
+        let mqtt_rx = mqtt_client.get_stream(100);
 
-        client_socket.close(None).await.ok();
+        let (status, program) = server
+            .request::<Program>(
+                Method::POST,
+                "/programs",
+                Body::from(serde_json::to_vec(&ProgramRequest::new("program")).unwrap()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        for name in ["renamed", "renamed again"] {
+            let (status, _) = server
+                .request::<Program>(
+                    Method::PUT,
+                    &format!("/programs/{}", program.id.as_str()),
+                    Body::from(serde_json::to_vec(&ProgramRequest::new(name)).unwrap()),
+                )
+                .await;
+            assert_eq!(status, StatusCode::OK);
+        }
 
-        handle.abort();
+        let msg = tokio::time::timeout(Duration::from_secs(5), mqtt_rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let batch: MqttPushNotificationBatch = serde_json::from_slice(msg.payload()).unwrap();
+        assert_eq!(batch.0.len(), 1);
+        assert_eq!(batch.0[0].id.as_str(), program.id.as_str());
+        assert_eq!(batch.0[0].operation, Operation::Update);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), mqtt_rx.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[sqlx::test]
+    async fn renew_extends_expiry(db: PgPool) {
+        let server = ApiTest::new(
+            db,
+            "ven-client",
+            vec![Scope::WriteSubscriptionsVen, Scope::ReadAll],
+        )
+        .await;
+
+        let (status, subscription) = server
+            .request::<Subscription>(
+                Method::POST,
+                "/subscriptions",
+                Body::from(
+                    serde_json::json!({
+                        "clientName": "ven-client",
+                        "timeToLive": "PT1H",
+                        "objectOperations": [{
+                            "objects": ["EVENT"],
+                            "operations": ["CREATE"],
+                            "mechanism": "WEBHOOK",
+                            "callbackUrl": "http://localhost/callback",
+                        }]
+                    })
+                    .to_string(),
+                ),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let expires = subscription.expires_date_time.unwrap();
+        assert!(expires > subscription.created_date_time + chrono::Duration::minutes(59));
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let (status, renewed) = server
+            .request::<Subscription>(
+                Method::POST,
+                &format!("/subscriptions/{}/renew", subscription.id.as_str()),
+                Body::empty(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(renewed.expires_date_time.unwrap() > expires);
+
+        let (status, _) = server
+            .request::<Problem>(
+                Method::POST,
+                "/subscriptions/does-not-exist/renew",
+                Body::empty(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
@@ -1569,6 +2756,7 @@ mod test {
                     "test_target_1".parse().unwrap(),
                     "test_target_2".parse().unwrap(),
                 ],
+                validation_mode: None,
             },
         });
         let no_scopes_result = privacy_filter_object(
@@ -1622,6 +2810,7 @@ mod test {
                 payload_descriptors: None,
                 attributes: None,
                 targets: vec!["test_target_2".parse().unwrap()],
+                validation_mode: None,
             },
         });
         let ven_scopes_result = privacy_filter_object(
@@ -1658,6 +2847,7 @@ mod test {
                 payload_descriptors: None,
                 attributes: None,
                 targets: vec![],
+                validation_mode: None,
             },
         });
         let ven_scopes_result = privacy_filter_object(
@@ -1800,9 +2990,12 @@ mod test {
             created_date_time: DateTime::from_timestamp_nanos(15_000_000),
             modification_date_time: DateTime::from_timestamp_nanos(15_000_000),
             client_id: "test_client_id".parse().unwrap(),
+            expires_date_time: None,
             content: SubscriptionRequest {
                 client_name: "client name".into(),
                 program_id: None,
+                time_to_live: None,
+                batch_window: None,
                 object_operations: vec![],
             },
         });
@@ -2063,7 +3256,7 @@ mod test {
         .await;
 
         let vtn_config = server.vtn_config();
-        let mqtt_client = paho_mqtt::AsyncClient::new(paho_mqtt::CreateOptions::new()).unwrap();
+        let mut mqtt_client = paho_mqtt::AsyncClient::new(paho_mqtt::CreateOptions::new()).unwrap();
         mqtt_client
             .connect(
                 paho_mqtt::ConnectOptionsBuilder::new()
@@ -2081,33 +3274,36 @@ mod test {
             )
             .await
             .unwrap();
-        let mqtt_rx = mqtt_client.start_consuming();
-
-        let expect_msg =
-            |id: &str, object_type: ObjectType, operation: Operation, topics: &[&str]| {
-                let mut topics = topics.iter().copied().collect::<BTreeSet<&str>>();
-                while !topics.is_empty() {
-                    let msg = mqtt_rx
-                        .recv_timeout(Duration::from_millis(50))
-                        .unwrap()
-                        .unwrap();
-                    if !topics.remove(
-                        msg.topic()
-                            .strip_prefix(&vtn_config.mqtt_topic_prefix)
-                            .unwrap(),
-                    ) {
-                        panic!("unexpected message {msg:?}");
-                    }
-                    let msg_data: MqttPushNotification =
-                        serde_json::from_slice(msg.payload()).unwrap();
-                    assert_eq!(msg_data.id.as_str(), id);
-                    assert_eq!(msg_data.object_type, object_type);
-                    assert_eq!(msg_data.operation, operation);
-                }
-                if let Ok(msg) = mqtt_rx.recv_timeout(Duration::from_millis(50)) {
-                    panic!("stray message {msg:?}")
+        // notifications are published in the background, so receive them without blocking the runtime
+        let mqtt_rx = mqtt_client.get_stream(100);
+
+        let expect_msg = async |id: &str,
+                                object_type: ObjectType,
+                                operation: Operation,
+                                topics: &[&str]| {
+            let mut topics = topics.iter().copied().collect::<BTreeSet<&str>>();
+            while !topics.is_empty() {
+                let msg = tokio::time::timeout(Duration::from_millis(50), mqtt_rx.recv())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+                if !topics.remove(
+                    msg.topic()
+                        .strip_prefix(&vtn_config.mqtt_topic_prefix)
+                        .unwrap(),
+                ) {
+                    panic!("unexpected message {msg:?}");
                 }
-            };
+                let msg_data: MqttPushNotification = serde_json::from_slice(msg.payload()).unwrap();
+                assert_eq!(msg_data.id.as_str(), id);
+                assert_eq!(msg_data.object_type, object_type);
+                assert_eq!(msg_data.operation, operation);
+            }
+            if let Ok(msg) = tokio::time::timeout(Duration::from_millis(50), mqtt_rx.recv()).await {
+                panic!("stray message {msg:?}")
+            }
+        };
 
         let (status, ven) = server
             .request::<Ven>(
@@ -2130,7 +3326,8 @@ mod test {
             ObjectType::Ven,
             Operation::Create,
             &["push/vens/create"],
-        );
+        )
+        .await;
 
         let (status, program) = server
             .request::<Program>(
@@ -2144,6 +3341,7 @@ mod test {
                         payload_descriptors: None,
                         attributes: None,
                         targets: vec![],
+                        validation_mode: None,
                     })
                     .unwrap(),
                 ),
@@ -2164,7 +3362,8 @@ mod test {
                 &format!("push/programs/{}/create", program.id),
                 &format!("push/vens/{}/programs/create", ven.id),
             ],
-        );
+        )
+        .await;
 
         let (status, program) = server
             .request::<Program>(
@@ -2178,6 +3377,7 @@ mod test {
                         payload_descriptors: None,
                         attributes: None,
                         targets: vec!["target-1".parse().unwrap(), "target-2".parse().unwrap()],
+                        validation_mode: None,
                     })
                     .unwrap(),
                 ),
@@ -2193,7 +3393,8 @@ mod test {
                 &format!("push/programs/{}/create", program.id),
                 &format!("push/vens/{}/programs/create", ven.id),
             ],
-        );
+        )
+        .await;
 
         let (status, resource) = server
             .request::<Resource>(
@@ -2219,6 +3420,131 @@ mod test {
                 "push/resources/create",
                 &format!("push/vens/{}/resources/create", ven.id),
             ],
+        )
+        .await;
+    }
+
+    /// Measures the fan-out of event notifications to 20 000 VENs, of which 200 are targeted,
+    /// and 1 000 websocket subscribers.
+    /// Run with `cargo test -p openleadr-vtn --all-features --release -- --ignored notification_fanout_benchmark --nocapture`
+    #[ignore]
+    #[sqlx::test(fixtures("programs"))]
+    async fn notification_fanout_benchmark(db: PgPool) {
+        const VENS: usize = 20_000;
+        const NOTIFICATIONS: u32 = 10;
+
+        sqlx::query(
+            "INSERT INTO ven (id, created_date_time, modification_date_time, ven_name, targets, client_id)
+             SELECT 'ven-' || i, now(), now(), 'ven-' || i, ARRAY['group-' || (i % 100)], 'client-' || i
+             FROM generate_series(1, $1) i",
+        )
+        .bind(VENS as i32)
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let state = api::test::state(db).await;
+        let vtn_config = crate::VtnConfig::from_env().unwrap();
+
+        let mut receivers = vec![];
+        for i in 0..1_000 {
+            let client_id: ClientId = format!("client-{i}").parse().unwrap();
+            let (tx, rx) = unbounded_channel();
+            state
+                .notifier
+                .insert_websocket_for_test(
+                    client_id.clone(),
+                    tx,
+                    Claims::from_scopes(vec![Scope::ReadTargets]),
+                )
+                .await;
+            state
+                .notifier
+                .insert_subscription_for_test(Subscription {
+                    id: format!("subscription-{i}").parse().unwrap(),
+                    created_date_time: Default::default(),
+                    modification_date_time: Default::default(),
+                    client_id,
+                    expires_date_time: None,
+                    content: SubscriptionRequest {
+                        client_name: format!("client-{i}"),
+                        program_id: None,
+                        time_to_live: None,
+                        batch_window: None,
+                        object_operations: vec![SubscriptionObjectOperation {
+                            objects: vec![ObjectType::Event],
+                            operations: vec![Operation::Update],
+                            mechanism: NotificationMechanism::Websocket,
+                            callback_url: None,
+                            bearer_token: None,
+                        }],
+                    },
+                })
+                .await;
+            receivers.push(rx);
+        }
+
+        let mut mqtt_client = paho_mqtt::AsyncClient::new(paho_mqtt::CreateOptions::new()).unwrap();
+        mqtt_client
+            .connect(
+                paho_mqtt::ConnectOptionsBuilder::new()
+                    .server_uris(&[&vtn_config.mqtt_url.as_ref().unwrap()])
+                    .user_name(vtn_config.mqtt_username.as_ref().unwrap())
+                    .password(vtn_config.mqtt_password.as_ref().unwrap())
+                    .finalize(),
+            )
+            .await
+            .unwrap();
+        mqtt_client
+            .subscribe(
+                format!("{}vens/#", vtn_config.mqtt_topic_prefix),
+                QoS::AtMostOnce,
+            )
+            .await
+            .unwrap();
+        let mqtt_rx = mqtt_client.get_stream(None);
+
+        let event = AnyObject::Event(Event {
+            id: "event-1".parse().unwrap(),
+            created_date_time: Default::default(),
+            modification_date_time: Default::default(),
+            content: EventRequest::new("program-1".parse().unwrap())
+                .with_targets(vec!["group-1".parse().unwrap()]),
+        });
+
+        let start = std::time::Instant::now();
+        for _ in 0..NOTIFICATIONS {
+            notify(
+                &*state.storage.events(),
+                &*state.storage.ven_object_privacy(),
+                &state.notifier,
+                Operation::Update,
+                event.clone(),
+            )
+            .await;
+        }
+        let notified = start.elapsed();
+
+        let expected_mqtt = NOTIFICATIONS as usize * VENS / 100;
+        for _ in 0..expected_mqtt {
+            tokio::time::timeout(Duration::from_secs(60), mqtt_rx.recv())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+        }
+        let delivered = start.elapsed();
+
+        let websocket_notifications: usize = receivers
+            .iter_mut()
+            .map(|rx| std::iter::from_fn(|| rx.try_recv().ok()).count())
+            .sum();
+        assert_eq!(websocket_notifications, NOTIFICATIONS as usize * 10);
+
+        println!(
+            "notify returned after {:?} and all MQTT messages were delivered after {:?} per notification",
+            notified / NOTIFICATIONS,
+            delivered / NOTIFICATIONS,
         );
     }
 }
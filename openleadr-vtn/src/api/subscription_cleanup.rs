@@ -0,0 +1,229 @@
+//! Periodic removal of subscriptions nobody listens to anymore.
+//!
+//! Subscriptions with a `timeToLive` are dropped once they were not renewed in time.
+//! Webhook subscriptions whose callback failed for longer than the configured
+//! `WEBHOOK_FAILURE_TIMEOUT` are dropped as well.
+//! Each dropped subscription is notified as a `DELETE` of the subscription object.
+
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::{api::subscription, error::AppError, state::AppState};
+
+/// Time between two runs of the cleanup
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Runs the cleanup until the application terminates.
+pub(crate) async fn run(state: AppState) {
+    let mut interval = tokio::time::interval(INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        if let Err(err) = tick(&state).await {
+            warn!("Could not clean up subscriptions: {}", err);
+        }
+    }
+}
+
+/// Failures to delete some subscriptions do not keep the others from being deleted and notified.
+/// The first failure is returned after that.
+async fn tick(state: &AppState) -> Result<(), AppError> {
+    let subscriptions = state.storage.subscriptions();
+    let mut result = Ok(());
+    let mut dropped = subscriptions.delete_expired().await.unwrap_or_else(|err| {
+        result = Err(err);
+        vec![]
+    });
+
+    for id in state.notifier.failing_webhooks() {
+        match subscriptions.delete(&id, &None).await {
+            Ok(subscription) => dropped.push(subscription),
+            // already deleted by its owner or by the expiry above
+            Err(AppError::NotFound) => state.notifier.deliveries.forget(&id),
+            // the first failure is logged by the caller
+            Err(err) if result.is_ok() => result = Err(err),
+            Err(err) => {
+                warn!(%id, "Could not drop subscription with failing webhook: {}", err);
+            }
+        }
+    }
+
+    subscription::forget_deleted(
+        &*state.storage.events(),
+        &*state.storage.ven_object_privacy(),
+        &state.notifier,
+        dropped,
+    )
+    .await;
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use openleadr_wire::{
+        Event,
+        subscription::{AnyObject, Notification, Operation, Subscription},
+    };
+    use reqwest::{Method, StatusCode};
+    use sqlx::PgPool;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use super::tick;
+    use crate::{
+        VtnConfig,
+        api::test::{ApiTest, webhook_receiver},
+        jwt::Scope,
+    };
+
+    async fn subscribe(server: &ApiTest, body: serde_json::Value) -> Subscription {
+        let (status, subscription) = server
+            .request::<Subscription>(Method::POST, "/subscriptions", Body::from(body.to_string()))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        subscription
+    }
+
+    /// Subscribes to the deletion of subscriptions with a working webhook
+    async fn watch_deletions(
+        server: &ApiTest,
+    ) -> UnboundedReceiver<(Option<String>, Notification)> {
+        let (callback_url, notifications) = webhook_receiver().await;
+        subscribe(
+            server,
+            serde_json::json!({
+                "clientName": "bl-client",
+                "objectOperations": [{
+                    "objects": ["SUBSCRIPTION"],
+                    "operations": ["DELETE"],
+                    "mechanism": "WEBHOOK",
+                    "callbackUrl": callback_url,
+                }]
+            }),
+        )
+        .await;
+        notifications
+    }
+
+    async fn expect_deleted(
+        notifications: &mut UnboundedReceiver<(Option<String>, Notification)>,
+        subscription: &Subscription,
+    ) {
+        let (_, notification) = tokio::time::timeout(Duration::from_secs(5), notifications.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification.operation, Operation::Delete);
+        let AnyObject::Subscription(deleted) = notification.object else {
+            panic!("expected a subscription, got {:?}", notification.object);
+        };
+        assert_eq!(deleted.id, subscription.id);
+    }
+
+    #[sqlx::test]
+    async fn expired_subscriptions_are_dropped(db: PgPool) {
+        let server = ApiTest::new(
+            db.clone(),
+            "bl-client",
+            vec![Scope::WriteSubscriptionsBl, Scope::ReadAll],
+        )
+        .await;
+        let mut notifications = watch_deletions(&server).await;
+
+        let expiring = subscribe(
+            &server,
+            serde_json::json!({
+                "clientName": "bl-client",
+                "timeToLive": "PT1H",
+                "objectOperations": [{
+                    "objects": ["EVENT"],
+                    "operations": ["CREATE"],
+                    "mechanism": "WEBHOOK",
+                    "callbackUrl": "http://localhost/callback",
+                }]
+            }),
+        )
+        .await;
+
+        tick(server.state()).await.unwrap();
+        assert!(notifications.try_recv().is_err());
+
+        sqlx::query("UPDATE subscription SET expires_date_time = now() WHERE id = $1")
+            .bind(expiring.id.as_str())
+            .execute(&db)
+            .await
+            .unwrap();
+
+        tick(server.state()).await.unwrap();
+        expect_deleted(&mut notifications, &expiring).await;
+
+        let status = server
+            .empty_request(
+                Method::GET,
+                &format!("/subscriptions/{}", expiring.id.as_str()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(fixtures("programs"))]
+    async fn failing_webhooks_are_dropped(db: PgPool) {
+        let vtn_config = VtnConfig {
+            webhook_failure_timeout: Duration::ZERO,
+            ..VtnConfig::from_env().unwrap()
+        };
+        let server = ApiTest::with_config(
+            db,
+            vtn_config,
+            "bl-client",
+            vec![
+                Scope::WriteEvents,
+                Scope::WriteSubscriptionsBl,
+                Scope::ReadAll,
+            ],
+        )
+        .await;
+        let mut notifications = watch_deletions(&server).await;
+
+        let failing = subscribe(
+            &server,
+            serde_json::json!({
+                "clientName": "bl-client",
+                "objectOperations": [{
+                    "objects": ["EVENT"],
+                    "operations": ["CREATE"],
+                    "mechanism": "WEBHOOK",
+                    // nothing listens on the discard port
+                    "callbackUrl": "http://127.0.0.1:9/callback",
+                }]
+            }),
+        )
+        .await;
+
+        let (status, _) = server
+            .request::<Event>(
+                Method::POST,
+                "/events",
+                Body::from(r#"{"programID": "program-1", "intervals": []}"#),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        tokio::time::timeout(Duration::from_secs(15), async {
+            while server.state().notifier.failing_webhooks().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        tick(server.state()).await.unwrap();
+        expect_deleted(&mut notifications, &failing).await;
+        assert!(server.state().notifier.failing_webhooks().is_empty());
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{
-    api::{AppResponse, ValidatedJson},
-    data_source::{AuthSource, UserDetails},
+    api::{
+        AppResponse, ValidatedJson,
+        subscription::{self, NotifierState},
+    },
+    data_source::{AuthSource, EventCrud, UserDetails, VenObjectPrivacy},
     error::AppError,
     jwt::{Scope, User},
 };
@@ -126,6 +129,9 @@ pub async fn edit(
 
 pub async fn delete_user(
     State(auth_source): State<Arc<dyn AuthSource>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
     Path(id): Path<String>,
     User(user): User,
 ) -> AppResponse<UserDetails> {
@@ -133,13 +139,19 @@ pub async fn delete_user(
         return Err(AppError::Forbidden("Missing 'write_users' scope"));
     }
 
-    let u = auth_source.remove_user(&id).await?;
+    let (u, subscriptions) = auth_source.remove_user(&id).await?;
     info!(user_id = u.id(), client_id = user.sub, "deleted user");
+
+    subscription::forget_deleted(&*event_source, &*privacy, &notifier_state, subscriptions).await;
+
     Ok(Json(u))
 }
 
 pub async fn delete_credential(
     State(auth_source): State<Arc<dyn AuthSource>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
     Path((user_id, client_id)): Path<(String, String)>,
     User(user): User,
 ) -> AppResponse<UserDetails> {
@@ -147,13 +159,16 @@ pub async fn delete_credential(
         return Err(AppError::Forbidden("Missing 'write_users' scope"));
     }
 
-    let u = auth_source.remove_credentials(&user_id, &client_id).await?;
+    let (u, subscriptions) = auth_source.remove_credentials(&user_id, &client_id).await?;
     info!(
         user_id = u.id(),
         removed_client_id = client_id,
         client_id = user.sub,
         "deleted credential"
     );
+
+    subscription::forget_deleted(&*event_source, &*privacy, &notifier_state, subscriptions).await;
+
     Ok(Json(u))
 }
 
@@ -543,4 +558,42 @@ mod test {
             r#"Bearer realm="VTN""#
         );
     }
+
+    #[sqlx::test(fixtures("users", "subscriptions"))]
+    async fn delete_user_deletes_subscriptions(db: PgPool) {
+        sqlx::query(
+            r#"
+            INSERT INTO subscription (id, created_date_time, modification_date_time, client_id, client_name, program_id, object_operations)
+            VALUES ('subscription-3', now(), now(), 'bl-client', 'bl-client', NULL, '[]'::jsonb)
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        let subscription_ids = || async {
+            sqlx::query_scalar::<_, String>("SELECT id FROM subscription ORDER BY id")
+                .fetch_all(&db)
+                .await
+                .unwrap()
+        };
+
+        let state = state(db.clone()).await;
+        let token = jwt_test_token(&state, "test-client-id", vec![Scope::WriteUsers]);
+        let mut app = state.into_router();
+
+        // the credential belongs to another user
+        let response = help_delete(&mut app, &token, "/users/ven-client/bl-client").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            subscription_ids().await,
+            ["subscription-1", "subscription-2", "subscription-3"]
+        );
+
+        let response = help_delete(&mut app, &token, "/users/bl-client").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            subscription_ids().await,
+            ["subscription-1", "subscription-2"]
+        );
+    }
 }
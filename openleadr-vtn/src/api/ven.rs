@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, RawQuery, State},
 };
 use reqwest::StatusCode;
 use serde::Deserialize;
@@ -10,16 +10,22 @@ use tracing::{info, trace};
 use validator::Validate;
 
 use openleadr_wire::{
+    ClientId,
+    batch::BatchRequest,
     subscription::{AnyObject, Operation},
-    ven::{BlVenRequest, Ven, VenId, VenRequest},
+    ven::{BlVenRequest, Ven, VenId, VenRequest, VenVenRequest},
 };
 
 use crate::{
     api::{
-        AppResponse, TargetQueryParams, ValidatedJson, ValidatedQuery, subscription,
+        AppResponse, Patch, TargetQueryParams, ValidatedJson, ValidatedQuery,
+        attributes::AttributeQueryParams,
+        batch,
+        pagination::{Cursor, Page, Paginated, deserialize_cursor, validate_cursor},
+        subscription,
         subscription::NotifierState,
     },
-    data_source::{EventCrud, VenCrud, VenObjectPrivacy},
+    data_source::{EventCrud, Locked, VenCrud, VenObjectPrivacy},
     error::AppError,
     jwt::{Scope, User},
 };
@@ -28,24 +34,27 @@ pub async fn get_all(
     State(ven_source): State<Arc<dyn VenCrud>>,
     ValidatedQuery(query_params): ValidatedQuery<QueryParams>,
     User(user): User,
-) -> AppResponse<Vec<Ven>> {
+    RawQuery(query): RawQuery,
+) -> Result<Page<Ven>, AppError> {
     trace!(?query_params);
 
-    let vens = if user.has_scope(Scope::ReadAll) {
-        ven_source.retrieve_all(&query_params, &None).await?
+    let permission_filter = if user.has_scope(Scope::ReadAll) {
+        None
     } else if user.has_scope(Scope::ReadVenObjects) {
-        ven_source
-            .retrieve_all(&query_params, &Some(user.client_id()?))
-            .await?
+        Some(user.client_id()?)
     } else {
         return Err(AppError::Forbidden(
             "Missing 'read_all' or 'read_ven_objects' scope",
         ));
     };
+    let vens = ven_source
+        .retrieve_all(&query_params, &permission_filter)
+        .await?;
+    let total = ven_source.count(&query_params, &permission_filter).await?;
 
     trace!(client_id = user.sub, "retrieved {} VENs", vens.len());
 
-    Ok(Json(vens))
+    Ok(Page::new(vens, total, &query_params, query.as_deref()))
 }
 
 pub async fn get(
@@ -108,7 +117,6 @@ pub async fn add(
     info!(%ven.id, ven.ven_name=ven.content.ven_name, client_id = user.sub, "VEN added");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -128,6 +136,80 @@ pub async fn edit(
     Path(id): Path<VenId>,
     User(user): User,
     ValidatedJson(update): ValidatedJson<VenRequest>,
+) -> AppResponse<Ven> {
+    if !user.has_scope(Scope::WriteVensBl) && !user.has_scope(Scope::WriteVensVen) {
+        return Err(AppError::Forbidden(
+            "Missing 'write_vens_bl' or 'write_vens_ven' scope",
+        ));
+    }
+
+    let lock = ven_source.lock(&id).await?;
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        User(user),
+        ValidatedJson(update),
+    )
+    .await
+}
+
+/// Applies a merge patch or JSON patch to the VEN, as if the result was sent with `PUT`.
+/// For VEN clients, the patch applies to the fields of a VEN request of a VEN.
+pub async fn patch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(ven_source): State<Arc<dyn VenCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    Path(id): Path<VenId>,
+    User(user): User,
+    patch: Patch,
+) -> AppResponse<Ven> {
+    if !user.has_scope(Scope::WriteVensBl) && !user.has_scope(Scope::WriteVensVen) {
+        return Err(AppError::Forbidden(
+            "Missing 'write_vens_bl' or 'write_vens_ven' scope",
+        ));
+    }
+    // fails if the client may not see the VEN
+    let _ = get(
+        State(ven_source.clone()),
+        Path(id.clone()),
+        User(user.clone()),
+    )
+    .await?;
+
+    let mut lock = ven_source.lock(&id).await?;
+    let ven = lock.current().await?;
+    let update = if user.has_scope(Scope::WriteVensBl) {
+        VenRequest::BlVenRequest(patch.apply(&ven.content)?)
+    } else {
+        VenRequest::VenVenRequest(patch.apply(&VenVenRequest {
+            ven_name: ven.content.ven_name,
+            attributes: ven.content.attributes,
+        })?)
+    };
+
+    edit_locked(
+        lock,
+        State(event_source),
+        State(privacy),
+        State(notifier_state),
+        User(user),
+        ValidatedJson(update),
+    )
+    .await
+}
+
+/// Updates the VEN through the `lock`, which [`edit`] and [`patch`] take
+/// after checking the scopes, and before reading the VEN
+async fn edit_locked(
+    mut lock: Box<dyn Locked<BlVenRequest, Ven, Option<ClientId>, AppError>>,
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    User(user): User,
+    ValidatedJson(update): ValidatedJson<VenRequest>,
 ) -> AppResponse<Ven> {
     let ven = if user.has_scope(Scope::WriteVensBl) {
         let VenRequest::BlVenRequest(update) = update else {
@@ -135,14 +217,15 @@ pub async fn edit(
                 "Did receive a VEN_VEN_REQUEST, but user is authenticated as a BL client",
             ));
         };
-        ven_source.update(&id, update, &None).await?
-    } else if user.has_scope(Scope::WriteVensVen) {
+        lock.update(update, &None).await?
+    } else {
         let VenRequest::VenVenRequest(update) = update else {
             return Err(AppError::BadRequest(
                 "Did receive a BL_VEN_REQUEST, but user is authenticated as a VEN client",
             ));
         };
-        let org_ven = ven_source.retrieve(&id, &Some(user.client_id()?)).await?;
+        // the update fails if the VEN belongs to another client
+        let org_ven = lock.current().await?;
 
         let update = BlVenRequest {
             client_id: org_ven.content.client_id,
@@ -151,19 +234,12 @@ pub async fn edit(
             ven_name: update.ven_name,
             attributes: update.attributes,
         };
-        ven_source
-            .update(&id, update, &Some(user.client_id()?))
-            .await?
-    } else {
-        return Err(AppError::Forbidden(
-            "Missing 'write_vens_bl' or 'write_vens_ven' scope",
-        ));
+        lock.update(update, &Some(user.client_id()?)).await?
     };
 
     info!(%ven.id, ven.ven_name=ven.content.ven_name, client_id = user.sub, "VEN updated");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -183,10 +259,12 @@ pub async fn delete(
     Path(id): Path<VenId>,
     User(user): User,
 ) -> AppResponse<Ven> {
-    let ven = if user.has_scope(Scope::WriteVensBl) {
-        ven_source.delete(&id, &None).await?
+    let (ven, subscriptions) = if user.has_scope(Scope::WriteVensBl) {
+        ven_source.delete_with_subscriptions(&id, &None).await?
     } else if user.has_scope(Scope::WriteVensVen) {
-        ven_source.delete(&id, &Some(user.client_id()?)).await?
+        ven_source
+            .delete_with_subscriptions(&id, &Some(user.client_id()?))
+            .await?
     } else {
         return Err(AppError::Forbidden("Missing 'write_vens_bl' scope"));
     };
@@ -194,7 +272,6 @@ pub async fn delete(
     info!(%ven.id, ven.ven_name=ven.content.ven_name, client_id = user.sub, "VEN deleted");
 
     subscription::notify(
-        &*ven_source,
         &*event_source,
         &*privacy,
         &notifier_state,
@@ -203,21 +280,85 @@ pub async fn delete(
     )
     .await;
 
+    subscription::forget_deleted(&*event_source, &*privacy, &notifier_state, subscriptions).await;
+
     Ok(Json(ven))
 }
 
+/// Creates, updates, and deletes VENs in a single transaction.
+/// Only available for business logic clients.
+/// The subscriptions of the clients of deleted VENs are deleted in the same transaction.
+/// Notifications are sent once all operations are committed.
+pub async fn batch(
+    State(event_source): State<Arc<dyn EventCrud>>,
+    State(ven_source): State<Arc<dyn VenCrud>>,
+    State(privacy): State<Arc<dyn VenObjectPrivacy>>,
+    State(notifier_state): State<Arc<NotifierState>>,
+    User(user): User,
+    ValidatedJson(request): ValidatedJson<BatchRequest<VenId, BlVenRequest>>,
+) -> Result<batch::Outcome<Ven>, AppError> {
+    if !user.has_scope(Scope::WriteVensBl) {
+        return Err(AppError::Forbidden("Missing 'write_vens_bl' scope"));
+    }
+    if let Some(invalid) = batch::Outcome::invalid(&request.operations) {
+        return Ok(invalid);
+    }
+
+    let operations = request.operations.iter().map(batch::operation).collect();
+    let (results, subscriptions) = ven_source.batch(request.operations, &None).await?;
+    let outcome = batch::Outcome::new(operations, results);
+
+    if let batch::Outcome::Committed(vens) = &outcome {
+        info!(client_id = user.sub, "applied batch of {} VENs", vens.len());
+
+        for (operation, ven) in vens {
+            subscription::notify(
+                &*event_source,
+                &*privacy,
+                &notifier_state,
+                *operation,
+                AnyObject::Ven(ven.clone()),
+            )
+            .await;
+        }
+
+        subscription::forget_deleted(&*event_source, &*privacy, &notifier_state, subscriptions)
+            .await;
+    }
+
+    Ok(outcome)
+}
+
 #[derive(Deserialize, Validate, Debug)]
 #[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_cursor"))]
 pub struct QueryParams {
     #[validate(length(min = 1, max = 128))]
     pub(crate) ven_name: Option<String>,
     pub(crate) targets: TargetQueryParams,
+    pub(crate) attributes: AttributeQueryParams,
     #[serde(default)]
     #[validate(range(min = 0))]
     pub(crate) skip: i64,
     #[validate(range(min = 1, max = 50))]
     #[serde(default = "get_50")]
     pub(crate) limit: i64,
+    #[serde(default, deserialize_with = "deserialize_cursor")]
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Paginated for QueryParams {
+    fn skip(&self) -> i64 {
+        self.skip
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
 }
 
 fn get_50() -> i64 {
@@ -231,7 +372,8 @@ mod tests {
     use axum::{body::Body, http::StatusCode};
     use openleadr_wire::{
         Ven,
-        problem::Problem,
+        problem::{ErrorCode, Problem},
+        subscription::Subscription,
         ven::{BlVenRequest, VenRequest, VenVenRequest},
     };
     use reqwest::Method;
@@ -377,6 +519,43 @@ mod tests {
         }
     }
 
+    #[sqlx::test(fixtures("users", "vens", "subscriptions"))]
+    async fn delete_ven_deletes_its_subscriptions(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![Scope::WriteVensBl, Scope::ReadAll]).await;
+
+        let (status, subscriptions) = test
+            .request::<Vec<Subscription>>(Method::GET, "/subscriptions", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(subscriptions.len(), 2);
+
+        let (status, _) = test
+            .request::<Ven>(Method::DELETE, "/vens/ven-1", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, subscriptions) = test
+            .request::<Vec<Subscription>>(Method::GET, "/subscriptions", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(subscriptions.is_empty());
+    }
+
+    #[sqlx::test(fixtures("users", "vens", "resources", "subscriptions"))]
+    async fn failed_ven_delete_keeps_its_subscriptions(db: PgPool) {
+        let test = ApiTest::new(db, "test-client", vec![Scope::WriteVensBl, Scope::ReadAll]).await;
+
+        // VENs with resources cannot be deleted
+        let status = test.empty_request(Method::DELETE, "/vens/ven-1").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, subscriptions) = test
+            .request::<Vec<Subscription>>(Method::GET, "/subscriptions", Body::empty())
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(subscriptions.len(), 2);
+    }
+
     #[sqlx::test(fixtures("users", "vens"))]
     async fn get_all_unfiltered(db: PgPool) {
         let test = ApiTest::new(db, "test-client", vec![Scope::ReadAll]).await;
@@ -667,6 +846,9 @@ mod tests {
 
         assert_eq!(status, StatusCode::CONFLICT);
         assert_eq!(problem.status, StatusCode::CONFLICT);
+        assert_eq!(problem.code, Some(ErrorCode::Conflict));
+        assert_eq!(problem.r#type, ErrorCode::Conflict.problem_type());
+        assert_eq!(problem.constraint.as_deref(), Some("ven_client_id_unique"));
     }
 
     #[sqlx::test(fixtures("users", "vens", "resources"))]
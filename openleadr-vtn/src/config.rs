@@ -0,0 +1,1089 @@
+//! Configuration of the VTN.
+//!
+//! Every setting can be given in a TOML file and overridden by an environment variable,
+//! e.g., `token_url` in the `[oauth]` table of the file by `OAUTH_TOKEN_URL`.
+//! A `.env` file in the working directory or one of its parents is read into the environment first.
+//! Loading the configuration validates all settings and reports every problem at once,
+//! so the VTN does not fail halfway through its startup.
+
+use std::{
+    fmt::{self, Display},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use base64::{
+    Engine, alphabet,
+    engine::{GeneralPurpose, general_purpose::PAD},
+};
+use jsonwebtoken::DecodingKey;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug)]
+pub struct VtnConfig {
+    pub port: u16,
+    /// Required with the `postgres` feature
+    pub database_url: Option<String>,
+    /// Apply pending database migrations at startup instead of refusing to start
+    pub auto_migrate: bool,
+    pub oauth: OAuthConfig,
+    /// Path the API is served under, e.g., `/openadr3/3.1.0`, or empty to serve it at the root
+    pub base_path: String,
+    /// Further paths the same API is served under, e.g., for clients of an older API version
+    pub additional_base_paths: Vec<String>,
+    pub mdns_ip_address: String,
+    pub mdns_host_name: String,
+    pub mdns_service_type: String,
+    pub mdns_server_name: String,
+    /// Base path advertised via mDNS, [`Self::base_path`] unless a reverse proxy serves the API under another path
+    pub mdns_base_path: String,
+    pub mqtt_url: Option<String>,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    pub mqtt_topic_prefix: String,
+    /// Shared secret the MQTT broker sends to the authentication hooks, which are disabled without it
+    pub mqtt_auth_hook_secret: Option<String>,
+    /// Messages to each MQTT topic are collected for this long and published as one JSON array
+    pub mqtt_batch_window: Option<Duration>,
+    /// Webhook subscriptions are deleted if their callback failed for this long
+    pub webhook_failure_timeout: Duration,
+    /// Time to deliver the queued notifications and close the connections when shutting down
+    pub shutdown_timeout: Duration,
+    /// Responses to requests with an `Idempotency-Key` are replayed for this long
+    pub idempotency_window: Duration,
+    /// Serve HTTPS instead of plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// Export traces to an OpenTelemetry collector
+    pub otel: Option<OtelConfig>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// PEM encoded certificate chain, reloaded when the file changes
+    pub cert: PathBuf,
+    /// PEM encoded private key, reloaded when the file changes
+    pub key: PathBuf,
+    /// Port of an additional plain HTTP listener that redirects all requests to HTTPS
+    pub redirect_port: Option<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OtelConfig {
+    /// Base URL of the OTLP/HTTP collector, e.g., `http://localhost:4318`
+    pub endpoint: Url,
+    pub service_name: String,
+    /// The spans and events to export, in the syntax of `RUST_LOG`
+    pub filter: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct OAuthConfig {
+    pub provider: OAuthType,
+    pub key_type: OAuthKeyType,
+    /// Shared secret of HMAC keys. The internal provider generates a random one if absent.
+    pub secret: Option<Vec<u8>>,
+    /// PEM encoded public key of RSA, EC, and Ed keys
+    pub pem: Option<Vec<u8>>,
+    pub jwks_location: Option<String>,
+    /// If set, tokens must have an `aud` claim with one of these audiences
+    pub valid_audiences: Option<Vec<String>>,
+    pub token_url: Url,
+}
+
+impl OAuthConfig {
+    /// The key to verify tokens with, if it is not retrieved from the JWKS location
+    pub fn decoding_key(&self) -> Result<Option<DecodingKey>, jsonwebtoken::errors::Error> {
+        match (self.key_type, &self.secret, &self.pem) {
+            (OAuthKeyType::Hmac, Some(secret), _) => Ok(Some(DecodingKey::from_secret(secret))),
+            (OAuthKeyType::Rsa, _, Some(pem)) => DecodingKey::from_rsa_pem(pem).map(Some),
+            (OAuthKeyType::Ec, _, Some(pem)) => DecodingKey::from_ec_pem(pem).map(Some),
+            (OAuthKeyType::Ed, _, Some(pem)) => DecodingKey::from_ed_pem(pem).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OAuthType {
+    #[default]
+    Internal,
+    External,
+}
+
+impl FromStr for OAuthType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "internal" => Ok(Self::Internal),
+            "external" => Ok(Self::External),
+            _ => Err("allowed are INTERNAL and EXTERNAL"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OAuthKeyType {
+    Hmac,
+    Rsa,
+    Ec,
+    #[serde(rename = "OKP")]
+    Ed,
+}
+
+impl FromStr for OAuthKeyType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hmac" => Ok(Self::Hmac),
+            "rsa" => Ok(Self::Rsa),
+            "ec" => Ok(Self::Ec),
+            "ed" => Ok(Self::Ed),
+            _ => Err("allowed are HMAC, RSA, EC, and ED"),
+        }
+    }
+}
+
+/// A setting, by its key in the config file and its environment variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setting {
+    pub key: &'static str,
+    pub env: &'static str,
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` ({})", self.key, self.env)
+    }
+}
+
+const fn setting(key: &'static str, env: &'static str) -> Setting {
+    Setting { key, env }
+}
+
+const PORT: Setting = setting("port", "PORT");
+const BASE_PATH: Setting = setting("base_path", "BASE_PATH");
+const ADDITIONAL_BASE_PATHS: Setting = setting("additional_base_paths", "ADDITIONAL_BASE_PATHS");
+pub(crate) const DATABASE_URL: Setting = setting("database_url", "DATABASE_URL");
+const AUTO_MIGRATE: Setting = setting("auto_migrate", "AUTO_MIGRATE");
+pub(crate) const OAUTH_TYPE: Setting = setting("oauth.type", "OAUTH_TYPE");
+const OAUTH_KEY_TYPE: Setting = setting("oauth.key_type", "OAUTH_KEY_TYPE");
+const OAUTH_BASE64_SECRET: Setting = setting("oauth.base64_secret", "OAUTH_BASE64_SECRET");
+const OAUTH_PEM: Setting = setting("oauth.pem", "OAUTH_PEM");
+const OAUTH_JWKS_LOCATION: Setting = setting("oauth.jwks_location", "OAUTH_JWKS_LOCATION");
+const OAUTH_VALID_AUDIENCES: Setting = setting("oauth.valid_audiences", "OAUTH_VALID_AUDIENCES");
+const OAUTH_TOKEN_URL: Setting = setting("oauth.token_url", "OAUTH_TOKEN_URL");
+const MQTT_URL: Setting = setting("mqtt.url", "MQTT_URL");
+const MQTT_USERNAME: Setting = setting("mqtt.username", "MQTT_USERNAME");
+const MQTT_PASSWORD: Setting = setting("mqtt.password", "MQTT_PASSWORD");
+const MQTT_TOPIC_PREFIX: Setting = setting("mqtt.topic_prefix", "MQTT_TOPIC_PREFIX");
+const MQTT_BATCH_WINDOW_MS: Setting = setting("mqtt.batch_window_ms", "MQTT_BATCH_WINDOW_MS");
+const MQTT_AUTH_HOOK_SECRET: Setting = setting("mqtt.auth_hook_secret", "MQTT_AUTH_HOOK_SECRET");
+const MDNS_IP_ADDRESS: Setting = setting("mdns.ip_address", "MDNS_IP_ADDRESS");
+const MDNS_HOST_NAME: Setting = setting("mdns.host_name", "MDNS_HOST_NAME");
+const MDNS_SERVICE_TYPE: Setting = setting("mdns.service_type", "MDNS_SERVICE_TYPE");
+const MDNS_SERVER_NAME: Setting = setting("mdns.server_name", "MDNS_SERVER_NAME");
+const MDNS_BASE_PATH: Setting = setting("mdns.base_path", "MDNS_BASE_PATH");
+const WEBHOOK_FAILURE_TIMEOUT: Setting =
+    setting("webhook_failure_timeout", "WEBHOOK_FAILURE_TIMEOUT");
+const SHUTDOWN_TIMEOUT: Setting = setting("shutdown_timeout", "SHUTDOWN_TIMEOUT");
+const IDEMPOTENCY_WINDOW: Setting = setting("idempotency_window", "IDEMPOTENCY_WINDOW");
+const TLS_CERT: Setting = setting("tls.cert", "TLS_CERT");
+const TLS_KEY: Setting = setting("tls.key", "TLS_KEY");
+const TLS_REDIRECT_PORT: Setting = setting("tls.redirect_port", "TLS_REDIRECT_PORT");
+const OTEL_ENDPOINT: Setting = setting("otel.endpoint", "OTEL_EXPORTER_OTLP_ENDPOINT");
+const OTEL_SERVICE_NAME: Setting = setting("otel.service_name", "OTEL_SERVICE_NAME");
+const OTEL_FILTER: Setting = setting("otel.filter", "OTEL_FILTER");
+
+const SETTINGS: &[Setting] = &[
+    PORT,
+    AUTO_MIGRATE,
+    BASE_PATH,
+    ADDITIONAL_BASE_PATHS,
+    DATABASE_URL,
+    OAUTH_TYPE,
+    OAUTH_KEY_TYPE,
+    OAUTH_BASE64_SECRET,
+    OAUTH_PEM,
+    OAUTH_JWKS_LOCATION,
+    OAUTH_VALID_AUDIENCES,
+    OAUTH_TOKEN_URL,
+    MQTT_URL,
+    MQTT_USERNAME,
+    MQTT_PASSWORD,
+    MQTT_TOPIC_PREFIX,
+    MQTT_BATCH_WINDOW_MS,
+    MQTT_AUTH_HOOK_SECRET,
+    MDNS_IP_ADDRESS,
+    MDNS_HOST_NAME,
+    MDNS_SERVICE_TYPE,
+    MDNS_SERVER_NAME,
+    MDNS_BASE_PATH,
+    WEBHOOK_FAILURE_TIMEOUT,
+    SHUTDOWN_TIMEOUT,
+    IDEMPOTENCY_WINDOW,
+    TLS_CERT,
+    TLS_KEY,
+    TLS_REDIRECT_PORT,
+    OTEL_ENDPOINT,
+    OTEL_SERVICE_NAME,
+    OTEL_FILTER,
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("cannot read config file {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("cannot parse config file {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("unknown setting `{0}` in config file")]
+    Unknown(String),
+    #[error("{setting}: {reason}")]
+    Invalid { setting: Setting, reason: String },
+}
+
+impl ConfigError {
+    pub(crate) fn invalid(setting: Setting, reason: impl Into<String>) -> Self {
+        Self::Invalid {
+            setting,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// All problems found while loading the configuration
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "- {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl From<ConfigError> for ConfigErrors {
+    fn from(error: ConfigError) -> Self {
+        Self(vec![error])
+    }
+}
+
+/// Looks up settings in the environment first and in the config file second,
+/// and collects the problems with their values.
+struct Loader<E> {
+    file: toml::Table,
+    env: E,
+    errors: Vec<ConfigError>,
+}
+
+impl<E: Fn(&str) -> Option<String>> Loader<E> {
+    fn new(path: Option<&Path>, env: E) -> Self {
+        let mut loader = Self {
+            file: toml::Table::new(),
+            env,
+            errors: Vec::new(),
+        };
+
+        if let Some(path) = path {
+            match std::fs::read_to_string(path) {
+                Ok(content) => match content.parse::<toml::Table>() {
+                    Ok(file) => loader.file = file,
+                    Err(source) => loader.errors.push(ConfigError::Parse {
+                        path: path.to_owned(),
+                        source,
+                    }),
+                },
+                Err(source) => loader.errors.push(ConfigError::Read {
+                    path: path.to_owned(),
+                    source,
+                }),
+            }
+            let file = std::mem::take(&mut loader.file);
+            loader.check_unknown(&file, "");
+            loader.file = file;
+        }
+
+        loader
+    }
+
+    fn check_unknown(&mut self, table: &toml::Table, prefix: &str) {
+        for (key, value) in table {
+            let key = format!("{prefix}{key}");
+            match value {
+                toml::Value::Table(table)
+                    if SETTINGS
+                        .iter()
+                        .any(|s| s.key.starts_with(&format!("{key}."))) =>
+                {
+                    self.check_unknown(table, &format!("{key}."))
+                }
+                _ if SETTINGS.iter().any(|s| s.key == key) => {}
+                _ => self.errors.push(ConfigError::Unknown(key)),
+            }
+        }
+    }
+
+    fn raw(&mut self, setting: Setting) -> Option<String> {
+        if let Some(value) = (self.env)(setting.env) {
+            return Some(value);
+        }
+
+        let (path, key) = match setting.key.rsplit_once('.') {
+            Some((table, key)) => (Some(table), key),
+            None => (None, setting.key),
+        };
+        let table = match path {
+            Some(path) => self.file.get(path)?.as_table()?,
+            None => &self.file,
+        };
+
+        match table.get(key)? {
+            toml::Value::String(value) => Some(value.clone()),
+            toml::Value::Integer(value) => Some(value.to_string()),
+            toml::Value::Boolean(value) => Some(value.to_string()),
+            toml::Value::Array(values) => {
+                let values: Option<Vec<_>> = values.iter().map(|v| v.as_str()).collect();
+                match values {
+                    Some(values) => Some(values.join(",")),
+                    None => {
+                        self.errors
+                            .push(ConfigError::invalid(setting, "expected a list of strings"));
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.errors
+                    .push(ConfigError::invalid(setting, "unexpected type"));
+                None
+            }
+        }
+    }
+
+    fn get<T>(&mut self, setting: Setting) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.raw(setting)?;
+        value
+            .parse()
+            .inspect_err(|err| {
+                self.errors.push(ConfigError::invalid(
+                    setting,
+                    format!("invalid value {value:?}: {err}"),
+                ))
+            })
+            .ok()
+    }
+
+    fn require<T>(&mut self, setting: Setting, hint: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.get(setting);
+        if value.is_none() && !self.errors.iter().any(|err| err.is_about(setting)) {
+            self.errors
+                .push(ConfigError::invalid(setting, format!("must be set {hint}")));
+        }
+        value
+    }
+
+    fn invalid(&mut self, setting: Setting, reason: impl Into<String>) {
+        self.errors.push(ConfigError::invalid(setting, reason));
+    }
+
+    /// `local_url` is where this VTN is reachable, to derive the URL of its `/auth/token` endpoint
+    fn oauth(&mut self, local_url: &str) -> Option<OAuthConfig> {
+        let provider = self.get(OAUTH_TYPE).unwrap_or_default();
+        let key_type: Option<OAuthKeyType> = self.get(OAUTH_KEY_TYPE);
+
+        let secret = self
+            .get::<String>(OAUTH_BASE64_SECRET)
+            .and_then(|base64_secret| {
+                match GeneralPurpose::new(&alphabet::STANDARD, PAD).decode(base64_secret) {
+                    // https://datatracker.ietf.org/doc/html/rfc7518#section-3.2
+                    Ok(secret) if secret.len() < 32 => {
+                        self.invalid(OAUTH_BASE64_SECRET, "must have at least 32 bytes");
+                        None
+                    }
+                    Ok(secret) => Some(secret),
+                    Err(err) => {
+                        self.invalid(OAUTH_BASE64_SECRET, format!("invalid base64 string: {err}"));
+                        None
+                    }
+                }
+            });
+
+        let pem = self
+            .get::<PathBuf>(OAUTH_PEM)
+            .and_then(|path| match std::fs::read(&path) {
+                Ok(pem) => Some(pem),
+                Err(err) => {
+                    self.invalid(OAUTH_PEM, format!("cannot read {}: {err}", path.display()));
+                    None
+                }
+            });
+
+        let jwks_location = self.get(OAUTH_JWKS_LOCATION);
+        let valid_audiences = self
+            .get::<String>(OAUTH_VALID_AUDIENCES)
+            .map(|audiences| audiences.split(',').map(|s| s.to_string()).collect());
+
+        let key_type = match provider {
+            OAuthType::Internal => {
+                #[cfg(not(feature = "internal-oauth"))]
+                self.invalid(
+                    OAUTH_TYPE,
+                    "the internal OAuth provider requires the `internal-oauth` feature. \
+                    Recompile with the feature enabled or use an EXTERNAL OAuth provider",
+                );
+                if key_type.is_some_and(|key_type| key_type != OAuthKeyType::Hmac) {
+                    self.invalid(
+                        OAUTH_KEY_TYPE,
+                        "the internal OAuth provider only supports HMAC keys",
+                    );
+                }
+                OAuthKeyType::Hmac
+            }
+            OAuthType::External => {
+                let Some(key_type) = key_type else {
+                    self.require::<OAuthKeyType>(OAUTH_KEY_TYPE, "for an external OAuth provider");
+                    return None;
+                };
+                if key_type == OAuthKeyType::Hmac && secret.is_none() {
+                    self.require::<String>(
+                        OAUTH_BASE64_SECRET,
+                        "for an external OAuth provider with HMAC keys",
+                    );
+                }
+                if key_type != OAuthKeyType::Hmac && pem.is_none() && jwks_location.is_none() {
+                    self.invalid(
+                        OAUTH_PEM,
+                        format!(
+                            "either this or {OAUTH_JWKS_LOCATION} must be set \
+                            for an external OAuth provider with the given key type"
+                        ),
+                    );
+                }
+                key_type
+            }
+        };
+
+        let token_url = match (self.get(OAUTH_TOKEN_URL), provider) {
+            (Some(token_url), _) => token_url,
+            (None, _) if self.errors.iter().any(|err| err.is_about(OAUTH_TOKEN_URL)) => {
+                return None;
+            }
+            (None, OAuthType::Internal) => match format!("{local_url}auth/token").parse() {
+                Ok(token_url) => token_url,
+                Err(err) => {
+                    self.invalid(
+                        OAUTH_TOKEN_URL,
+                        format!("must be set, as it cannot be derived from {local_url:?}: {err}"),
+                    );
+                    return None;
+                }
+            },
+            (None, OAuthType::External) => {
+                self.invalid(
+                    OAUTH_TOKEN_URL,
+                    "must be set to the token endpoint of the OAuth provider",
+                );
+                return None;
+            }
+        };
+
+        let oauth = OAuthConfig {
+            provider,
+            key_type,
+            secret,
+            pem,
+            jwks_location,
+            valid_audiences,
+            token_url,
+        };
+
+        if let Err(err) = oauth.decoding_key() {
+            self.invalid(OAUTH_PEM, format!("cannot read {key_type:?} key: {err}"));
+        }
+
+        Some(oauth)
+    }
+}
+
+impl<E: Fn(&str) -> Option<String>> Loader<E> {
+    /// Normalizes a base path to a leading and no trailing slash, or empty for the root
+    fn base_path(&mut self, setting: Setting, path: &str) -> Option<String> {
+        let path = path.trim().trim_matches('/');
+        let valid_segment = |segment: &str| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c))
+        };
+
+        if path.is_empty() {
+            Some(String::new())
+        } else if path.split('/').all(valid_segment) {
+            Some(format!("/{path}"))
+        } else {
+            self.invalid(
+                setting,
+                format!(
+                    "invalid path {path:?}, segments may only contain letters, digits, and `-._~`"
+                ),
+            );
+            None
+        }
+    }
+
+    fn base_paths(&mut self) -> (String, Vec<String>) {
+        let base_path = match self.get::<String>(BASE_PATH) {
+            Some(path) => self.base_path(BASE_PATH, &path).unwrap_or_default(),
+            None => String::new(),
+        };
+
+        let mut additional_base_paths: Vec<String> = Vec::new();
+        let paths = self
+            .get::<String>(ADDITIONAL_BASE_PATHS)
+            .unwrap_or_default();
+        for path in paths.split(',').filter(|path| !path.trim().is_empty()) {
+            let Some(path) = self.base_path(ADDITIONAL_BASE_PATHS, path) else {
+                continue;
+            };
+            if path == base_path || additional_base_paths.contains(&path) {
+                self.invalid(
+                    ADDITIONAL_BASE_PATHS,
+                    format!("the API is already served under {path:?}"),
+                );
+                continue;
+            }
+            additional_base_paths.push(path);
+        }
+
+        (base_path, additional_base_paths)
+    }
+
+    fn tls(&mut self) -> Option<TlsConfig> {
+        let cert: Option<PathBuf> = self.get(TLS_CERT);
+        let key: Option<PathBuf> = self.get(TLS_KEY);
+        let redirect_port = self.get(TLS_REDIRECT_PORT);
+
+        let (cert, key) = match (cert, key) {
+            (Some(cert), Some(key)) => (cert, key),
+            (None, None) => {
+                if redirect_port.is_some() {
+                    self.invalid(TLS_REDIRECT_PORT, "requires TLS to be configured");
+                }
+                return None;
+            }
+            (cert, _) => {
+                let (missing, given) = if cert.is_none() {
+                    (TLS_CERT, TLS_KEY)
+                } else {
+                    (TLS_KEY, TLS_CERT)
+                };
+                self.invalid(missing, format!("must be set together with {given}"));
+                return None;
+            }
+        };
+
+        #[cfg(feature = "tls")]
+        {
+            use rustls_pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+
+            match CertificateDer::pem_file_iter(&cert)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            {
+                Ok(certs) if certs.is_empty() => self.invalid(
+                    TLS_CERT,
+                    format!("no certificate found in {}", cert.display()),
+                ),
+                Ok(_) => {}
+                Err(err) => {
+                    self.invalid(TLS_CERT, format!("cannot read {}: {err}", cert.display()))
+                }
+            }
+            if let Err(err) = PrivateKeyDer::from_pem_file(&key) {
+                self.invalid(TLS_KEY, format!("cannot read {}: {err}", key.display()));
+            }
+        }
+        #[cfg(not(feature = "tls"))]
+        self.invalid(TLS_CERT, "serving HTTPS requires the `tls` feature");
+
+        Some(TlsConfig {
+            cert,
+            key,
+            redirect_port,
+        })
+    }
+}
+
+impl<E: Fn(&str) -> Option<String>> Loader<E> {
+    fn otel(&mut self) -> Option<OtelConfig> {
+        let endpoint = self.get(OTEL_ENDPOINT);
+        let service_name = self
+            .get(OTEL_SERVICE_NAME)
+            .unwrap_or_else(|| "openleadr-vtn".to_string());
+        let filter = self
+            .get(OTEL_FILTER)
+            .unwrap_or_else(|| "info,sqlx::query=debug".to_string());
+
+        if let Err(err) = tracing_subscriber::EnvFilter::try_new(&filter) {
+            self.invalid(OTEL_FILTER, format!("invalid filter {filter:?}: {err}"));
+        }
+
+        let endpoint = endpoint?;
+        #[cfg(not(feature = "otel"))]
+        self.invalid(
+            OTEL_ENDPOINT,
+            "exporting traces requires the `otel` feature",
+        );
+
+        Some(OtelConfig {
+            endpoint,
+            service_name,
+            filter,
+        })
+    }
+}
+
+fn scheme(tls: bool) -> &'static str {
+    if tls { "https" } else { "http" }
+}
+
+fn local_url(scheme: &str, host_name: &str, port: u16, base_path: &str) -> String {
+    format!("{scheme}://{host_name}:{port}{base_path}/")
+}
+
+impl ConfigError {
+    fn is_about(&self, setting: Setting) -> bool {
+        matches!(self, Self::Invalid { setting: s, .. } if *s == setting)
+    }
+}
+
+impl VtnConfig {
+    /// Load configuration from the TOML file at `path`, if given, overridden by environment variables
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigErrors> {
+        dotenvy::dotenv().ok();
+        Self::load_with(path, |name| std::env::var(name).ok())
+    }
+
+    /// Load configuration from environment variables
+    pub fn from_env() -> Result<Self, ConfigErrors> {
+        Self::load(None)
+    }
+
+    fn load_with(
+        path: Option<&Path>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigErrors> {
+        let mut loader = Loader::new(path, env);
+
+        let port = loader.get(PORT).unwrap_or(3000);
+        let (base_path, additional_base_paths) = loader.base_paths();
+
+        #[cfg(feature = "postgres")]
+        let database_url = loader.require(DATABASE_URL, "with the `postgres` feature");
+        #[cfg(not(feature = "postgres"))]
+        let database_url = loader.get(DATABASE_URL);
+        let auto_migrate = loader.get(AUTO_MIGRATE).unwrap_or(false);
+
+        let mqtt_url: Option<String> = loader.get(MQTT_URL);
+        let mqtt_username: Option<String> = loader.get(MQTT_USERNAME);
+        let mqtt_password: Option<String> = loader.get(MQTT_PASSWORD);
+        if mqtt_url.is_some() || mqtt_username.is_some() || mqtt_password.is_some() {
+            for (setting, value) in [
+                (MQTT_URL, &mqtt_url),
+                (MQTT_USERNAME, &mqtt_username),
+                (MQTT_PASSWORD, &mqtt_password),
+            ] {
+                if value.is_none() {
+                    loader.invalid(
+                        setting,
+                        "must be set when any of the MQTT url, username, and password is set",
+                    );
+                }
+            }
+        }
+
+        let mdns_ip_address = loader
+            .get::<IpAddr>(MDNS_IP_ADDRESS)
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let mdns_host_name = loader
+            .get(MDNS_HOST_NAME)
+            .unwrap_or_else(|| "vtn.local.".to_string());
+        let mdns_service_type = loader
+            .get(MDNS_SERVICE_TYPE)
+            .unwrap_or_else(|| "_openadr3._tcp.local.".to_string());
+        let mdns_server_name = loader
+            .get(MDNS_SERVER_NAME)
+            .unwrap_or_else(|| "openleadr-vtn".to_string());
+        let mdns_base_path = match loader.get::<String>(MDNS_BASE_PATH) {
+            Some(path) => loader.base_path(MDNS_BASE_PATH, &path).unwrap_or_default(),
+            None => base_path.clone(),
+        };
+        let mqtt_topic_prefix = loader.get(MQTT_TOPIC_PREFIX).unwrap_or_default();
+        let mqtt_batch_window = loader
+            .get::<u64>(MQTT_BATCH_WINDOW_MS)
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis);
+        let mqtt_auth_hook_secret = loader.get(MQTT_AUTH_HOOK_SECRET);
+        let webhook_failure_timeout =
+            Duration::from_secs(loader.get(WEBHOOK_FAILURE_TIMEOUT).unwrap_or(24 * 60 * 60));
+        let shutdown_timeout = Duration::from_secs(loader.get(SHUTDOWN_TIMEOUT).unwrap_or(10));
+        let idempotency_window =
+            Duration::from_secs(loader.get(IDEMPOTENCY_WINDOW).unwrap_or(24 * 60 * 60));
+        let tls = loader.tls();
+        let otel = loader.otel();
+        let oauth = loader.oauth(&local_url(
+            scheme(tls.is_some()),
+            &mdns_host_name,
+            port,
+            &mdns_base_path,
+        ));
+
+        match oauth {
+            Some(oauth) if loader.errors.is_empty() => Ok(VtnConfig {
+                port,
+                database_url,
+                auto_migrate,
+                oauth,
+                base_path,
+                additional_base_paths,
+                mdns_ip_address,
+                mdns_host_name,
+                mdns_service_type,
+                mdns_server_name,
+                mdns_base_path,
+                mqtt_url,
+                mqtt_username,
+                mqtt_password,
+                mqtt_topic_prefix,
+                mqtt_auth_hook_secret,
+                mqtt_batch_window,
+                webhook_failure_timeout,
+                shutdown_timeout,
+                idempotency_window,
+                tls,
+                otel,
+            }),
+            _ => Err(ConfigErrors(loader.errors)),
+        }
+    }
+
+    /// The URL scheme the VTN is served with
+    pub fn scheme(&self) -> &'static str {
+        scheme(self.tls.is_some())
+    }
+
+    /// The URL of the API advertised via mDNS, with a trailing slash,
+    /// such that the paths of the API can be joined to it
+    pub fn local_url(&self) -> String {
+        local_url(
+            self.scheme(),
+            &self.mdns_host_name,
+            self.port,
+            &self.mdns_base_path,
+        )
+    }
+
+    /// The paths the API is served under, starting with [`Self::base_path`]
+    pub fn base_paths(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.base_path.as_str())
+            .chain(self.additional_base_paths.iter().map(String::as_str))
+    }
+
+    /// Checks that the MQTT settings are complete, and returns them if MQTT is enabled
+    pub(crate) fn mqtt(&self) -> Result<Option<(&str, &str, &str)>, ConfigErrors> {
+        match (&self.mqtt_url, &self.mqtt_username, &self.mqtt_password) {
+            (Some(url), Some(username), Some(password)) => Ok(Some((url, username, password))),
+            (None, None, None) => Ok(None),
+            _ => Err(ConfigError::invalid(
+                MQTT_URL,
+                "MQTT url, username, and password must be set together",
+            )
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+    use super::*;
+
+    const SECRET: &str = "60QL3fluRYn/21n0zNoPe1np5aB6P9C75b0Nbkwu4FM=";
+
+    fn config_file(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("openleadr-vtn-{}-{name}.toml", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn load(path: Option<&Path>, env: &[(&str, &str)]) -> Result<VtnConfig, ConfigErrors> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        VtnConfig::load_with(path, |name| env.get(name).cloned())
+    }
+
+    fn messages(errors: ConfigErrors) -> Vec<String> {
+        errors.0.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let path = config_file(
+            "env_overrides_file",
+            r#"
+            port = 4000
+            database_url = "postgres://file"
+            webhook_failure_timeout = 60
+            shutdown_timeout = 5
+            idempotency_window = 600
+
+            [oauth]
+            token_url = "http://file/auth/token"
+            valid_audiences = ["a", "b"]
+
+            [mqtt]
+            url = "mqtt://file"
+            username = "user"
+            password = "password"
+            batch_window_ms = 100
+            "#,
+        );
+
+        let config = load(
+            Some(&path),
+            &[
+                ("PORT", "5000"),
+                ("OAUTH_BASE64_SECRET", SECRET),
+                ("MQTT_URL", "mqtt://env"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.database_url.as_deref(), Some("postgres://file"));
+        assert_eq!(config.webhook_failure_timeout, Duration::from_secs(60));
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(5));
+        assert_eq!(config.idempotency_window, Duration::from_secs(600));
+        assert_eq!(config.oauth.token_url.as_str(), "http://file/auth/token");
+        assert_eq!(
+            config.oauth.valid_audiences,
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(config.oauth.secret.as_ref().map(Vec::len), Some(32));
+        assert_eq!(config.mqtt_url.as_deref(), Some("mqtt://env"));
+        assert_eq!(config.mqtt_batch_window, Some(Duration::from_millis(100)));
+        assert_eq!(config.mdns_host_name, "vtn.local.");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let path = config_file(
+            "reports_all_problems",
+            r#"
+            prot = 4000
+
+            [mqtt]
+            url = "mqtt://file"
+            "#,
+        );
+
+        let errors = messages(
+            load(
+                Some(&path),
+                &[
+                    ("PORT", "70000"),
+                    ("OAUTH_TYPE", "EXTERNAL"),
+                    ("OAUTH_KEY_TYPE", "RSA"),
+                    ("OAUTH_BASE64_SECRET", "&"),
+                ],
+            )
+            .unwrap_err(),
+        );
+
+        for expected in [
+            "unknown setting `prot` in config file",
+            "`port` (PORT): invalid value \"70000\"",
+            "`oauth.base64_secret` (OAUTH_BASE64_SECRET): invalid base64 string",
+            "`oauth.pem` (OAUTH_PEM): either this or `oauth.jwks_location` (OAUTH_JWKS_LOCATION) must be set",
+            "`oauth.token_url` (OAUTH_TOKEN_URL): must be set",
+            "`mqtt.username` (MQTT_USERNAME): must be set",
+            "`mqtt.password` (MQTT_PASSWORD): must be set",
+        ] {
+            assert!(
+                errors.iter().any(|error| error.starts_with(expected)),
+                "missing {expected:?} in {errors:#?}"
+            );
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls() {
+        let env = [
+            ("DATABASE_URL", "postgres://env"),
+            ("OAUTH_TOKEN_URL", "http://localhost/auth/token"),
+            ("TLS_CERT", "./tests/assets/tls-cert-1.pem"),
+            ("TLS_KEY", "./tests/assets/tls-key-1.pem"),
+            ("TLS_REDIRECT_PORT", "8080"),
+        ];
+        let tls = load(None, &env).unwrap().tls.unwrap();
+        assert_eq!(tls.cert, Path::new("./tests/assets/tls-cert-1.pem"));
+        assert_eq!(tls.redirect_port, Some(8080));
+
+        let errors = messages(load(None, &[env[0], env[1], env[2]]).unwrap_err());
+        assert_eq!(
+            errors,
+            vec!["`tls.key` (TLS_KEY): must be set together with `tls.cert` (TLS_CERT)"]
+        );
+
+        let errors = messages(
+            load(
+                None,
+                &[
+                    env[0],
+                    env[1],
+                    env[2],
+                    ("TLS_KEY", "./tests/assets/tls-cert-2.pem"),
+                ],
+            )
+            .unwrap_err(),
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("`tls.key` (TLS_KEY): cannot read"));
+    }
+
+    #[test]
+    fn base_paths() {
+        let config = load(
+            None,
+            &[
+                ("DATABASE_URL", "postgres://env"),
+                ("BASE_PATH", "openadr3/3.1.0/"),
+                ("ADDITIONAL_BASE_PATHS", "/openadr3/3.0.1,/"),
+                ("PORT", "4000"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.base_path, "/openadr3/3.1.0");
+        assert_eq!(
+            config.base_paths().collect::<Vec<_>>(),
+            vec!["/openadr3/3.1.0", "/openadr3/3.0.1", ""]
+        );
+        assert_eq!(config.mdns_base_path, "/openadr3/3.1.0");
+        assert_eq!(config.local_url(), "http://vtn.local.:4000/openadr3/3.1.0/");
+        assert_eq!(
+            config.oauth.token_url.as_str(),
+            "http://vtn.local.:4000/openadr3/3.1.0/auth/token"
+        );
+
+        let config = load(
+            None,
+            &[
+                ("DATABASE_URL", "postgres://env"),
+                ("OAUTH_TOKEN_URL", "https://vtn.example.com/auth/token"),
+                ("MDNS_BASE_PATH", "/vtn"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.base_path, "");
+        assert_eq!(config.local_url(), "http://vtn.local.:3000/vtn/");
+        assert_eq!(
+            config.oauth.token_url.as_str(),
+            "https://vtn.example.com/auth/token"
+        );
+
+        let errors = messages(
+            load(
+                None,
+                &[
+                    ("DATABASE_URL", "postgres://env"),
+                    ("BASE_PATH", "/openadr3/{version}"),
+                    ("ADDITIONAL_BASE_PATHS", "/v1,v1/,/a//b"),
+                ],
+            )
+            .unwrap_err(),
+        );
+        assert_eq!(errors.len(), 3, "{errors:#?}");
+        assert!(errors[0].starts_with("`base_path` (BASE_PATH): invalid path"));
+        assert_eq!(
+            errors[1],
+            "`additional_base_paths` (ADDITIONAL_BASE_PATHS): the API is already served under \"/v1\""
+        );
+        assert!(
+            errors[2].starts_with("`additional_base_paths` (ADDITIONAL_BASE_PATHS): invalid path")
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        let errors = messages(
+            load(
+                Some(Path::new("/nonexistent/openleadr-vtn.toml")),
+                &[
+                    ("DATABASE_URL", "postgres://env"),
+                    ("OAUTH_TOKEN_URL", "http://localhost/auth/token"),
+                ],
+            )
+            .unwrap_err(),
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("cannot read config file /nonexistent/openleadr-vtn.toml"));
+    }
+
+    #[test]
+    fn external_pem_must_match_key_type() {
+        let errors = messages(
+            load(
+                None,
+                &[
+                    ("DATABASE_URL", "postgres://env"),
+                    ("OAUTH_TYPE", "EXTERNAL"),
+                    ("OAUTH_KEY_TYPE", "EC"),
+                    ("OAUTH_PEM", "./tests/assets/public-rsa.pem"),
+                    ("OAUTH_TOKEN_URL", "http://somewhere/auth/token"),
+                ],
+            )
+            .unwrap_err(),
+        );
+        assert_eq!(
+            errors,
+            vec!["`oauth.pem` (OAUTH_PEM): cannot read Ec key: InvalidKeyFormat"]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Correlation of a request with the work it causes, in this VTN and in other services.
+//!
+//! Every request gets a request ID, taken from its `X-Request-Id` header or generated.
+//! The ID is echoed in the response, used as `instance` of [`Problem`](openleadr_wire::problem::Problem)
+//! responses, and forwarded to the webhooks and JWKS requests the request causes.
+//! With the `otel` feature, the W3C `traceparent` header is accepted and forwarded as well.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{Span, info_span};
+use uuid::Uuid;
+
+pub(crate) static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longer IDs of clients are replaced, so they cannot bloat our logs
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The ID of the request currently handled, if any
+pub(crate) fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Middleware adopting the request ID of the client, or generating one,
+/// and echoing it in the response
+pub(crate) async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let header = HeaderValue::from_str(&id).expect("request ID is a valid header value");
+
+    // so the span of the trace layer can pick it up
+    request
+        .headers_mut()
+        .insert(X_REQUEST_ID.clone(), header.clone());
+    let mut response = REQUEST_ID.scope(id, next.run(request)).await;
+    response.headers_mut().insert(X_REQUEST_ID.clone(), header);
+    response
+}
+
+/// The span of a request, a child of the remote span given in the `traceparent` header, if any
+pub(crate) fn make_span<B>(request: &axum::http::Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+    // the MQTT broker may send the secret of the auth hooks as query parameter
+    let uri = match request.uri().path_and_query() {
+        Some(path) if path.path().contains("/notifiers/mqtt/auth/") => path.path(),
+        Some(path) => path.as_str(),
+        None => "",
+    };
+    let span = info_span!(
+        "request",
+        method = %request.method(),
+        uri,
+        version = ?request.version(),
+        request_id,
+    );
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry_http::HeaderExtractor;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(request.headers()))
+        });
+        if let Err(err) = span.set_parent(parent) {
+            tracing::debug!("cannot continue the trace of the request: {err}");
+        }
+    }
+
+    span
+}
+
+/// Headers for an outgoing request on behalf of the request with `request_id`,
+/// continuing the trace of the current span
+pub(crate) fn outgoing_headers(request_id: Option<&str>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    if let Some(id) = request_id.and_then(|id| HeaderValue::from_str(id).ok()) {
+        headers.insert(X_REQUEST_ID.clone(), id);
+    }
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry_http::HeaderInjector;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let context = Span::current().context();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+        });
+    }
+
+    headers
+}
+
+/// Where a notification delivery originates from, to correlate it with the causing request
+#[derive(Debug, Clone)]
+pub(crate) struct Origin {
+    pub(crate) request_id: Option<String>,
+    pub(crate) span: Span,
+}
+
+impl Origin {
+    pub(crate) fn current() -> Self {
+        Self {
+            request_id: request_id(),
+            span: Span::current(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::get,
+    };
+    use http_body_util::BodyExt;
+    use openleadr_wire::problem::Problem;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::error::AppError;
+
+    fn router() -> Router {
+        Router::new()
+            .route("/id", get(|| async { request_id().unwrap_or_default() }))
+            .route("/problem", get(|| async { AppError::NotFound }))
+            .layer(middleware::from_fn(request_id_middleware))
+    }
+
+    #[tokio::test]
+    async fn adopts_or_generates_request_id() {
+        let response = router()
+            .oneshot(
+                Request::get("/id")
+                    .header(&X_REQUEST_ID, "client-id-1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[&X_REQUEST_ID], "client-id-1");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "client-id-1");
+
+        for header in [None, Some("has spaces"), Some(&*"x".repeat(129))] {
+            let mut request = Request::get("/id");
+            if let Some(header) = header {
+                request = request.header(&X_REQUEST_ID, header);
+            }
+            let response = router()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let id = response.headers()[&X_REQUEST_ID]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            assert!(Uuid::parse_str(&id).is_ok(), "{id}");
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, id);
+        }
+    }
+
+    #[tokio::test]
+    async fn problem_contains_request_id() {
+        let response = router()
+            .oneshot(
+                Request::get("/problem")
+                    .header(&X_REQUEST_ID, "client-id-2")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[&X_REQUEST_ID], "client-id-2");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let problem: Problem = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem.instance.as_deref(), Some("client-id-2"));
+    }
+
+    #[test]
+    fn outgoing_headers_contain_request_id() {
+        let headers = outgoing_headers(Some("client-id-3"));
+        assert_eq!(headers[&X_REQUEST_ID], "client-id-3");
+        assert!(outgoing_headers(None).get(&X_REQUEST_ID).is_none());
+    }
+}
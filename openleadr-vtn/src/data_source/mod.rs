@@ -6,9 +6,11 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use openleadr_wire::{
     ClientId, Event, Program, Report,
+    batch::BatchOperation,
     event::{EventId, EventRequest},
+    interval::Interval,
     program::{ProgramId, ProgramRequest},
-    report::{ReportId, ReportRequest},
+    report::{ReportId, ReportRequest, ResourceName},
     resource::{BlResourceRequest, Resource, ResourceId},
     resource_group::{BlResourceGroupRequest, ResourceGroup, ResourceGroupId},
     subscription::{Subscription, SubscriptionId, SubscriptionRequest},
@@ -19,7 +21,10 @@ use openleadr_wire::{
 pub use postgres::PostgresStorage;
 use serde::{Deserialize, Serialize};
 use sqlx::migrate::MigrateError;
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 
 #[async_trait]
 pub trait VenObjectPrivacy: Send + Sync + 'static {
@@ -33,6 +38,21 @@ pub trait VenObjectPrivacy: Send + Sync + 'static {
     ) -> Result<bool, AppError>;
 
     async fn ven_id_by_client_id(&self, client_id: &ClientId) -> Result<Option<VenId>, AppError>;
+
+    /// Returns the [`VenVisibility`] of every VEN at once.
+    /// Notifications fan out over all VENs, so they use this instead of the per-client lookups above.
+    async fn all_ven_visibility(&self) -> Result<Vec<VenVisibility>, AppError>;
+}
+
+/// Everything that decides which targeted objects a VEN can see
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenVisibility {
+    pub ven_id: VenId,
+    pub client_id: ClientId,
+    /// Same as [`VenObjectPrivacy::targets_by_client_id`] for the VEN's client
+    pub targets: HashSet<Target>,
+    /// The root resource groups that contain any resource of the VEN
+    pub resource_groups: HashSet<ResourceGroupId>,
 }
 
 #[async_trait]
@@ -59,6 +79,12 @@ pub trait Crud: Send + Sync + 'static {
         filter: &Self::Filter,
         permission_filter: &Self::PermissionFilter,
     ) -> Result<Vec<Self::Type>, Self::Error>;
+    /// Number of objects matching the filter, regardless of `skip`, `limit`, and `cursor`
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        permission_filter: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error>;
     async fn update(
         &self,
         id: &Self::Id,
@@ -70,6 +96,32 @@ pub trait Crud: Send + Sync + 'static {
         id: &Self::Id,
         permission_filter: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error>;
+    /// Locks the object until it is updated through the returned [`Locked`], or that is dropped.
+    /// Read-modify-write cycles, like `PATCH` requests, take the lock before reading the object,
+    /// such that concurrent cycles on the same object are applied one after another.
+    /// Locking an object that does not exist succeeds, the update fails instead.
+    async fn lock(
+        &self,
+        id: &Self::Id,
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    >;
+}
+
+/// An object locked for an update, see [`Crud::lock`]
+#[async_trait]
+pub trait Locked<NewType, Type, PermissionFilter, Error>: Send {
+    /// Reads the object in the transaction holding the lock, without a permission filter.
+    /// Check that the client may see the object before taking the lock.
+    async fn current(&mut self) -> Result<Type, Error>;
+
+    /// Updates the object like [`Crud::update`] and releases the lock
+    async fn update(
+        self: Box<Self>,
+        new: NewType,
+        permission_filter: &PermissionFilter,
+    ) -> Result<Type, Error>;
 }
 
 pub trait ProgramCrud:
@@ -83,6 +135,7 @@ pub trait ProgramCrud:
     >
 {
 }
+#[async_trait]
 pub trait ReportCrud:
     Crud<
         Type = Report,
@@ -93,7 +146,34 @@ pub trait ReportCrud:
         PermissionFilter = Option<ClientId>,
     >
 {
+    /// Locks the intervals of the first resource of the report with the resource name,
+    /// such that intervals can be appended to them
+    /// without reading or rewriting the intervals the report already has.
+    /// Fails with a bad request if the report has no resource with that name.
+    async fn lock_intervals(
+        &self,
+        id: &ReportId,
+        resource_name: &ResourceName,
+        permission_filter: &Option<ClientId>,
+    ) -> Result<Box<dyn LockedIntervals>, AppError>;
+}
+
+/// The intervals of a resource of a report, locked by [`ReportCrud::lock_intervals`]
+#[async_trait]
+pub trait LockedIntervals: Send {
+    /// The report, with the intervals of its resources left out
+    fn report(&self) -> &Report;
+
+    /// The number of intervals of the resource per payload type they contain
+    fn interval_counts(&self) -> &BTreeMap<String, i32>;
+
+    /// Appends the intervals to the resource and releases the lock.
+    /// Returns the whole report, including the intervals it had before.
+    /// Fails with a conflict if the resource already has an interval with the id of a new one,
+    /// or if the new intervals share an id.
+    async fn append(self: Box<Self>, intervals: Vec<Interval>) -> Result<Report, AppError>;
 }
+#[async_trait]
 pub trait EventCrud:
     Crud<
         Type = Event,
@@ -104,8 +184,30 @@ pub trait EventCrud:
         PermissionFilter = Option<ClientId>,
     >
 {
+    /// Applies the operations in order, in a single transaction that is only committed
+    /// if all of them succeed, and returns the result of each operation
+    async fn batch(
+        &self,
+        operations: Vec<BatchOperation<EventId, EventRequest>>,
+        permission_filter: &Option<ClientId>,
+    ) -> Result<Vec<Result<Event, AppError>>, AppError>;
+
+    /// All events with a lifecycle transition in `(since, until]`, or with transitions both
+    /// before and after that window, see [`event_lifecycle`](crate::api::event_lifecycle)
+    async fn retrieve_with_transitions(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Event>, AppError>;
+
+    /// The earliest first lifecycle transition of any event after `after`
+    async fn next_first_transition(
+        &self,
+        after: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, AppError>;
 }
 
+#[async_trait]
 pub trait VenCrud:
     Crud<
         Type = Ven,
@@ -116,8 +218,26 @@ pub trait VenCrud:
         PermissionFilter = Option<ClientId>,
     >
 {
+    /// Applies the operations in order, in a single transaction that is only committed
+    /// if all of them succeed, and returns the result of each operation.
+    /// The subscriptions of the clients of deleted VENs are deleted in the same transaction,
+    /// and returned as well.
+    async fn batch(
+        &self,
+        operations: Vec<BatchOperation<VenId, BlVenRequest>>,
+        permission_filter: &Option<ClientId>,
+    ) -> Result<(Vec<Result<Ven, AppError>>, Vec<Subscription>), AppError>;
+
+    /// Deletes the VEN and all subscriptions of its client in a single transaction,
+    /// and returns the VEN and the deleted subscriptions
+    async fn delete_with_subscriptions(
+        &self,
+        id: &VenId,
+        permission_filter: &Option<ClientId>,
+    ) -> Result<(Ven, Vec<Subscription>), AppError>;
 }
 
+#[async_trait]
 pub trait ResourceCrud:
     Crud<
         Type = Resource,
@@ -128,6 +248,13 @@ pub trait ResourceCrud:
         PermissionFilter = Option<ClientId>,
     >
 {
+    /// Applies the operations in order, in a single transaction that is only committed
+    /// if all of them succeed, and returns the result of each operation
+    async fn batch(
+        &self,
+        operations: Vec<BatchOperation<ResourceId, BlResourceRequest>>,
+        permission_filter: &Option<ClientId>,
+    ) -> Result<Vec<Result<Resource, AppError>>, AppError>;
 }
 
 pub trait ResourceGroupCrud:
@@ -142,6 +269,7 @@ pub trait ResourceGroupCrud:
 {
 }
 
+#[async_trait]
 pub trait SubscriptionCrud:
     Crud<
         Type = Subscription,
@@ -152,6 +280,34 @@ pub trait SubscriptionCrud:
         PermissionFilter = Option<ClientId>,
     >
 {
+    /// Creates a subscription of `client_id`. `owner_scopes` are the read scopes the client has,
+    /// which determine the objects the webhooks of the subscription receive.
+    /// [`Crud::create`] creates subscriptions without owner scopes.
+    async fn create_with_owner_scopes(
+        &self,
+        new: SubscriptionRequest,
+        client_id: &ClientId,
+        owner_scopes: &[Scope],
+    ) -> Result<Subscription, AppError>;
+
+    /// The owner scopes of all subscriptions
+    async fn owner_scopes(&self) -> Result<HashMap<SubscriptionId, Vec<Scope>>, AppError>;
+
+    /// Restarts the [`time_to_live`](field@SubscriptionRequest::time_to_live) of the subscription
+    async fn renew(
+        &self,
+        id: &SubscriptionId,
+        permission_filter: &Option<ClientId>,
+    ) -> Result<Subscription, AppError>;
+
+    /// Deletes all subscriptions of a client and returns them
+    async fn delete_by_client_id(
+        &self,
+        client_id: &ClientId,
+    ) -> Result<Vec<Subscription>, AppError>;
+
+    /// Deletes all subscriptions that expired and returns them
+    async fn delete_expired(&self) -> Result<Vec<Subscription>, AppError>;
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -191,12 +347,19 @@ pub trait AuthSource: Send + Sync + 'static {
         client_id: &str,
         client_secret: &str,
     ) -> Result<UserDetails, AppError>;
+    /// Removes the credential and all subscriptions of its client in a single transaction,
+    /// and returns the user and the deleted subscriptions
     async fn remove_credentials(
         &self,
         user_id: &str,
         client_id: &str,
-    ) -> Result<UserDetails, AppError>;
-    async fn remove_user(&self, user_id: &str) -> Result<UserDetails, AppError>;
+    ) -> Result<(UserDetails, Vec<Subscription>), AppError>;
+    /// Removes the user and all subscriptions of its clients in a single transaction,
+    /// and returns the user and the deleted subscriptions
+    async fn remove_user(
+        &self,
+        user_id: &str,
+    ) -> Result<(UserDetails, Vec<Subscription>), AppError>;
     async fn edit_user(
         &self,
         user_id: &str,
@@ -206,6 +369,7 @@ pub trait AuthSource: Send + Sync + 'static {
     ) -> Result<UserDetails, AppError>;
 }
 
+#[async_trait]
 pub trait DataSource: Send + Sync + 'static {
     fn programs(&self) -> Arc<dyn ProgramCrud>;
     fn reports(&self) -> Arc<dyn ReportCrud>;
@@ -215,14 +379,291 @@ pub trait DataSource: Send + Sync + 'static {
     fn resources(&self) -> Arc<dyn ResourceCrud>;
     fn resource_groups(&self) -> Arc<dyn ResourceGroupCrud>;
     fn subscriptions(&self) -> Arc<dyn SubscriptionCrud>;
+    fn statistics(&self) -> Arc<dyn StatisticsSource>;
+    fn snapshots(&self) -> Arc<dyn SnapshotSource>;
+    fn idempotency(&self) -> Arc<dyn IdempotencySource>;
     #[cfg(feature = "internal-oauth")]
     fn auth(&self) -> Arc<dyn AuthSource>;
     fn connection_active(&self) -> bool;
+    /// Closes the connections to the store after the running queries completed
+    async fn close(&self);
+}
+
+/// Figures about the stored data and the connection to the store, e.g., for monitoring
+#[async_trait]
+pub trait StatisticsSource: Send + Sync + 'static {
+    async fn object_counts(&self) -> Result<ObjectCounts, AppError>;
+    fn pool_state(&self) -> PoolState;
+    /// Makes a round trip to the store
+    async fn ping(&self) -> Result<(), AppError>;
+    async fn schema_version(&self) -> Result<SchemaVersion, AppError>;
+}
+
+/// Copies of all stored objects, e.g., to back up a VTN or move it to another store
+#[async_trait]
+pub trait SnapshotSource: Send + Sync + 'static {
+    /// All objects, as seen at a single point in time
+    async fn export(&self) -> Result<Snapshot, AppError>;
+    /// Stores all objects of the snapshot with their IDs and timestamps, in a single transaction.
+    /// Objects with the same ID are replaced, others are kept.
+    async fn import(&self, snapshot: &Snapshot) -> Result<(), AppError>;
+}
+
+/// Responses to requests with an `Idempotency-Key`, to replay them when the request is retried
+#[async_trait]
+pub trait IdempotencySource: Send + Sync + 'static {
+    /// Reserves the key of the client for a request with the given fingerprint,
+    /// unless the key is already in use and did not expire yet
+    async fn begin(
+        &self,
+        client_id: &ClientId,
+        key: &str,
+        fingerprint: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<IdempotencyState, AppError>;
+    /// Stores the response to the request the key was reserved for
+    async fn complete(
+        &self,
+        client_id: &ClientId,
+        key: &str,
+        response: &StoredResponse,
+    ) -> Result<(), AppError>;
+    /// Releases a reserved key, such that the request can be retried with it
+    async fn release(&self, client_id: &ClientId, key: &str) -> Result<(), AppError>;
+    /// Deletes the expired keys and returns how many there were
+    async fn delete_expired(&self) -> Result<u64, AppError>;
+}
+
+/// What is known about an `Idempotency-Key` when a request with it arrives
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdempotencyState {
+    /// The key was not used before, and is now reserved for the request
+    New,
+    /// Another request with the key is still being processed
+    InProgress,
+    /// The key was used for the same request before, which got this response
+    Completed(StoredResponse),
+    /// The key was used for a different request before
+    Mismatch,
+}
+
+/// A response as stored for an `Idempotency-Key`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// All stored objects, in the order they have to be imported in
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    #[serde(default)]
+    pub users: Vec<SnapshotUser>,
+    #[serde(default)]
+    pub programs: Vec<Program>,
+    #[serde(default)]
+    pub events: Vec<Event>,
+    #[serde(default)]
+    pub reports: Vec<Report>,
+    #[serde(default)]
+    pub vens: Vec<Ven>,
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+    #[serde(default)]
+    pub resource_groups: Vec<ResourceGroup>,
+    #[serde(default)]
+    pub subscriptions: Vec<SnapshotSubscription>,
+}
+
+/// A subscription, with the read scopes the client had when it created the subscription
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSubscription {
+    #[serde(flatten)]
+    pub subscription: Subscription,
+    /// The scope names, kept as text like the [`scopes`](SnapshotUser::scopes) of users.
+    /// Archives written before the scopes were recorded do not contain them;
+    /// these subscriptions get the view of a VEN on import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_scopes: Option<Vec<String>>,
+}
+
+/// A user of the internal OAuth provider, with its credentials
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotUser {
+    pub id: String,
+    pub reference: String,
+    pub description: Option<String>,
+    /// The scope names, e.g., `read_all`.
+    /// Kept as text, so VTNs built without the `internal-oauth` feature can move them, too.
+    pub scopes: Vec<String>,
+    #[serde(with = "openleadr_wire::serde_rfc3339")]
+    pub created: DateTime<Utc>,
+    #[serde(with = "openleadr_wire::serde_rfc3339")]
+    pub modified: DateTime<Utc>,
+    pub credentials: Vec<SnapshotCredential>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotCredential {
+    pub client_id: ClientId,
+    /// The stored hash of the client secret.
+    /// If dropped, the credential is not imported, and a new secret has to be issued.
+    pub client_secret_hash: Option<String>,
+}
+
+impl Snapshot {
+    /// Removes the hashes of all client secrets and the bearer tokens of all webhooks,
+    /// e.g., before handing out the snapshot
+    pub fn drop_secrets(&mut self) {
+        for credential in self.users.iter_mut().flat_map(|user| &mut user.credentials) {
+            credential.client_secret_hash = None;
+        }
+        for object_operation in self
+            .subscriptions
+            .iter_mut()
+            .flat_map(|subscription| &mut subscription.subscription.content.object_operations)
+        {
+            object_operation.bearer_token = None;
+        }
+    }
+
+    /// Replaces the client IDs that are keys of `rewrites` everywhere,
+    /// e.g., as the VENs of a staging environment use other credentials than in production
+    pub fn rewrite_client_ids(&mut self, rewrites: &HashMap<ClientId, ClientId>) {
+        let rewrite = |client_id: &mut ClientId| {
+            if let Some(new) = rewrites.get(client_id) {
+                *client_id = new.clone();
+            }
+        };
+
+        self.users
+            .iter_mut()
+            .flat_map(|user| &mut user.credentials)
+            .for_each(|credential| rewrite(&mut credential.client_id));
+        self.reports
+            .iter_mut()
+            .for_each(|report| rewrite(&mut report.client_id));
+        self.vens
+            .iter_mut()
+            .for_each(|ven| rewrite(&mut ven.content.client_id));
+        self.resources
+            .iter_mut()
+            .for_each(|resource| rewrite(&mut resource.client_id));
+        self.subscriptions
+            .iter_mut()
+            .for_each(|subscription| rewrite(&mut subscription.subscription.client_id));
+    }
+
+    pub fn object_counts(&self) -> ObjectCounts {
+        ObjectCounts {
+            programs: self.programs.len() as i64,
+            events: self.events.len() as i64,
+            reports: self.reports.len() as i64,
+            vens: self.vens.len() as i64,
+            resources: self.resources.len() as i64,
+            resource_groups: self.resource_groups.len() as i64,
+            subscriptions: self.subscriptions.len() as i64,
+        }
+    }
+}
+
+/// Number of stored objects per object type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ObjectCounts {
+    pub programs: i64,
+    pub events: i64,
+    pub reports: i64,
+    pub vens: i64,
+    pub resources: i64,
+    pub resource_groups: i64,
+    pub subscriptions: i64,
+}
+
+impl ObjectCounts {
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, i64)> {
+        [
+            ("program", self.programs),
+            ("event", self.events),
+            ("report", self.reports),
+            ("ven", self.vens),
+            ("resource", self.resources),
+            ("resource_group", self.resource_groups),
+            ("subscription", self.subscriptions),
+        ]
+        .into_iter()
+    }
+}
+
+/// State of the database connection pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolState {
+    /// Open connections, including idle ones
+    pub size: u32,
+    pub idle: usize,
+    pub max_size: u32,
+}
+
+/// Applied migrations of the store, compared to the migrations this VTN ships with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaVersion {
+    /// Version of the last applied migration
+    pub applied: Option<i64>,
+    /// Version of the last migration this VTN ships with
+    pub latest: i64,
+    /// Versions of the migrations this VTN ships with that are not applied yet
+    pub pending: Vec<i64>,
+    /// Versions of applied migrations that differ from the ones this VTN ships with
+    pub modified: Vec<i64>,
+    /// Version of a migration that failed to apply
+    pub failed: Option<i64>,
+}
+
+impl SchemaVersion {
+    /// Why this VTN cannot run on the schema, if it cannot.
+    /// Migrations this VTN does not know are accepted,
+    /// so older instances keep working during a rolling deployment.
+    pub fn incompatibility(&self) -> Option<String> {
+        if let Some(failed) = self.failed {
+            return Some(format!("migration {failed} failed to apply"));
+        }
+        if !self.modified.is_empty() {
+            return Some(format!(
+                "migrations {:?} were modified after they were applied",
+                self.modified
+            ));
+        }
+        match self.pending.as_slice() {
+            [] => None,
+            [version] => Some(format!("migration {version} is pending")),
+            [first, ..] => Some(format!(
+                "{} migrations are pending, starting with {first}",
+                self.pending.len()
+            )),
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.incompatibility().is_none()
+    }
+}
+
+/// A migration this VTN ships with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub version: i64,
+    pub description: String,
 }
 
 #[async_trait]
 pub trait Migrate {
     async fn migrate(&self) -> Result<(), MigrateError>;
+    /// The migrations [`Self::migrate`] would apply
+    async fn pending_migrations(&self) -> Result<Vec<Migration>, AppError>;
 }
 
 #[derive(Debug, Clone)]
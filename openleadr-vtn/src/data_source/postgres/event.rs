@@ -1,8 +1,8 @@
 use crate::{
-    api::event::QueryParams,
+    api::{event::QueryParams, event_lifecycle, pagination::Cursor},
     data_source::{
-        Crud, EventCrud,
-        postgres::{get_ven_targets, intersection, to_json_value},
+        Crud, EventCrud, Locked,
+        postgres::{PgBatch, PgUpdate, batch, get_ven_targets, intersection, lock, to_json_value},
     },
     error::AppError,
 };
@@ -10,14 +10,74 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use openleadr_wire::{
     ClientId, Event,
+    batch::BatchOperation,
     event::{EventId, EventRequest, Priority},
     target::Target,
 };
-use sqlx::{PgPool, error::BoxDynError};
+use sqlx::{PgConnection, PgPool, Postgres, Transaction, error::BoxDynError};
 use std::str::FromStr;
 use tracing::error;
 
-impl EventCrud for PgEventStorage {}
+#[async_trait]
+impl EventCrud for PgEventStorage {
+    async fn batch(
+        &self,
+        operations: Vec<BatchOperation<EventId, EventRequest>>,
+        client_id: &Option<ClientId>,
+    ) -> Result<Vec<Result<Event, AppError>>, AppError> {
+        batch::<Self>(&self.db, operations, client_id).await
+    }
+
+    async fn retrieve_with_transitions(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Event>, AppError> {
+        sqlx::query_as!(
+            PostgresEvent,
+            r#"
+            SELECT e.id,
+                   e.created_date_time,
+                   e.modification_date_time,
+                   e.program_id,
+                   e.event_name,
+                   e.priority,
+                   e.targets as "targets:Vec<Target>",
+                   e.report_descriptors,
+                   e.payload_descriptors,
+                   e.interval_period,
+                   e.intervals,
+                   e.duration
+            FROM event e
+            WHERE e.first_transition <= $2
+              AND e.last_transition > $1
+            "#,
+            since,
+            until,
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn next_first_transition(
+        &self,
+        after: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, AppError> {
+        Ok(sqlx::query_scalar!(
+            r#"
+            SELECT min(first_transition)
+            FROM event
+            WHERE first_transition > $1
+            "#,
+            after,
+        )
+        .fetch_one(&self.db)
+        .await?)
+    }
+}
 
 pub(crate) struct PgEventStorage {
     db: PgPool,
@@ -126,41 +186,9 @@ impl Crud for PgEventStorage {
     async fn create(
         &self,
         new: Self::NewType,
-        _client_id: &Self::PermissionFilter,
+        client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        Ok(sqlx::query_as!(
-            PostgresEvent,
-            r#"
-            INSERT INTO event (id, created_date_time, modification_date_time, program_id, event_name, priority, targets, report_descriptors, payload_descriptors, interval_period, intervals, duration)
-            VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4, $5, $6, $7, $8, $9)
-            RETURNING
-                id,
-                created_date_time,
-                modification_date_time,
-                program_id,
-                event_name,
-                priority,
-                targets as "targets:Vec<Target>",
-                report_descriptors,
-                payload_descriptors,
-                interval_period,
-                intervals,
-                duration
-            "#,
-            new.program_id.as_str(),
-            new.event_name,
-            Into::<Option<i64>>::into(new.priority),
-            new.targets.as_slice() as &[Target],
-            to_json_value(new.report_descriptors)?,
-            to_json_value(new.payload_descriptors)?,
-            to_json_value(new.interval_period)?,
-            serde_json::to_value(&new.intervals).map_err(AppError::SerdeJsonBadRequest)?,
-            new.duration.map(|d| d.to_string()),
-        )
-            .fetch_one(&self.db)
-            .await?
-            .try_into()?
-        )
+        Self::create_in(&mut *self.db.acquire().await?, new, client_id).await
     }
 
     /// The `client_id` is set if the request has [`ReadTargets`](Scope::ReadTargets) scope (VEN clients).
@@ -171,7 +199,7 @@ impl Crud for PgEventStorage {
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
         match client_id {
-            None => self.retrieve_without_client_id(id).await,
+            None => Self::retrieve_in(&mut *self.db.acquire().await?, id).await,
             Some(client_id) => self.retrieve_with_client_id(id, client_id).await,
         }
     }
@@ -189,12 +217,121 @@ impl Crud for PgEventStorage {
         }
     }
 
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error> {
+        let ven_targets = match client_id {
+            None => None,
+            Some(client_id) => Some(get_ven_targets(self.db.clone(), client_id).await?),
+        };
+        // same as in `retrieve_all_with_client_id`
+        let filter_targets = match &ven_targets {
+            None => filter.targets.as_deref().iter().collect(),
+            Some(ven_targets) => intersection(ven_targets, filter.targets.as_deref()),
+        };
+        if filter_targets.is_empty() != filter.targets.as_deref().is_empty() {
+            return Ok(0);
+        }
+
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!"
+            FROM event e
+            WHERE ($1::text IS NULL OR e.program_id = $1)
+              AND (array_length($2::text[], 1) IS NULL OR e.targets && $2)
+              AND ($3::text[] IS NULL OR e.targets && $3 OR array_length(e.targets, 1) IS NULL)
+            "#,
+            filter.program_id.as_ref().map(|id| id.as_str()),
+            filter_targets as _,
+            ven_targets as _,
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count as u64)
+    }
+
     async fn update(
         &self,
         id: &Self::Id,
         new: Self::NewType,
-        _client_id: &Self::PermissionFilter,
+        client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
+        Self::update_in(&mut *self.db.acquire().await?, id, new, client_id).await
+    }
+
+    async fn delete(
+        &self,
+        id: &Self::Id,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<Self::Type, Self::Error> {
+        Self::delete_in(&mut *self.db.acquire().await?, id, client_id).await
+    }
+
+    async fn lock(
+        &self,
+        id: &Self::Id,
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    > {
+        lock::<Self>(&self.db, id).await
+    }
+}
+
+#[async_trait]
+impl PgUpdate for PgEventStorage {
+    type Id = EventId;
+    type NewType = EventRequest;
+    type Type = Event;
+
+    async fn lock_in(conn: &mut PgConnection, id: &EventId) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM event WHERE id = $1 FOR UPDATE
+            "#,
+            id.as_str()
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn retrieve_in(conn: &mut PgConnection, id: &EventId) -> Result<Event, AppError> {
+        sqlx::query_as!(
+            PostgresEvent,
+            r#"
+            SELECT e.id,
+                   e.created_date_time,
+                   e.modification_date_time,
+                   e.program_id,
+                   e.event_name,
+                   e.priority,
+                   e.targets as "targets:Vec<Target>",
+                   e.report_descriptors,
+                   e.payload_descriptors,
+                   e.interval_period,
+                   e.intervals,
+                   e.duration
+            FROM event e
+            WHERE e.id = $1
+            "#,
+            id.as_str(),
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .try_into()
+    }
+
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &EventId,
+        new: EventRequest,
+        _client_id: &Option<ClientId>,
+    ) -> Result<Event, AppError> {
+        let (first_transition, last_transition) = transition_bounds(&new);
         Ok(sqlx::query_as!(
             PostgresEvent,
             r#"
@@ -208,7 +345,9 @@ impl Crud for PgEventStorage {
                 payload_descriptors = $7,
                 interval_period = $8,
                 intervals = $9,
-                duration = $10
+                duration = $10,
+                first_transition = $11,
+                last_transition = $12
             WHERE id = $1
             RETURNING
                 id,
@@ -233,18 +372,66 @@ impl Crud for PgEventStorage {
             to_json_value(new.payload_descriptors)?,
             to_json_value(new.interval_period)?,
             serde_json::to_value(&new.intervals).map_err(AppError::SerdeJsonBadRequest)?,
-            new.duration.map(|d| d.to_string())
+            new.duration.map(|d| d.to_string()),
+            first_transition,
+            last_transition,
         )
-        .fetch_one(&self.db)
+        .fetch_one(&mut *conn)
         .await?
         .try_into()?)
     }
+}
 
-    async fn delete(
-        &self,
-        id: &Self::Id,
-        _client_id: &Self::PermissionFilter,
-    ) -> Result<Self::Type, Self::Error> {
+#[async_trait]
+impl PgBatch for PgEventStorage {
+    async fn create_in(
+        conn: &mut PgConnection,
+        new: EventRequest,
+        _client_id: &Option<ClientId>,
+    ) -> Result<Event, AppError> {
+        let (first_transition, last_transition) = transition_bounds(&new);
+        Ok(sqlx::query_as!(
+            PostgresEvent,
+            r#"
+            INSERT INTO event (id, created_date_time, modification_date_time, program_id, event_name, priority, targets, report_descriptors, payload_descriptors, interval_period, intervals, duration, first_transition, last_transition)
+            VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING
+                id,
+                created_date_time,
+                modification_date_time,
+                program_id,
+                event_name,
+                priority,
+                targets as "targets:Vec<Target>",
+                report_descriptors,
+                payload_descriptors,
+                interval_period,
+                intervals,
+                duration
+            "#,
+            new.program_id.as_str(),
+            new.event_name,
+            Into::<Option<i64>>::into(new.priority),
+            new.targets.as_slice() as &[Target],
+            to_json_value(new.report_descriptors)?,
+            to_json_value(new.payload_descriptors)?,
+            to_json_value(new.interval_period)?,
+            serde_json::to_value(&new.intervals).map_err(AppError::SerdeJsonBadRequest)?,
+            new.duration.map(|d| d.to_string()),
+            first_transition,
+            last_transition,
+        )
+            .fetch_one(&mut *conn)
+            .await?
+            .try_into()?
+        )
+    }
+
+    async fn delete_in(
+        conn: &mut PgConnection,
+        id: &EventId,
+        _client_id: &Option<ClientId>,
+    ) -> Result<Event, AppError> {
         Ok(sqlx::query_as!(
             PostgresEvent,
             r#"
@@ -267,7 +454,7 @@ impl Crud for PgEventStorage {
             "#,
             id.as_str()
         )
-        .fetch_one(&self.db)
+        .fetch_one(&mut *conn)
         .await?
         .try_into()?)
     }
@@ -290,6 +477,7 @@ impl PgEventStorage {
         if filter_targets.is_empty() != filter.targets.as_deref().is_empty() {
             return Ok(vec![]);
         }
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
 
         sqlx::query_as!(
             PostgresEvent,
@@ -318,14 +506,20 @@ impl PgEventStorage {
                         -- or IF the event targets are empty
                         OR array_length(e.targets, 1) IS NULL
                   )
-            ORDER BY priority ASC, created_date_time DESC
+              AND ($7::timestamptz IS NULL OR (e.created_date_time, e.id) > ($7, $8))
+            ORDER BY CASE WHEN $6 THEN e.created_date_time END,
+                     CASE WHEN $6 THEN e.id END,
+                     priority ASC, created_date_time DESC
             OFFSET $4 LIMIT $5
             "#,
             filter.program_id.as_ref().map(|id| id.as_str()),
             filter_targets as _,
             ven_targets as _,
             filter.skip,
-            filter.limit
+            filter.limit,
+            filter.cursor.is_some(),
+            after_time,
+            after_id,
         )
         .fetch_all(&self.db)
         .await?
@@ -349,7 +543,7 @@ impl PgEventStorage {
     /// It is provided if the request has [`ReadTargets`](Scope::ReadTargets) scope, which
     /// is the case for VEN clients (aka. customer logic).
     /// BL clients have a [`ReadAll`](Scope::ReadAll) scope, and therefore the API layer will
-    /// call the [`retrieve_without_client_id`](PgEventStorage::retrieve_without_client_id) function.
+    /// call [`retrieve_in`](PgEventStorage::retrieve_in) instead.
     async fn retrieve_with_client_id(
         &self,
         id: &EventId,
@@ -403,6 +597,8 @@ impl PgEventStorage {
         &self,
         filter: &QueryParams,
     ) -> Result<Vec<Event>, AppError> {
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
+
         sqlx::query_as!(
             PostgresEvent,
             r#"
@@ -423,13 +619,19 @@ impl PgEventStorage {
               -- IF filter targets are empty, do not filter.
               -- IF filter targets are not empty, filter only if they are in the event targets.
               AND (array_length($2::text[], 1) IS NULL OR e.targets && $2)
-            ORDER BY priority ASC, created_date_time DESC
+              AND ($6::timestamptz IS NULL OR (e.created_date_time, e.id) > ($6, $7))
+            ORDER BY CASE WHEN $5 THEN e.created_date_time END,
+                     CASE WHEN $5 THEN e.id END,
+                     priority ASC, created_date_time DESC
             OFFSET $3 LIMIT $4
             "#,
             filter.program_id.as_ref().map(|id| id.as_str()),
             filter.targets.as_deref() as _,
             filter.skip,
-            filter.limit
+            filter.limit,
+            filter.cursor.is_some(),
+            after_time,
+            after_id,
         )
         .fetch_all(&self.db)
         .await?
@@ -437,32 +639,91 @@ impl PgEventStorage {
         .map(TryInto::try_into)
         .collect::<Result<_, _>>()
     }
+}
 
-    async fn retrieve_without_client_id(&self, id: &EventId) -> Result<Event, AppError> {
+impl PgEventStorage {
+    /// All events, in the order they were created
+    pub(super) async fn export(tx: &mut Transaction<'_, Postgres>) -> Result<Vec<Event>, AppError> {
         sqlx::query_as!(
             PostgresEvent,
             r#"
-            SELECT e.id,
-                   e.created_date_time,
-                   e.modification_date_time,
-                   e.program_id,
-                   e.event_name,
-                   e.priority,
-                   e.targets as "targets:Vec<Target>",
-                   e.report_descriptors,
-                   e.payload_descriptors,
-                   e.interval_period,
-                   e.intervals,
-                   e.duration
-            FROM event e
-            WHERE e.id = $1
+            SELECT id,
+                   created_date_time,
+                   modification_date_time,
+                   program_id,
+                   event_name,
+                   priority,
+                   targets AS "targets:Vec<Target>",
+                   report_descriptors,
+                   payload_descriptors,
+                   interval_period,
+                   intervals,
+                   duration
+            FROM event
+            ORDER BY created_date_time, id
             "#,
-            id.as_str(),
         )
-        .fetch_one(&self.db)
+        .fetch_all(tx.as_mut())
         .await?
-        .try_into()
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect()
     }
+
+    /// Inserts the event with its ID and timestamps, or replaces the event with the same ID
+    pub(super) async fn import(
+        tx: &mut Transaction<'_, Postgres>,
+        event: &Event,
+    ) -> Result<(), AppError> {
+        let (first_transition, last_transition) = transition_bounds(&event.content);
+        sqlx::query!(
+            r#"
+            INSERT INTO event (id, created_date_time, modification_date_time, program_id, event_name, priority, targets, report_descriptors, payload_descriptors, interval_period, intervals, duration, first_transition, last_transition)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            ON CONFLICT (id) DO UPDATE
+            SET created_date_time = excluded.created_date_time,
+                modification_date_time = excluded.modification_date_time,
+                program_id = excluded.program_id,
+                event_name = excluded.event_name,
+                priority = excluded.priority,
+                targets = excluded.targets,
+                report_descriptors = excluded.report_descriptors,
+                payload_descriptors = excluded.payload_descriptors,
+                interval_period = excluded.interval_period,
+                intervals = excluded.intervals,
+                duration = excluded.duration,
+                first_transition = excluded.first_transition,
+                last_transition = excluded.last_transition
+            "#,
+            event.id.as_str(),
+            event.created_date_time,
+            event.modification_date_time,
+            event.content.program_id.as_str(),
+            event.content.event_name,
+            Into::<Option<i64>>::into(event.content.priority),
+            event.content.targets.as_slice() as &[Target],
+            to_json_value(event.content.report_descriptors.as_ref())?,
+            to_json_value(event.content.payload_descriptors.as_ref())?,
+            to_json_value(event.content.interval_period.as_ref())?,
+            serde_json::to_value(&event.content.intervals).map_err(AppError::SerdeJsonBadRequest)?,
+            event.content.duration.as_ref().map(|d| d.to_string()),
+            first_transition,
+            last_transition,
+        )
+        .execute(tx.as_mut())
+        .await?;
+        Ok(())
+    }
+}
+
+/// The times of the first and the last lifecycle transition of the event,
+/// see [`event_lifecycle`]
+fn transition_bounds(event: &EventRequest) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let transitions = event_lifecycle::transitions(event);
+    (
+        transitions.first().map(|(at, _)| *at),
+        transitions.last().map(|(at, _)| *at),
+    )
 }
 
 #[cfg(test)]
@@ -492,6 +753,7 @@ mod tests {
                 targets: TargetQueryParams(None),
                 skip: 0,
                 limit: 50,
+                cursor: None,
             }
         }
     }
@@ -0,0 +1,216 @@
+use crate::{
+    data_source::{IdempotencySource, IdempotencyState, StoredResponse},
+    error::AppError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use openleadr_wire::ClientId;
+use sqlx::PgPool;
+use tracing::{error, trace};
+
+pub(crate) struct PgIdempotencyStorage {
+    db: PgPool,
+}
+
+impl From<PgPool> for PgIdempotencyStorage {
+    fn from(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl IdempotencySource for PgIdempotencyStorage {
+    async fn begin(
+        &self,
+        client_id: &ClientId,
+        key: &str,
+        fingerprint: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<IdempotencyState, AppError> {
+        loop {
+            // takes over expired keys as if they never existed
+            let reserved = sqlx::query_scalar!(
+                r#"
+                INSERT INTO idempotency_key (client_id, key, fingerprint, expires)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (client_id, key) DO UPDATE
+                SET fingerprint = excluded.fingerprint,
+                    status = NULL,
+                    headers = NULL,
+                    body = NULL,
+                    created = now(),
+                    expires = excluded.expires
+                WHERE idempotency_key.expires <= now()
+                RETURNING key
+                "#,
+                client_id.as_str(),
+                key,
+                fingerprint,
+                expires,
+            )
+            .fetch_optional(&self.db)
+            .await?;
+
+            if reserved.is_some() {
+                trace!(%client_id, key, "reserved idempotency key");
+                return Ok(IdempotencyState::New);
+            }
+
+            let existing = sqlx::query!(
+                r#"
+                SELECT fingerprint, status, headers, body
+                FROM idempotency_key
+                WHERE client_id = $1 AND key = $2
+                "#,
+                client_id.as_str(),
+                key,
+            )
+            .fetch_optional(&self.db)
+            .await?;
+
+            let Some(existing) = existing else {
+                // released in the meantime, try to reserve it again
+                continue;
+            };
+
+            if existing.fingerprint != fingerprint {
+                return Ok(IdempotencyState::Mismatch);
+            }
+
+            let Some(status) = existing.status else {
+                return Ok(IdempotencyState::InProgress);
+            };
+
+            let headers = match existing.headers {
+                None => Vec::new(),
+                Some(headers) => serde_json::from_value(headers)
+                    .inspect_err(|err| {
+                        error!(?err, "Failed to deserialize stored response headers")
+                    })
+                    .map_err(AppError::SerdeJsonInternalServerError)?,
+            };
+
+            return Ok(IdempotencyState::Completed(StoredResponse {
+                status: status as u16,
+                headers,
+                body: existing.body.unwrap_or_default(),
+            }));
+        }
+    }
+
+    async fn complete(
+        &self,
+        client_id: &ClientId,
+        key: &str,
+        response: &StoredResponse,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            UPDATE idempotency_key
+            SET status = $3, headers = $4, body = $5
+            WHERE client_id = $1 AND key = $2
+            "#,
+            client_id.as_str(),
+            key,
+            response.status as i16,
+            serde_json::to_value(&response.headers)
+                .map_err(AppError::SerdeJsonInternalServerError)?,
+            response.body,
+        )
+        .execute(&self.db)
+        .await?;
+
+        trace!(%client_id, key, "stored response for idempotency key");
+
+        Ok(())
+    }
+
+    async fn release(&self, client_id: &ClientId, key: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM idempotency_key
+            WHERE client_id = $1 AND key = $2 AND status IS NULL
+            "#,
+            client_id.as_str(),
+            key,
+        )
+        .execute(&self.db)
+        .await?;
+
+        trace!(%client_id, key, "released idempotency key");
+
+        Ok(())
+    }
+
+    async fn delete_expired(&self) -> Result<u64, AppError> {
+        let deleted = sqlx::query!("DELETE FROM idempotency_key WHERE expires <= now()")
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+
+        trace!("deleted {deleted} expired idempotency keys");
+
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use openleadr_wire::ClientId;
+    use sqlx::PgPool;
+
+    use super::PgIdempotencyStorage;
+    use crate::data_source::{IdempotencySource, IdempotencyState, StoredResponse};
+
+    #[sqlx::test]
+    async fn reserve_complete_and_expire(db: PgPool) {
+        let store = PgIdempotencyStorage::from(db);
+        let client_id: ClientId = "client".parse().unwrap();
+        let expires = Utc::now() + Duration::hours(1);
+
+        assert_eq!(
+            store.begin(&client_id, "key", "a", expires).await.unwrap(),
+            IdempotencyState::New
+        );
+        assert_eq!(
+            store.begin(&client_id, "key", "a", expires).await.unwrap(),
+            IdempotencyState::InProgress
+        );
+        // keys are scoped per client
+        let other: ClientId = "other".parse().unwrap();
+        assert_eq!(
+            store.begin(&other, "key", "b", expires).await.unwrap(),
+            IdempotencyState::New
+        );
+
+        let response = StoredResponse {
+            status: 201,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: b"{}".to_vec(),
+        };
+        store.complete(&client_id, "key", &response).await.unwrap();
+        assert_eq!(
+            store.begin(&client_id, "key", "a", expires).await.unwrap(),
+            IdempotencyState::Completed(response)
+        );
+        assert_eq!(
+            store.begin(&client_id, "key", "b", expires).await.unwrap(),
+            IdempotencyState::Mismatch
+        );
+
+        // completed keys are not released
+        store.release(&client_id, "key").await.unwrap();
+        store.release(&other, "key").await.unwrap();
+        assert_eq!(
+            store.begin(&other, "key", "c", Utc::now()).await.unwrap(),
+            IdempotencyState::New
+        );
+
+        assert_eq!(store.delete_expired().await.unwrap(), 1);
+        assert_eq!(
+            store.begin(&client_id, "key", "b", expires).await.unwrap(),
+            IdempotencyState::Mismatch
+        );
+    }
+}
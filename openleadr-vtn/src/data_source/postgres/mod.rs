@@ -1,42 +1,59 @@
 #[cfg(feature = "internal-oauth")]
 use crate::data_source::{AuthSource, postgres::user::PgAuthSource};
 
-use super::{Migrate, VenObjectPrivacy};
+use super::{
+    IdempotencySource, Locked, Migrate, Migration, SnapshotSource, StatisticsSource,
+    VenObjectPrivacy,
+};
 use crate::{
+    api::attributes::{AttributeFilter, Comparison},
     data_source::{
         DataSource, EventCrud, ProgramCrud, ReportCrud, ResourceCrud, ResourceGroupCrud, VenCrud,
         postgres::{
-            event::PgEventStorage, program::PgProgramStorage, report::PgReportStorage,
-            resource_group::PgResourceGroupStorage, subscription::PgSubscriptionStorage,
-            ven::PgVenStorage,
+            event::PgEventStorage, idempotency::PgIdempotencyStorage, program::PgProgramStorage,
+            report::PgReportStorage, resource_group::PgResourceGroupStorage,
+            snapshot::PgSnapshotStorage, statistics::PgStatisticsStorage,
+            subscription::PgSubscriptionStorage, ven::PgVenStorage,
         },
     },
     error::AppError,
 };
 use async_trait::async_trait;
 use dotenvy::dotenv;
-use openleadr_wire::{ClientId, target::Target};
+use openleadr_wire::{ClientId, batch::BatchOperation, target::Target};
 use resource::PgResourceStorage;
 use serde::Serialize;
-use sqlx::{PgPool, migrate::MigrateError, postgres::PgPoolOptions};
+use serde_json::json;
+use sqlx::{
+    Acquire, PgConnection, PgPool, Postgres, Transaction,
+    migrate::{MigrateError, Migrator},
+    postgres::PgPoolOptions,
+};
 use std::sync::Arc;
 use tracing::{error, info};
 
 mod event;
+mod idempotency;
 mod program;
 mod report;
 mod resource;
 mod resource_group;
+mod snapshot;
+mod statistics;
 mod subscription;
 #[cfg(feature = "internal-oauth")]
 mod user;
 mod ven;
 
+/// The migrations this VTN ships with
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
 #[derive(Clone)]
 pub struct PostgresStorage {
     db: PgPool,
 }
 
+#[async_trait]
 impl DataSource for PostgresStorage {
     fn programs(&self) -> Arc<dyn ProgramCrud> {
         Arc::<PgProgramStorage>::new(self.db.clone().into())
@@ -70,6 +87,18 @@ impl DataSource for PostgresStorage {
         Arc::<PgSubscriptionStorage>::new(self.db.clone().into())
     }
 
+    fn statistics(&self) -> Arc<dyn StatisticsSource> {
+        Arc::<PgStatisticsStorage>::new(self.db.clone().into())
+    }
+
+    fn snapshots(&self) -> Arc<dyn SnapshotSource> {
+        Arc::<PgSnapshotStorage>::new(self.db.clone().into())
+    }
+
+    fn idempotency(&self) -> Arc<dyn IdempotencySource> {
+        Arc::<PgIdempotencyStorage>::new(self.db.clone().into())
+    }
+
     #[cfg(feature = "internal-oauth")]
     fn auth(&self) -> Arc<dyn AuthSource> {
         Arc::<PgAuthSource>::new(self.db.clone().into())
@@ -79,12 +108,28 @@ impl DataSource for PostgresStorage {
     fn connection_active(&self) -> bool {
         !self.db.is_closed() && self.db.size() > 0
     }
+
+    async fn close(&self) {
+        self.db.close().await
+    }
 }
 
 #[async_trait]
 impl Migrate for PostgresStorage {
     async fn migrate(&self) -> Result<(), MigrateError> {
-        sqlx::migrate!("./migrations").run(&self.db).await
+        MIGRATOR.run(&self.db).await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<Migration>, AppError> {
+        let pending = self.statistics().schema_version().await?.pending;
+        Ok(MIGRATOR
+            .iter()
+            .filter(|migration| pending.contains(&migration.version))
+            .map(|migration| Migration {
+                version: migration.version,
+                description: migration.description.to_string(),
+            })
+            .collect())
     }
 }
 
@@ -98,9 +143,13 @@ impl PostgresStorage {
         let db_url = std::env::var("DATABASE_URL")
             .expect("Missing DATABASE_URL env var even though the 'postgres' feature is active");
 
+        Self::connect(&db_url).await
+    }
+
+    pub async fn connect(db_url: &str) -> Result<Self, sqlx::Error> {
         let db = PgPoolOptions::new()
             .min_connections(1)
-            .connect(&db_url)
+            .connect(db_url)
             .await?;
 
         let connect_options = db.connect_options();
@@ -122,6 +171,126 @@ impl PostgresStorage {
     }
 }
 
+/// Locks and updates objects on a given connection,
+/// such that the lock is held until the update is committed
+#[async_trait]
+trait PgUpdate {
+    type Id: Clone + Send + Sync + 'static;
+    type NewType: Send + 'static;
+    type Type: Send + 'static;
+
+    /// Locks the object with `SELECT ... FOR UPDATE`, if it exists
+    async fn lock_in(conn: &mut PgConnection, id: &Self::Id) -> Result<(), AppError>;
+    /// Reads the object without a permission filter
+    async fn retrieve_in(conn: &mut PgConnection, id: &Self::Id) -> Result<Self::Type, AppError>;
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &Self::Id,
+        new: Self::NewType,
+        client_id: &Option<ClientId>,
+    ) -> Result<Self::Type, AppError>;
+}
+
+/// An object locked in a transaction, which is committed after the update
+struct PgLocked<S: PgUpdate> {
+    tx: Transaction<'static, Postgres>,
+    id: S::Id,
+}
+
+#[async_trait]
+impl<S: PgUpdate + Send + 'static> Locked<S::NewType, S::Type, Option<ClientId>, AppError>
+    for PgLocked<S>
+{
+    async fn current(&mut self) -> Result<S::Type, AppError> {
+        S::retrieve_in(&mut self.tx, &self.id).await
+    }
+
+    async fn update(
+        mut self: Box<Self>,
+        new: S::NewType,
+        client_id: &Option<ClientId>,
+    ) -> Result<S::Type, AppError> {
+        let updated = S::update_in(&mut self.tx, &self.id, new, client_id).await?;
+        self.tx.commit().await?;
+        Ok(updated)
+    }
+}
+
+/// Begins a transaction that holds the lock on the object, see [`Crud::lock`](super::Crud::lock)
+async fn lock<S: PgUpdate + Send + 'static>(
+    db: &PgPool,
+    id: &S::Id,
+) -> Result<Box<dyn Locked<S::NewType, S::Type, Option<ClientId>, AppError>>, AppError> {
+    let mut tx = db.begin().await?;
+    S::lock_in(&mut tx, id).await?;
+    Ok(Box::new(PgLocked::<S> { tx, id: id.clone() }))
+}
+
+/// Creates, updates, and deletes objects on a given connection,
+/// such that many of these operations can share a transaction
+#[async_trait]
+trait PgBatch: PgUpdate {
+    async fn create_in(
+        conn: &mut PgConnection,
+        new: Self::NewType,
+        client_id: &Option<ClientId>,
+    ) -> Result<Self::Type, AppError>;
+    async fn delete_in(
+        conn: &mut PgConnection,
+        id: &Self::Id,
+        client_id: &Option<ClientId>,
+    ) -> Result<Self::Type, AppError>;
+}
+
+/// Applies each operation in its own savepoint of a single transaction,
+/// such that a failed operation does not abort the following ones and all failures are reported.
+/// The transaction is only committed if all operations succeeded.
+async fn batch<S: PgBatch>(
+    db: &PgPool,
+    operations: Vec<BatchOperation<S::Id, S::NewType>>,
+    client_id: &Option<ClientId>,
+) -> Result<Vec<Result<S::Type, AppError>>, AppError> {
+    let mut tx = db.begin().await?;
+    let results = batch_in::<S>(&mut tx, operations, client_id).await?;
+
+    if results.iter().all(Result::is_ok) {
+        tx.commit().await?;
+    } else {
+        tx.rollback().await?;
+    }
+
+    Ok(results)
+}
+
+/// Applies the operations like [`batch`], leaving it to the caller to commit the transaction
+async fn batch_in<S: PgBatch>(
+    tx: &mut Transaction<'static, Postgres>,
+    operations: Vec<BatchOperation<S::Id, S::NewType>>,
+    client_id: &Option<ClientId>,
+) -> Result<Vec<Result<S::Type, AppError>>, AppError> {
+    let mut results = Vec::with_capacity(operations.len());
+
+    for operation in operations {
+        let mut savepoint = tx.begin().await?;
+        let result = match operation {
+            BatchOperation::Create { content } => {
+                S::create_in(&mut savepoint, content, client_id).await
+            }
+            BatchOperation::Update { id, content } => {
+                S::update_in(&mut savepoint, &id, content, client_id).await
+            }
+            BatchOperation::Delete { id } => S::delete_in(&mut savepoint, &id, client_id).await,
+        };
+        match result {
+            Ok(_) => savepoint.commit().await?,
+            Err(_) => savepoint.rollback().await?,
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
 fn to_json_value<T: Serialize>(v: Option<T>) -> Result<Option<serde_json::Value>, AppError> {
     v.map(|v| serde_json::to_value(v).map_err(AppError::SerdeJsonBadRequest))
         .transpose()
@@ -142,6 +311,47 @@ async fn get_ven_targets(db: PgPool, client_id: &ClientId) -> Result<Vec<Target>
     }
 }
 
+/// The attribute filters as SQL parameters.
+/// The JSONB array the attributes must contain holds the attribute types and exact values,
+/// such that the GIN index on the attributes can find the candidates.
+/// The numeric ranges are checked on those by a JSON path predicate and its variables.
+struct AttributeQuery {
+    contains: Option<serde_json::Value>,
+    predicate: Option<String>,
+    vars: serde_json::Value,
+}
+
+impl AttributeQuery {
+    fn new(filters: &[AttributeFilter]) -> Self {
+        let mut contains = vec![];
+        let mut predicates = vec![];
+        let mut vars = serde_json::Map::new();
+        for (i, filter) in filters.iter().enumerate() {
+            match &filter.condition {
+                Some((Comparison::Eq, value)) => {
+                    contains.push(json!({"type": filter.value_type, "values": [value]}))
+                }
+                None => contains.push(json!({"type": filter.value_type})),
+                Some((comparison, value)) => {
+                    contains.push(json!({"type": filter.value_type}));
+                    predicates.push(format!(
+                        "exists($[*] ? (@.type == $type{i} && exists(@.values[*] ? (@ {} $value{i}))))",
+                        comparison.operator()
+                    ));
+                    vars.insert(format!("type{i}"), json!(filter.value_type));
+                    vars.insert(format!("value{i}"), value.clone());
+                }
+            }
+        }
+
+        Self {
+            contains: (!contains.is_empty()).then_some(serde_json::Value::Array(contains)),
+            predicate: (!predicates.is_empty()).then(|| predicates.join(" && ")),
+            vars: serde_json::Value::Object(vars),
+        }
+    }
+}
+
 fn intersection<'a>(a: &'a [Target], b: &'a [Target]) -> Vec<&'a Target> {
     a.iter().filter(|x| b.contains(x)).collect()
 }
@@ -1,8 +1,8 @@
 use crate::{
-    api::program::QueryParams,
+    api::{pagination::Cursor, program::QueryParams},
     data_source::{
-        Crud, ProgramCrud,
-        postgres::{get_ven_targets, intersection, to_json_value},
+        Crud, Locked, ProgramCrud,
+        postgres::{PgUpdate, get_ven_targets, intersection, lock, to_json_value},
     },
     error::AppError,
 };
@@ -13,7 +13,7 @@ use openleadr_wire::{
     program::{ProgramId, ProgramRequest},
     target::Target,
 };
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
 use tracing::error;
 
 impl ProgramCrud for PgProgramStorage {}
@@ -39,6 +39,7 @@ struct PostgresProgram {
     payload_descriptors: Option<serde_json::Value>,
     attributes: Option<serde_json::Value>,
     targets: Vec<Target>,
+    validation_mode: Option<String>,
 }
 
 impl TryFrom<PostgresProgram> for Program {
@@ -103,6 +104,17 @@ impl TryFrom<PostgresProgram> for Program {
                 payload_descriptors,
                 attributes,
                 targets: value.targets,
+                validation_mode: value
+                    .validation_mode
+                    .map(|validation_mode| validation_mode.parse())
+                    .transpose()
+                    .inspect_err(|err| {
+                        error!(
+                            ?err,
+                            "Failed to parse the validation mode of a program from DB"
+                        )
+                    })
+                    .map_err(|_| AppError::BadRequest("Invalid validation mode"))?,
             },
         })
     }
@@ -133,8 +145,9 @@ impl Crud for PgProgramStorage {
                                  program_descriptions,
                                  payload_descriptors,
                                  targets,
-                                 attributes)
-            VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4, $5, $6)
+                                 attributes,
+                                 validation_mode)
+            VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4, $5, $6, $7)
             RETURNING id,
                       created_date_time,
                       modification_date_time,
@@ -143,7 +156,8 @@ impl Crud for PgProgramStorage {
                       program_descriptions,
                       payload_descriptors,
                       targets as  "targets:Vec<Target>",
-                      attributes
+                      attributes,
+                      validation_mode
             "#,
             new.program_name,
             to_json_value(new.interval_period)?,
@@ -151,6 +165,7 @@ impl Crud for PgProgramStorage {
             to_json_value(new.payload_descriptors)?,
             new.targets.as_slice() as &[Target],
             to_json_value(new.attributes)?,
+            new.validation_mode.map(|mode| mode.as_str()),
         )
         .fetch_one(&self.db)
         .await?
@@ -167,7 +182,7 @@ impl Crud for PgProgramStorage {
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
         match client_id {
-            None => self.retrieve_without_client_id(id).await,
+            None => Self::retrieve_in(&mut *self.db.acquire().await?, id).await,
             Some(client_id) => self.retrieve_with_client_id(id, client_id).await,
         }
     }
@@ -185,24 +200,59 @@ impl Crud for PgProgramStorage {
         }
     }
 
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error> {
+        let ven_targets = match client_id {
+            None => None,
+            Some(client_id) => Some(get_ven_targets(self.db.clone(), client_id).await?),
+        };
+        // same as in `retrieve_all_with_client_id`
+        let filter_targets = match &ven_targets {
+            None => filter.targets.as_deref().iter().collect(),
+            Some(ven_targets) => intersection(ven_targets, filter.targets.as_deref()),
+        };
+        if filter_targets.is_empty() != filter.targets.as_deref().is_empty() {
+            return Ok(0);
+        }
+
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!"
+            FROM program p
+            WHERE (array_length($1::text[], 1) IS NULL OR p.targets && $1)
+              AND ($2::text[] IS NULL OR p.targets && $2 OR array_length(p.targets, 1) IS NULL)
+            "#,
+            filter_targets as _,
+            ven_targets as _,
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count as u64)
+    }
+
     async fn update(
         &self,
         id: &Self::Id,
         new: Self::NewType,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<Self::Type, Self::Error> {
+        Self::update_in(&mut *self.db.acquire().await?, id, new, client_id).await
+    }
+
+    async fn delete(
+        &self,
+        id: &Self::Id,
         _client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let program: Program = sqlx::query_as!(
+        Ok(sqlx::query_as!(
             PostgresProgram,
             r#"
-            UPDATE program p
-            SET modification_date_time = now(),
-                program_name = $2,
-                interval_period = $3,
-                program_descriptions = $4,
-                payload_descriptors = $5,
-                targets = $6,
-                attributes = $7
-            WHERE id = $1
+            DELETE FROM program p
+                   WHERE id = $1
             RETURNING p.id,
                    p.created_date_time,
                    p.modification_date_time,
@@ -210,34 +260,89 @@ impl Crud for PgProgramStorage {
                    p.interval_period,
                    p.program_descriptions,
                    p.payload_descriptors,
-                   p.targets as "targets:Vec<Target>",
-                   p.attributes
+                   p.targets as  "targets:Vec<Target>",
+                   p.attributes,
+                   p.validation_mode
             "#,
             id.as_str(),
-            new.program_name,
-            to_json_value(new.interval_period)?,
-            to_json_value(new.program_descriptions)?,
-            to_json_value(new.payload_descriptors)?,
-            new.targets.as_slice() as _,
-            to_json_value(new.attributes)?
         )
         .fetch_one(&self.db)
         .await?
-        .try_into()?;
-
-        Ok(program)
+        .try_into()?)
     }
 
-    async fn delete(
+    async fn lock(
         &self,
         id: &Self::Id,
-        _client_id: &Self::PermissionFilter,
-    ) -> Result<Self::Type, Self::Error> {
-        Ok(sqlx::query_as!(
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    > {
+        lock::<Self>(&self.db, id).await
+    }
+}
+
+#[async_trait]
+impl PgUpdate for PgProgramStorage {
+    type Id = ProgramId;
+    type NewType = ProgramRequest;
+    type Type = Program;
+
+    async fn lock_in(conn: &mut PgConnection, id: &ProgramId) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM program WHERE id = $1 FOR UPDATE
+            "#,
+            id.as_str()
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn retrieve_in(conn: &mut PgConnection, id: &ProgramId) -> Result<Program, AppError> {
+        sqlx::query_as!(
             PostgresProgram,
             r#"
-            DELETE FROM program p
-                   WHERE id = $1
+            SELECT p.id,
+                   p.created_date_time,
+                   p.modification_date_time,
+                   p.program_name,
+                   p.interval_period,
+                   p.program_descriptions,
+                   p.payload_descriptors,
+                   p.targets AS "targets:Vec<Target>",
+                   p.attributes,
+                   p.validation_mode
+            FROM program p
+            WHERE p.id = $1
+            "#,
+            id.as_str(),
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .try_into()
+    }
+
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &ProgramId,
+        new: ProgramRequest,
+        _client_id: &Option<ClientId>,
+    ) -> Result<Program, AppError> {
+        let program: Program = sqlx::query_as!(
+            PostgresProgram,
+            r#"
+            UPDATE program p
+            SET modification_date_time = now(),
+                program_name = $2,
+                interval_period = $3,
+                program_descriptions = $4,
+                payload_descriptors = $5,
+                targets = $6,
+                attributes = $7,
+                validation_mode = $8
+            WHERE id = $1
             RETURNING p.id,
                    p.created_date_time,
                    p.modification_date_time,
@@ -245,14 +350,24 @@ impl Crud for PgProgramStorage {
                    p.interval_period,
                    p.program_descriptions,
                    p.payload_descriptors,
-                   p.targets as  "targets:Vec<Target>",
-                   p.attributes
+                   p.targets as "targets:Vec<Target>",
+                   p.attributes,
+                   p.validation_mode
             "#,
             id.as_str(),
+            new.program_name,
+            to_json_value(new.interval_period)?,
+            to_json_value(new.program_descriptions)?,
+            to_json_value(new.payload_descriptors)?,
+            new.targets.as_slice() as _,
+            to_json_value(new.attributes)?,
+            new.validation_mode.map(|mode| mode.as_str()),
         )
-        .fetch_one(&self.db)
+        .fetch_one(&mut *conn)
         .await?
-        .try_into()?)
+        .try_into()?;
+
+        Ok(program)
     }
 }
 
@@ -273,6 +388,7 @@ impl PgProgramStorage {
         if filter_targets.is_empty() != filter.targets.as_deref().is_empty() {
             return Ok(vec![]);
         }
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
 
         sqlx::query_as!(
             PostgresProgram,
@@ -285,7 +401,8 @@ impl PgProgramStorage {
                    p.program_descriptions,
                    p.payload_descriptors,
                    p.targets AS "targets:Vec<Target>",
-                   p.attributes
+                   p.attributes,
+                   p.validation_mode
             FROM program p
             WHERE
               -- according to the spec, we MUST only test query params
@@ -298,13 +415,19 @@ impl PgProgramStorage {
                         -- or IF the program targets are empty
                         OR array_length(p.targets, 1) IS NULL
                   )
-            ORDER BY created_date_time DESC
+              AND ($6::timestamptz IS NULL OR (p.created_date_time, p.id) > ($6, $7))
+            ORDER BY CASE WHEN $5 THEN p.created_date_time END,
+                     CASE WHEN $5 THEN p.id END,
+                     created_date_time DESC
             OFFSET $3 LIMIT $4
             "#,
             filter_targets as _,
             ven_targets as _,
             filter.skip,
-            filter.limit
+            filter.limit,
+            filter.cursor.is_some(),
+            after_time,
+            after_id,
         )
         .fetch_all(&self.db)
         .await?
@@ -328,7 +451,7 @@ impl PgProgramStorage {
     /// It is provided if the request has [`ReadTargets`](Scope::ReadTargets) scope, which
     /// is the case for VEN clients (aka. customer logic).
     /// BL clients have a [`ReadAll`](Scope::ReadAll) scope, and therefore the API layer will
-    /// call the [`retrieve_without_client_id`](PgProgramStorage::retrieve_without_client_id) function.
+    /// call [`retrieve_in`](PgProgramStorage::retrieve_in) instead.
     async fn retrieve_with_client_id(
         &self,
         id: &ProgramId,
@@ -347,7 +470,8 @@ impl PgProgramStorage {
                    p.program_descriptions,
                    p.payload_descriptors,
                    p.targets AS "targets:Vec<Target>",
-                   p.attributes
+                   p.attributes,
+                   p.validation_mode
             FROM program p
             WHERE p.id = $1
               AND (
@@ -379,6 +503,8 @@ impl PgProgramStorage {
         &self,
         filter: &QueryParams,
     ) -> Result<Vec<Program>, AppError> {
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
+
         sqlx::query_as!(
             PostgresProgram,
             r#"
@@ -390,18 +516,25 @@ impl PgProgramStorage {
                    p.program_descriptions,
                    p.payload_descriptors,
                    p.targets AS "targets:Vec<Target>",
-                   p.attributes
+                   p.attributes,
+                   p.validation_mode
             FROM program p
             WHERE
               -- IF filter targets are empty, do not filter.
               -- IF filter targets are not empty, filter only if they are in the program targets.
               (array_length($1::text[], 1) IS NULL OR p.targets && $1)
-            ORDER BY created_date_time DESC
+              AND ($5::timestamptz IS NULL OR (p.created_date_time, p.id) > ($5, $6))
+            ORDER BY CASE WHEN $4 THEN p.created_date_time END,
+                     CASE WHEN $4 THEN p.id END,
+                     created_date_time DESC
             OFFSET $2 LIMIT $3
             "#,
             filter.targets.as_deref() as _,
             filter.skip,
-            filter.limit
+            filter.limit,
+            filter.cursor.is_some(),
+            after_time,
+            after_id,
         )
         .fetch_all(&self.db)
         .await?
@@ -409,28 +542,80 @@ impl PgProgramStorage {
         .map(TryInto::try_into)
         .collect::<Result<_, _>>()
     }
+}
 
-    async fn retrieve_without_client_id(&self, id: &ProgramId) -> Result<Program, AppError> {
+impl PgProgramStorage {
+    /// All programs, in the order they were created
+    pub(super) async fn export(
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<Program>, AppError> {
         sqlx::query_as!(
             PostgresProgram,
             r#"
-            SELECT p.id,
-                   p.created_date_time,
-                   p.modification_date_time,
-                   p.program_name,
-                   p.interval_period,
-                   p.program_descriptions,
-                   p.payload_descriptors,
-                   p.targets AS "targets:Vec<Target>",
-                   p.attributes
-            FROM program p
-            WHERE p.id = $1
+            SELECT id,
+                   created_date_time,
+                   modification_date_time,
+                   program_name,
+                   interval_period,
+                   program_descriptions,
+                   payload_descriptors,
+                   targets AS "targets:Vec<Target>",
+                   attributes,
+                   validation_mode
+            FROM program
+            ORDER BY created_date_time, id
             "#,
-            id.as_str(),
         )
-        .fetch_one(&self.db)
+        .fetch_all(tx.as_mut())
         .await?
-        .try_into()
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    /// Inserts the program with its ID and timestamps, or replaces the program with the same ID
+    pub(super) async fn import(
+        tx: &mut Transaction<'_, Postgres>,
+        program: &Program,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO program (id,
+                                 created_date_time,
+                                 modification_date_time,
+                                 program_name,
+                                 interval_period,
+                                 program_descriptions,
+                                 payload_descriptors,
+                                 targets,
+                                 attributes,
+                                 validation_mode)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (id) DO UPDATE
+            SET created_date_time = excluded.created_date_time,
+                modification_date_time = excluded.modification_date_time,
+                program_name = excluded.program_name,
+                interval_period = excluded.interval_period,
+                program_descriptions = excluded.program_descriptions,
+                payload_descriptors = excluded.payload_descriptors,
+                targets = excluded.targets,
+                attributes = excluded.attributes,
+                validation_mode = excluded.validation_mode
+            "#,
+            program.id.as_str(),
+            program.created_date_time,
+            program.modification_date_time,
+            program.content.program_name,
+            to_json_value(program.content.interval_period.as_ref())?,
+            to_json_value(program.content.program_descriptions.as_ref())?,
+            to_json_value(program.content.payload_descriptors.as_ref())?,
+            program.content.targets.as_slice() as &[Target],
+            to_json_value(program.content.attributes.as_ref())?,
+            program.content.validation_mode.map(|mode| mode.as_str()),
+        )
+        .execute(tx.as_mut())
+        .await?;
+        Ok(())
     }
 }
 
@@ -458,6 +643,7 @@ mod tests {
                 targets: TargetQueryParams(None),
                 skip: 0,
                 limit: 50,
+                cursor: None,
             }
         }
     }
@@ -483,6 +669,7 @@ mod tests {
                     Target::from_str("group-1").unwrap(),
                     Target::from_str("private-value").unwrap(),
                 ],
+                validation_mode: None,
             },
         }
     }
@@ -502,6 +689,7 @@ mod tests {
                     Target::from_str("group-1").unwrap(),
                     Target::from_str("group-2").unwrap(),
                 ],
+                validation_mode: None,
             },
         }
     }
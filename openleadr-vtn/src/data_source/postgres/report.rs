@@ -1,18 +1,193 @@
 use crate::{
-    api::report::QueryParams,
-    data_source::{Crud, ReportCrud, postgres::to_json_value},
+    api::{pagination::Cursor, report::QueryParams},
+    data_source::{
+        Crud, Locked, LockedIntervals, ReportCrud,
+        postgres::{PgUpdate, lock, to_json_value},
+    },
     error::AppError,
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use openleadr_wire::{
     ClientId, Report,
-    report::{ReportId, ReportRequest},
+    interval::Interval,
+    report::{ReportId, ReportRequest, ReportResource, ResourceName},
 };
-use sqlx::PgPool;
+use sqlx::{Acquire, PgConnection, PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashSet};
 use tracing::{error, info, trace};
 
-impl ReportCrud for PgReportStorage {}
+#[async_trait]
+impl ReportCrud for PgReportStorage {
+    async fn lock_intervals(
+        &self,
+        id: &ReportId,
+        resource_name: &ResourceName,
+        client_id: &Option<ClientId>,
+    ) -> Result<Box<dyn LockedIntervals>, AppError> {
+        let Some(client_id) = client_id else {
+            return Err(AppError::Forbidden(
+                "client_id is required to update a report",
+            ));
+        };
+
+        let mut tx = self.db.begin().await?;
+
+        // locks the report, such that concurrent appends cannot add intervals with the same id
+        let report: Report = sqlx::query_as!(
+            PostgresReport,
+            r#"
+            SELECT id,
+                   created_date_time,
+                   modification_date_time,
+                   event_id,
+                   client_name,
+                   report_name,
+                   payload_descriptors,
+                   resources,
+                   client_id
+            FROM report
+            WHERE id = $1
+              AND client_id = $2
+            FOR UPDATE
+            "#,
+            id.as_str(),
+            client_id as _,
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .try_into()?;
+        let Some(resource_index) = report
+            .content
+            .resources
+            .iter()
+            .position(|resource| &resource.resource_name == resource_name)
+        else {
+            return Err(AppError::BadRequest(
+                "The report has no resource with this name",
+            ));
+        };
+        let resource_index = resource_index as i32;
+
+        let interval_counts = sqlx::query!(
+            r#"
+            SELECT p.value ->> 'type' AS "payload_type!",
+                   count(*)::integer  AS "count!"
+            FROM report_interval i,
+                 jsonb_array_elements(i.payloads) AS p(value)
+            WHERE i.report_id = $1
+              AND i.resource_index = $2
+            GROUP BY 1
+            "#,
+            id.as_str(),
+            resource_index,
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| (row.payload_type, row.count))
+        .collect();
+
+        Ok(Box::new(PgLockedIntervals {
+            tx,
+            report,
+            resource_index,
+            interval_counts,
+        }))
+    }
+}
+
+/// The intervals of a resource of a report, locked in a transaction
+/// which is committed after appending to them
+struct PgLockedIntervals {
+    tx: Transaction<'static, Postgres>,
+    report: Report,
+    resource_index: i32,
+    interval_counts: BTreeMap<String, i32>,
+}
+
+#[async_trait]
+impl LockedIntervals for PgLockedIntervals {
+    fn report(&self) -> &Report {
+        &self.report
+    }
+
+    fn interval_counts(&self) -> &BTreeMap<String, i32> {
+        &self.interval_counts
+    }
+
+    async fn append(mut self: Box<Self>, intervals: Vec<Interval>) -> Result<Report, AppError> {
+        let id = self.report.id.as_str();
+
+        let ids: Vec<i32> = intervals.iter().map(|interval| interval.id).collect();
+        let mut duplicates = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM report_interval
+            WHERE report_id = $1
+              AND resource_index = $2
+              AND id = ANY($3)
+            "#,
+            id,
+            self.resource_index,
+            &ids,
+        )
+        .fetch_all(&mut *self.tx)
+        .await?;
+        let mut seen = HashSet::new();
+        duplicates.extend(ids.iter().filter(|id| !seen.insert(**id)));
+        if !duplicates.is_empty() {
+            duplicates.sort();
+            duplicates.dedup();
+            return Err(AppError::Conflict(
+                format!("The interval ids {duplicates:?} would not be unique in the resource"),
+                None,
+            ));
+        }
+
+        let position = sqlx::query_scalar!(
+            r#"
+            SELECT coalesce(max(position) + 1, 0) AS "position!"
+            FROM report_interval
+            WHERE report_id = $1
+              AND resource_index = $2
+            "#,
+            id,
+            self.resource_index,
+        )
+        .fetch_one(&mut *self.tx)
+        .await?;
+        PgReportStorage::insert_intervals(
+            &mut self.tx,
+            id,
+            self.resource_index,
+            position,
+            &intervals,
+        )
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE report
+            SET modification_date_time = now()
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut *self.tx)
+        .await?;
+        let report = PgReportStorage::fetch(&mut self.tx, id, None).await?;
+        self.tx.commit().await?;
+
+        info!(
+            report_id = id,
+            "appended {} intervals to report",
+            intervals.len()
+        );
+
+        Ok(report)
+    }
+}
 
 pub(crate) struct PgReportStorage {
     db: PgPool,
@@ -92,23 +267,25 @@ impl Crud for PgReportStorage {
             ));
         };
 
-        let report: Report = sqlx::query_as!(
-            PostgresReport,
+        let mut tx = self.db.begin().await?;
+        let id = sqlx::query_scalar!(
             r#"
             INSERT INTO report (id, created_date_time, modification_date_time, event_id, client_name, report_name, payload_descriptors, resources, client_id)
             VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4, $5, $6)
-            RETURNING *
+            RETURNING id
             "#,
             new.event_id.as_str(),
             new.client_name,
             new.report_name,
             to_json_value(new.payload_descriptors)?,
-            serde_json::to_value(new.resources).map_err(AppError::SerdeJsonBadRequest)?,
+            without_intervals(&new.resources)?,
             client_id as _,
         )
-            .fetch_one(&self.db)
-            .await?
-            .try_into()?;
+            .fetch_one(&mut *tx)
+            .await?;
+        Self::insert_resources_intervals(&mut tx, &id, &new.resources).await?;
+        let report = Self::fetch(&mut tx, &id, None).await?;
+        tx.commit().await?;
 
         info!(report_id = report.id.as_str(), "created report");
 
@@ -120,20 +297,12 @@ impl Crud for PgReportStorage {
         id: &Self::Id,
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let report: Report = sqlx::query_as!(
-            PostgresReport,
-            r#"
-            SELECT r.*
-            FROM report r
-            WHERE r.id = $1
-              AND ($2::text IS NULL OR r.client_id = $2)
-            "#,
+        let report = Self::fetch(
+            &mut *self.db.acquire().await?,
             id.as_str(),
-            client_id as _,
+            client_id.as_ref().map(ClientId::as_str),
         )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
+        .await?;
 
         trace!(report_id = report.id.as_str(), "retrieved report");
 
@@ -146,17 +315,30 @@ impl Crud for PgReportStorage {
         client_id: &Self::PermissionFilter,
     ) -> Result<Vec<Self::Type>, Self::Error> {
         println!("{:?}", filter.client_name);
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
+        // the event is unique for each report, so joining it does not duplicate reports
         let reports = sqlx::query_as!(
             PostgresReport,
             r#"
-            SELECT DISTINCT r.*
+            SELECT r.id,
+                   r.created_date_time,
+                   r.modification_date_time,
+                   r.event_id,
+                   r.client_name,
+                   r.report_name,
+                   r.payload_descriptors,
+                   report_resources(r.id, r.resources) AS "resources!",
+                   r.client_id
             FROM report r
                 JOIN event e ON e.id = r.event_id
             WHERE ($1::text IS NULL OR $1 = e.program_id)
               AND ($2::text IS NULL OR $2 = r.event_id)
               AND ($3::text IS NULL OR $3 = r.client_name)
               AND ($4::text IS NULL OR $4 = r.client_id)
-            ORDER BY r.created_date_time DESC
+              AND ($8::timestamptz IS NULL OR (r.created_date_time, r.id) > ($8, $9))
+            ORDER BY CASE WHEN $7 THEN r.created_date_time END,
+                     CASE WHEN $7 THEN r.id END,
+                     r.created_date_time DESC
             OFFSET $5 LIMIT $6
             "#,
             filter.program_id.as_ref().map(|x| x.to_string()),
@@ -165,6 +347,9 @@ impl Crud for PgReportStorage {
             client_id as _,
             filter.skip,
             filter.limit,
+            filter.cursor.is_some(),
+            after_time,
+            after_id,
         )
         .fetch_all(&self.db)
         .await?
@@ -177,20 +362,118 @@ impl Crud for PgReportStorage {
         Ok(reports)
     }
 
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!"
+            FROM report r
+                JOIN event e ON e.id = r.event_id
+            WHERE ($1::text IS NULL OR $1 = e.program_id)
+              AND ($2::text IS NULL OR $2 = r.event_id)
+              AND ($3::text IS NULL OR $3 = r.client_name)
+              AND ($4::text IS NULL OR $4 = r.client_id)
+            "#,
+            filter.program_id.as_ref().map(|x| x.to_string()),
+            filter.event_id.as_ref().map(|x| x.to_string()),
+            filter.client_name,
+            client_id as _,
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count as u64)
+    }
+
     async fn update(
         &self,
         id: &Self::Id,
         new: Self::NewType,
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
+        Self::update_in(&mut *self.db.acquire().await?, id, new, client_id).await
+    }
+
+    async fn delete(
+        &self,
+        id: &Self::Id,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<Self::Type, Self::Error> {
+        let Some(client_id) = client_id else {
+            return Err(AppError::Forbidden(
+                "client_id is required to delete a report",
+            ));
+        };
+
+        let mut tx = self.db.begin().await?;
+        // the intervals are deleted with the report, so the report is read before
+        let report = Self::fetch(&mut tx, id.as_str(), Some(client_id.as_str())).await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM report
+            WHERE id = $1
+            "#,
+            id.as_str(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!(report_id = report.id.as_str(), "deleted report");
+
+        Ok(report)
+    }
+
+    async fn lock(
+        &self,
+        id: &Self::Id,
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    > {
+        lock::<Self>(&self.db, id).await
+    }
+}
+
+#[async_trait]
+impl PgUpdate for PgReportStorage {
+    type Id = ReportId;
+    type NewType = ReportRequest;
+    type Type = Report;
+
+    async fn lock_in(conn: &mut PgConnection, id: &ReportId) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM report WHERE id = $1 FOR UPDATE
+            "#,
+            id.as_str()
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn retrieve_in(conn: &mut PgConnection, id: &ReportId) -> Result<Report, AppError> {
+        Self::fetch(conn, id.as_str(), None).await
+    }
+
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &ReportId,
+        new: ReportRequest,
+        client_id: &Option<ClientId>,
+    ) -> Result<Report, AppError> {
         let Some(client_id) = client_id else {
             return Err(AppError::Forbidden(
                 "client_id is required to update a report",
             ));
         };
 
-        let report: Report = sqlx::query_as!(
-            PostgresReport,
+        let mut tx = conn.begin().await?;
+        sqlx::query_scalar!(
             r#"
             UPDATE report r
             SET modification_date_time = now(),
@@ -199,56 +482,197 @@ impl Crud for PgReportStorage {
                 report_name = $4,
                 payload_descriptors = $5,
                 resources = $6
-            FROM program p
             WHERE r.id = $1
               AND client_id = $7
-            RETURNING r.*
+            RETURNING r.id
             "#,
             id.as_str(),
             new.event_id.as_str(),
             new.client_name,
             new.report_name,
             to_json_value(new.payload_descriptors)?,
-            serde_json::to_value(new.resources).map_err(AppError::SerdeJsonBadRequest)?,
+            without_intervals(&new.resources)?,
             client_id as _
         )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
+        .fetch_one(&mut *tx)
+        .await?;
+        Self::delete_intervals(&mut tx, id.as_str()).await?;
+        Self::insert_resources_intervals(&mut tx, id.as_str(), &new.resources).await?;
+        let report = Self::fetch(&mut tx, id.as_str(), None).await?;
+        tx.commit().await?;
 
         info!(report_id = report.id.as_str(), "updated report");
 
         Ok(report)
     }
+}
 
-    async fn delete(
-        &self,
-        id: &Self::Id,
-        client_id: &Self::PermissionFilter,
-    ) -> Result<Self::Type, Self::Error> {
-        let Some(client_id) = client_id else {
-            return Err(AppError::Forbidden(
-                "client_id is required to delete a report",
-            ));
-        };
+impl PgReportStorage {
+    /// All reports, in the order they were created
+    pub(super) async fn export(
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<Report>, AppError> {
+        sqlx::query_as!(
+            PostgresReport,
+            r#"
+            SELECT id,
+                   created_date_time,
+                   modification_date_time,
+                   event_id,
+                   client_name,
+                   report_name,
+                   payload_descriptors,
+                   report_resources(id, resources) AS "resources!",
+                   client_id
+            FROM report
+            ORDER BY created_date_time, id
+            "#,
+        )
+        .fetch_all(tx.as_mut())
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect()
+    }
 
-        let report: Report = sqlx::query_as!(
+    /// Inserts the report with its ID and timestamps, or replaces the report with the same ID
+    pub(super) async fn import(
+        tx: &mut Transaction<'_, Postgres>,
+        report: &Report,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO report (id, created_date_time, modification_date_time, event_id, client_name, report_name, payload_descriptors, resources, client_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (id) DO UPDATE
+            SET created_date_time = excluded.created_date_time,
+                modification_date_time = excluded.modification_date_time,
+                event_id = excluded.event_id,
+                client_name = excluded.client_name,
+                report_name = excluded.report_name,
+                payload_descriptors = excluded.payload_descriptors,
+                resources = excluded.resources,
+                client_id = excluded.client_id
+            "#,
+            report.id.as_str(),
+            report.created_date_time,
+            report.modification_date_time,
+            report.content.event_id.as_str(),
+            report.content.client_name,
+            report.content.report_name,
+            to_json_value(report.content.payload_descriptors.as_ref())?,
+            without_intervals(&report.content.resources)?,
+            report.client_id.as_str(),
+        )
+        .execute(tx.as_mut())
+        .await?;
+        Self::delete_intervals(tx, report.id.as_str()).await?;
+        Self::insert_resources_intervals(tx, report.id.as_str(), &report.content.resources).await
+    }
+
+    /// The report with its intervals
+    async fn fetch(
+        conn: &mut PgConnection,
+        id: &str,
+        client_id: Option<&str>,
+    ) -> Result<Report, AppError> {
+        sqlx::query_as!(
             PostgresReport,
             r#"
-            DELETE FROM report r
-                   WHERE r.id = $1
-                     AND r.client_id = $2
-                   RETURNING r.*
+            SELECT r.id,
+                   r.created_date_time,
+                   r.modification_date_time,
+                   r.event_id,
+                   r.client_name,
+                   r.report_name,
+                   r.payload_descriptors,
+                   report_resources(r.id, r.resources) AS "resources!",
+                   r.client_id
+            FROM report r
+            WHERE r.id = $1
+              AND ($2::text IS NULL OR r.client_id = $2)
             "#,
-            id.as_str(),
-            client_id as _,
+            id,
+            client_id,
         )
-        .fetch_one(&self.db)
+        .fetch_one(conn)
         .await?
-        .try_into()?;
+        .try_into()
+    }
 
-        info!(report_id = report.id.as_str(), "deleted report");
+    async fn delete_intervals(conn: &mut PgConnection, report_id: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM report_interval
+            WHERE report_id = $1
+            "#,
+            report_id,
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
 
-        Ok(report)
+    async fn insert_resources_intervals(
+        conn: &mut PgConnection,
+        report_id: &str,
+        resources: &[ReportResource],
+    ) -> Result<(), AppError> {
+        for (resource_index, resource) in resources.iter().enumerate() {
+            Self::insert_intervals(
+                conn,
+                report_id,
+                resource_index as i32,
+                0,
+                &resource.intervals,
+            )
+            .await?;
+        }
+        Ok(())
     }
+
+    /// Inserts the intervals of the resource at `resource_index`, starting at `position`
+    async fn insert_intervals(
+        conn: &mut PgConnection,
+        report_id: &str,
+        resource_index: i32,
+        position: i32,
+        intervals: &[Interval],
+    ) -> Result<(), AppError> {
+        if intervals.is_empty() {
+            return Ok(());
+        }
+        sqlx::query!(
+            r#"
+            INSERT INTO report_interval (report_id, resource_index, position, id, interval_period, payloads)
+            SELECT $1,
+                   $2::integer,
+                   $3::integer + iv.ordinality::integer - 1,
+                   (iv.value ->> 'id')::integer,
+                   iv.value -> 'intervalPeriod',
+                   iv.value -> 'payloads'
+            FROM jsonb_array_elements($4::jsonb) WITH ORDINALITY AS iv(value, ordinality)
+            "#,
+            report_id,
+            resource_index,
+            position,
+            serde_json::to_value(intervals).map_err(AppError::SerdeJsonBadRequest)?,
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// The resources with empty `intervals`, which are stored in `report_interval` instead
+fn without_intervals(resources: &[ReportResource]) -> Result<serde_json::Value, AppError> {
+    let resources: Vec<_> = resources
+        .iter()
+        .map(|resource| ReportResource {
+            resource_name: resource.resource_name.clone(),
+            interval_period: resource.interval_period.clone(),
+            intervals: vec![],
+        })
+        .collect();
+    serde_json::to_value(resources).map_err(AppError::SerdeJsonBadRequest)
 }
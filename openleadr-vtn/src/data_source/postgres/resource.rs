@@ -1,19 +1,32 @@
 use crate::{
-    api::resource::QueryParams,
-    data_source::{Crud, ResourceCrud, postgres::to_json_value},
+    api::{pagination::Cursor, resource::QueryParams},
+    data_source::{
+        Crud, Locked, ResourceCrud,
+        postgres::{AttributeQuery, PgBatch, PgUpdate, batch, lock, to_json_value},
+    },
     error::AppError,
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use openleadr_wire::{
     ClientId,
+    batch::BatchOperation,
     resource::{BlResourceRequest, Resource, ResourceId},
     target::Target,
 };
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
 use tracing::{error, trace, warn};
 
-impl ResourceCrud for PgResourceStorage {}
+#[async_trait]
+impl ResourceCrud for PgResourceStorage {
+    async fn batch(
+        &self,
+        operations: Vec<BatchOperation<ResourceId, BlResourceRequest>>,
+        client_id: &Option<ClientId>,
+    ) -> Result<Vec<Result<Resource, AppError>>, AppError> {
+        batch::<Self>(&self.db, operations, client_id).await
+    }
+}
 
 pub(crate) struct PgResourceStorage {
     db: PgPool,
@@ -81,43 +94,9 @@ impl Crud for PgResourceStorage {
     async fn create(
         &self,
         new: Self::NewType,
-        _client_id: &Self::PermissionFilter,
+        client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let resource: Resource = sqlx::query_as!(
-            PostgresResource,
-            r#"
-            WITH new_resource AS (INSERT INTO resource (
-                                                        id,
-                                                        created_date_time,
-                                                        modification_date_time,
-                                                        resource_name,
-                                                        ven_id,
-                                                        attributes,
-                                                        targets
-                )
-                VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4)
-                RETURNING
-                    id,
-                    created_date_time,
-                    modification_date_time,
-                    resource_name,
-                    ven_id,
-                    attributes,
-                    targets as "targets:Vec<Target>")
-            SELECT new_resource.*, v.client_id
-            FROM new_resource
-                     JOIN ven v ON v.id = new_resource.ven_id;
-            "#,
-            new.resource_name,
-            new.ven_id.as_str(),
-            to_json_value(new.attributes)?,
-            new.targets as _,
-        )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
-
-        Ok(resource)
+        Self::create_in(&mut *self.db.acquire().await?, new, client_id).await
     }
 
     async fn retrieve(
@@ -125,31 +104,7 @@ impl Crud for PgResourceStorage {
         id: &Self::Id,
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let resource = sqlx::query_as!(
-            PostgresResource,
-            r#"
-            SELECT
-                r.id,
-                r.created_date_time,
-                r.modification_date_time,
-                r.resource_name,
-                r.ven_id,
-                r.attributes,
-                r.targets as "targets:Vec<Target>",
-                v.client_id
-            FROM resource r
-                JOIN ven v on r.ven_id = v.id
-            WHERE r.id = $1
-              AND ($2::text IS NULL OR v.client_id = $2)
-            "#,
-            id.as_str(),
-            client_id as _
-        )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
-
-        Ok(resource)
+        Self::fetch(&mut *self.db.acquire().await?, id, client_id).await
     }
 
     async fn retrieve_all(
@@ -157,6 +112,8 @@ impl Crud for PgResourceStorage {
         filter: &Self::Filter,
         client_id: &Self::PermissionFilter,
     ) -> Result<Vec<Self::Type>, Self::Error> {
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
+        let attributes = AttributeQuery::new(filter.attributes.as_deref());
         let res = sqlx::query_as!(
             PostgresResource,
             r#"
@@ -175,7 +132,10 @@ impl Crud for PgResourceStorage {
                 AND ($2::text IS NULL OR r.resource_name = $2)
                 AND (array_length($3::text[], 1) IS NULL OR r.targets && $3)
                 AND ($4::text IS NULL OR v.client_id = $4)
-            ORDER BY r.created_date_time
+                AND ($7::timestamptz IS NULL OR (r.created_date_time, r.id) > ($7, $8))
+                AND ($9::jsonb IS NULL OR r.attributes @> $9)
+                AND ($10::text IS NULL OR jsonb_path_match(r.attributes, $10::text::jsonpath, $11))
+            ORDER BY r.created_date_time, r.id
             OFFSET $5 LIMIT $6
             "#,
             filter.ven_id as _,
@@ -184,6 +144,11 @@ impl Crud for PgResourceStorage {
             client_id as _,
             filter.skip,
             filter.limit,
+            after_time,
+            after_id,
+            attributes.contains,
+            attributes.predicate,
+            attributes.vars,
         )
         .fetch_all(&self.db)
         .await?
@@ -196,6 +161,38 @@ impl Crud for PgResourceStorage {
         Ok(res)
     }
 
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error> {
+        let attributes = AttributeQuery::new(filter.attributes.as_deref());
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!"
+            FROM resource r
+                JOIN ven v on r.ven_id = v.id
+            WHERE ($1::text IS NULL OR r.ven_id = $1)
+                AND ($2::text IS NULL OR r.resource_name = $2)
+                AND (array_length($3::text[], 1) IS NULL OR r.targets && $3)
+                AND ($4::text IS NULL OR v.client_id = $4)
+                AND ($5::jsonb IS NULL OR r.attributes @> $5)
+                AND ($6::text IS NULL OR jsonb_path_match(r.attributes, $6::text::jsonpath, $7))
+            "#,
+            filter.ven_id as _,
+            filter.resource_name,
+            filter.targets.as_deref() as _,
+            client_id as _,
+            attributes.contains,
+            attributes.predicate,
+            attributes.vars,
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count as u64)
+    }
+
     async fn update(
         &self,
         id: &Self::Id,
@@ -203,14 +200,66 @@ impl Crud for PgResourceStorage {
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
         let mut tx = self.db.begin().await?;
+        let result = Self::update_in(&mut tx, id, new, client_id).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    async fn delete(
+        &self,
+        id: &Self::Id,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<Self::Type, Self::Error> {
+        Self::delete_in(&mut *self.db.acquire().await?, id, client_id).await
+    }
+
+    async fn lock(
+        &self,
+        id: &Self::Id,
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    > {
+        lock::<Self>(&self.db, id).await
+    }
+}
+
+#[async_trait]
+impl PgUpdate for PgResourceStorage {
+    type Id = ResourceId;
+    type NewType = BlResourceRequest;
+    type Type = Resource;
+
+    async fn lock_in(conn: &mut PgConnection, id: &ResourceId) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM resource WHERE id = $1 FOR UPDATE
+            "#,
+            id.as_str()
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn retrieve_in(conn: &mut PgConnection, id: &ResourceId) -> Result<Resource, AppError> {
+        Self::fetch(conn, id, &None).await
+    }
 
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &ResourceId,
+        new: BlResourceRequest,
+        client_id: &Option<ClientId>,
+    ) -> Result<Resource, AppError> {
         let old_ven_id = sqlx::query_scalar!(
             r#"
             SELECT ven_id FROM resource WHERE id = $1
             "#,
             id.as_str()
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?;
 
         if old_ven_id != new.ven_id.as_str() {
@@ -219,7 +268,7 @@ impl Crud for PgResourceStorage {
             clear about if that should be allowed. If you disagree with that interpretation, please open \
             an issue on GitHub.";
             error!(resource_id = id.as_str(), "{}", error);
-            return Err(Self::Error::BadRequest(error));
+            return Err(AppError::BadRequest(error));
         }
 
         let resource: Resource = sqlx::query_as!(
@@ -250,20 +299,63 @@ impl Crud for PgResourceStorage {
             new.targets as _,
             client_id as _
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?
         .try_into()?;
 
-        tx.commit().await?;
+        Ok(resource)
+    }
+}
+
+#[async_trait]
+impl PgBatch for PgResourceStorage {
+    async fn create_in(
+        conn: &mut PgConnection,
+        new: BlResourceRequest,
+        _client_id: &Option<ClientId>,
+    ) -> Result<Resource, AppError> {
+        let resource: Resource = sqlx::query_as!(
+            PostgresResource,
+            r#"
+            WITH new_resource AS (INSERT INTO resource (
+                                                        id,
+                                                        created_date_time,
+                                                        modification_date_time,
+                                                        resource_name,
+                                                        ven_id,
+                                                        attributes,
+                                                        targets
+                )
+                VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4)
+                RETURNING
+                    id,
+                    created_date_time,
+                    modification_date_time,
+                    resource_name,
+                    ven_id,
+                    attributes,
+                    targets as "targets:Vec<Target>")
+            SELECT new_resource.*, v.client_id
+            FROM new_resource
+                     JOIN ven v ON v.id = new_resource.ven_id;
+            "#,
+            new.resource_name,
+            new.ven_id.as_str(),
+            to_json_value(new.attributes)?,
+            new.targets as _,
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .try_into()?;
 
         Ok(resource)
     }
 
-    async fn delete(
-        &self,
-        id: &Self::Id,
-        client_id: &Self::PermissionFilter,
-    ) -> Result<Self::Type, Self::Error> {
+    async fn delete_in(
+        conn: &mut PgConnection,
+        id: &ResourceId,
+        client_id: &Option<ClientId>,
+    ) -> Result<Resource, AppError> {
         Ok(sqlx::query_as!(
             PostgresResource,
             r#"
@@ -285,17 +377,116 @@ impl Crud for PgResourceStorage {
             id.as_str(),
             client_id as _
         )
-        .fetch_one(&self.db)
+        .fetch_one(&mut *conn)
         .await?
         .try_into()?)
     }
 }
 
+impl PgResourceStorage {
+    async fn fetch(
+        conn: &mut PgConnection,
+        id: &ResourceId,
+        client_id: &Option<ClientId>,
+    ) -> Result<Resource, AppError> {
+        let resource = sqlx::query_as!(
+            PostgresResource,
+            r#"
+            SELECT
+                r.id,
+                r.created_date_time,
+                r.modification_date_time,
+                r.resource_name,
+                r.ven_id,
+                r.attributes,
+                r.targets as "targets:Vec<Target>",
+                v.client_id
+            FROM resource r
+                JOIN ven v on r.ven_id = v.id
+            WHERE r.id = $1
+              AND ($2::text IS NULL OR v.client_id = $2)
+            "#,
+            id.as_str(),
+            client_id as _
+        )
+        .fetch_one(conn)
+        .await?
+        .try_into()?;
+
+        Ok(resource)
+    }
+
+    /// All resources, in the order they were created
+    pub(super) async fn export(
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<Resource>, AppError> {
+        sqlx::query_as!(
+            PostgresResource,
+            r#"
+            SELECT r.id,
+                   r.created_date_time,
+                   r.modification_date_time,
+                   r.resource_name,
+                   r.ven_id,
+                   r.attributes,
+                   r.targets AS "targets:Vec<Target>",
+                   v.client_id
+            FROM resource r
+                     JOIN ven v ON v.id = r.ven_id
+            ORDER BY r.created_date_time, r.id
+            "#,
+        )
+        .fetch_all(tx.as_mut())
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    /// Inserts the resource with its ID and timestamps, or replaces the resource with the same ID
+    pub(super) async fn import(
+        tx: &mut Transaction<'_, Postgres>,
+        resource: &Resource,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO resource (
+                id,
+                created_date_time,
+                modification_date_time,
+                resource_name,
+                ven_id,
+                attributes,
+                targets
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (id) DO UPDATE
+            SET created_date_time = excluded.created_date_time,
+                modification_date_time = excluded.modification_date_time,
+                resource_name = excluded.resource_name,
+                ven_id = excluded.ven_id,
+                attributes = excluded.attributes,
+                targets = excluded.targets
+            "#,
+            resource.id.as_str(),
+            resource.created_date_time,
+            resource.modification_date_time,
+            resource.content.resource_name,
+            resource.content.ven_id.as_str(),
+            to_json_value(resource.content.attributes.as_ref())?,
+            resource.content.targets.as_slice() as &[Target],
+        )
+        .execute(tx.as_mut())
+        .await?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "live-db-test")]
 mod test {
     use crate::{
-        api::{TargetQueryParams, resource::QueryParams},
+        api::{TargetQueryParams, attributes::AttributeQueryParams, resource::QueryParams},
         data_source::{Crud, postgres::resource::PgResourceStorage},
     };
     use sqlx::PgPool;
@@ -306,8 +497,10 @@ mod test {
                 resource_name: None,
                 ven_id: None,
                 targets: TargetQueryParams(None),
+                attributes: AttributeQueryParams(None),
                 skip: 0,
                 limit: 50,
+                cursor: None,
             }
         }
     }
@@ -1,6 +1,9 @@
 use crate::{
-    api::resource_group::QueryParams,
-    data_source::{Crud, ResourceGroupCrud, postgres::to_json_value},
+    api::{pagination::Cursor, resource_group::QueryParams},
+    data_source::{
+        Crud, Locked, ResourceGroupCrud,
+        postgres::{PgUpdate, lock, to_json_value},
+    },
     error::AppError,
 };
 use async_trait::async_trait;
@@ -10,7 +13,8 @@ use openleadr_wire::{
     resource_group::{BlResourceGroupRequest, ResourceGroup, ResourceGroupChild, ResourceGroupId},
     target::Target,
 };
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{Acquire, PgConnection, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use tracing::{error, trace, warn};
 
 impl ResourceGroupCrud for PgResourceGroupStorage {}
@@ -67,7 +71,7 @@ impl TryFrom<PostgresResourceGroup> for ResourceGroup {
 }
 
 async fn get_rg_children(
-    tx: &mut Transaction<'_, Postgres>,
+    conn: &mut PgConnection,
     rg_id: &ResourceGroupId,
     client_id: &Option<ClientId>,
 ) -> Result<Vec<ResourceGroupChild>, <PgResourceGroupStorage as Crud>::Error> {
@@ -104,7 +108,7 @@ async fn get_rg_children(
         rg_id.as_str(),
         client_id as _
     )
-    .fetch_all(tx.as_mut())
+    .fetch_all(&mut *conn)
     .await?
     .into_iter()
     .filter_map(|r| {
@@ -125,7 +129,7 @@ async fn get_rg_children(
         rg_id.as_str(),
         client_id as _
     )
-    .fetch_all(tx.as_mut())
+    .fetch_all(&mut *conn)
     .await?
     .into_iter()
     .map(|r| {
@@ -255,49 +259,7 @@ impl Crud for PgResourceGroupStorage {
     ) -> Result<Self::Type, Self::Error> {
         let mut tx = self.db.begin().await?;
 
-        let mut resource_group: ResourceGroup = sqlx::query_as!(
-            PostgresResourceGroup,
-            r#"
-                SELECT
-                    rg.id,
-                    rg.created_date_time,
-                    rg.modification_date_time,
-                    rg.resource_group_name,
-                    rg.attributes,
-                    rg.targets as "targets:Vec<Target>"
-                FROM resource_group rg
-                WHERE rg.id = $1
-
-                AND (
-                    -- If client_id is null, it is a business logic request
-                    $2::text IS NULL
-
-                    -- Otherwise, for a VEN, the resource group should only be visible if there
-                    -- is at least 1 VEN resource (grand) child, with matching client_id.
-                    OR EXISTS (
-                        SELECT r.id FROM resource r
-                        INNER JOIN rg_child_ven_resource rcvr
-                            ON rcvr.rg_child_ven_resource_id = r.id
-                        INNER JOIN rg_family rg_fam
-                            ON rg_fam.id = rcvr.rg_parent_rg_id
-                        WHERE r.ven_id = (SELECT v.id FROM ven v WHERE v.client_id = $2)
-                          AND rg_fam.root = $1
-
-                    )
-                )
-                "#,
-            id.as_str(),
-            client_id as _
-        )
-        .fetch_one(tx.as_mut())
-        .await?
-        .try_into()?;
-
-        resource_group
-            .content
-            .children
-            .extend(get_rg_children(&mut tx, &resource_group.id, client_id).await?);
-
+        let resource_group = Self::fetch(&mut tx, id, client_id).await?;
         tx.commit().await?;
 
         Ok(resource_group)
@@ -309,6 +271,7 @@ impl Crud for PgResourceGroupStorage {
         client_id: &Self::PermissionFilter,
     ) -> Result<Vec<Self::Type>, Self::Error> {
         let mut tx = self.db.begin().await?;
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
 
         let mut rgs = sqlx::query_as!(
             PostgresResourceGroup,
@@ -342,7 +305,9 @@ impl Crud for PgResourceGroupStorage {
                   )
             )
 
-            ORDER BY rg.created_date_time
+            AND ($6::timestamptz IS NULL OR (rg.created_date_time, rg.id) > ($6, $7))
+
+            ORDER BY rg.created_date_time, rg.id
             OFFSET $4 LIMIT $5
             "#,
             filter.resource_group_name,
@@ -350,6 +315,8 @@ impl Crud for PgResourceGroupStorage {
             client_id as _,
             filter.skip,
             filter.limit,
+            after_time,
+            after_id,
         )
         .fetch_all(tx.as_mut())
         .await?
@@ -368,22 +335,62 @@ impl Crud for PgResourceGroupStorage {
         Ok(rgs)
     }
 
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!"
+            FROM resource_group rg
+            WHERE ($1::text IS NULL OR rg.resource_group_name = $1)
+            AND (array_length($2::text[], 1) IS NULL OR rg.targets && $2)
+            AND (
+                $3::text IS NULL
+                  OR EXISTS (
+                      SELECT r.id
+                      FROM resource r
+                      INNER JOIN rg_child_ven_resource AS rcvr
+                          ON rcvr.rg_child_ven_resource_id = r.id
+                      INNER JOIN rg_family AS rg_fam
+                          ON rg_fam.id = rcvr.rg_parent_rg_id
+                      WHERE r.ven_id = (SELECT v.id FROM ven v WHERE v.client_id = $3)
+                        AND rg_fam.root = rg.id
+                  )
+            )
+            "#,
+            filter.resource_group_name,
+            filter.targets.as_deref() as _,
+            client_id as _,
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count as u64)
+    }
+
     async fn update(
         &self,
         id: &Self::Id,
         new: Self::NewType,
-        _client_id: &Self::PermissionFilter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<Self::Type, Self::Error> {
+        Self::update_in(&mut *self.db.acquire().await?, id, new, client_id).await
+    }
+
+    async fn delete(
+        &self,
+        id: &Self::Id,
+        client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
         let mut tx = self.db.begin().await?;
 
+        let children = get_rg_children(&mut tx, id, client_id).await?;
         let mut resource_group: ResourceGroup = sqlx::query_as!(
             PostgresResourceGroup,
             r#"
-            UPDATE resource_group rg
-            SET modification_date_time = now(),
-                resource_group_name = $2,
-                attributes = $3,
-                targets = $4
+            DELETE FROM resource_group rg
             WHERE rg.id = $1
             RETURNING
                 rg.id,
@@ -394,14 +401,13 @@ impl Crud for PgResourceGroupStorage {
                 rg.targets as "targets:Vec<Target>"
             "#,
             id.as_str(),
-            new.resource_group_name,
-            to_json_value(new.attributes)?,
-            new.targets as _,
         )
         .fetch_one(tx.as_mut())
         .await?
         .try_into()?;
 
+        resource_group.content.children.extend(children);
+
         sqlx::query!(
             r#"
             DELETE FROM rg_child_rg rg_child_ven_resource WHERE rg_parent_rg_id = $1
@@ -411,24 +417,62 @@ impl Crud for PgResourceGroupStorage {
         .execute(tx.as_mut())
         .await?;
 
-        insert_resource_group_children(&mut tx, &mut resource_group, &new.children).await?;
         tx.commit().await?;
-
         Ok(resource_group)
     }
 
-    async fn delete(
+    async fn lock(
         &self,
         id: &Self::Id,
-        client_id: &Self::PermissionFilter,
-    ) -> Result<Self::Type, Self::Error> {
-        let mut tx = self.db.begin().await?;
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    > {
+        lock::<Self>(&self.db, id).await
+    }
+}
+
+#[async_trait]
+impl PgUpdate for PgResourceGroupStorage {
+    type Id = ResourceGroupId;
+    type NewType = BlResourceGroupRequest;
+    type Type = ResourceGroup;
+
+    async fn lock_in(conn: &mut PgConnection, id: &ResourceGroupId) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM resource_group WHERE id = $1 FOR UPDATE
+            "#,
+            id.as_str()
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn retrieve_in(
+        conn: &mut PgConnection,
+        id: &ResourceGroupId,
+    ) -> Result<ResourceGroup, AppError> {
+        Self::fetch(conn, id, &None).await
+    }
+
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &ResourceGroupId,
+        new: BlResourceGroupRequest,
+        _client_id: &Option<ClientId>,
+    ) -> Result<ResourceGroup, AppError> {
+        let mut tx = conn.begin().await?;
 
-        let children = get_rg_children(&mut tx, id, client_id).await?;
         let mut resource_group: ResourceGroup = sqlx::query_as!(
             PostgresResourceGroup,
             r#"
-            DELETE FROM resource_group rg
+            UPDATE resource_group rg
+            SET modification_date_time = now(),
+                resource_group_name = $2,
+                attributes = $3,
+                targets = $4
             WHERE rg.id = $1
             RETURNING
                 rg.id,
@@ -439,13 +483,14 @@ impl Crud for PgResourceGroupStorage {
                 rg.targets as "targets:Vec<Target>"
             "#,
             id.as_str(),
+            new.resource_group_name,
+            to_json_value(new.attributes)?,
+            new.targets as _,
         )
         .fetch_one(tx.as_mut())
         .await?
         .try_into()?;
 
-        resource_group.content.children.extend(children);
-
         sqlx::query!(
             r#"
             DELETE FROM rg_child_rg rg_child_ven_resource WHERE rg_parent_rg_id = $1
@@ -455,11 +500,186 @@ impl Crud for PgResourceGroupStorage {
         .execute(tx.as_mut())
         .await?;
 
+        insert_resource_group_children(&mut tx, &mut resource_group, &new.children).await?;
         tx.commit().await?;
+
         Ok(resource_group)
     }
 }
 
+impl PgResourceGroupStorage {
+    async fn fetch(
+        conn: &mut PgConnection,
+        id: &ResourceGroupId,
+        client_id: &Option<ClientId>,
+    ) -> Result<ResourceGroup, AppError> {
+        let mut resource_group: ResourceGroup = sqlx::query_as!(
+            PostgresResourceGroup,
+            r#"
+                SELECT
+                    rg.id,
+                    rg.created_date_time,
+                    rg.modification_date_time,
+                    rg.resource_group_name,
+                    rg.attributes,
+                    rg.targets as "targets:Vec<Target>"
+                FROM resource_group rg
+                WHERE rg.id = $1
+
+                AND (
+                    -- If client_id is null, it is a business logic request
+                    $2::text IS NULL
+
+                    -- Otherwise, for a VEN, the resource group should only be visible if there
+                    -- is at least 1 VEN resource (grand) child, with matching client_id.
+                    OR EXISTS (
+                        SELECT r.id FROM resource r
+                        INNER JOIN rg_child_ven_resource rcvr
+                            ON rcvr.rg_child_ven_resource_id = r.id
+                        INNER JOIN rg_family rg_fam
+                            ON rg_fam.id = rcvr.rg_parent_rg_id
+                        WHERE r.ven_id = (SELECT v.id FROM ven v WHERE v.client_id = $2)
+                          AND rg_fam.root = $1
+
+                    )
+                )
+                "#,
+            id.as_str(),
+            client_id as _
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .try_into()?;
+
+        resource_group
+            .content
+            .children
+            .extend(get_rg_children(conn, &resource_group.id, client_id).await?);
+
+        Ok(resource_group)
+    }
+
+    /// All resource groups with their children, in the order they were created
+    pub(super) async fn export(
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<ResourceGroup>, AppError> {
+        let mut resource_groups: Vec<ResourceGroup> = sqlx::query_as!(
+            PostgresResourceGroup,
+            r#"
+            SELECT id,
+                   created_date_time,
+                   modification_date_time,
+                   resource_group_name,
+                   attributes,
+                   targets AS "targets:Vec<Target>"
+            FROM resource_group
+            ORDER BY created_date_time, id
+            "#,
+        )
+        .fetch_all(tx.as_mut())
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<_, AppError>>()?;
+
+        let mut children: HashMap<String, Vec<ResourceGroupChild>> = HashMap::new();
+        for row in sqlx::query!(
+            r#"
+            SELECT rg_parent_rg_id, rg_child_rg_id
+            FROM rg_child_rg
+            ORDER BY rg_parent_rg_id, rg_child_rg_id
+            "#
+        )
+        .fetch_all(tx.as_mut())
+        .await?
+        {
+            children.entry(row.rg_parent_rg_id).or_default().push(
+                ResourceGroupChild::ResourceGroup(row.rg_child_rg_id.parse()?),
+            );
+        }
+        for row in sqlx::query!(
+            r#"
+            SELECT rg_parent_rg_id, rg_child_ven_resource_id
+            FROM rg_child_ven_resource
+            ORDER BY rg_parent_rg_id, rg_child_ven_resource_id
+            "#
+        )
+        .fetch_all(tx.as_mut())
+        .await?
+        {
+            children
+                .entry(row.rg_parent_rg_id)
+                .or_default()
+                .push(ResourceGroupChild::VenResource(
+                    row.rg_child_ven_resource_id.parse()?,
+                ));
+        }
+
+        for resource_group in &mut resource_groups {
+            if let Some(children) = children.remove(resource_group.id.as_str()) {
+                resource_group.content.children = children;
+            }
+        }
+        Ok(resource_groups)
+    }
+
+    /// Inserts the resource groups with their IDs and timestamps,
+    /// or replaces the resource groups with the same IDs, including their children.
+    /// All groups are inserted before their children, as groups can be children of each other.
+    pub(super) async fn import(
+        tx: &mut Transaction<'_, Postgres>,
+        resource_groups: &[ResourceGroup],
+    ) -> Result<(), AppError> {
+        for resource_group in resource_groups {
+            sqlx::query!(
+                r#"
+                INSERT INTO resource_group (id, created_date_time, modification_date_time, resource_group_name, attributes, targets)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (id) DO UPDATE
+                SET created_date_time = excluded.created_date_time,
+                    modification_date_time = excluded.modification_date_time,
+                    resource_group_name = excluded.resource_group_name,
+                    attributes = excluded.attributes,
+                    targets = excluded.targets
+                "#,
+                resource_group.id.as_str(),
+                resource_group.created_date_time,
+                resource_group.modification_date_time,
+                resource_group.content.resource_group_name,
+                to_json_value(resource_group.content.attributes.as_ref())?,
+                resource_group.content.targets.as_slice() as &[Target],
+            )
+            .execute(tx.as_mut())
+            .await?;
+        }
+
+        let ids: Vec<_> = resource_groups
+            .iter()
+            .map(|resource_group| resource_group.id.to_string())
+            .collect();
+        sqlx::query!(
+            "DELETE FROM rg_child_rg WHERE rg_parent_rg_id = ANY($1)",
+            &ids
+        )
+        .execute(tx.as_mut())
+        .await?;
+        sqlx::query!(
+            "DELETE FROM rg_child_ven_resource WHERE rg_parent_rg_id = ANY($1)",
+            &ids
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        for resource_group in resource_groups {
+            let mut inserted = resource_group.clone();
+            inserted.content.children.clear();
+            insert_resource_group_children(tx, &mut inserted, &resource_group.content.children)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "live-db-test")]
 mod test {
@@ -476,6 +696,7 @@ mod test {
                 targets: TargetQueryParams(None),
                 skip: 0,
                 limit: 50,
+                cursor: None,
             }
         }
     }
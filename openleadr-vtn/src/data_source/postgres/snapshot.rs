@@ -0,0 +1,189 @@
+use crate::{
+    data_source::{
+        Snapshot, SnapshotCredential, SnapshotSource, SnapshotUser,
+        postgres::{
+            event::PgEventStorage, program::PgProgramStorage, report::PgReportStorage,
+            resource::PgResourceStorage, resource_group::PgResourceGroupStorage,
+            subscription::PgSubscriptionStorage, ven::PgVenStorage,
+        },
+    },
+    error::AppError,
+};
+use async_trait::async_trait;
+use openleadr_wire::ClientId;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+pub(crate) struct PgSnapshotStorage {
+    db: PgPool,
+}
+
+impl From<PgPool> for PgSnapshotStorage {
+    fn from(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SnapshotSource for PgSnapshotStorage {
+    async fn export(&self) -> Result<Snapshot, AppError> {
+        let mut tx = self.db.begin().await?;
+        // all tables are read from the same snapshot of the database
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(tx.as_mut())
+            .await?;
+
+        let snapshot = Snapshot {
+            users: export_users(&mut tx).await?,
+            programs: PgProgramStorage::export(&mut tx).await?,
+            events: PgEventStorage::export(&mut tx).await?,
+            reports: PgReportStorage::export(&mut tx).await?,
+            vens: PgVenStorage::export(&mut tx).await?,
+            resources: PgResourceStorage::export(&mut tx).await?,
+            resource_groups: PgResourceGroupStorage::export(&mut tx).await?,
+            subscriptions: PgSubscriptionStorage::export(&mut tx).await?,
+        };
+        tx.commit().await?;
+
+        Ok(snapshot)
+    }
+
+    async fn import(&self, snapshot: &Snapshot) -> Result<(), AppError> {
+        let mut tx = self.db.begin().await?;
+
+        for user in &snapshot.users {
+            import_user(&mut tx, user).await?;
+        }
+        for program in &snapshot.programs {
+            PgProgramStorage::import(&mut tx, program).await?;
+        }
+        for event in &snapshot.events {
+            PgEventStorage::import(&mut tx, event).await?;
+        }
+        for report in &snapshot.reports {
+            PgReportStorage::import(&mut tx, report).await?;
+        }
+        for ven in &snapshot.vens {
+            PgVenStorage::import(&mut tx, ven).await?;
+        }
+        for resource in &snapshot.resources {
+            PgResourceStorage::import(&mut tx, resource).await?;
+        }
+        PgResourceGroupStorage::import(&mut tx, &snapshot.resource_groups).await?;
+        for subscription in &snapshot.subscriptions {
+            PgSubscriptionStorage::import(&mut tx, subscription).await?;
+        }
+
+        tx.commit().await?;
+        info!(counts = ?snapshot.object_counts(), "imported snapshot");
+
+        Ok(())
+    }
+}
+
+/// The users of the internal OAuth provider, in the order they were created
+async fn export_users(tx: &mut Transaction<'_, Postgres>) -> Result<Vec<SnapshotUser>, AppError> {
+    let mut credentials: HashMap<String, Vec<SnapshotCredential>> = HashMap::new();
+    for row in sqlx::query!(
+        r#"
+        SELECT user_id,
+               client_id AS "client_id: ClientId",
+               client_secret
+        FROM user_credentials
+        ORDER BY user_id, client_id
+        "#
+    )
+    .fetch_all(tx.as_mut())
+    .await?
+    {
+        credentials
+            .entry(row.user_id)
+            .or_default()
+            .push(SnapshotCredential {
+                client_id: row.client_id,
+                client_secret_hash: Some(row.client_secret),
+            });
+    }
+
+    Ok(sqlx::query!(
+        r#"
+        SELECT id,
+               reference,
+               description,
+               created,
+               modified,
+               scopes::text[] AS "scopes!"
+        FROM "user"
+        ORDER BY created, id
+        "#
+    )
+    .fetch_all(tx.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| SnapshotUser {
+        credentials: credentials.remove(&row.id).unwrap_or_default(),
+        id: row.id,
+        reference: row.reference,
+        description: row.description,
+        scopes: row.scopes,
+        created: row.created,
+        modified: row.modified,
+    })
+    .collect())
+}
+
+/// Inserts the user with its ID and timestamps, or replaces the user with the same ID.
+/// Credentials without a secret are skipped, other credentials of the user are kept.
+async fn import_user(
+    tx: &mut Transaction<'_, Postgres>,
+    user: &SnapshotUser,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO "user" (id, reference, description, created, modified, scopes)
+        VALUES ($1, $2, $3, $4, $5, $6::text[]::scope[])
+        ON CONFLICT (id) DO UPDATE
+        SET reference = excluded.reference,
+            description = excluded.description,
+            created = excluded.created,
+            modified = excluded.modified,
+            scopes = excluded.scopes
+        "#,
+        user.id,
+        user.reference,
+        user.description,
+        user.created,
+        user.modified,
+        &user.scopes,
+    )
+    .execute(tx.as_mut())
+    .await?;
+
+    for credential in &user.credentials {
+        let Some(client_secret_hash) = &credential.client_secret_hash else {
+            warn!(
+                user_id = user.id,
+                client_id = %credential.client_id,
+                "skipping credentials without secret"
+            );
+            continue;
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO user_credentials (user_id, client_id, client_secret)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (client_id) DO UPDATE
+            SET user_id = excluded.user_id,
+                client_secret = excluded.client_secret
+            "#,
+            user.id,
+            credential.client_id.as_str(),
+            client_secret_hash,
+        )
+        .execute(tx.as_mut())
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use crate::{
+    data_source::{ObjectCounts, PoolState, SchemaVersion, StatisticsSource, postgres::MIGRATOR},
+    error::AppError,
+};
+use async_trait::async_trait;
+use sqlx::{Connection, PgPool, migrate::Migrate};
+
+pub(crate) struct PgStatisticsStorage {
+    db: PgPool,
+}
+
+impl From<PgPool> for PgStatisticsStorage {
+    fn from(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl StatisticsSource for PgStatisticsStorage {
+    async fn object_counts(&self) -> Result<ObjectCounts, AppError> {
+        Ok(sqlx::query_as!(
+            ObjectCounts,
+            r#"
+            SELECT
+                (SELECT count(*) FROM program) AS "programs!",
+                (SELECT count(*) FROM event) AS "events!",
+                (SELECT count(*) FROM report) AS "reports!",
+                (SELECT count(*) FROM ven) AS "vens!",
+                (SELECT count(*) FROM resource) AS "resources!",
+                (SELECT count(*) FROM resource_group) AS "resource_groups!",
+                (SELECT count(*) FROM subscription) AS "subscriptions!"
+            "#
+        )
+        .fetch_one(&self.db)
+        .await?)
+    }
+
+    fn pool_state(&self) -> PoolState {
+        PoolState {
+            size: self.db.size(),
+            idle: self.db.num_idle(),
+            max_size: self.db.options().get_max_connections(),
+        }
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        Ok(self.db.acquire().await?.ping().await?)
+    }
+
+    async fn schema_version(&self) -> Result<SchemaVersion, AppError> {
+        let mut conn = self.db.acquire().await?;
+        // the table is only created by the first migration run
+        let initialized: bool =
+            sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+                .fetch_one(&mut *conn)
+                .await?;
+        let (applied, failed) = if initialized {
+            (
+                conn.list_applied_migrations()
+                    .await
+                    .map_err(sqlx::Error::from)?,
+                conn.dirty_version().await.map_err(sqlx::Error::from)?,
+            )
+        } else {
+            (Vec::new(), None)
+        };
+
+        let mut pending = Vec::new();
+        let mut modified = Vec::new();
+        for migration in MIGRATOR
+            .iter()
+            .filter(|migration| migration.migration_type.is_up_migration())
+        {
+            match applied.iter().find(|a| a.version == migration.version) {
+                None => pending.push(migration.version),
+                Some(a) if a.checksum != migration.checksum => modified.push(migration.version),
+                Some(_) => {}
+            }
+        }
+
+        Ok(SchemaVersion {
+            applied: applied.iter().map(|migration| migration.version).max(),
+            latest: MIGRATOR
+                .iter()
+                .map(|migration| migration.version)
+                .max()
+                .unwrap_or_default(),
+            pending,
+            modified,
+            failed,
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "live-db-test")]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(fixtures("programs", "events"))]
+    async fn object_counts(db: PgPool) {
+        let repo: PgStatisticsStorage = db.into();
+        let counts = repo.object_counts().await.unwrap();
+        assert_eq!(
+            counts,
+            ObjectCounts {
+                programs: 3,
+                events: 5,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[sqlx::test]
+    async fn schema_version(db: PgPool) {
+        let repo: PgStatisticsStorage = db.clone().into();
+        repo.ping().await.unwrap();
+
+        let version = repo.schema_version().await.unwrap();
+        assert_eq!(version.applied, Some(version.latest));
+        assert_eq!(version.incompatibility(), None);
+
+        sqlx::query("UPDATE _sqlx_migrations SET checksum = '\\x00' WHERE version = $1")
+            .bind(version.latest)
+            .execute(&db)
+            .await
+            .unwrap();
+        let modified = repo.schema_version().await.unwrap();
+        assert_eq!(modified.modified, vec![version.latest]);
+        assert!(!modified.is_current());
+
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+            .bind(version.latest)
+            .execute(&db)
+            .await
+            .unwrap();
+        let pending = repo.schema_version().await.unwrap();
+        assert_eq!(pending.pending, vec![version.latest]);
+        assert_eq!(
+            pending.incompatibility(),
+            Some(format!("migration {} is pending", version.latest))
+        );
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn schema_version_without_migrations(db: PgPool) {
+        let repo: PgStatisticsStorage = db.into();
+        let version = repo.schema_version().await.unwrap();
+        assert_eq!(version.applied, None);
+        assert_eq!(version.pending.len(), MIGRATOR.iter().count());
+        assert!(!version.is_current());
+    }
+}
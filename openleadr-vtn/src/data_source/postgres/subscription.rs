@@ -1,19 +1,22 @@
 use crate::{
-    api::subscription::QueryParams,
-    data_source::{Crud, SubscriptionCrud},
+    api::{pagination::Cursor, subscription::QueryParams},
+    data_source::{
+        Crud, Locked, SnapshotSubscription, SubscriptionCrud,
+        postgres::{PgUpdate, lock},
+    },
     error::AppError,
+    jwt::Scope,
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use openleadr_wire::{
-    ClientId,
+    ClientId, Duration,
     subscription::{Subscription, SubscriptionId, SubscriptionRequest},
 };
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use tracing::{error, trace, warn};
 
-impl SubscriptionCrud for PgSubscriptionStorage {}
-
 pub(crate) struct PgSubscriptionStorage {
     db: PgPool,
 }
@@ -33,6 +36,9 @@ pub(crate) struct PostgresSubscription {
     client_name: String,
     program_id: Option<String>,
     object_operations: serde_json::Value,
+    time_to_live: Option<String>,
+    expires_date_time: Option<DateTime<Utc>>,
+    batch_window: Option<String>,
     //targets: Vec<Target>,
 }
 
@@ -55,6 +61,7 @@ impl TryFrom<PostgresSubscription> for Subscription {
             created_date_time: value.created_date_time,
             modification_date_time: value.modification_date_time,
             client_id: value.client_id.parse()?,
+            expires_date_time: value.expires_date_time,
             content: SubscriptionRequest {
                 client_name: value.client_name,
                 program_id: value
@@ -62,6 +69,28 @@ impl TryFrom<PostgresSubscription> for Subscription {
                     .map(|program_id| program_id.parse())
                     .transpose()?,
                 object_operations,
+                time_to_live: value
+                    .time_to_live
+                    .map(|time_to_live| time_to_live.parse())
+                    .transpose()
+                    .inspect_err(|err| {
+                        error!(
+                            ?err,
+                            "Failed to parse the time to live of a subscription from DB"
+                        )
+                    })
+                    .map_err(|_| AppError::BadRequest("Invalid time to live"))?,
+                batch_window: value
+                    .batch_window
+                    .map(|batch_window| batch_window.parse())
+                    .transpose()
+                    .inspect_err(|err| {
+                        error!(
+                            ?err,
+                            "Failed to parse the batch window of a subscription from DB"
+                        )
+                    })
+                    .map_err(|_| AppError::BadRequest("Invalid batch window"))?,
             },
         })
     }
@@ -81,41 +110,14 @@ impl Crud for PgSubscriptionStorage {
         new: Self::NewType,
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let subscription: Subscription = sqlx::query_as!(
-            PostgresSubscription,
-            r#"
-            INSERT INTO subscription (
-                id,
-                created_date_time,
-                modification_date_time,
-                client_id,
-                client_name,
-                program_id,
-                object_operations
-            )
-            VALUES (gen_random_uuid(), now(), now(), $1, $2::text, $3, $4)
-            RETURNING
-                id,
-                created_date_time,
-                modification_date_time,
-                client_id,
-                client_name,
-                program_id,
-                object_operations
-            "#,
+        self.create_with_owner_scopes(
+            new,
             client_id
                 .as_ref()
-                .expect("subscription create requires client id")
-                .as_str(),
-            new.client_name,
-            new.program_id.as_ref().map(|id| id.as_str()),
-            serde_json::to_value(new.object_operations).map_err(AppError::SerdeJsonBadRequest)?,
+                .expect("subscription create requires client id"),
+            &[],
         )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
-
-        Ok(subscription)
+        .await
     }
 
     async fn retrieve(
@@ -123,29 +125,7 @@ impl Crud for PgSubscriptionStorage {
         id: &Self::Id,
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let subscription = sqlx::query_as!(
-            PostgresSubscription,
-            r#"
-            SELECT
-                id,
-                created_date_time,
-                modification_date_time,
-                client_id,
-                client_name,
-                program_id,
-                object_operations
-            FROM subscription
-            WHERE id = $1
-              AND ($2::text IS NULL OR client_id = $2)
-            "#,
-            id.as_str(),
-            client_id as _
-        )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
-
-        Ok(subscription)
+        Self::fetch(&mut *self.db.acquire().await?, id, client_id).await
     }
 
     async fn retrieve_all(
@@ -153,6 +133,7 @@ impl Crud for PgSubscriptionStorage {
         filter: &Self::Filter,
         client_id: &Self::PermissionFilter,
     ) -> Result<Vec<Self::Type>, Self::Error> {
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
         let res = sqlx::query_as!(
             PostgresSubscription,
             r#"
@@ -163,7 +144,10 @@ impl Crud for PgSubscriptionStorage {
                 client_id,
                 client_name,
                 program_id,
-                object_operations
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
             FROM subscription
             WHERE ($1::text IS NULL OR client_id = $1)
               AND ($2::text IS NULL OR client_name = $2)
@@ -173,7 +157,8 @@ impl Crud for PgSubscriptionStorage {
                     '$[*].objects[*] ? (@ == $obj)',
                     jsonb_build_object('obj', $4)
                   ))
-            ORDER BY created_date_time
+              AND ($7::timestamptz IS NULL OR (created_date_time, id) > ($7, $8))
+            ORDER BY created_date_time, id
             OFFSET $5 LIMIT $6
             "#,
             client_id as _,
@@ -186,6 +171,8 @@ impl Crud for PgSubscriptionStorage {
             filter.objects.as_ref().map(|objects| objects[0].as_str()),
             filter.skip,
             filter.limit,
+            after_time,
+            after_id,
         )
         .fetch_all(&self.db)
         .await?
@@ -198,12 +185,120 @@ impl Crud for PgSubscriptionStorage {
         Ok(res)
     }
 
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!"
+            FROM subscription
+            WHERE ($1::text IS NULL OR client_id = $1)
+              AND ($2::text IS NULL OR client_name = $2)
+              AND ($3::text IS NULL OR program_id = $3 OR program_id IS NULL)
+              AND ($4::text IS NULL OR jsonb_path_exists(
+                    object_operations,
+                    '$[*].objects[*] ? (@ == $obj)',
+                    jsonb_build_object('obj', $4)
+                  ))
+            "#,
+            client_id as _,
+            filter.client_name,
+            filter
+                .program_id
+                .as_ref()
+                .map(|program_id| program_id.as_str()),
+            filter.objects.as_ref().map(|objects| objects[0].as_str()),
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count as u64)
+    }
+
     async fn update(
         &self,
         id: &Self::Id,
         new: Self::NewType,
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
+        Self::update_in(&mut *self.db.acquire().await?, id, new, client_id).await
+    }
+
+    async fn delete(
+        &self,
+        id: &Self::Id,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<Self::Type, Self::Error> {
+        Ok(sqlx::query_as!(
+            PostgresSubscription,
+            r#"
+            DELETE FROM subscription
+            WHERE id = $1
+              AND ($2::text IS NULL OR client_id = $2)
+            RETURNING
+                id,
+                created_date_time,
+                modification_date_time,
+                client_id,
+                client_name,
+                program_id,
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
+            "#,
+            id.as_str(),
+            client_id as _
+        )
+        .fetch_one(&self.db)
+        .await?
+        .try_into()?)
+    }
+
+    async fn lock(
+        &self,
+        id: &Self::Id,
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    > {
+        lock::<Self>(&self.db, id).await
+    }
+}
+
+#[async_trait]
+impl PgUpdate for PgSubscriptionStorage {
+    type Id = SubscriptionId;
+    type NewType = SubscriptionRequest;
+    type Type = Subscription;
+
+    async fn lock_in(conn: &mut PgConnection, id: &SubscriptionId) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM subscription WHERE id = $1 FOR UPDATE
+            "#,
+            id.as_str()
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn retrieve_in(
+        conn: &mut PgConnection,
+        id: &SubscriptionId,
+    ) -> Result<Subscription, AppError> {
+        Self::fetch(conn, id, &None).await
+    }
+
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &SubscriptionId,
+        new: SubscriptionRequest,
+        client_id: &Option<ClientId>,
+    ) -> Result<Subscription, AppError> {
         let subscription: Subscription = sqlx::query_as!(
             PostgresSubscription,
             r#"
@@ -211,7 +306,10 @@ impl Crud for PgSubscriptionStorage {
             SET modification_date_time = now(),
                 client_name = $2,
                 program_id = $3,
-                object_operations = $4
+                object_operations = $4,
+                time_to_live = $6,
+                expires_date_time = $7,
+                batch_window = $8
             WHERE id = $1
               AND ($5::text IS NULL OR client_id = $5)
             RETURNING
@@ -221,13 +319,73 @@ impl Crud for PgSubscriptionStorage {
                 client_id,
                 client_name,
                 program_id,
-                object_operations
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
             "#,
             id.as_str(),
             new.client_name,
             new.program_id.as_ref().map(|id| id.as_str()),
             serde_json::to_value(&new.object_operations).map_err(AppError::SerdeJsonBadRequest)?,
-            client_id as _
+            client_id as _,
+            new.time_to_live.as_ref().map(|ttl| ttl.to_string()),
+            expires_date_time(new.time_to_live.as_ref()),
+            new.batch_window.as_ref().map(|window| window.to_string()),
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .try_into()?;
+
+        Ok(subscription)
+    }
+}
+
+#[async_trait]
+impl SubscriptionCrud for PgSubscriptionStorage {
+    async fn create_with_owner_scopes(
+        &self,
+        new: SubscriptionRequest,
+        client_id: &ClientId,
+        owner_scopes: &[Scope],
+    ) -> Result<Subscription, AppError> {
+        let subscription: Subscription = sqlx::query_as!(
+            PostgresSubscription,
+            r#"
+            INSERT INTO subscription (
+                id,
+                created_date_time,
+                modification_date_time,
+                client_id,
+                client_name,
+                program_id,
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window,
+                owner_scopes
+            )
+            VALUES (gen_random_uuid(), now(), now(), $1, $2::text, $3, $4, $5, $6, $7, $8)
+            RETURNING
+                id,
+                created_date_time,
+                modification_date_time,
+                client_id,
+                client_name,
+                program_id,
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
+            "#,
+            client_id.as_str(),
+            new.client_name,
+            new.program_id.as_ref().map(|id| id.as_str()),
+            serde_json::to_value(&new.object_operations).map_err(AppError::SerdeJsonBadRequest)?,
+            new.time_to_live.as_ref().map(|ttl| ttl.to_string()),
+            expires_date_time(new.time_to_live.as_ref()),
+            new.batch_window.as_ref().map(|window| window.to_string()),
+            owner_scopes as _,
         )
         .fetch_one(&self.db)
         .await?
@@ -236,17 +394,33 @@ impl Crud for PgSubscriptionStorage {
         Ok(subscription)
     }
 
-    async fn delete(
+    async fn owner_scopes(&self) -> Result<HashMap<SubscriptionId, Vec<Scope>>, AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id, owner_scopes AS "owner_scopes: Vec<Scope>"
+            FROM subscription
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| Ok((row.id.parse()?, row.owner_scopes)))
+        .collect()
+    }
+
+    async fn renew(
         &self,
-        id: &Self::Id,
-        client_id: &Self::PermissionFilter,
-    ) -> Result<Self::Type, Self::Error> {
+        id: &SubscriptionId,
+        client_id: &Option<ClientId>,
+    ) -> Result<Subscription, AppError> {
+        let subscription = self.retrieve(id, client_id).await?;
+
         Ok(sqlx::query_as!(
             PostgresSubscription,
             r#"
-            DELETE FROM subscription
+            UPDATE subscription
+            SET expires_date_time = $2
             WHERE id = $1
-              AND ($2::text IS NULL OR client_id = $2)
             RETURNING
                 id,
                 created_date_time,
@@ -254,15 +428,246 @@ impl Crud for PgSubscriptionStorage {
                 client_id,
                 client_name,
                 program_id,
-                object_operations
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
             "#,
             id.as_str(),
-            client_id as _
+            expires_date_time(subscription.content.time_to_live.as_ref()),
         )
         .fetch_one(&self.db)
         .await?
         .try_into()?)
     }
+
+    async fn delete_by_client_id(
+        &self,
+        client_id: &ClientId,
+    ) -> Result<Vec<Subscription>, AppError> {
+        Self::delete_by_client_id_in(&mut *self.db.acquire().await?, client_id).await
+    }
+
+    async fn delete_expired(&self) -> Result<Vec<Subscription>, AppError> {
+        let subscriptions = sqlx::query_as!(
+            PostgresSubscription,
+            r#"
+            DELETE FROM subscription
+            WHERE expires_date_time <= now()
+            RETURNING
+                id,
+                created_date_time,
+                modification_date_time,
+                client_id,
+                client_name,
+                program_id,
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>, _>>()?;
+
+        trace!("deleted {} expired subscriptions", subscriptions.len());
+
+        Ok(subscriptions)
+    }
+}
+
+/// The expiry of a subscription with the given time to live that is created, updated or renewed now
+fn expires_date_time(time_to_live: Option<&Duration>) -> Option<DateTime<Utc>> {
+    let now = Utc::now();
+    time_to_live.map(|time_to_live| {
+        now.checked_add_signed(time_to_live.to_chrono_at_datetime(now))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    })
+}
+
+impl PgSubscriptionStorage {
+    async fn fetch(
+        conn: &mut PgConnection,
+        id: &SubscriptionId,
+        client_id: &Option<ClientId>,
+    ) -> Result<Subscription, AppError> {
+        let subscription = sqlx::query_as!(
+            PostgresSubscription,
+            r#"
+            SELECT
+                id,
+                created_date_time,
+                modification_date_time,
+                client_id,
+                client_name,
+                program_id,
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
+            FROM subscription
+            WHERE id = $1
+              AND ($2::text IS NULL OR client_id = $2)
+            "#,
+            id.as_str(),
+            client_id as _
+        )
+        .fetch_one(conn)
+        .await?
+        .try_into()?;
+
+        Ok(subscription)
+    }
+
+    /// Deletes all subscriptions of a client and returns them
+    pub(super) async fn delete_by_client_id_in(
+        conn: &mut PgConnection,
+        client_id: &ClientId,
+    ) -> Result<Vec<Subscription>, AppError> {
+        let subscriptions = sqlx::query_as!(
+            PostgresSubscription,
+            r#"
+            DELETE FROM subscription
+            WHERE client_id = $1
+            RETURNING
+                id,
+                created_date_time,
+                modification_date_time,
+                client_id,
+                client_name,
+                program_id,
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window
+            "#,
+            client_id.as_str(),
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>, _>>()?;
+
+        trace!(%client_id, "deleted {} subscriptions of client", subscriptions.len());
+
+        Ok(subscriptions)
+    }
+
+    /// All subscriptions with their owner scopes, in the order they were created
+    pub(super) async fn export(
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<SnapshotSubscription>, AppError> {
+        let mut owner_scopes: HashMap<String, Vec<String>> = sqlx::query!(
+            r#"
+            SELECT id, owner_scopes::text[] AS "owner_scopes!"
+            FROM subscription
+            "#,
+        )
+        .fetch_all(tx.as_mut())
+        .await?
+        .into_iter()
+        .map(|row| (row.id, row.owner_scopes))
+        .collect();
+
+        sqlx::query_as!(
+            PostgresSubscription,
+            r#"
+            SELECT id,
+                   created_date_time,
+                   modification_date_time,
+                   client_id,
+                   client_name,
+                   program_id,
+                   object_operations,
+                   time_to_live,
+                   expires_date_time,
+                   batch_window
+            FROM subscription
+            ORDER BY created_date_time, id
+            "#,
+        )
+        .fetch_all(tx.as_mut())
+        .await?
+        .into_iter()
+        .map(|row| {
+            let owner_scopes = owner_scopes.remove(&row.id);
+            Ok(SnapshotSubscription {
+                subscription: row.try_into()?,
+                owner_scopes,
+            })
+        })
+        .collect()
+    }
+
+    /// Inserts the subscription with its ID and timestamps,
+    /// or replaces the subscription with the same ID
+    pub(super) async fn import(
+        tx: &mut Transaction<'_, Postgres>,
+        snapshot_subscription: &SnapshotSubscription,
+    ) -> Result<(), AppError> {
+        let subscription = &snapshot_subscription.subscription;
+        sqlx::query!(
+            r#"
+            INSERT INTO subscription (
+                id,
+                created_date_time,
+                modification_date_time,
+                client_id,
+                client_name,
+                program_id,
+                object_operations,
+                time_to_live,
+                expires_date_time,
+                batch_window,
+                owner_scopes
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                    coalesce($11::text[]::scope[], '{read_targets,read_ven_objects}'))
+            ON CONFLICT (id) DO UPDATE
+            SET created_date_time = excluded.created_date_time,
+                modification_date_time = excluded.modification_date_time,
+                client_id = excluded.client_id,
+                client_name = excluded.client_name,
+                program_id = excluded.program_id,
+                object_operations = excluded.object_operations,
+                time_to_live = excluded.time_to_live,
+                expires_date_time = excluded.expires_date_time,
+                batch_window = excluded.batch_window,
+                owner_scopes = excluded.owner_scopes
+            "#,
+            subscription.id.as_str(),
+            subscription.created_date_time,
+            subscription.modification_date_time,
+            subscription.client_id.as_str(),
+            subscription.content.client_name,
+            subscription
+                .content
+                .program_id
+                .as_ref()
+                .map(|id| id.as_str()),
+            serde_json::to_value(&subscription.content.object_operations)
+                .map_err(AppError::SerdeJsonBadRequest)?,
+            subscription
+                .content
+                .time_to_live
+                .as_ref()
+                .map(|ttl| ttl.to_string()),
+            subscription.expires_date_time,
+            subscription
+                .content
+                .batch_window
+                .as_ref()
+                .map(|window| window.to_string()),
+            snapshot_subscription.owner_scopes.as_deref(),
+        )
+        .execute(tx.as_mut())
+        .await?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -270,8 +675,9 @@ impl Crud for PgSubscriptionStorage {
 mod test {
     use crate::{
         api::subscription::QueryParams,
-        data_source::{Crud, postgres::subscription::PgSubscriptionStorage},
+        data_source::{Crud, SubscriptionCrud, postgres::subscription::PgSubscriptionStorage},
     };
+    use chrono::Utc;
     use sqlx::PgPool;
 
     impl Default for QueryParams {
@@ -282,6 +688,7 @@ mod test {
                 objects: None,
                 skip: 0,
                 limit: 50,
+                cursor: None,
             }
         }
     }
@@ -309,4 +716,83 @@ mod test {
             .unwrap();
         assert_eq!(subscription.len(), 0);
     }
+
+    #[sqlx::test(fixtures("users", "vens", "subscriptions"))]
+    async fn delete_expired(db: PgPool) {
+        let repo = PgSubscriptionStorage::from(db.clone());
+
+        sqlx::query(
+            "UPDATE subscription SET time_to_live = 'PT1H', expires_date_time = now() - interval '1 minute' WHERE id = 'subscription-1'",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let deleted = repo.delete_expired().await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].id.as_str(), "subscription-1");
+
+        assert!(repo.delete_expired().await.unwrap().is_empty());
+        let remaining = repo
+            .retrieve_all(&QueryParams::default(), &None)
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+    }
+
+    #[sqlx::test(fixtures("users", "vens", "subscriptions"))]
+    async fn renew(db: PgPool) {
+        let repo = PgSubscriptionStorage::from(db.clone());
+
+        sqlx::query(
+            "UPDATE subscription SET time_to_live = 'PT1H', expires_date_time = now() - interval '1 minute' WHERE id = 'subscription-1'",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let id = "subscription-1".parse().unwrap();
+        assert!(
+            repo.renew(&id, &Some("ven-2-client-id".parse().unwrap()))
+                .await
+                .is_err()
+        );
+
+        let renewed = repo
+            .renew(&id, &Some("ven-1-client-id".parse().unwrap()))
+            .await
+            .unwrap();
+        assert!(renewed.expires_date_time.unwrap() > Utc::now() + chrono::Duration::minutes(59));
+        assert!(repo.delete_expired().await.unwrap().is_empty());
+
+        // subscriptions without time to live never expire
+        let renewed = repo
+            .renew(&"subscription-2".parse().unwrap(), &None)
+            .await
+            .unwrap();
+        assert_eq!(renewed.expires_date_time, None);
+    }
+
+    #[sqlx::test(fixtures("users", "vens", "subscriptions"))]
+    async fn delete_by_client_id(db: PgPool) {
+        let repo = PgSubscriptionStorage::from(db.clone());
+
+        let deleted = repo
+            .delete_by_client_id(&"ven-2-client-id".parse().unwrap())
+            .await
+            .unwrap();
+        assert!(deleted.is_empty());
+
+        let deleted = repo
+            .delete_by_client_id(&"ven-1-client-id".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(deleted.len(), 2);
+        assert!(
+            repo.retrieve_all(&QueryParams::default(), &None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
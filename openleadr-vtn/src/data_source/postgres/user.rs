@@ -1,5 +1,7 @@
 use crate::{
-    data_source::{AuthInfo, AuthSource, UserDetails},
+    data_source::{
+        AuthInfo, AuthSource, UserDetails, postgres::subscription::PgSubscriptionStorage,
+    },
     error::AppError,
     jwt::Scope,
 };
@@ -8,7 +10,7 @@ use argon2::{
     password_hash::{SaltString, rand_core::OsRng},
 };
 use async_trait::async_trait;
-use openleadr_wire::ClientId;
+use openleadr_wire::{ClientId, subscription::Subscription};
 use sqlx::{Executor, PgPool, Postgres};
 use tracing::warn;
 
@@ -140,9 +142,9 @@ impl AuthSource for PgAuthSource {
         &self,
         user_id: &str,
         client_id: &str,
-    ) -> Result<UserDetails, AppError> {
+    ) -> Result<(UserDetails, Vec<Subscription>), AppError> {
         let mut tx = self.db.begin().await?;
-        sqlx::query!(
+        let removed = sqlx::query!(
             r#"
             DELETE FROM user_credentials WHERE user_id = $1 AND client_id = $2
             "#,
@@ -150,24 +152,42 @@ impl AuthSource for PgAuthSource {
             client_id
         )
         .execute(&mut *tx)
-        .await?;
+        .await?
+        .rows_affected();
         let user = Self::get_user(&mut *tx, user_id).await?;
+        // subscriptions of a removed credential can never be read, renewed, or deleted again
+        let subscriptions = if removed > 0 {
+            PgSubscriptionStorage::delete_by_client_id_in(&mut tx, &client_id.parse()?).await?
+        } else {
+            vec![]
+        };
         tx.commit().await?;
-        Ok(user)
+
+        Ok((user, subscriptions))
     }
 
-    async fn remove_user(&self, user_id: &str) -> Result<UserDetails, AppError> {
-        let user = Self::get_user(&self.db, user_id).await?;
+    async fn remove_user(
+        &self,
+        user_id: &str,
+    ) -> Result<(UserDetails, Vec<Subscription>), AppError> {
+        let mut tx = self.db.begin().await?;
+        let user = Self::get_user(&mut *tx, user_id).await?;
         sqlx::query!(
             r#"
             DELETE FROM "user" WHERE id = $1
             "#,
             user_id
         )
-        .execute(&self.db)
+        .execute(&mut *tx)
         .await?;
+        let mut subscriptions = vec![];
+        for client_id in &user.client_ids {
+            subscriptions
+                .extend(PgSubscriptionStorage::delete_by_client_id_in(&mut tx, client_id).await?);
+        }
+        tx.commit().await?;
 
-        Ok(user)
+        Ok((user, subscriptions))
     }
 
     async fn edit_user(
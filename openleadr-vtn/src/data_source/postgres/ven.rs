@@ -1,21 +1,75 @@
 use crate::{
-    api::ven::QueryParams,
-    data_source::{Crud, VenCrud, VenObjectPrivacy, postgres::to_json_value},
+    api::{pagination::Cursor, ven::QueryParams},
+    data_source::{
+        Crud, Locked, VenCrud, VenObjectPrivacy, VenVisibility,
+        postgres::{
+            AttributeQuery, PgBatch, PgUpdate, batch_in, lock, subscription::PgSubscriptionStorage,
+            to_json_value,
+        },
+    },
     error::AppError,
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use openleadr_wire::{
     ClientId,
+    batch::BatchOperation,
     resource_group::ResourceGroupId,
+    subscription::Subscription,
     target::Target,
     ven::{BlVenRequest, Ven, VenId},
 };
-use sqlx::PgPool;
-use std::collections::HashSet;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
 use tracing::{error, trace, warn};
 
-impl VenCrud for PgVenStorage {}
+#[async_trait]
+impl VenCrud for PgVenStorage {
+    async fn batch(
+        &self,
+        operations: Vec<BatchOperation<VenId, BlVenRequest>>,
+        client_id: &Option<ClientId>,
+    ) -> Result<(Vec<Result<Ven, AppError>>, Vec<Subscription>), AppError> {
+        let deletes: Vec<bool> = operations
+            .iter()
+            .map(|operation| matches!(operation, BatchOperation::Delete { .. }))
+            .collect();
+
+        let mut tx = self.db.begin().await?;
+        let results = batch_in::<Self>(&mut tx, operations, client_id).await?;
+        if !results.iter().all(Result::is_ok) {
+            tx.rollback().await?;
+            return Ok((results, vec![]));
+        }
+
+        let mut subscriptions = vec![];
+        for (ven, delete) in results.iter().zip(deletes) {
+            if delete && let Ok(ven) = ven {
+                subscriptions.extend(
+                    PgSubscriptionStorage::delete_by_client_id_in(&mut tx, &ven.content.client_id)
+                        .await?,
+                );
+            }
+        }
+        tx.commit().await?;
+
+        Ok((results, subscriptions))
+    }
+
+    async fn delete_with_subscriptions(
+        &self,
+        id: &VenId,
+        client_id: &Option<ClientId>,
+    ) -> Result<(Ven, Vec<Subscription>), AppError> {
+        let mut tx = self.db.begin().await?;
+        let ven = Self::delete_in(&mut tx, id, client_id).await?;
+        let subscriptions =
+            PgSubscriptionStorage::delete_by_client_id_in(&mut tx, &ven.content.client_id).await?;
+        tx.commit().await?;
+
+        Ok((ven, subscriptions))
+    }
+}
 
 pub(crate) struct PgVenStorage {
     db: PgPool,
@@ -76,42 +130,9 @@ impl Crud for PgVenStorage {
     async fn create(
         &self,
         new: Self::NewType,
-        _client_id: &Self::PermissionFilter,
+        client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let ven: Ven = sqlx::query_as!(
-            PostgresVen,
-            r#"
-            INSERT INTO ven (
-                id,
-                created_date_time,
-                modification_date_time,
-                ven_name,
-                attributes,
-                targets,
-                client_id
-            )
-            VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4)
-            RETURNING
-                id,
-                created_date_time,
-                modification_date_time,
-                ven_name,
-                attributes,
-                targets as "targets:Vec<Target>",
-                client_id
-            "#,
-            new.ven_name,
-            to_json_value(new.attributes)?,
-            new.targets as _,
-            new.client_id as _
-        )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
-
-        trace!(ven_id = ven.id.as_str(), "created ven");
-
-        Ok(ven)
+        Self::create_in(&mut *self.db.acquire().await?, new, client_id).await
     }
 
     async fn retrieve(
@@ -119,31 +140,7 @@ impl Crud for PgVenStorage {
         id: &Self::Id,
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
-        let ven: Ven = sqlx::query_as!(
-            PostgresVen,
-            r#"
-            SELECT
-                id,
-                created_date_time,
-                modification_date_time,
-                ven_name,
-                attributes,
-                targets as "targets:Vec<Target>",
-                client_id
-            FROM ven
-            WHERE id = $1
-            AND ($2::text IS NULL OR client_id = $2)
-            "#,
-            id.as_str(),
-            client_id as _,
-        )
-        .fetch_one(&self.db)
-        .await?
-        .try_into()?;
-
-        trace!(ven_id = ven.id.as_str(), "retrieved ven");
-
-        Ok(ven)
+        Self::fetch(&mut *self.db.acquire().await?, id, client_id).await
     }
 
     async fn retrieve_all(
@@ -151,6 +148,8 @@ impl Crud for PgVenStorage {
         filter: &Self::Filter,
         client_id: &Self::PermissionFilter,
     ) -> Result<Vec<Self::Type>, Self::Error> {
+        let (after_time, after_id) = Cursor::position(filter.cursor.as_ref());
+        let attributes = AttributeQuery::new(filter.attributes.as_deref());
         let vens = sqlx::query_as!(
             PostgresVen,
             r#"
@@ -166,7 +165,12 @@ impl Crud for PgVenStorage {
             WHERE ($1::text IS NULL OR v.ven_name = $1)
               AND (array_length($2::text[], 1) IS NULL OR v.targets && $2)
               AND ($3::text IS NULL OR v.client_id = $3)
-            ORDER BY v.created_date_time DESC
+              AND ($7::timestamptz IS NULL OR (v.created_date_time, v.id) > ($7, $8))
+              AND ($9::jsonb IS NULL OR v.attributes @> $9)
+              AND ($10::text IS NULL OR jsonb_path_match(v.attributes, $10::text::jsonpath, $11))
+            ORDER BY CASE WHEN $6 THEN v.created_date_time END,
+                     CASE WHEN $6 THEN v.id END,
+                     v.created_date_time DESC
             OFFSET $4 LIMIT $5
             "#,
             filter.ven_name,
@@ -174,6 +178,12 @@ impl Crud for PgVenStorage {
             client_id as _,
             filter.skip,
             filter.limit,
+            filter.cursor.is_some(),
+            after_time,
+            after_id,
+            attributes.contains,
+            attributes.predicate,
+            attributes.vars,
         )
         .fetch_all(&self.db)
         .await?
@@ -186,6 +196,35 @@ impl Crud for PgVenStorage {
         Ok(vens)
     }
 
+    async fn count(
+        &self,
+        filter: &Self::Filter,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<u64, Self::Error> {
+        let attributes = AttributeQuery::new(filter.attributes.as_deref());
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!"
+            FROM ven v
+            WHERE ($1::text IS NULL OR v.ven_name = $1)
+              AND (array_length($2::text[], 1) IS NULL OR v.targets && $2)
+              AND ($3::text IS NULL OR v.client_id = $3)
+              AND ($4::jsonb IS NULL OR v.attributes @> $4)
+              AND ($5::text IS NULL OR jsonb_path_match(v.attributes, $5::text::jsonpath, $6))
+            "#,
+            filter.ven_name,
+            filter.targets.as_deref() as _,
+            client_id as _,
+            attributes.contains,
+            attributes.predicate,
+            attributes.vars,
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(count as u64)
+    }
+
     async fn update(
         &self,
         id: &Self::Id,
@@ -193,14 +232,229 @@ impl Crud for PgVenStorage {
         client_id: &Self::PermissionFilter,
     ) -> Result<Self::Type, Self::Error> {
         let mut tx = self.db.begin().await?;
+        let result = Self::update_in(&mut tx, id, new, client_id).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    async fn delete(
+        &self,
+        id: &Self::Id,
+        client_id: &Self::PermissionFilter,
+    ) -> Result<Self::Type, Self::Error> {
+        let mut tx = self.db.begin().await?;
+        let result = Self::delete_in(&mut tx, id, client_id).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    async fn lock(
+        &self,
+        id: &Self::Id,
+    ) -> Result<
+        Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+        Self::Error,
+    > {
+        lock::<Self>(&self.db, id).await
+    }
+}
+
+#[async_trait]
+impl VenObjectPrivacy for PgVenStorage {
+    async fn targets_by_client_id(&self, client_id: &ClientId) -> Result<Vec<Target>, AppError> {
+        // According to the spec, a client_id MUST match at most one VEN object, see also https://github.com/oadr3-org/specification/discussions/372
+        let ven_targets = sqlx::query_scalar!(
+            r#"
+            SELECT targets FROM ven WHERE ven.client_id = $1
+            "#,
+            client_id.as_str()
+        )
+        .fetch_one(&self.db)
+        .await?;
 
+        let resource_targets = sqlx::query_scalar!(
+            r#"
+            SELECT resource.targets
+            FROM ven
+                     JOIN resource ON ven.id = resource.ven_id
+            WHERE ven.client_id = $1
+            "#,
+            client_id.as_str()
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        // If a resource owned by a ven with matching client_id is a descendant of a certain resource group,
+        // also include that resource group's targets
+        let resource_group_targets = sqlx::query_scalar!(
+            r#"
+             SELECT DISTINCT rg.targets
+             FROM rg_family AS fam
+             INNER JOIN rg_child_ven_resource AS rcvr ON fam.id = rcvr.rg_parent_rg_id
+             INNER JOIN resource AS r ON rcvr.rg_child_ven_resource_id = r.id
+             INNER JOIN ven AS v ON r.ven_id = v.id
+             INNER JOIN resource_group rg on fam.root = rg.id
+             WHERE v.client_id = $1
+            "#,
+            client_id.as_str()
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let unique_targets = ven_targets
+            .into_iter()
+            .chain(resource_targets.into_iter().flatten())
+            .chain(resource_group_targets.into_iter().flatten())
+            .map(|target| target.parse())
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        Ok(unique_targets.into_iter().collect())
+    }
+
+    async fn resource_group_visible_for_client(
+        &self,
+        client_id: &ClientId,
+        resource_group_id: &ResourceGroupId,
+    ) -> Result<bool, AppError> {
+        Ok(sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT r.id FROM resource r
+                INNER JOIN rg_child_ven_resource rcvr
+                    ON rcvr.rg_child_ven_resource_id = r.id
+                INNER JOIN rg_family rg_fam
+                    ON rg_fam.id = rcvr.rg_parent_rg_id
+                WHERE r.ven_id = (SELECT v.id FROM ven v WHERE v.client_id = $2)
+                    AND rg_fam.root = $1
+            ) AS "exists!"
+            "#,
+            resource_group_id.as_str(),
+            client_id.as_str()
+        )
+        .fetch_one(&self.db)
+        .await?)
+    }
+
+    async fn ven_id_by_client_id(&self, client_id: &ClientId) -> Result<Option<VenId>, AppError> {
+        Ok(sqlx::query_scalar!(
+            r#"
+            SELECT id FROM ven WHERE client_id = $1
+            "#,
+            client_id.as_str()
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .map(|id| id.parse())
+        .transpose()?)
+    }
+
+    async fn all_ven_visibility(&self) -> Result<Vec<VenVisibility>, AppError> {
+        let vens = sqlx::query!(
+            r#"
+            SELECT id, client_id, targets FROM ven
+            "#
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut visibility = vens
+            .into_iter()
+            .map(|ven| {
+                Ok((
+                    ven.id.clone(),
+                    VenVisibility {
+                        ven_id: ven.id.parse()?,
+                        client_id: ven.client_id.parse()?,
+                        targets: ven
+                            .targets
+                            .iter()
+                            .map(|target| target.parse())
+                            .collect::<Result<_, _>>()?,
+                        resource_groups: HashSet::new(),
+                    },
+                ))
+            })
+            .collect::<Result<HashMap<_, _>, AppError>>()?;
+
+        let resources = sqlx::query!(
+            r#"
+            SELECT ven_id, targets FROM resource
+            "#
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        for resource in resources {
+            if let Some(ven) = visibility.get_mut(&resource.ven_id) {
+                for target in resource.targets {
+                    ven.targets.insert(target.parse()?);
+                }
+            }
+        }
+
+        // same as in `targets_by_client_id`, but for all VENs at once
+        let resource_groups = sqlx::query!(
+            r#"
+             SELECT DISTINCT r.ven_id, rg.id, rg.targets
+             FROM rg_family AS fam
+             INNER JOIN rg_child_ven_resource AS rcvr ON fam.id = rcvr.rg_parent_rg_id
+             INNER JOIN resource AS r ON rcvr.rg_child_ven_resource_id = r.id
+             INNER JOIN resource_group rg on fam.root = rg.id
+            "#
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        for resource_group in resource_groups {
+            if let Some(ven) = visibility.get_mut(&resource_group.ven_id) {
+                ven.resource_groups.insert(resource_group.id.parse()?);
+                for target in resource_group.targets {
+                    ven.targets.insert(target.parse()?);
+                }
+            }
+        }
+
+        Ok(visibility.into_values().collect())
+    }
+}
+
+#[async_trait]
+impl PgUpdate for PgVenStorage {
+    type Id = VenId;
+    type NewType = BlVenRequest;
+    type Type = Ven;
+
+    async fn lock_in(conn: &mut PgConnection, id: &VenId) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            SELECT id FROM ven WHERE id = $1 FOR UPDATE
+            "#,
+            id.as_str()
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn retrieve_in(conn: &mut PgConnection, id: &VenId) -> Result<Ven, AppError> {
+        Self::fetch(conn, id, &None).await
+    }
+
+    async fn update_in(
+        conn: &mut PgConnection,
+        id: &VenId,
+        new: BlVenRequest,
+        client_id: &Option<ClientId>,
+    ) -> Result<Ven, AppError> {
         let old = sqlx::query!(
             r#"
             SELECT client_id FROM ven WHERE id = $1
             "#,
             id.as_str()
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?;
 
         if let Some(client_id) = client_id
@@ -211,7 +465,7 @@ impl Crud for PgVenStorage {
                 ven_id = id.as_str(),
                 "Client tried to update VEN it does not own"
             );
-            return Err(Self::Error::NotFound);
+            return Err(AppError::NotFound);
         }
 
         if old.client_id != new.client_id.as_str() {
@@ -220,7 +474,7 @@ impl Crud for PgVenStorage {
                 clear about if that should be allowed. If you disagree with that interpretation, please open \
                 an issue on GitHub.";
             error!(ven_id = id.as_str(), "{}", error);
-            return Err(Self::Error::BadRequest(error));
+            return Err(AppError::BadRequest(error));
         }
 
         let ven: Ven = sqlx::query_as!(
@@ -246,30 +500,71 @@ impl Crud for PgVenStorage {
             to_json_value(new.attributes)?,
             new.targets as _,
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?
         .try_into()?;
 
-        tx.commit().await?;
         trace!(ven_id = id.as_str(), "updated ven");
 
         Ok(ven)
     }
+}
 
-    async fn delete(
-        &self,
-        id: &Self::Id,
-        _client_id: &Self::PermissionFilter,
-    ) -> Result<Self::Type, Self::Error> {
-        let mut tx = self.db.begin().await?;
+#[async_trait]
+impl PgBatch for PgVenStorage {
+    async fn create_in(
+        conn: &mut PgConnection,
+        new: BlVenRequest,
+        _client_id: &Option<ClientId>,
+    ) -> Result<Ven, AppError> {
+        let ven: Ven = sqlx::query_as!(
+            PostgresVen,
+            r#"
+            INSERT INTO ven (
+                id,
+                created_date_time,
+                modification_date_time,
+                ven_name,
+                attributes,
+                targets,
+                client_id
+            )
+            VALUES (gen_random_uuid(), now(), now(), $1, $2, $3, $4)
+            RETURNING
+                id,
+                created_date_time,
+                modification_date_time,
+                ven_name,
+                attributes,
+                targets as "targets:Vec<Target>",
+                client_id
+            "#,
+            new.ven_name,
+            to_json_value(new.attributes)?,
+            new.targets as _,
+            new.client_id as _
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .try_into()?;
 
+        trace!(ven_id = ven.id.as_str(), "created ven");
+
+        Ok(ven)
+    }
+
+    async fn delete_in(
+        conn: &mut PgConnection,
+        id: &VenId,
+        _client_id: &Option<ClientId>,
+    ) -> Result<Ven, AppError> {
         let resource_id = sqlx::query_scalar!(
             r#"
             SELECT id FROM resource WHERE ven_id = $1 LIMIT 1
             "#,
             id.as_str()
         )
-        .fetch_optional(&mut *tx)
+        .fetch_optional(&mut *conn)
         .await?;
 
         if resource_id.is_some() {
@@ -294,105 +589,108 @@ impl Crud for PgVenStorage {
             "#,
             id.as_str(),
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?
         .try_into()?;
 
-        tx.commit().await?;
-
         trace!(ven_id = id.as_str(), "deleted ven");
 
         Ok(ven)
     }
 }
 
-#[async_trait]
-impl VenObjectPrivacy for PgVenStorage {
-    async fn targets_by_client_id(&self, client_id: &ClientId) -> Result<Vec<Target>, AppError> {
-        // According to the spec, a client_id MUST match at most one VEN object, see also https://github.com/oadr3-org/specification/discussions/372
-        let ven_targets = sqlx::query_scalar!(
-            r#"
-            SELECT targets FROM ven WHERE ven.client_id = $1
-            "#,
-            client_id.as_str()
-        )
-        .fetch_one(&self.db)
-        .await?;
-
-        let resource_targets = sqlx::query_scalar!(
+impl PgVenStorage {
+    async fn fetch(
+        conn: &mut PgConnection,
+        id: &VenId,
+        client_id: &Option<ClientId>,
+    ) -> Result<Ven, AppError> {
+        let ven: Ven = sqlx::query_as!(
+            PostgresVen,
             r#"
-            SELECT resource.targets
+            SELECT
+                id,
+                created_date_time,
+                modification_date_time,
+                ven_name,
+                attributes,
+                targets as "targets:Vec<Target>",
+                client_id
             FROM ven
-                     JOIN resource ON ven.id = resource.ven_id
-            WHERE ven.client_id = $1
-            "#,
-            client_id.as_str()
-        )
-        .fetch_all(&self.db)
-        .await?;
-
-        // If a resource owned by a ven with matching client_id is a descendant of a certain resource group,
-        // also include that resource group's targets
-        let resource_group_targets = sqlx::query_scalar!(
-            r#"
-             SELECT DISTINCT rg.targets
-             FROM rg_family AS fam
-             INNER JOIN rg_child_ven_resource AS rcvr ON fam.id = rcvr.rg_parent_rg_id
-             INNER JOIN resource AS r ON rcvr.rg_child_ven_resource_id = r.id
-             INNER JOIN ven AS v ON r.ven_id = v.id
-             INNER JOIN resource_group rg on fam.root = rg.id
-             WHERE v.client_id = $1
+            WHERE id = $1
+            AND ($2::text IS NULL OR client_id = $2)
             "#,
-            client_id.as_str()
+            id.as_str(),
+            client_id as _,
         )
-        .fetch_all(&self.db)
-        .await?;
+        .fetch_one(conn)
+        .await?
+        .try_into()?;
 
-        let unique_targets = ven_targets
-            .into_iter()
-            .chain(resource_targets.into_iter().flatten())
-            .chain(resource_group_targets.into_iter().flatten())
-            .map(|target| target.parse())
-            .collect::<Result<HashSet<_>, _>>()?;
+        trace!(ven_id = ven.id.as_str(), "retrieved ven");
 
-        Ok(unique_targets.into_iter().collect())
+        Ok(ven)
     }
 
-    async fn resource_group_visible_for_client(
-        &self,
-        client_id: &ClientId,
-        resource_group_id: &ResourceGroupId,
-    ) -> Result<bool, AppError> {
-        Ok(sqlx::query_scalar!(
+    /// All VENs, in the order they were created
+    pub(super) async fn export(tx: &mut Transaction<'_, Postgres>) -> Result<Vec<Ven>, AppError> {
+        sqlx::query_as!(
+            PostgresVen,
             r#"
-            SELECT EXISTS (
-                SELECT r.id FROM resource r
-                INNER JOIN rg_child_ven_resource rcvr
-                    ON rcvr.rg_child_ven_resource_id = r.id
-                INNER JOIN rg_family rg_fam
-                    ON rg_fam.id = rcvr.rg_parent_rg_id
-                WHERE r.ven_id = (SELECT v.id FROM ven v WHERE v.client_id = $2)
-                    AND rg_fam.root = $1
-            ) AS "exists!"
+            SELECT id,
+                   created_date_time,
+                   modification_date_time,
+                   ven_name,
+                   attributes,
+                   targets AS "targets:Vec<Target>",
+                   client_id
+            FROM ven
+            ORDER BY created_date_time, id
             "#,
-            resource_group_id.as_str(),
-            client_id.as_str()
         )
-        .fetch_one(&self.db)
-        .await?)
+        .fetch_all(tx.as_mut())
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect()
     }
 
-    async fn ven_id_by_client_id(&self, client_id: &ClientId) -> Result<Option<VenId>, AppError> {
-        Ok(sqlx::query_scalar!(
+    /// Inserts the VEN with its ID and timestamps, or replaces the VEN with the same ID
+    pub(super) async fn import(
+        tx: &mut Transaction<'_, Postgres>,
+        ven: &Ven,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
             r#"
-            SELECT id FROM ven WHERE client_id = $1
+            INSERT INTO ven (
+                id,
+                created_date_time,
+                modification_date_time,
+                ven_name,
+                attributes,
+                targets,
+                client_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (id) DO UPDATE
+            SET created_date_time = excluded.created_date_time,
+                modification_date_time = excluded.modification_date_time,
+                ven_name = excluded.ven_name,
+                attributes = excluded.attributes,
+                targets = excluded.targets,
+                client_id = excluded.client_id
             "#,
-            client_id.as_str()
+            ven.id.as_str(),
+            ven.created_date_time,
+            ven.modification_date_time,
+            ven.content.ven_name,
+            to_json_value(ven.content.attributes.as_ref())?,
+            ven.content.targets.as_slice() as &[Target],
+            ven.content.client_id.as_str(),
         )
-        .fetch_optional(&self.db)
-        .await?
-        .map(|id| id.parse())
-        .transpose()?)
+        .execute(tx.as_mut())
+        .await?;
+        Ok(())
     }
 }
 
@@ -400,7 +698,7 @@ impl VenObjectPrivacy for PgVenStorage {
 #[cfg(feature = "live-db-test")]
 mod tests {
     use crate::{
-        api::{TargetQueryParams, ven::QueryParams},
+        api::{TargetQueryParams, attributes::AttributeQueryParams, ven::QueryParams},
         data_source::{Crud, VenObjectPrivacy, postgres::ven::PgVenStorage},
         error::AppError,
     };
@@ -416,8 +714,10 @@ mod tests {
             Self {
                 ven_name: None,
                 targets: TargetQueryParams(None),
+                attributes: AttributeQueryParams(None),
                 skip: 0,
                 limit: 50,
+                cursor: None,
             }
         }
     }
@@ -7,13 +7,17 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use axum_extra::extract::QueryRejection;
-use openleadr_wire::{IdentifierError, problem::Problem};
+use openleadr_wire::{
+    IdentifierError,
+    problem::{ErrorCode, FieldError, Problem},
+};
 #[cfg(feature = "sqlx")]
 use sqlx::error::DatabaseError;
 #[cfg(feature = "sqlx")]
 use tracing::warn;
 use tracing::{error, info, trace};
 use uuid::Uuid;
+use validator::{ValidationErrors, ValidationErrorsKind};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
@@ -63,6 +67,16 @@ pub enum AppError {
     PasswordHashError(password_hash::Error),
     #[error("Unsupported Media Type: {0}")]
     UnsupportedMediaType(String),
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(&'static str),
+    #[error("Patch cannot be applied: {0}")]
+    PatchFailed(String),
+    #[error("Idempotency key was used for another request")]
+    IdempotencyKeyReused,
+    #[error("A request with the same idempotency key is still being processed")]
+    RequestInProgress,
+    #[error("Request does not match the descriptors of the program")]
+    DescriptorMismatch(Vec<FieldError>),
 }
 
 #[cfg(feature = "sqlx")]
@@ -118,22 +132,128 @@ impl From<password_hash::Error> for AppError {
     }
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let reference = Uuid::new_v4();
+impl AppError {
+    /// The status code and [`ErrorCode`] of the [`Problem`] response
+    pub(crate) fn kind(&self) -> (StatusCode, ErrorCode) {
+        match self {
+            AppError::Validation(_) => (StatusCode::BAD_REQUEST, ErrorCode::ValidationFailed),
+            AppError::Json(_) | AppError::Form(_) => {
+                (StatusCode::BAD_REQUEST, ErrorCode::MalformedBody)
+            }
+            #[cfg(feature = "sqlx")]
+            AppError::SerdeJsonBadRequest(_) => (StatusCode::BAD_REQUEST, ErrorCode::MalformedBody),
+            AppError::QueryParams(_) => (StatusCode::BAD_REQUEST, ErrorCode::MalformedQuery),
+            AppError::Identifier(_) => (StatusCode::BAD_REQUEST, ErrorCode::MalformedIdentifier),
+            AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, ErrorCode::BadRequest),
+            AppError::Auth(_) => (StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized),
+            AppError::Forbidden(_) => (StatusCode::FORBIDDEN, ErrorCode::Forbidden),
+            AppError::NotFound => (StatusCode::NOT_FOUND, ErrorCode::NotFound),
+            AppError::MethodNotAllowed => {
+                (StatusCode::METHOD_NOT_ALLOWED, ErrorCode::MethodNotAllowed)
+            }
+            #[cfg(feature = "sqlx")]
+            AppError::Conflict(..) => (StatusCode::CONFLICT, ErrorCode::Conflict),
+            #[cfg(feature = "sqlx")]
+            AppError::ForeignKeyConstraintViolated(..) => {
+                (StatusCode::CONFLICT, ErrorCode::ReferenceViolated)
+            }
+            AppError::UnsupportedMediaType(_) => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorCode::UnsupportedMediaType,
+            ),
+            AppError::NotImplemented(_) => (StatusCode::NOT_IMPLEMENTED, ErrorCode::NotImplemented),
+            AppError::ServiceUnavailable(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::ServiceUnavailable,
+            ),
+            AppError::PatchFailed(_) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::PatchFailed),
+            AppError::IdempotencyKeyReused => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::IdempotencyKeyReused,
+            ),
+            AppError::RequestInProgress => (StatusCode::CONFLICT, ErrorCode::RequestInProgress),
+            AppError::DescriptorMismatch(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::DescriptorMismatch,
+            ),
+            #[cfg(feature = "sqlx")]
+            AppError::Sql(_)
+            | AppError::SerdeJsonInternalServerError(_)
+            | AppError::PasswordHashError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+            }
+            AppError::Mqtt(_) | AppError::StorageConnectionError => {
+                (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+            }
+        }
+    }
+}
+
+/// The fields of `errors`, with paths like `intervals[0].payloads`
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    fn collect(prefix: &str, errors: &ValidationErrors, result: &mut Vec<FieldError>) {
+        for (field, kind) in errors.errors() {
+            let path = match (prefix, field.as_ref()) {
+                (prefix, "__all__") => prefix.to_string(),
+                ("", field) => field.to_string(),
+                (prefix, field) => format!("{prefix}.{field}"),
+            };
+            match kind {
+                ValidationErrorsKind::Field(errors) => {
+                    result.extend(errors.iter().map(|error| FieldError {
+                        field: path.clone(),
+                        code: error.code.to_string(),
+                        message: error.message.as_ref().map(ToString::to_string),
+                    }))
+                }
+                ValidationErrorsKind::Struct(errors) => collect(&path, errors, result),
+                ValidationErrorsKind::List(errors) => {
+                    for (index, errors) in errors {
+                        collect(&format!("{path}[{index}]"), errors, result);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    collect("", errors, &mut result);
+    result.sort_by(|a, b| a.field.cmp(&b.field));
+    result
+}
+
+/// The name of the constraint `err` violated, if the database reports it
+#[cfg(feature = "sqlx")]
+fn constraint(err: &Option<Box<dyn DatabaseError>>) -> Option<String> {
+    err.as_ref()?.constraint().map(ToOwned::to_owned)
+}
 
-        let problem = match self {
+impl AppError {
+    /// The [`Problem`] response, logging the error on the way
+    pub(crate) fn into_problem(self) -> Problem {
+        let reference =
+            crate::correlation::request_id().unwrap_or_else(|| Uuid::new_v4().to_string());
+        let (status, code) = self.kind();
+        let problem = |detail: Option<String>| Problem {
+            r#type: code.problem_type(),
+            title: Some(status.to_string()),
+            status,
+            detail,
+            instance: Some(reference.clone()),
+            code: Some(code),
+            errors: None,
+            constraint: None,
+        };
+
+        match self {
             AppError::Validation(err) => {
                 trace!(%reference,
                     "Received invalid request: {}",
                     err
                 );
                 Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::BAD_REQUEST.to_string()),
-                    status: StatusCode::BAD_REQUEST,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
+                    errors: Some(field_errors(&err)),
+                    ..problem(Some(err.to_string()))
                 }
             }
             AppError::Json(err) => {
@@ -141,95 +261,50 @@ impl IntoResponse for AppError {
                     "Received invalid JSON in request: {}",
                     err.body_text(),
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::BAD_REQUEST.to_string()),
-                    status: StatusCode::BAD_REQUEST,
-                    detail: Some(err.body_text()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.body_text()))
             }
             AppError::Form(err) => {
                 trace!(%reference,
                     "Received invalid form data: {}",
                     err
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::BAD_REQUEST.to_string()),
-                    status: StatusCode::BAD_REQUEST,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             AppError::QueryParams(err) => {
                 trace!(%reference,
                     "Received invalid query parameters: {}",
                     err
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::BAD_REQUEST.to_string()),
-                    status: StatusCode::BAD_REQUEST,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             AppError::NotFound => {
                 trace!(%reference, "Object not found");
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::NOT_FOUND.to_string()),
-                    status: StatusCode::NOT_FOUND,
-                    detail: None,
-                    instance: Some(reference.to_string()),
-                }
+                problem(None)
             }
             AppError::BadRequest(err) => {
                 trace!(%reference,
                     "Received invalid request: {}",
                     err
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::BAD_REQUEST.to_string()),
-                    status: StatusCode::BAD_REQUEST,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             AppError::Forbidden(err) => {
                 trace!(%reference,
                     "Forbidden: {}",
                     err
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::FORBIDDEN.to_string()),
-                    status: StatusCode::FORBIDDEN,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             AppError::NotImplemented(err) => {
                 error!(%reference, "Not implemented: {}", err);
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::NOT_IMPLEMENTED.to_string()),
-                    status: StatusCode::NOT_IMPLEMENTED,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             #[cfg(feature = "sqlx")]
             AppError::Conflict(err, db_err) => {
                 warn!(%reference, "Conflict: {}, DB err: {:?}", err, db_err);
                 Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::CONFLICT.to_string()),
-                    status: StatusCode::CONFLICT,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
+                    constraint: constraint(&db_err),
+                    ..problem(Some(err.to_string()))
                 }
             }
             AppError::Auth(err) => {
@@ -237,79 +312,37 @@ impl IntoResponse for AppError {
                     "Authentication error: {}",
                     err
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::UNAUTHORIZED.to_string()),
-                    status: StatusCode::UNAUTHORIZED,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             #[cfg(feature = "sqlx")]
             AppError::Sql(err) => {
                 error!(%reference, "SQL error: {}", err);
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                    detail: Some("A database error occurred".to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some("A database error occurred".to_string()))
             }
             AppError::Mqtt(err) => {
                 error!(%reference, "MQTT error: {}", err);
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                    detail: Some("An mqtt error occurred".to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some("An mqtt error occurred".to_string()))
             }
             AppError::StorageConnectionError => {
                 error!(%reference, "Storage connection pool closed");
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                    detail: Some("Storage connection pool closed".to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some("Storage connection pool closed".to_string()))
             }
             #[cfg(feature = "sqlx")]
             AppError::SerdeJsonInternalServerError(err) => {
                 trace!(%reference, "serde json error: {}", err);
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                    detail: None,
-                    instance: Some(reference.to_string()),
-                }
+                problem(None)
             }
             #[cfg(feature = "sqlx")]
             AppError::SerdeJsonBadRequest(err) => {
                 trace!(%reference, "serde json error: {}", err);
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::BAD_REQUEST.to_string()),
-                    status: StatusCode::BAD_REQUEST,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             AppError::Identifier(err) => {
                 trace!(%reference,
                     "Malformed identifier: {}",
                     err
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::BAD_REQUEST.to_string()),
-                    status: StatusCode::BAD_REQUEST,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some(err.to_string()))
             }
             #[cfg(feature = "sqlx")]
             AppError::ForeignKeyConstraintViolated(err, db_err) => {
@@ -319,50 +352,55 @@ impl IntoResponse for AppError {
                     db_err
                 );
                 Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::CONFLICT.to_string()),
-                    status: StatusCode::CONFLICT,
-                    detail: Some(err.to_string()),
-                    instance: Some(reference.to_string()),
+                    constraint: constraint(&db_err),
+                    ..problem(Some(err.to_string()))
                 }
             }
             AppError::MethodNotAllowed => {
                 trace!(%reference,
                     "Method not allowed"
                 );
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::METHOD_NOT_ALLOWED.to_string()),
-                    status: StatusCode::METHOD_NOT_ALLOWED,
-                    detail: Some("See allow headers for allowed methods".to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some("See allow headers for allowed methods".to_string()))
             }
             #[cfg(feature = "sqlx")]
             AppError::PasswordHashError(err) => {
                 warn!(%reference,
                 "Password hash error: {}",
                 err);
-                Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::INTERNAL_SERVER_ERROR.to_string()),
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                    detail: Some("An internal error occurred".to_string()),
-                    instance: Some(reference.to_string()),
-                }
+                problem(Some("An internal error occurred".to_string()))
             }
             AppError::UnsupportedMediaType(err) => {
                 info!(%reference, "Unsupported media type: {}", err);
+                problem(Some(err))
+            }
+            AppError::ServiceUnavailable(err) => {
+                info!(%reference, "Service unavailable: {}", err);
+                problem(Some(err.to_string()))
+            }
+            AppError::PatchFailed(err) => {
+                trace!(%reference, "Patch cannot be applied: {}", err);
+                problem(Some(err))
+            }
+            err @ (AppError::IdempotencyKeyReused | AppError::RequestInProgress) => {
+                trace!(%reference, "{}", err);
+                problem(Some(err.to_string()))
+            }
+            AppError::DescriptorMismatch(errors) => {
+                trace!(%reference, ?errors, "Request does not match the descriptors of the program");
                 Problem {
-                    r#type: Default::default(),
-                    title: Some(StatusCode::UNSUPPORTED_MEDIA_TYPE.to_string()),
-                    status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
-                    detail: Some(err),
-                    instance: Some(reference.to_string()),
+                    errors: Some(errors),
+                    ..problem(Some(
+                        "Request does not match the descriptors of the program".to_string(),
+                    ))
                 }
             }
-        };
+        }
+    }
+}
 
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let problem = self.into_problem();
         let mut response = (problem.status, Json(problem)).into_response();
         if response.status() == StatusCode::UNAUTHORIZED {
             response.headers_mut().insert(
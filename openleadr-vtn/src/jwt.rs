@@ -4,12 +4,9 @@ use std::sync::Arc;
 use jsonwebtoken::{Header, encode};
 
 use crate::api::auth::ResponseOAuthError;
-use openleadr_wire::{
-    Ven,
-    oauth::{OAuthError, OAuthErrorType},
-};
+use openleadr_wire::oauth::{OAuthError, OAuthErrorType};
 
-use crate::{error::AppError, state::OAuthKeyType};
+use crate::{config::OAuthKeyType, correlation, error::AppError};
 use axum::{
     extract::{FromRef, FromRequestParts},
     http::request::Parts,
@@ -140,6 +137,9 @@ pub enum Scope {
     WriteUsers,
 }
 
+/// The scopes that grant reading objects, as opposed to writing them
+const READ_SCOPES: [Scope; 3] = [Scope::ReadAll, Scope::ReadTargets, Scope::ReadVenObjects];
+
 impl FromStr for Scope {
     type Err = String;
 
@@ -212,7 +212,7 @@ struct EdKeys {
     keys: Vec<EdKey>,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 pub(crate) struct Claims {
     /// (subject): Subject of the JWT (the user)
     pub(crate) sub: String,
@@ -246,10 +246,20 @@ impl Claims {
         self.scope.contains(scope) || self.roles.contains(scope)
     }
 
+    /// Returns true if the 'exp' claim lies in the past.
+    pub(crate) fn is_expired(&self) -> bool {
+        self.exp < chrono::Utc::now().timestamp()
+    }
+
+    /// The 'exp' claim, in seconds since the Unix epoch
+    pub(crate) fn expiration(&self) -> i64 {
+        self.exp
+    }
+
     /// A read-only set of claims to use as the basis for mqtt object privacy.
-    pub(crate) fn temporary_claims_for_mqtt_ven(ven: &Ven) -> Self {
+    pub(crate) fn temporary_claims_for_mqtt_ven(client_id: &ClientId) -> Self {
         Self {
-            sub: ven.content.client_id.as_str().into(),
+            sub: client_id.as_str().into(),
             exp: 0,
             iat: None,
             nbf: None,
@@ -259,6 +269,37 @@ impl Claims {
         }
     }
 
+    /// The scopes that determine which objects the client can read
+    pub(crate) fn read_scopes(&self) -> Vec<Scope> {
+        READ_SCOPES
+            .into_iter()
+            .filter(|scope| self.has_scope(*scope))
+            .collect()
+    }
+
+    /// A read-only set of claims to use as the basis for the object privacy of webhooks.
+    /// `owner_scopes` are the scopes the client had when it created the subscription,
+    /// only its read scopes are taken over.
+    pub(crate) fn temporary_claims_for_webhook(
+        client_id: &ClientId,
+        owner_scopes: &[Scope],
+    ) -> Self {
+        Self {
+            sub: client_id.as_str().into(),
+            exp: 0,
+            iat: None,
+            nbf: None,
+            aud: None,
+            scope: vec![].into(),
+            roles: owner_scopes
+                .iter()
+                .copied()
+                .filter(|scope| READ_SCOPES.contains(scope))
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+
     #[cfg(test)]
     pub(crate) fn from_scopes(scopes: Vec<Scope>) -> Self {
         Self {
@@ -271,6 +312,11 @@ impl Claims {
             roles: scopes.into(),
         }
     }
+
+    #[cfg(test)]
+    pub(crate) fn with_expiration(self, exp: i64) -> Self {
+        Self { exp, ..self }
+    }
 }
 
 #[derive(Clone, Debug, serde::Serialize, Default, derive_more::From, AsRef, PartialEq)]
@@ -420,11 +466,18 @@ impl JwtManager {
     }
 
     /// Decode and validate a given JWT token, returning the validated claims
-    async fn decode_and_validate(&self, token: &str) -> Result<Claims, ResponseOAuthError> {
+    pub(crate) async fn decode_and_validate(
+        &self,
+        token: &str,
+    ) -> Result<Claims, ResponseOAuthError> {
         match &self.decoding_key {
             Some(key) => {
                 let token_data = jsonwebtoken::decode::<Claims>(token, key, &self.validation)
-                    .inspect_err(|err| warn!("received invalid authentication token: {err}"))?;
+                    .inspect_err(|err| {
+                        warn!("received invalid authentication token: {err}");
+                        #[cfg(feature = "metrics")]
+                        crate::metrics::jwt_failure(crate::metrics::jwt_error_reason(err.kind()));
+                    })?;
                 Ok(Self::check_time(token_data.claims)?)
             }
             None => {
@@ -432,6 +485,8 @@ impl JwtManager {
                 let keys = self.fetch_keys_with_kid().await;
 
                 if keys.is_empty() {
+                    #[cfg(feature = "metrics")]
+                    crate::metrics::jwt_failure("no_available_keys");
                     return Err(OAuthError::new(OAuthErrorType::NoAvailableKeys)
                         .with_description(
                             "No usable keys returned from the OAuth server".to_string(),
@@ -461,6 +516,10 @@ impl JwtManager {
                                         "JWT validation failed for kid={}: {e}",
                                         key_ref
                                     );
+                                    #[cfg(feature = "metrics")]
+                                    crate::metrics::jwt_failure(crate::metrics::jwt_error_reason(
+                                        e.kind(),
+                                    ));
                                     // Stop trying, return error
                                     return Err(OAuthError::new(OAuthErrorType::InvalidGrant)
                                         .with_description(format!("JWT validation failed: {e}"))
@@ -471,6 +530,8 @@ impl JwtManager {
                     }
                 }
 
+                #[cfg(feature = "metrics")]
+                crate::metrics::jwt_failure("invalid_signature");
                 Err(OAuthError::new(OAuthErrorType::UnsupportedGrantType)
                     .with_description("No usable keys found".to_string())
                     .into())
@@ -485,6 +546,8 @@ impl JwtManager {
             && now < nbf
         {
             warn!("received token not yet valid: nbf={nbf} now={now}");
+            #[cfg(feature = "metrics")]
+            crate::metrics::jwt_failure("not_yet_valid");
             return Err(OAuthError {
                 error: OAuthErrorType::NotYetValid,
                 error_description: Some(
@@ -496,6 +559,8 @@ impl JwtManager {
 
         if claims.exp < now {
             warn!("received expired token: exp={} now={now}", claims.exp);
+            #[cfg(feature = "metrics")]
+            crate::metrics::jwt_failure("expired");
             return Err(OAuthError {
                 error: OAuthErrorType::Expired,
                 error_description: Some(
@@ -508,6 +573,31 @@ impl JwtManager {
         Ok(claims)
     }
 
+    /// Requests the JWKS, forwarding the request ID and trace context of the current request
+    async fn get_jwks(jwks_location: &str) -> reqwest::Result<reqwest::Response> {
+        reqwest::Client::new()
+            .get(jwks_location)
+            .headers(correlation::outgoing_headers(
+                correlation::request_id().as_deref(),
+            ))
+            .send()
+            .await
+    }
+
+    /// Checks the JWKS of the OAuth provider can be retrieved,
+    /// or `None` if the tokens are validated with a configured key instead
+    pub(crate) fn check_jwks(&self) -> Option<impl Future<Output = reqwest::Result<()>> + '_> {
+        if self.decoding_key.is_some() {
+            return None;
+        }
+        let jwks_location = self.jwks_location.as_ref()?;
+
+        Some(async move {
+            Self::get_jwks(jwks_location).await?.error_for_status()?;
+            Ok(())
+        })
+    }
+
     /// Fetch OAUTH decoding keys from OAUTH_JWKS_LOCATION
     pub async fn fetch_keys_with_kid(&self) -> Vec<(Option<String>, DecodingKey)> {
         let mut keys = Vec::new();
@@ -516,7 +606,7 @@ impl JwtManager {
             OAuthKeyType::Hmac => {}
             OAuthKeyType::Rsa => {
                 let jwks_location = self.jwks_location.as_ref().expect("OAUTH_JWKS_LOCATION environment variable must be set for external OAuth provider with key type RSA");
-                let rsa_params = reqwest::get(jwks_location)
+                let rsa_params = Self::get_jwks(jwks_location)
                     .await
                     .expect("Could not reach OAUTH_JWKS_LOCATION");
                 let rsa_keys: RsaKeys = rsa_params
@@ -541,7 +631,7 @@ impl JwtManager {
             }
             OAuthKeyType::Ec => {
                 let jwks_location = self.jwks_location.as_ref().expect("OAUTH_JWKS_LOCATION environment variable must be set for external OAuth provider with key type EC");
-                let ec_params = reqwest::get(jwks_location)
+                let ec_params = Self::get_jwks(jwks_location)
                     .await
                     .expect("Could not reach OAUTH_JWKS_LOCATION");
                 let ec_keys: EcKeys = ec_params
@@ -566,7 +656,7 @@ impl JwtManager {
             }
             OAuthKeyType::Ed => {
                 let jwks_location = self.jwks_location.as_ref().expect("OAUTH_JWKS_LOCATION environment variable must be set for external OAuth provider with key type EC");
-                let ed_params = reqwest::get(jwks_location)
+                let ed_params = Self::get_jwks(jwks_location)
                     .await
                     .expect("Could not reach OAUTH_JWKS_LOCATION");
                 let ed_keys: EdKeys = ed_params
@@ -607,6 +697,8 @@ where
         let Ok(TypedHeader(bearer)) =
             TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state).await
         else {
+            #[cfg(feature = "metrics")]
+            crate::metrics::jwt_failure("missing_token");
             return Err(AppError::Auth(
                 "Authorization via Bearer token in Authorization header required".to_string(),
             ));
@@ -1,58 +1,67 @@
 #![deny(rustdoc::broken_intra_doc_links)]
 #![deny(rustdoc::private_intra_doc_links)]
 
+pub mod admin;
 mod api;
+pub mod config;
+mod correlation;
 pub mod data_source;
 mod error;
 pub mod jwt;
 #[cfg(feature = "mdns")]
 pub mod mdns;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "otel")]
+pub mod otel;
 pub mod state;
+#[cfg(feature = "tls")]
+mod tls;
 
 #[cfg(feature = "postgres")]
 use crate::data_source::PostgresStorage;
-use crate::{data_source::Migrate, state::AppState};
+use crate::{
+    config::{ConfigError, ConfigErrors},
+    data_source::{DataSource, Migrate},
+    state::AppState,
+};
+pub use config::VtnConfig;
 
-use tokio::net::TcpListener;
-use tracing::{info, warn};
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
-pub struct VtnConfig {
-    pub port: u16,
-    pub mdns_ip_address: String,
-    pub mdns_host_name: String,
-    pub mdns_service_type: String,
-    pub mdns_server_name: String,
-    pub mdns_base_path: String,
-    pub mqtt_url: Option<String>,
-    pub mqtt_username: Option<String>,
-    pub mqtt_password: Option<String>,
-    pub mqtt_topic_prefix: String,
-}
+use tokio::{net::TcpListener, task::JoinHandle};
+use tracing::{info, warn};
 
-impl VtnConfig {
-    /// Load configuration from environment variables
-    pub fn from_env() -> Self {
-        Self {
-            port: std::env::var("PORT")
-                .ok()
-                .and_then(|s| s.parse::<u16>().ok())
-                .unwrap_or(3000),
-            mdns_ip_address: std::env::var("MDNS_IP_ADDRESS")
-                .unwrap_or_else(|_| "127.0.0.1".to_string()),
-            mdns_host_name: std::env::var("MDNS_HOST_NAME")
-                .unwrap_or_else(|_| "vtn.local.".to_string()),
-            mdns_service_type: std::env::var("MDNS_SERVICE_TYPE")
-                .unwrap_or_else(|_| "_openadr3._tcp.local.".to_string()),
-            mdns_server_name: std::env::var("MDNS_SERVER_NAME")
-                .unwrap_or_else(|_| "openleadr-vtn".to_string()),
-            mdns_base_path: std::env::var("MDNS_BASE_PATH").unwrap_or_else(|_| "".to_string()),
-            mqtt_url: std::env::var("MQTT_URL").ok(),
-            mqtt_username: std::env::var("MQTT_USERNAME").ok(),
-            mqtt_password: std::env::var("MQTT_PASSWORD").ok(),
-            mqtt_topic_prefix: std::env::var("MQTT_TOPIC_PREFIX").unwrap_or_default(),
-        }
-    }
+/// Problems that prevent the VTN from starting
+#[derive(Debug, thiserror::Error)]
+pub enum StartupError {
+    #[error("invalid configuration:\n{0}")]
+    Config(#[from] ConfigErrors),
+    #[error("cannot listen on port {port}: {source}")]
+    Listen { port: u16, source: std::io::Error },
+    #[cfg(feature = "postgres")]
+    #[error("cannot connect to the database: {0}")]
+    Database(#[from] sqlx::Error),
+    #[cfg(feature = "postgres")]
+    #[error("cannot migrate the database: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("cannot read the database schema version: {0}")]
+    SchemaVersion(#[source] error::AppError),
+    #[error(
+        "incompatible database schema: {0}. \
+        Run `openleadr-vtn migrate` or set AUTO_MIGRATE to apply the migrations at startup"
+    )]
+    IncompatibleSchema(String),
+    #[error("invalid OAuth key: {0}")]
+    OAuthKey(#[from] jsonwebtoken::errors::Error),
+    #[error("cannot set up notifications: {0}")]
+    Notifier(#[source] error::AppError),
+    #[cfg(feature = "mdns")]
+    #[error("cannot register mDNS service: {0}")]
+    Mdns(#[from] mdns_sd::Error),
+    #[cfg(feature = "tls")]
+    #[error("cannot load TLS certificate: {0}")]
+    Tls(#[source] std::io::Error),
 }
 
 pub struct VtnServer {
@@ -61,28 +70,96 @@ pub struct VtnServer {
     pub router: axum::Router,
     pub listener: TcpListener,
     pub config: VtnConfig,
+    state: AppState,
+    background_tasks: Vec<JoinHandle<()>>,
+    #[cfg(feature = "tls")]
+    rustls_config: Option<axum_server::tls_rustls::RustlsConfig>,
+    #[cfg(feature = "tls")]
+    redirect_listener: Option<TcpListener>,
+}
+
+/// Connects to the database given in the configuration
+#[cfg(feature = "postgres")]
+pub async fn connect_storage(config: &VtnConfig) -> Result<PostgresStorage, StartupError> {
+    let database_url = config.database_url.as_deref().ok_or_else(|| {
+        ConfigErrors::from(ConfigError::invalid(
+            config::DATABASE_URL,
+            "must be set with the `postgres` feature",
+        ))
+    })?;
+    Ok(PostgresStorage::connect(database_url).await?)
+}
+
+/// Applies the pending migrations if `auto_migrate` is set,
+/// and checks this VTN can run on the resulting database schema
+async fn check_schema(
+    storage: &(impl DataSource + Migrate),
+    auto_migrate: bool,
+) -> Result<(), StartupError> {
+    if auto_migrate {
+        for migration in storage
+            .pending_migrations()
+            .await
+            .map_err(StartupError::SchemaVersion)?
+        {
+            info!(
+                version = migration.version,
+                "applying migration {}", migration.description
+            );
+        }
+        storage.migrate().await?;
+    }
+
+    let schema_version = storage
+        .statistics()
+        .schema_version()
+        .await
+        .map_err(StartupError::SchemaVersion)?;
+    match schema_version.incompatibility() {
+        Some(problem) => Err(StartupError::IncompatibleSchema(problem)),
+        None => Ok(()),
+    }
+}
+
+async fn bind(port: u16) -> Result<TcpListener, StartupError> {
+    TcpListener::bind(("0.0.0.0", port))
+        .await
+        .map_err(|source| StartupError::Listen { port, source })
 }
 
 impl VtnServer {
-    pub async fn new(config: VtnConfig) -> Result<Self, Box<dyn std::error::Error>> {
-        let addr = format!("0.0.0.0:{}", config.port);
-        let listener = TcpListener::bind(addr).await.unwrap();
-        info!("listening on http://{}", listener.local_addr().unwrap());
+    pub async fn new(config: VtnConfig) -> Result<Self, StartupError> {
+        let listener = bind(config.port).await?;
+
+        #[cfg(feature = "tls")]
+        let (rustls_config, redirect_listener) = match &config.tls {
+            Some(tls) => (
+                Some(tls::rustls_config(tls).await.map_err(StartupError::Tls)?),
+                match tls.redirect_port {
+                    Some(port) => Some(bind(port).await?),
+                    None => None,
+                },
+            ),
+            None => (None, None),
+        };
 
         #[cfg(feature = "postgres")]
-        let storage = PostgresStorage::from_env().await?;
+        let storage = connect_storage(&config).await?;
 
         #[cfg(not(feature = "postgres"))]
         compile_error!(
             "No storage backend selected. Please enable the `postgres` feature flag during compilation"
         );
 
-        if let Err(e) = storage.migrate().await {
-            warn!("Database migration failed: {}", e);
-        }
+        check_schema(&storage, config.auto_migrate).await?;
 
-        let state = AppState::new(storage, &config).await;
-        let router = state.into_router();
+        let state = AppState::new(storage, &config).await?;
+        let background_tasks = vec![
+            tokio::spawn(api::event_lifecycle::run(state.clone())),
+            tokio::spawn(api::subscription_cleanup::run(state.clone())),
+            tokio::spawn(api::idempotency::run_cleanup(state.clone())),
+        ];
+        let router = state.clone().into_router();
 
         #[cfg(any(
             feature = "compression-br",
@@ -93,7 +170,7 @@ impl VtnServer {
         let router = router.layer(tower_http::compression::CompressionLayer::new());
 
         #[cfg(feature = "mdns")]
-        let mdns_handle = crate::mdns::register_mdns_vtn_service(&config).await;
+        let mdns_handle = crate::mdns::register_mdns_vtn_service(&config).await?;
 
         Ok(VtnServer {
             #[cfg(feature = "mdns")]
@@ -101,9 +178,79 @@ impl VtnServer {
             router,
             listener,
             config,
+            state,
+            background_tasks,
+            #[cfg(feature = "tls")]
+            rustls_config,
+            #[cfg(feature = "tls")]
+            redirect_listener,
         })
     }
 
+    /// Serves the VTN until `shutdown` completes, over HTTPS if TLS is configured.
+    /// Then waits for the running requests and shuts the VTN down, see [`Self::shutdown`].
+    pub async fn serve(
+        self,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> std::io::Result<()> {
+        let VtnServer {
+            #[cfg(feature = "mdns")]
+            mdns_handle,
+            router,
+            listener,
+            config,
+            state,
+            background_tasks,
+            #[cfg(feature = "tls")]
+            rustls_config,
+            #[cfg(feature = "tls")]
+            redirect_listener,
+        } = self;
+
+        let notifier = Arc::clone(&state.notifier);
+        let shutdown = async move {
+            shutdown.await;
+            info!("shutting down");
+            // the HTTP server waits for the running requests, but not for upgraded websocket connections
+            notifier.stop_accepting().await;
+        };
+
+        let result = async {
+            #[cfg(feature = "tls")]
+            if let (Some(rustls_config), Some(tls)) = (rustls_config, config.tls.clone()) {
+                let https_port = listener.local_addr()?.port();
+                let redirect = redirect_listener.map(|listener| {
+                    tokio::spawn(
+                        axum::serve(listener, tls::redirect_router(https_port)).into_future(),
+                    )
+                });
+
+                let result = tls::serve(listener, router, rustls_config, tls, shutdown).await;
+
+                if let Some(redirect) = redirect {
+                    redirect.abort();
+                }
+                return result;
+            }
+
+            axum::serve(listener, router)
+                .with_graceful_shutdown(shutdown)
+                .await
+        }
+        .await;
+
+        Shutdown {
+            config: &config,
+            state,
+            background_tasks,
+            #[cfg(feature = "mdns")]
+            mdns_handle: &mdns_handle,
+        }
+        .run()
+        .await;
+        result
+    }
+
     /// Wait for mDNS service to become discoverable
     #[cfg(feature = "mdns")]
     pub async fn wait_for_mdns_ready(
@@ -133,8 +280,88 @@ impl VtnServer {
         }
     }
 
-    #[cfg(feature = "mdns")]
+    /// Rejects new subscriptions and websocket connections, closes the open websocket connections,
+    /// delivers the queued notifications, disconnects from the MQTT broker,
+    /// unregisters the mDNS service, and closes the database connections.
+    /// Gives up on what did not finish within [`VtnConfig::shutdown_timeout`].
+    ///
+    /// [`Self::serve`] does this after the HTTP server stopped.
     pub async fn shutdown(self) {
-        self.mdns_handle.shutdown().ok();
+        Shutdown {
+            config: &self.config,
+            state: self.state,
+            background_tasks: self.background_tasks,
+            #[cfg(feature = "mdns")]
+            mdns_handle: &self.mdns_handle,
+        }
+        .run()
+        .await
+    }
+}
+
+/// Everything of a VTN to stop besides its HTTP server
+struct Shutdown<'a> {
+    config: &'a VtnConfig,
+    state: AppState,
+    background_tasks: Vec<JoinHandle<()>>,
+    #[cfg(feature = "mdns")]
+    mdns_handle: &'a mdns_sd::ServiceDaemon,
+}
+
+impl Shutdown<'_> {
+    async fn run(self) {
+        let deadline = tokio::time::Instant::now() + self.config.shutdown_timeout;
+
+        // they would only queue more notifications
+        for task in self.background_tasks {
+            task.abort();
+        }
+
+        self.state.notifier.shutdown(deadline).await;
+
+        #[cfg(feature = "mdns")]
+        match tokio::time::timeout_at(
+            deadline,
+            mdns::unregister_mdns_vtn_service(self.mdns_handle, self.config),
+        )
+        .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!("Could not unregister mDNS service: {}", err),
+            Err(_) => warn!("Shutdown deadline reached before unregistering mDNS service"),
+        }
+
+        if tokio::time::timeout_at(deadline, self.state.storage.close())
+            .await
+            .is_err()
+        {
+            warn!("Shutdown deadline reached before closing the database connections");
+        }
+
+        info!("shutdown complete");
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "postgres")]
+mod test {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(migrations = false)]
+    async fn refuses_incompatible_schema(db: PgPool) {
+        let storage = PostgresStorage::new(db).unwrap();
+
+        let err = check_schema(&storage, false).await.unwrap_err();
+        assert!(
+            matches!(&err, StartupError::IncompatibleSchema(problem) if problem.contains("pending")),
+            "{err}"
+        );
+        assert!(!storage.pending_migrations().await.unwrap().is_empty());
+
+        check_schema(&storage, true).await.unwrap();
+        assert!(storage.pending_migrations().await.unwrap().is_empty());
+        check_schema(&storage, false).await.unwrap();
     }
 }
@@ -1,29 +1,205 @@
-use openleadr_vtn::{VtnConfig, VtnServer};
+use std::{path::PathBuf, process::ExitCode};
+
+use openleadr_vtn::{
+    VtnConfig, VtnServer,
+    admin::{self, AdminCommand},
+    data_source::{DataSource, Migrate},
+};
 use tokio::signal;
 use tracing::{error, info};
-use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+const USAGE: &str = "Usage: openleadr-vtn [--config <FILE>] [--check-config] [<COMMAND>]
+
+Commands:
+  migrate [--dry-run]  Apply the pending database migrations, or only list them with --dry-run
+  schema-version       Show the database schema version and whether the VTN can run on it
+  admin <COMMAND>      Manage users and stored objects, see the admin commands below
+
+Without a command, the VTN is started.
+
+Options:
+  --config <FILE>  Read settings from a TOML file. Environment variables take precedence.
+  --check-config   Validate the configuration, report all problems, and exit";
+
+enum Command {
+    Migrate { dry_run: bool },
+    SchemaVersion,
+    Admin(AdminCommand),
+}
+
+#[derive(Default)]
+struct Args {
+    config: Option<PathBuf>,
+    check_config: bool,
+    command: Option<Command>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let path = args.next().ok_or("--config requires a file")?;
+                    parsed.config = Some(path.into());
+                }
+                "--check-config" => parsed.check_config = true,
+                "migrate" if parsed.command.is_none() => {
+                    parsed.command = Some(Command::Migrate { dry_run: false })
+                }
+                "--dry-run" if matches!(parsed.command, Some(Command::Migrate { .. })) => {
+                    parsed.command = Some(Command::Migrate { dry_run: true })
+                }
+                "schema-version" if parsed.command.is_none() => {
+                    parsed.command = Some(Command::SchemaVersion)
+                }
+                "admin" if parsed.command.is_none() => {
+                    parsed.command = Some(Command::Admin(AdminCommand::parse(args.by_ref())?))
+                }
+                _ => return Err(format!("unexpected argument '{arg}'")),
+            }
+        }
+        Ok(parsed)
+    }
+}
 
 #[tokio::main]
-async fn main() {
-    tracing_subscriber::registry()
-        .with(fmt::layer().with_file(true).with_line_number(true))
-        .with(EnvFilter::from_default_env())
-        .init();
+async fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}\n\n{}", admin::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let vtn_config = match VtnConfig::load(args.config.as_deref()) {
+        Ok(vtn_config) => vtn_config,
+        Err(errors) => {
+            eprintln!("invalid configuration:\n{errors}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.check_config {
+        println!("configuration is valid");
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(command) = args.command {
+        return match run_command(command, &vtn_config).await {
+            Ok(exit_code) => exit_code,
+            Err(err) => {
+                eprintln!("error: {err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let registry = tracing_subscriber::registry().with(
+        fmt::layer()
+            .with_file(true)
+            .with_line_number(true)
+            .with_filter(EnvFilter::from_default_env()),
+    );
+
+    #[cfg(feature = "otel")]
+    let tracer_provider = match &vtn_config.otel {
+        Some(otel) => match openleadr_vtn::otel::layer(otel) {
+            Ok((layer, provider)) => {
+                registry.with(layer).init();
+                Some(provider)
+            }
+            Err(err) => {
+                eprintln!("cannot export traces: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => {
+            registry.init();
+            None
+        }
+    };
+    #[cfg(not(feature = "otel"))]
+    registry.init();
 
-    // Load config from environment
-    let vtn_config = VtnConfig::from_env();
-    let server = VtnServer::new(vtn_config).await.unwrap();
+    let server = match VtnServer::new(vtn_config).await {
+        Ok(server) => server,
+        Err(err) => {
+            error!("could not start the VTN: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
 
     info!(
-        "VTN listening on http://{}",
+        "VTN listening on {}://{}",
+        server.config.scheme(),
         server.listener.local_addr().unwrap()
     );
 
-    if let Err(e) = axum::serve(server.listener, server.router)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
+    let result = server.serve(shutdown_signal()).await;
+
+    #[cfg(feature = "otel")]
+    if let Some(provider) = tracer_provider
+        && let Err(err) = provider.shutdown()
     {
+        error!("could not export the remaining traces: {}", err);
+    }
+
+    if let Err(e) = result {
         error!("webserver crashed: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+async fn run_command(
+    command: Command,
+    config: &VtnConfig,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let storage = openleadr_vtn::connect_storage(config).await?;
+
+    match command {
+        Command::Migrate { dry_run } => {
+            let pending = storage.pending_migrations().await?;
+            if pending.is_empty() {
+                println!("database schema is up to date");
+                return Ok(ExitCode::SUCCESS);
+            }
+            for migration in &pending {
+                let action = if dry_run { "would apply" } else { "applying" };
+                println!("{action} {} {}", migration.version, migration.description);
+            }
+            if !dry_run {
+                storage.migrate().await?;
+                println!("applied {} migrations", pending.len());
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::SchemaVersion => {
+            let version = storage.statistics().schema_version().await?;
+            match version.applied {
+                Some(applied) => println!("applied: {applied}"),
+                None => println!("applied: none"),
+            }
+            println!("latest:  {}", version.latest);
+            match version.incompatibility() {
+                Some(problem) => {
+                    println!("the VTN cannot run on this schema: {problem}");
+                    Ok(ExitCode::FAILURE)
+                }
+                None => {
+                    println!("the VTN can run on this schema");
+                    Ok(ExitCode::SUCCESS)
+                }
+            }
+        }
+        Command::Admin(command) => {
+            command.run(&storage, &mut std::io::stdout().lock()).await?;
+            Ok(ExitCode::SUCCESS)
+        }
     }
 }
 
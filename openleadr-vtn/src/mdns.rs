@@ -1,13 +1,12 @@
 use crate::VtnConfig;
 use mdns_sd::{ServiceDaemon, ServiceInfo};
 
-pub async fn register_mdns_vtn_service(config: &VtnConfig) -> ServiceDaemon {
-    let mdns = ServiceDaemon::new().expect("Failed to create daemon");
+pub async fn register_mdns_vtn_service(
+    config: &VtnConfig,
+) -> Result<ServiceDaemon, mdns_sd::Error> {
+    let mdns = ServiceDaemon::new()?;
 
-    let local_url = format!(
-        "http://{}:{}/{}",
-        config.mdns_host_name, config.port, config.mdns_base_path
-    );
+    let local_url = config.local_url();
 
     // Include metadata about the VTN service, such as version and API path
     let properties = [
@@ -23,12 +22,22 @@ pub async fn register_mdns_vtn_service(config: &VtnConfig) -> ServiceDaemon {
         &config.mdns_ip_address,
         config.port,
         &properties[..],
-    )
-    .expect("valid service info");
+    )?;
 
-    mdns.register(vtn_service)
-        .expect("Failed to register VTN service");
-    mdns
+    mdns.register(vtn_service)?;
+    Ok(mdns)
+}
+
+/// Announces that the VTN service is gone and stops the mDNS daemon
+pub async fn unregister_mdns_vtn_service(
+    mdns: &ServiceDaemon,
+    config: &VtnConfig,
+) -> Result<(), mdns_sd::Error> {
+    let fullname = format!("{}.{}", config.mdns_server_name, config.mdns_service_type);
+    // reports the status after sending the goodbye packets
+    let _ = mdns.unregister(&fullname)?.recv_async().await;
+    let _ = mdns.shutdown()?.recv_async().await;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -54,10 +63,14 @@ mod tests {
             mqtt_username: None,
             mqtt_password: None,
             mqtt_topic_prefix: String::new(),
+            mqtt_auth_hook_secret: None,
+            mqtt_batch_window: None,
+            webhook_failure_timeout: std::time::Duration::from_secs(86400),
+            ..VtnConfig::from_env().unwrap()
         };
 
         // Use a single daemon for both advertising and browsing so that we can reliably discover the service on localhost without network complexities.
-        let mdns_daemon = register_mdns_vtn_service(&config).await;
+        let mdns_daemon = register_mdns_vtn_service(&config).await.unwrap();
 
         // We will browse for it on the same daemon
         let receiver = mdns_daemon
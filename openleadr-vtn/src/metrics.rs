@@ -0,0 +1,342 @@
+//! Prometheus metrics of the VTN, served in the OpenMetrics text format at `/metrics`.
+//!
+//! Counters are recorded where the events happen and live for the whole process.
+//! Gauges that describe the current state, e.g., the number of stored objects,
+//! are computed on every scrape instead.
+
+use std::{sync::LazyLock, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::errors::ErrorKind;
+use prometheus_client::{
+    encoding::{EncodeLabelSet, text},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{Histogram, exponential_buckets},
+    },
+    registry::{Registry, Unit},
+};
+use tracing::warn;
+
+use crate::state::AppState;
+
+const PREFIX: &str = "openleadr_vtn";
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+    method: String,
+    /// The route pattern, e.g., `/programs/{id}`, to keep the number of label values bounded
+    route: String,
+    status: u16,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct NotificationLabels {
+    binding: &'static str,
+    result: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct JwtFailureLabels {
+    reason: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PoolLabels {
+    state: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ObjectLabels {
+    object: &'static str,
+}
+
+/// The channel a notification is sent through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Binding {
+    Websocket,
+    Mqtt,
+    Webhook,
+}
+
+impl Binding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Binding::Websocket => "websocket",
+            Binding::Mqtt => "mqtt",
+            Binding::Webhook => "webhook",
+        }
+    }
+}
+
+type HistogramFamily<L> = Family<L, Histogram, fn() -> Histogram>;
+
+struct Metrics {
+    registry: Registry,
+    requests: Family<RequestLabels, Counter>,
+    request_duration: HistogramFamily<RequestLabels>,
+    notifications: Family<NotificationLabels, Counter>,
+    jwt_failures: Family<JwtFailureLabels, Counter>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let mut registry = Registry::with_prefix(PREFIX);
+
+        let requests = Family::default();
+        registry.register(
+            "http_requests",
+            "Handled HTTP requests by route and status",
+            requests.clone(),
+        );
+
+        let request_duration: HistogramFamily<_> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.001, 2.0, 14)));
+        registry.register_with_unit(
+            "http_request_duration",
+            "Time to handle an HTTP request",
+            Unit::Seconds,
+            request_duration.clone(),
+        );
+
+        let notifications = Family::default();
+        registry.register(
+            "notifications",
+            "Notifications, or batches thereof, sent to subscribers by binding",
+            notifications.clone(),
+        );
+
+        let jwt_failures = Family::default();
+        registry.register(
+            "jwt_validation_failures",
+            "Rejected authentication tokens by reason",
+            jwt_failures.clone(),
+        );
+
+        Self {
+            registry,
+            requests,
+            request_duration,
+            notifications,
+            jwt_failures,
+        }
+    }
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Middleware counting and timing the requests per route and response status
+pub(crate) async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_owned();
+
+    let response = next.run(request).await;
+
+    let labels = RequestLabels {
+        method,
+        route,
+        status: response.status().as_u16(),
+    };
+    METRICS.requests.get_or_create(&labels).inc();
+    METRICS
+        .request_duration
+        .get_or_create(&labels)
+        .observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Records a notification, or a batch of notifications, sent via `binding`
+pub(crate) fn notification(binding: Binding, delivered: bool) {
+    let labels = NotificationLabels {
+        binding: binding.as_str(),
+        result: if delivered { "sent" } else { "failed" },
+    };
+    METRICS.notifications.get_or_create(&labels).inc();
+}
+
+/// Records a notification dropped because the queue of its destination was full
+pub(crate) fn notification_dropped(binding: Binding) {
+    let labels = NotificationLabels {
+        binding: binding.as_str(),
+        result: "dropped",
+    };
+    METRICS.notifications.get_or_create(&labels).inc();
+}
+
+/// Records a rejected authentication token
+pub(crate) fn jwt_failure(reason: &'static str) {
+    METRICS
+        .jwt_failures
+        .get_or_create(&JwtFailureLabels { reason })
+        .inc();
+}
+
+/// The reason label for a token `jsonwebtoken` could not validate
+pub(crate) fn jwt_error_reason(kind: &ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::ExpiredSignature => "expired",
+        ErrorKind::ImmatureSignature => "not_yet_valid",
+        ErrorKind::InvalidSignature => "invalid_signature",
+        ErrorKind::InvalidAudience => "invalid_audience",
+        ErrorKind::InvalidIssuer => "invalid_issuer",
+        ErrorKind::InvalidAlgorithm | ErrorKind::MissingAlgorithm => "invalid_algorithm",
+        ErrorKind::MissingRequiredClaim(_) => "missing_claim",
+        _ => "malformed",
+    }
+}
+
+/// Handles `GET /metrics`
+pub(crate) async fn get(State(state): State<AppState>) -> Response {
+    let mut scrape = Registry::with_prefix(PREFIX);
+
+    let pool_state = state.storage.statistics().pool_state();
+    let pool_connections = Family::<PoolLabels, Gauge>::default();
+    pool_connections
+        .get_or_create(&PoolLabels { state: "idle" })
+        .set(pool_state.idle as i64);
+    pool_connections
+        .get_or_create(&PoolLabels { state: "in_use" })
+        .set(i64::from(pool_state.size) - pool_state.idle as i64);
+    scrape.register(
+        "db_pool_connections",
+        "Open connections of the database pool",
+        pool_connections,
+    );
+    let pool_max_connections = Gauge::<i64>::default();
+    pool_max_connections.set(pool_state.max_size.into());
+    scrape.register(
+        "db_pool_max_connections",
+        "Maximum number of connections of the database pool",
+        pool_max_connections,
+    );
+
+    let websockets = Gauge::<i64>::default();
+    websockets.set(state.notifier.websocket_connections().await.len() as i64);
+    scrape.register(
+        "websocket_connections",
+        "Open websocket notifier connections",
+        websockets,
+    );
+
+    // The other metrics are still useful if the database is not reachable
+    match state.storage.statistics().object_counts().await {
+        Ok(counts) => {
+            let objects = Family::<ObjectLabels, Gauge>::default();
+            for (object, count) in counts.iter() {
+                objects.get_or_create(&ObjectLabels { object }).set(count);
+            }
+            scrape.register("objects", "Stored objects by type", objects);
+        }
+        Err(err) => warn!("Could not count objects for the metrics: {}", err),
+    }
+
+    let mut body = String::new();
+    text::encode_registry(&mut body, &METRICS.registry)
+        .and_then(|()| text::encode_registry(&mut body, &scrape))
+        .and_then(|()| text::encode_eof(&mut body))
+        .expect("writing to a String cannot fail");
+
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], body).into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode, header},
+    };
+    use http_body_util::BodyExt;
+    use reqwest::Method;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use crate::{api::test::ApiTest, jwt::Scope};
+
+    async fn scrape(test: &ApiTest) -> String {
+        let response = test
+            .state()
+            .clone()
+            .into_router()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn value<'a>(metrics: &'a str, series: &str) -> &'a str {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("{series} missing in\n{metrics}"))
+    }
+
+    #[sqlx::test(fixtures("api/fixtures/programs.sql"))]
+    async fn exposes_metrics(db: PgPool) {
+        let test = ApiTest::new(db, "bl-client", vec![Scope::ReadAll]).await;
+
+        let status = test.empty_request(Method::GET, "/programs/program-1").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response = test
+            .state()
+            .clone()
+            .into_router()
+            .oneshot(
+                Request::get("/programs")
+                    .header(header::AUTHORIZATION, "Bearer not-a-jwt")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let metrics = scrape(&test).await;
+        assert!(metrics.ends_with("# EOF\n"));
+
+        // other tests run in the same process and share the counters
+        let requests: u64 = value(
+            &metrics,
+            r#"openleadr_vtn_http_requests_total{method="GET",route="/programs/{id}",status="200"}"#,
+        )
+        .parse()
+        .unwrap();
+        assert!(requests >= 1);
+        let failures: u64 = value(
+            &metrics,
+            r#"openleadr_vtn_jwt_validation_failures_total{reason="malformed"}"#,
+        )
+        .parse()
+        .unwrap();
+        assert!(failures >= 1);
+        assert!(metrics.contains("openleadr_vtn_http_request_duration_seconds_bucket{"));
+
+        assert_eq!(
+            value(&metrics, r#"openleadr_vtn_objects{object="program"}"#),
+            "3"
+        );
+        assert_eq!(
+            value(&metrics, r#"openleadr_vtn_objects{object="event"}"#),
+            "0"
+        );
+        assert_eq!(value(&metrics, "openleadr_vtn_websocket_connections"), "0");
+        assert!(metrics.contains(r#"openleadr_vtn_db_pool_connections{state="idle"}"#));
+    }
+}
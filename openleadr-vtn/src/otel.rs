@@ -0,0 +1,150 @@
+//! Export of the `tracing` spans to an OpenTelemetry collector via OTLP/HTTP.
+
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tracing::Subscriber;
+use tracing_subscriber::{EnvFilter, Layer, registry::LookupSpan};
+
+use crate::config::OtelConfig;
+
+/// A `tracing` layer exporting the spans to the configured collector,
+/// and the provider that batches them in the background.
+/// Shut the provider down before exiting, so it exports the remaining spans.
+///
+/// Also makes the VTN accept and forward the W3C `traceparent` header.
+pub fn layer<S>(
+    config: &OtelConfig,
+) -> Result<(impl Layer<S> + use<S>, SdkTracerProvider), ExporterBuildError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let endpoint = format!(
+        "{}/v1/traces",
+        config.endpoint.as_str().trim_end_matches('/')
+    );
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("openleadr-vtn"))
+        .with_filter(EnvFilter::new(&config.filter));
+
+    Ok((layer, provider))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        Router,
+        body::{Body, Bytes},
+        extract::State,
+        http::Request,
+        routing::{get, post},
+    };
+    use opentelemetry_proto::tonic::{
+        collector::trace::v1::ExportTraceServiceRequest, common::v1::any_value::Value,
+    };
+    use prost::Message;
+    use tokio::sync::mpsc;
+    use tower::ServiceExt;
+    use tower_http::trace::TraceLayer;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::correlation;
+
+    const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+    const PARENT_ID: &str = "b7ad6b7169203331";
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Stands in for a collector, forwarding the bodies it receives
+    async fn collector() -> (reqwest::Url, mpsc::UnboundedReceiver<Bytes>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route(
+                "/v1/traces",
+                post(
+                    |State(tx): State<mpsc::UnboundedSender<Bytes>>, body: Bytes| async move {
+                        tx.send(body).unwrap();
+                    },
+                ),
+            )
+            .with_state(tx);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (endpoint.parse().unwrap(), rx)
+    }
+
+    // the exporter uses a blocking HTTP client, which needs a thread to spare
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_request_span_continuing_the_trace() {
+        let (endpoint, mut bodies) = collector().await;
+        let config = OtelConfig {
+            endpoint,
+            service_name: "test-vtn".to_string(),
+            filter: "info".to_string(),
+        };
+        let (layer, provider) = layer(&config).unwrap();
+        let guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        let app = Router::new()
+            .route("/", get(|| async {}))
+            .layer(TraceLayer::new_for_http().make_span_with(correlation::make_span));
+        app.oneshot(
+            Request::get("/")
+                .header("traceparent", format!("00-{TRACE_ID}-{PARENT_ID}-01"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        drop(guard);
+
+        tokio::task::spawn_blocking(move || provider.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let body = bodies.recv().await.unwrap();
+        let export = ExportTraceServiceRequest::decode(body).unwrap();
+        let resource_spans = &export.resource_spans[0];
+        let service_name = resource_spans
+            .resource
+            .iter()
+            .flat_map(|resource| &resource.attributes)
+            .find(|attribute| attribute.key == "service.name")
+            .and_then(|attribute| attribute.value.as_ref()?.value.as_ref());
+        assert!(
+            matches!(service_name, Some(Value::StringValue(name)) if name == "test-vtn"),
+            "{service_name:?}"
+        );
+
+        let span = resource_spans
+            .scope_spans
+            .iter()
+            .flat_map(|scope| &scope.spans)
+            .find(|span| span.name == "request")
+            .expect("request span exported");
+        assert_eq!(hex(&span.trace_id), TRACE_ID);
+        assert_eq!(hex(&span.parent_span_id), PARENT_ID);
+    }
+}
@@ -1,17 +1,20 @@
 #[cfg(feature = "internal-oauth")]
 use crate::api::auth;
 use crate::{
-    VtnConfig,
+    StartupError, VtnConfig,
     api::subscription::MqttConfig,
+    config::{OAuthConfig, OAuthKeyType, OAuthType},
+    correlation,
     data_source::{ResourceGroupCrud, SubscriptionCrud},
 };
 #[cfg(feature = "internal-oauth")]
 use crate::{api::user, data_source::AuthSource};
-#[cfg(feature = "internal-oauth")]
-use axum::routing::{delete, post};
 
 use crate::{
-    api::{event, healthcheck, program, report, resource, resource_group, subscription, ven},
+    api::{
+        compliance, event, health, healthcheck, idempotency, notifier_admin, program, report,
+        resource, resource_group, subscription, ven,
+    },
     data_source::{
         DataSource, EventCrud, ProgramCrud, ReportCrud, ResourceCrud, VenCrud, VenObjectPrivacy,
     },
@@ -23,96 +26,25 @@ use axum::{
     extract::{FromRef, Request, State},
     middleware::{self, Next},
     response::IntoResponse,
-    routing::get,
-};
-use base64::{
-    Engine, alphabet,
-    engine::{GeneralPurpose, general_purpose::PAD},
+    routing::{delete, get, post},
 };
+use jsonwebtoken::{Algorithm, Validation};
 #[cfg(feature = "internal-oauth")]
-use jsonwebtoken::EncodingKey;
-use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use jsonwebtoken::{DecodingKey, EncodingKey};
 use openleadr_wire::oauth::AuthServerInfo;
-use reqwest::{StatusCode, Url};
-use serde::{Deserialize, Serialize};
-use std::{
-    cmp::PartialEq,
-    env,
-    env::VarError,
-    io::{BufReader, Read},
-    str::FromStr,
-    sync::Arc,
-};
+use reqwest::StatusCode;
+use std::{sync::Arc, time::Duration};
 use tower_http::trace::TraceLayer;
-use tracing::info;
 
 #[derive(Clone, FromRef)]
 pub struct AppState {
     pub storage: Arc<dyn DataSource>,
     pub jwt_manager: Arc<JwtManager>,
     pub(crate) notifier: Arc<subscription::NotifierState>,
-}
-
-#[derive(Debug, Default, Copy, Clone)]
-pub(crate) enum OAuthType {
-    #[default]
-    Internal,
-    External,
-}
-
-impl FromStr for OAuthType {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "internal" => Ok(Self::Internal),
-            "external" => Ok(Self::External),
-            _ => Err(()),
-        }
-    }
-}
-
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
-#[serde(rename_all = "UPPERCASE")]
-pub(crate) enum OAuthKeyType {
-    Hmac,
-    Rsa,
-    Ec,
-    #[serde(rename = "OKP")]
-    Ed,
-}
-
-impl FromStr for OAuthKeyType {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "hmac" => Ok(Self::Hmac),
-            "rsa" => Ok(Self::Rsa),
-            "ec" => Ok(Self::Ec),
-            "ed" => Ok(Self::Ed),
-            _ => Err(()),
-        }
-    }
-}
-
-fn audiences_from_env() -> Option<Vec<String>> {
-    let audience_str = env::var("OAUTH_VALID_AUDIENCES").ok()?;
-    // Split the string by commas and collect into a vector
-    Some(audience_str.split(',').map(|s| s.to_string()).collect())
-}
-
-fn hmac_from_env() -> Result<Vec<u8>, VarError> {
-    env::var("OAUTH_BASE64_SECRET").map(|base64_secret| {
-        let secret = GeneralPurpose::new(&alphabet::STANDARD, PAD)
-            .decode(base64_secret)
-            .expect("OAUTH_BASE64_SECRET contains invalid base64 string");
-        if secret.len() < 32 {
-            // https://datatracker.ietf.org/doc/html/rfc7518#section-3.2
-            panic!("OAUTH_BASE64_SECRET must have at least 32 bytes");
-        }
-        secret
-    })
+    /// The paths the API is served under
+    pub(crate) base_paths: Arc<[String]>,
+    /// Responses to requests with an `Idempotency-Key` are replayed for this long
+    pub(crate) idempotency_window: Duration,
 }
 
 fn signing_algorithms_from_key_type(key_type: &OAuthKeyType) -> Vec<Algorithm> {
@@ -139,242 +71,183 @@ fn signing_algorithms_from_key_type(key_type: &OAuthKeyType) -> Vec<Algorithm> {
     }
 }
 
-fn validation_from_key_type_and_env(key_type: &OAuthKeyType) -> Validation {
+fn validation_from_config(config: &OAuthConfig) -> Validation {
     let mut validation = Validation::default();
     validation.validate_nbf = true;
-    validation.algorithms = signing_algorithms_from_key_type(key_type);
-    if let Some(audiences) = audiences_from_env() {
+    validation.algorithms = signing_algorithms_from_key_type(&config.key_type);
+    if let Some(audiences) = &config.valid_audiences {
         validation.set_required_spec_claims(&["exp", "aud"]);
-        validation.set_audience(&audiences);
+        validation.set_audience(audiences);
     }
     validation
 }
 
-#[cfg(feature = "internal-oauth")]
-fn internal_oauth_from_env(key_type: Option<OAuthKeyType>) -> JwtManager {
-    if let Some(k_type) = key_type
-        && k_type != OAuthKeyType::Hmac
-    {
-        panic!("Internal OAuth provider only supports HMAC JWT keys");
-    }
-
-    let secret = hmac_from_env().unwrap_or_else(|_| {
-        tracing::warn!("Generating random secret as OAUTH_BASE64_SECRET env var was not found");
-        let secret: [u8; 32] = rand::random();
-        secret.to_vec()
-    });
-
-    let key_type: OAuthKeyType = key_type.unwrap_or(OAuthKeyType::Hmac);
-
-    let validation = validation_from_key_type_and_env(&key_type);
-
-    let token_url: Url = env::var("OAUTH_TOKEN_URL")
-        .expect("OAUTH_TOKEN_URL environment variable must be set. As you are using the internal OAuth provider, you must set this to the URL of your OpenLEADR server. For example, if your OpenLEADR server is running on localhost:3000, then set this to http://localhost:3000/auth/token")
-        .parse()
-        .expect("OAUTH_TOKEN_URL environment variable must be a valid URL");
-
-    JwtManager::new_internal_auth(
-        EncodingKey::from_secret(&secret),
-        DecodingKey::from_secret(&secret),
-        key_type,
-        validation,
-        token_url,
-    )
-}
-
-async fn external_oauth_from_env(key_type: Option<OAuthKeyType>) -> JwtManager {
-    let key_type = key_type.expect("Must specify key type for external OAuth provider. Use OAUTH_KEY_TYPE environment variable");
-
-    let validation = validation_from_key_type_and_env(&key_type);
-
-    let oauth_jwks_location = env::var("OAUTH_JWKS_LOCATION");
-    let oauth_keyfile = env::var("OAUTH_PEM");
-
-    // Try to load decoding key from environment;
-    //
-    // for HMAC by loading OAUTH_BASE64_SECRET
-    // for other key types, by looking at OAUTH_PEM
-    let key = match key_type {
-        OAuthKeyType::Hmac => {
-            let secret = hmac_from_env().expect("OAUTH_BASE64_SECRET environment variable must be set for external OAuth provider with key type HMAC");
-            Some(DecodingKey::from_secret(&secret))
-        }
-
-        OAuthKeyType::Rsa => match oauth_keyfile {
-            Ok(rsa_file) => {
-                let pem_bytes = BufReader::new(
-                    std::fs::File::open(rsa_file)
-                        .expect("File specified in OAUTH_PEM environment variable does not exist"),
-                )
-                .bytes()
-                .collect::<Result<Vec<u8>, _>>()
-                .expect("Cannot read RSA key");
-
-                Some(DecodingKey::from_rsa_pem(&pem_bytes).expect("Cannot read RSA key"))
-            }
-            Err(_) => None,
-        },
-
-        OAuthKeyType::Ec => match oauth_keyfile {
-            Ok(ec_file) => {
-                let pem_bytes = BufReader::new(
-                    std::fs::File::open(ec_file)
-                        .expect("File specified in OAUTH_PEM environment variable does not exist"),
-                )
-                .bytes()
-                .collect::<Result<Vec<u8>, _>>()
-                .expect("Cannot read RSA key");
-
-                Some(DecodingKey::from_ec_pem(&pem_bytes).expect("Cannot read EC key"))
-            }
-            Err(_) => None,
-        },
-
-        OAuthKeyType::Ed => match oauth_keyfile {
-            Ok(ed_file) => {
-                let pem_bytes = BufReader::new(
-                    std::fs::File::open(ed_file)
-                        .expect("File specified in OAUTH_PEM environment variable does not exist"),
-                )
-                .bytes()
-                .collect::<Result<Vec<u8>, _>>()
-                .expect("Cannot read RSA key");
+fn jwt_manager_from_config(config: &OAuthConfig) -> Result<JwtManager, StartupError> {
+    let validation = validation_from_config(config);
 
-                Some(DecodingKey::from_ed_pem(&pem_bytes).expect("Cannot read Ed key"))
-            }
-            Err(_) => None,
-        },
-    };
-
-    // If no decoding key was found, then OAUTH_JWKS_LOCATION must be used
-    if key.is_none() && oauth_jwks_location.is_err() {
-        panic!(
-            "OAUTH_PEM or OAUTH_JWKS_LOCATION environment variable must be set for external OAuth provider with the given key type"
-        );
+    match config.provider {
+        #[cfg(feature = "internal-oauth")]
+        OAuthType::Internal => {
+            let secret = config.secret.clone().unwrap_or_else(|| {
+                tracing::warn!("Generating random secret as no OAuth secret was configured");
+                let secret: [u8; 32] = rand::random();
+                secret.to_vec()
+            });
+
+            Ok(JwtManager::new_internal_auth(
+                EncodingKey::from_secret(&secret),
+                DecodingKey::from_secret(&secret),
+                config.key_type,
+                validation,
+                config.token_url.clone(),
+            ))
+        }
+        #[cfg(not(feature = "internal-oauth"))]
+        OAuthType::Internal => Err(crate::config::ConfigErrors::from(
+            crate::config::ConfigError::invalid(
+                crate::config::OAUTH_TYPE,
+                "the internal OAuth provider requires the `internal-oauth` feature",
+            ),
+        )
+        .into()),
+        OAuthType::External => Ok(JwtManager::new(
+            config.decoding_key()?,
+            config.jwks_location.clone(),
+            config.key_type,
+            validation,
+            config.token_url.clone(),
+        )),
     }
-
-    let token_url: Url = env::var("OAUTH_TOKEN_URL")
-        .expect("OAUTH_TOKEN_URL environment variable must be set for external OAuth provider")
-        .parse()
-        .expect("OAUTH_TOKEN_URL environment variable must be a valid URL");
-
-    JwtManager::new(
-        key,
-        oauth_jwks_location.ok(),
-        key_type,
-        validation,
-        token_url,
-    )
 }
 
 impl AppState {
-    pub async fn new<S: DataSource>(storage: S, config: &VtnConfig) -> Self {
-        let oauth_type: OAuthType = env::var("OAUTH_TYPE")
-            .inspect_err(|_|{
-            info!("Did not find OAUTH_TYPE environment variable, using internal OAuth provider.")}
-            )
-            .map(|env| env.parse()
-                .expect("Invalid value for OAUTH_TYPE environment variable. Allowed are INTERNAL and EXTERNAL."))
-            .unwrap_or_default();
-
-        let key_type: Option<OAuthKeyType> = env::var("OAUTH_KEY_TYPE").ok().map(|k| k.parse().expect("Invalid value for OAUTH_KEY_TYPE environment variable. Allowed are HMAC, RSA, EC, and ED."));
-
-        let jwt_manager = match oauth_type {
-            #[cfg(feature = "internal-oauth")]
-            OAuthType::Internal => internal_oauth_from_env(key_type),
-            #[cfg(not(feature = "internal-oauth"))]
-            OAuthType::Internal => {
-                panic!(
-                    "Can't use internal OAuth provider as the 'internal-oauth' feature is disabled. \
-            Please recompile with the 'internal-oauth' feature enabled if you want to use it or set \
-            OAUTH_TYPE to EXTERNAL if you want to use an external OAuth provider."
-                );
-            }
-            OAuthType::External => external_oauth_from_env(key_type).await,
-        };
-
-        let mqtt_config = match (
-            &config.mqtt_url,
-            &config.mqtt_username,
-            &config.mqtt_password,
-        ) {
-            (Some(url), Some(username), Some(password)) => Some(MqttConfig {
-                url: url.clone(),
-                username: username.clone(),
-                password: password.clone(),
-                topic_prefix: config.mqtt_topic_prefix.clone(),
-            }),
-            (None, None, None) => None,
-            _ => panic!(
-                "Incomplete MQTT configuration. Expect all of the MQTT_URL, MQTT_USERNAME and MQTT_PASSWORD environment variables to be set when one of them is present."
-            ),
-        };
-
-        let notifier =
-            subscription::NotifierState::load_from_storage(&*storage.subscriptions(), mqtt_config)
-                .await
-                .expect("failed to retrieve subscriptions from database");
-
-        Self {
+    pub async fn new<S: DataSource>(storage: S, config: &VtnConfig) -> Result<Self, StartupError> {
+        let jwt_manager = jwt_manager_from_config(&config.oauth)?;
+
+        let mqtt_config = config.mqtt()?.map(|(url, username, password)| MqttConfig {
+            url: url.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            topic_prefix: config.mqtt_topic_prefix.clone(),
+            auth_hook_secret: config.mqtt_auth_hook_secret.clone(),
+            batch_window: config.mqtt_batch_window,
+        });
+
+        let notifier = subscription::NotifierState::load_from_storage(
+            &*storage.subscriptions(),
+            mqtt_config,
+            config.webhook_failure_timeout,
+        )
+        .await
+        .map_err(StartupError::Notifier)?;
+
+        Ok(Self {
             storage: Arc::new(storage),
             jwt_manager: Arc::new(jwt_manager),
             notifier: Arc::new(notifier),
+            base_paths: config.base_paths().map(ToOwned::to_owned).collect(),
+            idempotency_window: config.idempotency_window,
+        })
+    }
+
+    /// The routes creating objects, which support safe retries with an `Idempotency-Key`
+    fn create_router(&self) -> axum::Router<Self> {
+        #[allow(unused_mut)]
+        let mut router = axum::Router::new()
+            .route("/programs", post(program::add))
+            .route("/reports", post(report::add))
+            .route("/reports/{id}/intervals", post(report::append_intervals))
+            .route("/events", post(event::add))
+            .route("/events:batch", post(event::batch))
+            .route("/vens", post(ven::add))
+            .route("/vens:batch", post(ven::batch))
+            .route("/resource_groups", post(resource_group::add))
+            .route("/resources", post(resource::add))
+            .route("/resources:batch", post(resource::batch))
+            .route("/subscriptions", post(subscription::add));
+        #[cfg(feature = "internal-oauth")]
+        {
+            router = router.route("/users", post(user::add_user));
         }
+        router.route_layer(middleware::from_fn_with_state(
+            self.clone(),
+            idempotency::middleware,
+        ))
     }
 
-    fn router_without_state() -> axum::Router<Self> {
+    /// The routes of the API, relative to a base path
+    fn api_router(&self) -> axum::Router<Self> {
         #[allow(unused_mut)]
         let mut router = axum::Router::new()
-            .route("/health", get(healthcheck))
-            .route("/programs", get(program::get_all).post(program::add))
+            .route("/programs", get(program::get_all))
             .route(
                 "/programs/{id}",
-                get(program::get).put(program::edit).delete(program::delete),
+                get(program::get)
+                    .put(program::edit)
+                    .patch(program::patch)
+                    .delete(program::delete),
             )
-            .route("/reports", get(report::get_all).post(report::add))
+            .route("/reports", get(report::get_all))
             .route(
                 "/reports/{id}",
-                get(report::get).put(report::edit).delete(report::delete),
+                get(report::get)
+                    .put(report::edit)
+                    .patch(report::patch)
+                    .delete(report::delete),
             )
-            .route("/events", get(event::get_all).post(event::add))
+            .route("/events", get(event::get_all))
             .route(
                 "/events/{id}",
-                get(event::get).put(event::edit).delete(event::delete),
+                get(event::get)
+                    .put(event::edit)
+                    .patch(event::patch)
+                    .delete(event::delete),
             )
-            .route("/vens", get(ven::get_all).post(ven::add))
+            .route("/events/{id}/compliance", get(compliance::get))
+            .route("/vens", get(ven::get_all))
             .route(
                 "/vens/{id}",
-                get(ven::get).put(ven::edit).delete(ven::delete),
-            )
-            .route(
-                "/resource_groups",
-                get(resource_group::get_all).post(resource_group::add),
+                get(ven::get)
+                    .put(ven::edit)
+                    .patch(ven::patch)
+                    .delete(ven::delete),
             )
+            .route("/resource_groups", get(resource_group::get_all))
             .route(
                 "/resource_groups/{id}",
                 get(resource_group::get)
                     .put(resource_group::edit)
+                    .patch(resource_group::patch)
                     .delete(resource_group::delete),
             )
-            .route("/resources", get(resource::get_all).post(resource::add))
+            .route("/resources", get(resource::get_all))
             .route(
                 "/resources/{id}",
                 get(resource::get)
                     .put(resource::edit)
+                    .patch(resource::patch)
                     .delete(resource::delete),
             )
-            .route(
-                "/subscriptions",
-                get(subscription::get_all).post(subscription::add),
-            )
+            .route("/subscriptions", get(subscription::get_all))
             .route(
                 "/subscriptions/{id}",
                 get(subscription::get)
                     .put(subscription::edit)
+                    .patch(subscription::patch)
                     .delete(subscription::delete),
             )
+            .route("/subscriptions/{id}/renew", post(subscription::renew))
             .route("/auth/server", get(auth_server_handler))
-            .route("/notifiers", get(subscription::notifier_get));
+            .route("/notifiers", get(subscription::notifier_get))
+            .route("/notifiers/admin", get(notifier_admin::get))
+            .route(
+                "/notifiers/admin/websockets/{client_id}",
+                delete(notifier_admin::disconnect_websocket),
+            )
+            .route(
+                "/notifiers/admin/subscriptions/{id}/test",
+                post(notifier_admin::send_test_notification),
+            );
         #[cfg(feature = "experimental-websockets")]
         {
             router = router.route("/notifiers/ws", get(subscription::notifier_websocket_get));
@@ -386,7 +259,7 @@ impl AppState {
         {
             router = router
                 .route("/auth/token", post(auth::token))
-                .route("/users", get(user::get_all).post(user::add_user))
+                .route("/users", get(user::get_all))
                 .route(
                     "/users/{id}",
                     get(user::get)
@@ -399,14 +272,40 @@ impl AppState {
                     delete(user::delete_credential),
                 );
         }
-        router
+        router.merge(self.create_router())
+    }
+
+    /// The API under each of `base_paths`, and the health checks and metrics at the root
+    fn router_without_state(&self) -> axum::Router<Self> {
+        #[allow(unused_mut)]
+        let mut router = axum::Router::new()
+            .route("/health", get(healthcheck))
+            .route("/health/live", get(health::live))
+            .route("/health/ready", get(health::ready));
+        #[cfg(feature = "metrics")]
+        {
+            router = router.route("/metrics", get(crate::metrics::get));
+        }
+        for base_path in self.base_paths.iter() {
+            router = match base_path.as_str() {
+                "" => router.merge(self.api_router()),
+                base_path => router.nest(base_path, self.api_router()),
+            };
+        }
+        router = router
             .fallback(handler_404)
-            .layer(middleware::from_fn(method_not_allowed))
-            .layer(TraceLayer::new_for_http())
+            .layer(middleware::from_fn(method_not_allowed));
+        #[cfg(feature = "metrics")]
+        {
+            router = router.layer(middleware::from_fn(crate::metrics::track_requests));
+        }
+        router
+            .layer(TraceLayer::new_for_http().make_span_with(correlation::make_span))
+            .layer(middleware::from_fn(correlation::request_id_middleware))
     }
 
     pub fn into_router(self) -> axum::Router {
-        Self::router_without_state().with_state(self)
+        self.router_without_state().with_state(self)
     }
 }
 
@@ -487,16 +386,22 @@ impl FromRef<AppState> for Arc<dyn SubscriptionCrud> {
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+
     use openleadr_wire::{
         ClientId,
         subscription::{Subscription, SubscriptionId, SubscriptionRequest},
     };
 
-    use crate::data_source::Crud;
+    use crate::{
+        data_source::{Crud, Locked},
+        jwt::Scope,
+    };
 
     use super::*;
 
     struct MockDataSource {}
+    #[async_trait::async_trait]
     impl DataSource for MockDataSource {
         fn programs(&self) -> Arc<dyn ProgramCrud> {
             unimplemented!()
@@ -530,6 +435,18 @@ mod test {
             Arc::new(MockSubscriptionSource)
         }
 
+        fn statistics(&self) -> Arc<dyn crate::data_source::StatisticsSource> {
+            unimplemented!()
+        }
+
+        fn snapshots(&self) -> Arc<dyn crate::data_source::SnapshotSource> {
+            unimplemented!()
+        }
+
+        fn idempotency(&self) -> Arc<dyn crate::data_source::IdempotencySource> {
+            unimplemented!()
+        }
+
         #[cfg(feature = "internal-oauth")]
         fn auth(&self) -> Arc<dyn AuthSource> {
             unimplemented!()
@@ -538,6 +455,10 @@ mod test {
         fn connection_active(&self) -> bool {
             unimplemented!()
         }
+
+        async fn close(&self) {
+            unimplemented!()
+        }
     }
 
     struct MockSubscriptionSource;
@@ -575,6 +496,14 @@ mod test {
             Ok(vec![])
         }
 
+        async fn count(
+            &self,
+            _filter: &Self::Filter,
+            _client_id: &Self::PermissionFilter,
+        ) -> Result<u64, Self::Error> {
+            Ok(0)
+        }
+
         async fn update(
             &self,
             _id: &Self::Id,
@@ -591,14 +520,59 @@ mod test {
         ) -> Result<Self::Type, Self::Error> {
             unimplemented!()
         }
+
+        async fn lock(
+            &self,
+            _id: &Self::Id,
+        ) -> Result<
+            Box<dyn Locked<Self::NewType, Self::Type, Self::PermissionFilter, Self::Error>>,
+            Self::Error,
+        > {
+            unimplemented!()
+        }
     }
 
-    impl SubscriptionCrud for MockSubscriptionSource {}
+    #[async_trait::async_trait]
+    impl SubscriptionCrud for MockSubscriptionSource {
+        async fn create_with_owner_scopes(
+            &self,
+            _new: SubscriptionRequest,
+            _client_id: &ClientId,
+            _owner_scopes: &[Scope],
+        ) -> Result<Subscription, AppError> {
+            unimplemented!()
+        }
+
+        async fn owner_scopes(&self) -> Result<HashMap<SubscriptionId, Vec<Scope>>, AppError> {
+            Ok(HashMap::new())
+        }
+
+        async fn renew(
+            &self,
+            _id: &SubscriptionId,
+            _client_id: &Option<ClientId>,
+        ) -> Result<Subscription, AppError> {
+            unimplemented!()
+        }
+
+        async fn delete_by_client_id(
+            &self,
+            _client_id: &ClientId,
+        ) -> Result<Vec<Subscription>, AppError> {
+            unimplemented!()
+        }
+
+        async fn delete_expired(&self) -> Result<Vec<Subscription>, AppError> {
+            Ok(vec![])
+        }
+    }
 
     // It is critical for the safety assumption of set_env_var and remove_env_var that the tests in
     // this module do not run in parallel.
     #[serial_test::serial]
     mod state_from_env_var {
+        use std::env;
+
         use super::*;
 
         fn set_env_var(key: &str, value: &str) {
@@ -615,6 +589,16 @@ mod test {
             }
         }
 
+        fn assert_config_error(expected: &str) {
+            let errors = VtnConfig::from_env().unwrap_err().to_string();
+            assert!(
+                errors
+                    .lines()
+                    .any(|error| error.starts_with(&format!("- {expected}"))),
+                "missing {expected:?} in\n{errors}"
+            );
+        }
+
         fn clean_env() {
             remove_env_var("OAUTH_BASE64_SECRET");
             remove_env_var("OAUTH_TYPE");
@@ -624,22 +608,24 @@ mod test {
             remove_env_var("OAUTH_VALID_AUDIENCES");
         }
 
-        #[tokio::test]
-        #[should_panic(expected = "OAUTH_BASE64_SECRET must have at least 32 bytes")]
-        async fn internal_oauth_short_secret() {
+        #[test]
+        fn internal_oauth_short_secret() {
             clean_env();
             set_env_var("OAUTH_TOKEN_URL", "http://localhost:3000/auth/token");
             set_env_var("OAUTH_BASE64_SECRET", "1234");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            assert_config_error(
+                "`oauth.base64_secret` (OAUTH_BASE64_SECRET): must have at least 32 bytes",
+            );
         }
 
-        #[tokio::test]
-        #[should_panic(expected = "OAUTH_BASE64_SECRET contains invalid base64 string")]
-        async fn internal_oauth_invalid_base64_secret() {
+        #[test]
+        fn internal_oauth_invalid_base64_secret() {
             clean_env();
             set_env_var("OAUTH_TOKEN_URL", "http://localhost:3000/auth/token");
             set_env_var("OAUTH_BASE64_SECRET", "&");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            assert_config_error(
+                "`oauth.base64_secret` (OAUTH_BASE64_SECRET): invalid base64 string",
+            );
         }
 
         #[tokio::test]
@@ -650,7 +636,9 @@ mod test {
                 "OAUTH_BASE64_SECRET",
                 "60QL3fluRYn/21n0zNoPe1np5aB6P9C75b0Nbkwu4FM=",
             );
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            AppState::new(MockDataSource {}, &VtnConfig::from_env().unwrap())
+                .await
+                .unwrap();
         }
 
         #[tokio::test]
@@ -662,7 +650,9 @@ mod test {
                 "OAUTH_BASE64_SECRET",
                 "60QL3fluRYn/21n0zNoPe1np5aB6P9C75b0Nbkwu4FM=",
             );
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            AppState::new(MockDataSource {}, &VtnConfig::from_env().unwrap())
+                .await
+                .unwrap();
         }
 
         #[tokio::test]
@@ -675,12 +665,13 @@ mod test {
                 "OAUTH_BASE64_SECRET",
                 "60QL3fluRYn/21n0zNoPe1np5aB6P9C75b0Nbkwu4FM=",
             );
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            AppState::new(MockDataSource {}, &VtnConfig::from_env().unwrap())
+                .await
+                .unwrap();
         }
 
-        #[tokio::test]
-        #[should_panic(expected = "Internal OAuth provider only supports HMAC JWT keys")]
-        async fn explicit_internal_explicit_wrong_key_type_oauth() {
+        #[test]
+        fn explicit_internal_explicit_wrong_key_type_oauth() {
             clean_env();
             set_env_var("OAUTH_TOKEN_URL", "http://localhost:3000/auth/token");
             set_env_var("OAUTH_TYPE", "INTERNAL");
@@ -689,33 +680,33 @@ mod test {
                 "OAUTH_BASE64_SECRET",
                 "60QL3fluRYn/21n0zNoPe1np5aB6P9C75b0Nbkwu4FM=",
             );
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            assert_config_error(
+                "`oauth.key_type` (OAUTH_KEY_TYPE): the internal OAuth provider only supports HMAC keys",
+            );
         }
 
-        #[tokio::test]
-        #[should_panic(
-            expected = "Must specify key type for external OAuth provider. Use OAUTH_KEY_TYPE environment variable"
-        )]
-        async fn external_missing_key_type_oauth() {
+        #[test]
+        fn external_missing_key_type_oauth() {
             clean_env();
             set_env_var("OAUTH_TOKEN_URL", "http://sometwhere/auth/token");
             set_env_var("OAUTH_TYPE", "EXTERNAL");
             set_env_var("OAUTH_VALID_AUDIENCES", "http://localhost:3000,");
             set_env_var("OAUTH_PEM", "./tests/assets/public-rsa.pem");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            assert_config_error(
+                "`oauth.key_type` (OAUTH_KEY_TYPE): must be set for an external OAuth provider",
+            );
         }
 
-        #[tokio::test]
-        #[should_panic(
-            expected = "OAUTH_PEM or OAUTH_JWKS_LOCATION environment variable must be set for external OAuth provider with the given key type"
-        )]
-        async fn external_missing_jwks_location_oauth_and_oauth_pem() {
+        #[test]
+        fn external_missing_jwks_location_oauth_and_oauth_pem() {
             clean_env();
             set_env_var("OAUTH_TOKEN_URL", "http://sometwhere/auth/token");
             set_env_var("OAUTH_TYPE", "EXTERNAL");
             set_env_var("OAUTH_VALID_AUDIENCES", "http://localhost:3000,");
             set_env_var("OAUTH_KEY_TYPE", "RSA");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            assert_config_error(
+                "`oauth.pem` (OAUTH_PEM): either this or `oauth.jwks_location` (OAUTH_JWKS_LOCATION) must be set for an external OAuth provider with the given key type",
+            );
         }
 
         #[tokio::test]
@@ -725,7 +716,9 @@ mod test {
             set_env_var("OAUTH_TYPE", "EXTERNAL");
             set_env_var("OAUTH_KEY_TYPE", "RSA");
             set_env_var("OAUTH_JWKS_LOCATION", "http://localhost:3000/jwks");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            AppState::new(MockDataSource {}, &VtnConfig::from_env().unwrap())
+                .await
+                .unwrap();
         }
 
         #[tokio::test]
@@ -736,31 +729,31 @@ mod test {
             set_env_var("OAUTH_KEY_TYPE", "RSA");
             set_env_var("OAUTH_JWKS_LOCATION", "http://localhost:3000/jwks");
             set_env_var("OAUTH_VALID_AUDIENCES", "http://localhost:3000,");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            AppState::new(MockDataSource {}, &VtnConfig::from_env().unwrap())
+                .await
+                .unwrap();
         }
 
-        #[tokio::test]
-        #[should_panic(expected = "Cannot read EC key: Error(InvalidKeyFormat)")]
-        async fn external_provide_rsa_key_instead_of_ec() {
+        #[test]
+        fn external_provide_rsa_key_instead_of_ec() {
             clean_env();
             set_env_var("OAUTH_TOKEN_URL", "http://sometwhere/auth/token");
             set_env_var("OAUTH_TYPE", "EXTERNAL");
             set_env_var("OAUTH_KEY_TYPE", "EC");
             set_env_var("OAUTH_VALID_AUDIENCES", "http://localhost:3000,");
             set_env_var("OAUTH_PEM", "./tests/assets/public-rsa.pem");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            assert_config_error("`oauth.pem` (OAUTH_PEM): cannot read Ec key: InvalidKeyFormat");
         }
 
-        #[tokio::test]
-        #[should_panic(expected = "Cannot read Ed key: Error(InvalidKeyFormat)")]
-        async fn external_provide_rsa_key_instead_of_ed() {
+        #[test]
+        fn external_provide_rsa_key_instead_of_ed() {
             clean_env();
             set_env_var("OAUTH_TOKEN_URL", "http://sometwhere/auth/token");
             set_env_var("OAUTH_TYPE", "EXTERNAL");
             set_env_var("OAUTH_KEY_TYPE", "ED");
             set_env_var("OAUTH_VALID_AUDIENCES", "http://localhost:3000,");
             set_env_var("OAUTH_PEM", "./tests/assets/public-rsa.pem");
-            AppState::new(MockDataSource {}, &VtnConfig::from_env()).await;
+            assert_config_error("`oauth.pem` (OAUTH_PEM): cannot read Ed key: InvalidKeyFormat");
         }
     }
 }
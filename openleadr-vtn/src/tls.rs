@@ -0,0 +1,230 @@
+//! HTTPS serving with certificates that are reloaded when their files change.
+
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    Router,
+    http::{HeaderMap, StatusCode, Uri, header, uri::Authority},
+    response::Redirect,
+};
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use tokio::{net::TcpListener, time::MissedTickBehavior};
+use tracing::{info, warn};
+
+use crate::config::TlsConfig;
+
+/// Time between two checks whether the certificate or key file changed
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+pub(crate) async fn rustls_config(tls: &TlsConfig) -> io::Result<RustlsConfig> {
+    // Several crypto providers are compiled in through our dependencies,
+    // so rustls cannot pick one on its own. Fails if one is installed already.
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    RustlsConfig::from_pem_file(&tls.cert, &tls.key).await
+}
+
+/// Serves `router` over HTTPS until `shutdown` completes
+pub(crate) async fn serve(
+    listener: TcpListener,
+    router: Router,
+    rustls_config: RustlsConfig,
+    tls: TlsConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let reload = tokio::spawn(reload_on_change(
+        rustls_config.clone(),
+        tls,
+        RELOAD_INTERVAL,
+    ));
+
+    let handle = axum_server::Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown.await;
+        shutdown_handle.graceful_shutdown(None);
+    });
+
+    let result = axum_server::Server::<SocketAddr>::from_listener(listener)
+        .acceptor(RustlsAcceptor::new(rustls_config))
+        .handle(handle)
+        .serve(router.into_make_service())
+        .await;
+
+    reload.abort();
+    result
+}
+
+fn modified(tls: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some((modified(&tls.cert)?, modified(&tls.key)?))
+}
+
+/// Reloads the certificate and key whenever one of their files changed.
+/// Keeps serving the current certificate as long as the new files cannot be loaded,
+/// e.g., because only one of them was replaced yet.
+async fn reload_on_change(rustls_config: RustlsConfig, tls: TlsConfig, interval: Duration) {
+    let mut last_modified = modified(&tls);
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        let current = modified(&tls);
+        if current == last_modified {
+            continue;
+        }
+
+        match rustls_config
+            .reload_from_pem_file(&tls.cert, &tls.key)
+            .await
+        {
+            Ok(()) => {
+                info!(cert = %tls.cert.display(), "reloaded TLS certificate");
+                last_modified = current;
+            }
+            Err(err) => warn!("Could not reload TLS certificate: {}", err),
+        }
+    }
+}
+
+/// Redirects every request to the same host and path on the HTTPS port
+pub(crate) fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        redirect(&headers, &uri, https_port)
+    })
+}
+
+fn redirect(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Result<Redirect, StatusCode> {
+    let authority = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let port = match https_port {
+        443 => String::new(),
+        port => format!(":{port}"),
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+
+    Ok(Redirect::permanent(&format!(
+        "https://{}{port}{path}",
+        authority.host()
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::Duration};
+
+    use axum::{Router, body::Body, http::Request, routing::get};
+    use reqwest::{Certificate, StatusCode};
+    use tokio::net::TcpListener;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn client(cert: &str) -> reqwest::Client {
+        let cert = std::fs::read(format!("./tests/assets/{cert}")).unwrap();
+        reqwest::Client::builder()
+            .tls_certs_only([Certificate::from_pem(&cert).unwrap()])
+            .pool_max_idle_per_host(0)
+            .build()
+            .unwrap()
+    }
+
+    fn install(dir: &Path, n: u8) {
+        for file in ["cert", "key"] {
+            std::fs::copy(
+                format!("./tests/assets/tls-{file}-{n}.pem"),
+                dir.join(format!("{file}.pem")),
+            )
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_https_and_reloads_certificate() {
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("openleadr-vtn-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        install(&dir, 1);
+        let tls = TlsConfig {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+            redirect_port: None,
+        };
+
+        let rustls_config = rustls_config(&tls).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "https://localhost:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        let router = Router::new().route("/", get(|| async { "hello" }));
+        tokio::spawn(serve(
+            listener,
+            router,
+            rustls_config.clone(),
+            tls.clone(),
+            std::future::pending(),
+        ));
+        tokio::spawn(reload_on_change(
+            rustls_config,
+            tls,
+            Duration::from_millis(10),
+        ));
+
+        let response = client("tls-cert-1.pem").get(&url).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "hello");
+        assert!(client("tls-cert-2.pem").get(&url).send().await.is_err());
+
+        install(&dir, 2);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client("tls-cert-2.pem").get(&url).send().await.is_err() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(client("tls-cert-1.pem").get(&url).send().await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn redirects_to_https() {
+        let request = |host: &str| {
+            Request::builder()
+                .uri("/programs?limit=1")
+                .header(header::HOST, host)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = redirect_router(3443)
+            .oneshot(request("vtn.example.com:8080"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://vtn.example.com:3443/programs?limit=1"
+        );
+
+        let response = redirect_router(443)
+            .oneshot(request("vtn.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://vtn.example.com/programs?limit=1"
+        );
+    }
+}
@@ -19,6 +19,10 @@ async fn test_vtn_client_mdns_discovery() {
         mqtt_username: Some("user".to_string()),
         mqtt_password: Some("password".to_string()),
         mqtt_topic_prefix: String::new(),
+        mqtt_auth_hook_secret: None,
+        mqtt_batch_window: None,
+        webhook_failure_timeout: Duration::from_secs(86400),
+        ..VtnConfig::from_env().unwrap()
     };
 
     // Simulate VTN registration
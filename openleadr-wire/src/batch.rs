@@ -0,0 +1,114 @@
+//! Not part of the OpenADR specification.
+//! Requests and responses of the batch endpoints, e.g., `POST /events:batch`,
+//! which create, update, and delete many objects in a single transaction.
+
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use validator::{Validate, ValidateLength, ValidationError, ValidationErrors};
+
+use crate::problem::Problem;
+
+/// Most operations a single batch request may contain
+pub const MAX_BATCH_OPERATIONS: usize = 1000;
+
+/// A single operation of a [`BatchRequest`],
+/// with the same content as the body of the `POST` or `PUT` request of a business logic client
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum BatchOperation<Id, Content> {
+    Create { content: Content },
+    Update { id: Id, content: Content },
+    Delete { id: Id },
+}
+
+/// Operations that are applied in order, all or nothing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequest<Id, Content> {
+    pub operations: Vec<BatchOperation<Id, Content>>,
+}
+
+impl<Id, Content> BatchRequest<Id, Content> {
+    pub fn new(operations: Vec<BatchOperation<Id, Content>>) -> Self {
+        Self { operations }
+    }
+}
+
+/// Checks the number of operations only.
+/// The content of each operation is validated on its own,
+/// such that the failures are reported for each operation.
+impl<Id, Content> Validate for BatchRequest<Id, Content> {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        if self
+            .operations
+            .validate_length(Some(1), Some(MAX_BATCH_OPERATIONS as u64), None)
+        {
+            return Ok(());
+        }
+
+        let mut err = ValidationError::new("length");
+        err.add_param("min".into(), &1);
+        err.add_param("max".into(), &MAX_BATCH_OPERATIONS);
+
+        let mut errors = ValidationErrors::new();
+        errors.add("operations", err);
+        Err(errors)
+    }
+}
+
+/// Outcome of a single [`BatchOperation`]
+#[skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchResult<T> {
+    /// The status the operation would have had as a single request.
+    /// Operations that succeeded, but were rolled back as another one failed,
+    /// have status `424 Failed Dependency`.
+    #[serde(with = "crate::problem::status_code_serialization")]
+    pub status: StatusCode,
+    /// The created, updated, or deleted object, if the batch was committed
+    pub object: Option<T>,
+    /// Why the operation failed
+    pub problem: Option<Problem>,
+}
+
+/// The outcome of each operation of a [`BatchRequest`], in the same order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchResponse<T> {
+    pub results: Vec<BatchResult<T>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventId;
+
+    #[test]
+    fn operations() {
+        let request: BatchRequest<EventId, serde_json::Value> = serde_json::from_str(
+            r#"{"operations": [
+                {"op": "create", "content": {"eventName": "a"}},
+                {"op": "update", "id": "event-1", "content": {"eventName": "b"}},
+                {"op": "delete", "id": "event-2"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            request.operations,
+            vec![
+                BatchOperation::Create {
+                    content: serde_json::json!({"eventName": "a"})
+                },
+                BatchOperation::Update {
+                    id: "event-1".parse().unwrap(),
+                    content: serde_json::json!({"eventName": "b"})
+                },
+                BatchOperation::Delete {
+                    id: "event-2".parse().unwrap()
+                },
+            ]
+        );
+        assert!(request.validate().is_ok());
+        assert!(BatchRequest::<EventId, ()>::new(vec![]).validate().is_err());
+    }
+}
@@ -10,6 +10,7 @@ use serde::{Deserialize, Serialize};
 use serde_with::{DefaultOnNull, serde_as, skip_serializing_none};
 use std::{
     fmt::{Display, Formatter},
+    ops::Range,
     str::FromStr,
 };
 use validator::{Validate, ValidationError};
@@ -112,6 +113,67 @@ impl EventRequest {
         self.intervals = Some(intervals);
         self
     }
+
+    /// Resolves the time range each of the intervals of this event is active in.
+    ///
+    /// If both the event and an interval specify an [`IntervalPeriod`],
+    /// the one of the interval takes precedence.
+    /// An interval without a period starts at the end of the previous interval,
+    /// or at the start of the event's period if it is the first interval,
+    /// and lasts for the duration of the event's period.
+    /// If an interval has no period and the event does not specify one either,
+    /// this function returns [`None`].
+    /// Intervals without a duration never end.
+    pub fn resolved_intervals(&self) -> Option<Vec<ResolvedInterval<'_>>> {
+        let default_period = self.interval_period.as_ref();
+        let mut current_start = default_period.map(|p| p.start);
+        let mut resolved = vec![];
+
+        for interval in self.intervals.iter().flatten() {
+            let (start, duration, randomize_start) = match interval.interval_period.as_ref() {
+                Some(IntervalPeriod {
+                    start,
+                    duration,
+                    randomize_start,
+                }) => (*start, duration.as_ref(), randomize_start.as_ref()),
+                None => (
+                    current_start?,
+                    default_period?.duration.as_ref(),
+                    default_period?.randomize_start.as_ref(),
+                ),
+            };
+
+            let end = match duration {
+                Some(duration) => duration
+                    .checked_to_chrono_at_datetime(start)
+                    .and_then(|duration| start.checked_add_signed(duration))
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC),
+                None => DateTime::<Utc>::MAX_UTC,
+            };
+
+            current_start = Some(end);
+
+            resolved.push(ResolvedInterval {
+                interval,
+                range: start..end,
+                randomize_start,
+            });
+        }
+
+        Some(resolved)
+    }
+}
+
+/// An [`EventInterval`] together with the time range it is active in.
+/// See [`EventRequest::resolved_intervals`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedInterval<'a> {
+    pub interval: &'a EventInterval,
+    /// The time range the interval is active in. Ends at `DateTime::<Utc>::MAX_UTC` if the
+    /// interval does not have a duration.
+    pub range: Range<DateTime<Utc>>,
+    /// Indicates a randomization time that may be applied to the start.
+    pub randomize_start: Option<&'a Duration>,
 }
 
 /// URL safe VTN assigned object ID
@@ -398,6 +460,57 @@ mod tests {
         assert!(serde_json::from_str::<EventType>(&format!("\"{}\"", "x".repeat(129))).is_err());
     }
 
+    #[test]
+    fn resolve_intervals() {
+        let start: DateTime<Utc> = "2024-01-01T10:00:00Z".parse().unwrap();
+        let period = |start: DateTime<Utc>| IntervalPeriod {
+            start,
+            duration: Some(Duration::PT1H),
+            randomize_start: None,
+        };
+        let payloads = vec![EventValuesMap {
+            value_type: EventType::Simple,
+            values: vec![Value::Integer(1)],
+        }];
+
+        let mut event = EventRequest::new("program".parse().unwrap()).with_intervals(vec![
+            EventInterval::new(0, payloads.clone()),
+            EventInterval::new(1, payloads.clone()),
+            EventInterval {
+                id: 2,
+                interval_period: Some(period(start + chrono::Duration::hours(5))),
+                payloads: payloads.clone(),
+            },
+            EventInterval::new(3, payloads.clone()),
+        ]);
+        assert_eq!(event.resolved_intervals(), None);
+
+        event.interval_period = Some(period(start));
+        let ranges: Vec<_> = event
+            .resolved_intervals()
+            .unwrap()
+            .into_iter()
+            .map(|resolved| (resolved.interval.id, resolved.range))
+            .collect();
+        let hours = |h| start + chrono::Duration::hours(h);
+        assert_eq!(
+            ranges,
+            vec![
+                (0, hours(0)..hours(1)),
+                (1, hours(1)..hours(2)),
+                (2, hours(5)..hours(6)),
+                (3, hours(6)..hours(7)),
+            ]
+        );
+
+        event.interval_period.as_mut().unwrap().duration = None;
+        event.intervals.as_mut().unwrap().truncate(1);
+        assert_eq!(
+            event.resolved_intervals().unwrap()[0].range,
+            start..DateTime::<Utc>::MAX_UTC
+        );
+    }
+
     #[test]
     fn parse_minimal() {
         let example = r#"{"programID":"foo"}"#;
@@ -56,6 +56,17 @@ impl IntervalPeriod {
             randomize_start: None,
         }
     }
+
+    /// The end of the period, or `DateTime::<Utc>::MAX_UTC` if it has no duration
+    pub fn end(&self) -> DateTime<Utc> {
+        match &self.duration {
+            Some(duration) => duration
+                .checked_to_chrono_at_datetime(self.start)
+                .and_then(|duration| self.start.checked_add_signed(duration))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            None => DateTime::<Utc>::MAX_UTC,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -14,6 +14,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Unexpected};
 use std::{fmt::Display, str::FromStr};
 pub use ven::Ven;
 
+pub mod batch;
 pub mod event;
 pub mod interval;
 pub mod oauth;
@@ -54,6 +55,34 @@ pub mod serde_rfc3339 {
             )),
         }
     }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S, Tz>(
+            time: &Option<DateTime<Tz>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            Tz: TimeZone,
+        {
+            match time {
+                Some(time) => super::serialize(time, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super")] DateTime<Utc>);
+
+            Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(time)| time))
+        }
+    }
 }
 
 pub fn string_within_range_inclusive<'de, const MIN: usize, const MAX: usize, D>(
@@ -143,7 +172,7 @@ impl Display for Identifier {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum ObjectType {
     Program,
@@ -202,12 +231,68 @@ impl Duration {
     /// fixed duration. Their real duration (in real units like seconds) can only be determined
     /// when a starting time is given.
     ///
+    /// Saturates at [`chrono::Duration::MAX`] if the duration does not fit into
+    /// [`chrono::Duration`] or ends after the latest representable date,
+    /// see [`Duration::checked_to_chrono_at_datetime`].
+    ///
     /// NOTE: does not consider leap seconds!
     pub fn to_chrono_at_datetime<Tz: chrono::TimeZone>(
         &self,
         at: chrono::DateTime<Tz>,
     ) -> chrono::Duration {
-        self.0.to_chrono_at_datetime(at)
+        self.checked_to_chrono_at_datetime(at)
+            .unwrap_or(chrono::Duration::MAX)
+    }
+
+    /// Like [`Duration::to_chrono_at_datetime`], but returns [`None`] if the duration does not fit
+    /// into [`chrono::Duration`] or ends after the latest representable date.
+    /// Years and months last as long as the year and month `at` lies in.
+    pub fn checked_to_chrono_at_datetime<Tz: chrono::TimeZone>(
+        &self,
+        at: chrono::DateTime<Tz>,
+    ) -> Option<chrono::Duration> {
+        use chrono::{Datelike, NaiveDate};
+
+        fn seconds(seconds: f32) -> Option<chrono::Duration> {
+            if !seconds.is_finite() {
+                return None;
+            }
+            let nanoseconds = (seconds.fract() * 1_000_000_000.) as i64;
+            chrono::Duration::try_seconds(seconds.trunc() as i64)?
+                .checked_add(&chrono::Duration::nanoseconds(nanoseconds))
+        }
+
+        let iso8601_duration::Duration {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        } = self.0;
+        let date = at.date_naive();
+        let mut duration = chrono::Duration::zero();
+
+        if year > 0.0 {
+            let this_year = NaiveDate::from_ymd_opt(date.year(), 1, 1)?;
+            let next_year = NaiveDate::from_ymd_opt(date.year().checked_add(1)?, 1, 1)?;
+            let seconds_in_this_year = (next_year - this_year).num_seconds();
+            duration = duration.checked_add(&seconds(year * seconds_in_this_year as f32)?)?;
+        }
+
+        if month > 0.0 {
+            let this_month = NaiveDate::from_ymd_opt(date.year(), date.month(), 1)?;
+            let next_month = this_month.checked_add_months(chrono::Months::new(1))?;
+            let seconds_in_this_month = (next_month - this_month).num_seconds();
+            duration = duration.checked_add(&seconds(month * seconds_in_this_month as f32)?)?;
+        }
+
+        duration = duration.checked_add(&seconds(
+            day * 60. * 60. * 24. + hour * 60. * 60. + minute * 60. + second,
+        )?)?;
+
+        at.checked_add_signed(duration)?;
+        Some(duration)
     }
 
     /// One (1) hour
@@ -240,6 +325,17 @@ impl Duration {
         second: 0.0,
     });
 
+    pub const fn seconds(second: f32) -> Self {
+        Self(iso8601_duration::Duration {
+            year: 0.0,
+            month: 0.0,
+            day: 0.0,
+            hour: 0.0,
+            minute: 0.0,
+            second,
+        })
+    }
+
     pub const fn hours(hour: f32) -> Self {
         Self(iso8601_duration::Duration {
             year: 0.0,
@@ -405,9 +501,36 @@ impl ClientId {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Attribute, DataQuality, Identifier, OperatingState, Unit};
+    use crate::{Attribute, DataQuality, Duration, Identifier, OperatingState, Unit};
     use chrono::{DateTime, Utc};
 
+    #[test]
+    fn duration_to_chrono() {
+        let at: DateTime<Utc> = "2024-02-01T00:00:00Z".parse().unwrap();
+        let chrono = |duration: &str| {
+            duration
+                .parse::<Duration>()
+                .unwrap()
+                .checked_to_chrono_at_datetime(at)
+        };
+
+        assert_eq!(chrono("PT1H30M"), Some(chrono::Duration::minutes(90)));
+        // February of a leap year
+        assert_eq!(chrono("P1M"), Some(chrono::Duration::days(29)));
+        assert_eq!(chrono("P1Y"), Some(chrono::Duration::days(366)));
+        assert_eq!(chrono("P0.5D"), Some(chrono::Duration::hours(12)));
+
+        assert_eq!(chrono("P100000000Y"), None);
+        assert_eq!(chrono("PT99999999999999999999S"), None);
+        assert_eq!(
+            "P100000000Y"
+                .parse::<Duration>()
+                .unwrap()
+                .to_chrono_at_datetime(at),
+            chrono::Duration::MAX
+        );
+    }
+
     #[test]
     fn test_operating_state_serialization() {
         assert_eq!(
@@ -1,3 +1,5 @@
+use std::fmt::{Display, Formatter};
+
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
@@ -11,6 +13,110 @@ impl Default for ProblemUri {
     }
 }
 
+impl ProblemUri {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Not part of the OpenADR specification.
+/// Machine-readable kind of a [`Problem`], stable across versions of the VTN.
+/// Each code has its own [`ProblemUri`] of the form `urn:openleadr:problem:<code>`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request does not satisfy the constraints on its fields, see [`Problem::errors`]
+    ValidationFailed,
+    /// The request body is not valid JSON or form data, or does not match the schema
+    MalformedBody,
+    /// The query parameters cannot be parsed
+    MalformedQuery,
+    /// An object ID in the request is malformed
+    MalformedIdentifier,
+    /// Any other invalid request
+    BadRequest,
+    /// The request lacks valid authentication
+    Unauthorized,
+    /// The client is not allowed to perform the request
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    /// An object with the same unique properties exists, see [`Problem::constraint`]
+    Conflict,
+    /// The request refers to an object that does not exist,
+    /// or deletes an object other objects still refer to, see [`Problem::constraint`]
+    ReferenceViolated,
+    UnsupportedMediaType,
+    NotImplemented,
+    /// The VTN cannot handle the request right now, e.g., as it is shutting down
+    ServiceUnavailable,
+    /// The `PATCH` request cannot be applied to the object, e.g., as a `test` operation failed,
+    /// or the patched object does not match the schema
+    PatchFailed,
+    /// The `Idempotency-Key` of the request was used for a different request before
+    IdempotencyKeyReused,
+    /// A request with the same `Idempotency-Key` is still being processed, retry later
+    RequestInProgress,
+    /// The object contradicts the descriptors of its program, e.g., an event uses a payload type
+    /// the program does not describe, see [`Problem::errors`]
+    DescriptorMismatch,
+    /// The VTN failed to handle the request, e.g., as its database failed
+    InternalError,
+    /// A code this version of the library does not know
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::MalformedBody => "malformed_body",
+            ErrorCode::MalformedQuery => "malformed_query",
+            ErrorCode::MalformedIdentifier => "malformed_identifier",
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::ReferenceViolated => "reference_violated",
+            ErrorCode::UnsupportedMediaType => "unsupported_media_type",
+            ErrorCode::NotImplemented => "not_implemented",
+            ErrorCode::ServiceUnavailable => "service_unavailable",
+            ErrorCode::PatchFailed => "patch_failed",
+            ErrorCode::IdempotencyKeyReused => "idempotency_key_reused",
+            ErrorCode::RequestInProgress => "request_in_progress",
+            ErrorCode::DescriptorMismatch => "descriptor_mismatch",
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+
+    /// The [`Problem::r#type`] of problems with this code
+    pub fn problem_type(self) -> ProblemUri {
+        ProblemUri(format!("urn:openleadr:problem:{}", self.as_str()))
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Not part of the OpenADR specification.
+/// A field of the request that failed validation.
+#[skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// Path of the field in the request, e.g., `intervals[0].payloads`
+    pub field: String,
+    /// The violated rule, e.g., `length` or `range`
+    pub code: String,
+    pub message: Option<String>,
+}
+
 /// Reusable error response. From <https://opensource.zalando.com/problem/schema.yaml>.
 #[skip_serializing_none]
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
@@ -34,9 +140,20 @@ pub struct Problem {
     /// An absolute URI that identifies the specific occurrence of the problem.
     /// It may or may not yield further information if dereferenced.
     pub instance: Option<String>,
+    /// Not part of the OpenADR specification.
+    /// Machine-readable kind of the problem, matching [`r#type`](Self::r#type).
+    pub code: Option<ErrorCode>,
+    /// Not part of the OpenADR specification.
+    /// The fields that failed validation,
+    /// for [`ErrorCode::ValidationFailed`] and [`ErrorCode::DescriptorMismatch`].
+    pub errors: Option<Vec<FieldError>>,
+    /// Not part of the OpenADR specification.
+    /// The database constraint that was violated,
+    /// for [`ErrorCode::Conflict`] and [`ErrorCode::ReferenceViolated`].
+    pub constraint: Option<String>,
 }
 
-mod status_code_serialization {
+pub(crate) mod status_code_serialization {
     use super::*;
 
     use serde::{Deserializer, Serializer, de::Unexpected};
@@ -62,3 +179,28 @@ mod status_code_serialization {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(
+            serde_json::to_value(ErrorCode::ReferenceViolated).unwrap(),
+            "reference_violated"
+        );
+        assert_eq!(
+            ErrorCode::NotFound.problem_type().as_str(),
+            "urn:openleadr:problem:not_found"
+        );
+
+        // problems of VTNs that are newer or do not send codes can be read as well
+        let problem: Problem =
+            serde_json::from_str(r#"{"status": 409, "code": "something_new"}"#).unwrap();
+        assert_eq!(problem.code, Some(ErrorCode::Unknown));
+        let problem: Problem = serde_json::from_str(r#"{"status": 404}"#).unwrap();
+        assert_eq!(problem.code, None);
+        assert_eq!(problem.r#type, ProblemUri::default());
+    }
+}
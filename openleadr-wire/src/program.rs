@@ -58,6 +58,12 @@ pub struct ProgramRequest {
     #[serde(default)]
     #[serde_as(deserialize_as = "DefaultOnNull")]
     pub targets: Vec<Target>,
+    /// Not part of the OpenADR specification.
+    /// Whether the VTN rejects events that do not match the payload descriptors
+    /// and interval period of this program, or only warns about them.
+    /// Defaults to [`ValidationMode::Lenient`].
+    #[serde(default)]
+    pub validation_mode: Option<ValidationMode>,
 }
 
 impl ProgramRequest {
@@ -69,6 +75,47 @@ impl ProgramRequest {
             payload_descriptors: Default::default(),
             attributes: Default::default(),
             targets: Default::default(),
+            validation_mode: Default::default(),
+        }
+    }
+}
+
+/// Not part of the OpenADR specification.
+/// How the VTN treats objects that contradict the descriptors of their program,
+/// see [`ProgramRequest::validation_mode`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ValidationMode {
+    /// The request is rejected
+    Strict,
+    /// The request is accepted, and the response carries a `Warning` header for each violation
+    #[default]
+    Lenient,
+}
+
+impl ValidationMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValidationMode::Strict => "STRICT",
+            ValidationMode::Lenient => "LENIENT",
+        }
+    }
+}
+
+impl Display for ValidationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ValidationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "STRICT" => Ok(ValidationMode::Strict),
+            "LENIENT" => Ok(ValidationMode::Lenient),
+            _ => Err(format!("unknown validation mode '{s}'")),
         }
     }
 }
@@ -160,6 +207,7 @@ mod test {
                 payload_descriptors: None,
                 attributes: None,
                 targets: vec![],
+                validation_mode: None,
             },
         }];
 
@@ -179,7 +227,13 @@ mod test {
                 payload_descriptors: None,
                 attributes: None,
                 targets: vec![],
+                validation_mode: None,
             }
         );
+
+        let strict: ProgramRequest =
+            serde_json::from_str(r#"{"programName":"test","validationMode":"STRICT"}"#).unwrap();
+        assert_eq!(strict.validation_mode, Some(ValidationMode::Strict));
+        assert_eq!("STRICT".parse(), Ok(ValidationMode::Strict));
     }
 }
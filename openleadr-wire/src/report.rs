@@ -115,6 +115,19 @@ pub struct ReportResource {
     pub intervals: Vec<Interval>,
 }
 
+/// Not part of the OpenADR specification.
+/// Intervals appended to a resource of an existing report with `POST /reports/{reportID}/intervals`,
+/// without sending the intervals the report already has.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct AppendedIntervals {
+    /// The resource of the report to append the intervals to
+    pub resource_name: ResourceName,
+    /// Intervals with ids that are not used by the resource yet
+    #[validate(length(min = 1))]
+    pub intervals: Vec<Interval>,
+}
+
 /// An object that may be used to request a report from a VEN.
 // TODO: replace "-1 means" with proper enum
 #[skip_serializing_none]
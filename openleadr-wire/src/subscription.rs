@@ -9,7 +9,7 @@ use serde_with::{serde_as, skip_serializing_none};
 use validator::Validate;
 
 use crate::{
-    ClientId, Event, Identifier, IdentifierError, ObjectType, Program, Report, Ven,
+    ClientId, Duration, Event, Identifier, IdentifierError, ObjectType, Program, Report, Ven,
     program::ProgramId, resource::Resource, resource_group::ResourceGroup,
 };
 
@@ -26,6 +26,15 @@ pub struct Subscription {
     #[serde(with = "crate::serde_rfc3339")]
     pub modification_date_time: DateTime<Utc>,
     pub client_id: ClientId,
+    /// Not part of the OpenADR specification.
+    /// The time the subscription expires unless it is renewed,
+    /// only set if the subscription has a [`time_to_live`](SubscriptionRequest::time_to_live).
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::serde_rfc3339::option"
+    )]
+    pub expires_date_time: Option<DateTime<Utc>>,
     #[serde(flatten)]
     #[validate(nested)]
     pub content: SubscriptionRequest,
@@ -50,6 +59,16 @@ pub struct SubscriptionRequest {
     /// list of objects and operations to subscribe to.
     #[validate(length(min = 1, max = 15))]
     pub object_operations: Vec<SubscriptionObjectOperation>,
+    /// Not part of the OpenADR specification.
+    /// If present, the VTN deletes the subscription unless it is renewed within this duration.
+    /// Creating, updating, or renewing the subscription restarts the duration.
+    #[serde(default)]
+    pub time_to_live: Option<Duration>,
+    /// Not part of the OpenADR specification.
+    /// If present, webhook and websocket notifications for this subscription are collected
+    /// for this duration and delivered together as a [`NotificationBatch`].
+    #[serde(default)]
+    pub batch_window: Option<Duration>,
     // /// A list of target objects. Used by server to filter notifications.
     // #[serde(default)]
     // #[serde_as(deserialize_as = "DefaultOnNull")]
@@ -81,10 +100,37 @@ pub struct SubscriptionObjectOperation {
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
+#[non_exhaustive]
 pub enum Operation {
     Create,
     Update,
     Delete,
+    /// The first interval of an event became active.
+    /// Time-triggered and only sent for events. Not part of the OpenADR specification.
+    #[serde(rename = "EVENT_STARTED")]
+    EventStarted,
+    /// An interval of an event other than the first one became active.
+    /// Time-triggered and only sent for events. Not part of the OpenADR specification.
+    #[serde(rename = "INTERVAL_STARTED")]
+    IntervalStarted,
+    /// The last interval of an event ended.
+    /// Time-triggered and only sent for events. Not part of the OpenADR specification.
+    #[serde(rename = "EVENT_ENDED")]
+    EventEnded,
+    /// Sent on request of an operator to check that a subscriber receives notifications.
+    /// The object is the subscription the notification was sent for.
+    /// Not part of the OpenADR specification.
+    Test,
+}
+
+impl Operation {
+    /// Whether the operation is one of the time-triggered event lifecycle operations
+    pub fn is_lifecycle(&self) -> bool {
+        matches!(
+            self,
+            Self::EventStarted | Self::IntervalStarted | Self::EventEnded
+        )
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
@@ -163,6 +209,45 @@ pub struct MqttPushNotification {
     pub notification_date_time: DateTime<Utc>,
 }
 
+/// Not part of the OpenADR specification.
+/// The notifications collected during the batch window of a subscription or notifier binding,
+/// delivered as a single JSON array.
+///
+/// An update of an object replaces an earlier update of the same object in the same batch,
+/// such that a burst of changes to one object results in a single notification.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NotificationBatch(pub Vec<Notification>);
+
+impl NotificationBatch {
+    /// Adds the notification to the batch, merging it with an earlier update of the same object
+    pub fn push(&mut self, notification: Notification) {
+        if notification.operation == Operation::Update
+            && let Some(earlier) = self.0.iter_mut().find(|earlier| {
+                earlier.operation == Operation::Update
+                    && earlier.object.kind() == notification.object.kind()
+                    && earlier.object.id() == notification.object.id()
+            })
+        {
+            *earlier = notification;
+        } else {
+            self.0.push(notification);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Not part of the OpenADR specification.
+/// The push notifications collected during the batch window of the MQTT notifier binding,
+/// delivered as a single JSON array.
+/// Updates are merged like in a [`NotificationBatch`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MqttPushNotificationBatch(pub Vec<MqttPushNotification>);
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "objectType", content = "object", rename_all = "UPPERCASE")]
 pub enum AnyObject {
@@ -223,6 +308,10 @@ pub struct MqttNotifierBindingObject {
     /// Currently always JSON, perhaps other formats supported in future
     pub serialization: SerializationType,
     pub authentication: MqttNotifierAuthentication,
+    /// Not part of the OpenADR specification.
+    /// If present, the messages published to a topic are collected for this duration
+    /// and published together as a [`NotificationBatch`] or [`MqttPushNotificationBatch`].
+    pub batch_window: Option<Duration>,
 }
 
 /// MQTT broker authentication details
@@ -328,11 +417,46 @@ mod tests {
                         bearer_token: None,
                     }
                 ],
+                time_to_live: None,
+                batch_window: None,
                 // targets: vec![],
             }
         );
     }
 
+    #[test]
+    fn subscription_expiry() {
+        let example = r#"{
+  "id": "subscription-1",
+  "createdDateTime": "2024-01-01T10:00:00Z",
+  "modificationDateTime": "2024-01-01T10:00:00Z",
+  "clientId": "client-1",
+  "expiresDateTime": "2024-01-01T11:00:00Z",
+  "clientName": "myClient",
+  "objectOperations": [{"operations": ["CREATE"], "objects": ["EVENT"]}],
+  "timeToLive": "PT1H"
+}"#;
+        let subscription = serde_json::from_str::<Subscription>(example).unwrap();
+        assert_eq!(
+            subscription.expires_date_time,
+            Some("2024-01-01T11:00:00Z".parse().unwrap())
+        );
+        assert_eq!(subscription.content.time_to_live, Some(Duration::PT1H));
+
+        let serialized = serde_json::to_value(&subscription).unwrap();
+        assert_eq!(
+            serde_json::from_value::<Subscription>(serialized).unwrap(),
+            subscription
+        );
+
+        let mut without_expiry = subscription;
+        without_expiry.expires_date_time = None;
+        without_expiry.content.time_to_live = None;
+        let serialized = serde_json::to_value(&without_expiry).unwrap();
+        assert!(serialized.get("expiresDateTime").is_none());
+        assert!(serialized.get("timeToLive").is_none());
+    }
+
     #[test]
     fn parse_notification() {
         let example = r#"{
@@ -365,10 +489,47 @@ mod tests {
                         payload_descriptors: None,
                         attributes: None,
                         targets: vec![],
+                        validation_mode: None,
                     }
                 }),
                 // targets: vec![],
             }
         );
     }
+
+    #[test]
+    fn notification_batch_merges_updates() {
+        let notification = |id: &str, operation: Operation, program_id: &str| Notification {
+            id: id.parse().unwrap(),
+            operation,
+            object: AnyObject::Event(crate::Event {
+                id: "event-1".parse().unwrap(),
+                created_date_time: "2024-01-01T10:00:00Z".parse().unwrap(),
+                modification_date_time: "2024-01-01T10:00:00Z".parse().unwrap(),
+                content: crate::event::EventRequest::new(program_id.parse().unwrap()),
+            }),
+        };
+
+        let mut batch = NotificationBatch::default();
+        batch.push(notification("n-1", Operation::Create, "program-1"));
+        batch.push(notification("n-2", Operation::Update, "program-1"));
+        batch.push(notification("n-3", Operation::Update, "program-2"));
+        batch.push(notification("n-4", Operation::Delete, "program-2"));
+
+        assert_eq!(
+            batch.0,
+            vec![
+                notification("n-1", Operation::Create, "program-1"),
+                notification("n-3", Operation::Update, "program-2"),
+                notification("n-4", Operation::Delete, "program-2"),
+            ]
+        );
+
+        let serialized = serde_json::to_value(&batch).unwrap();
+        assert!(serialized.is_array());
+        assert_eq!(
+            serde_json::from_value::<NotificationBatch>(serialized).unwrap(),
+            batch
+        );
+    }
 }